@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+const WINDOWS: bool = cfg!(target_os = "windows");
+const ALL: bool = true;
+
+/// Kind of value an argument of an action takes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ArgKind {
+    // Existing file or directory
+    Path,
+    Paths,
+    // Existing directory such as the destination of a copy
+    Directory,
+    Text,
+    Integer,
+    Bool,
+    // Native window handle of the app
+    WindowHandle,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionArg {
+    pub name: String,
+    pub kind: ArgKind,
+    pub required: bool,
+}
+
+/// Capability of the crate that apps can offer in a command palette or on a toolbar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Action {
+    // Module and function such as "fs.copy_all", which stays the same across versions
+    pub id: String,
+    pub label: String,
+    // Module such as "fs", "shell" or "clipboard"
+    pub category: String,
+    pub args: Vec<ActionArg>,
+}
+
+/* Id, label, arguments with whether each is required, and whether the action is in this build */
+type Entry = (&'static str, &'static str, &'static [(&'static str, ArgKind, bool)], bool);
+
+const ACTIONS: &[Entry] = &[
+    ("fs.copy_all", "Copy to", &[("froms", ArgKind::Paths, true), ("to", ArgKind::Directory, true)], ALL),
+    ("fs.mv_all", "Move to", &[("froms", ArgKind::Paths, true), ("to", ArgKind::Directory, true)], ALL),
+    ("fs.trash_all", "Move to Recycle Bin", &[("file_paths", ArgKind::Paths, true)], ALL),
+    ("fs.delete_all", "Delete permanently", &[("file_paths", ArgKind::Paths, true)], ALL),
+    ("fs.copy_elevated", "Copy to as administrator", &[("froms", ArgKind::Paths, true), ("to", ArgKind::Directory, true)], ALL),
+    ("fs.delete_elevated", "Delete permanently as administrator", &[("file_paths", ArgKind::Paths, true)], ALL),
+    ("fs.mkdir", "New folder", &[("directory", ArgKind::Path, true)], ALL),
+    ("fs.create_symlink", "Create symbolic link", &[("full_path", ArgKind::Path, true), ("link_path", ArgKind::Path, true)], ALL),
+    ("fs.create_hardlink", "Create hard link", &[("file_path", ArgKind::Path, true), ("link_path", ArgKind::Path, true)], ALL),
+    ("fs.create_junction", "Create junction", &[("directory", ArgKind::Path, true), ("target", ArgKind::Directory, true)], WINDOWS),
+    ("fs.set_no_index", "Exclude from search index", &[("file_path", ArgKind::Path, true), ("no_index", ArgKind::Bool, true)], ALL),
+    ("fs.hash_file", "Calculate hash", &[("file_path", ArgKind::Path, true), ("algorithm", ArgKind::Text, true)], ALL),
+    ("fs.compare_files", "Compare files", &[("a", ArgKind::Path, true), ("b", ArgKind::Path, true), ("mode", ArgKind::Text, true)], ALL),
+    ("fs.undelete", "Restore from Recycle Bin", &[("file_paths", ArgKind::Paths, true)], ALL),
+    ("fs.empty_recycle_bin", "Empty Recycle Bin", &[("root", ArgKind::Directory, false)], ALL),
+    ("shell.open_path", "Open", &[("file_path", ArgKind::Path, true)], ALL),
+    ("shell.open_path_with", "Open with", &[("file_path", ArgKind::Path, true), ("app_path", ArgKind::Path, true)], ALL),
+    ("shell.show_open_with_dialog", "Choose another app", &[("file_path", ArgKind::Path, true)], ALL),
+    ("shell.execute_as", "Run as administrator", &[("file_path", ArgKind::Path, true), ("app_path", ArgKind::Path, true)], ALL),
+    ("shell.show_item_in_folder", "Show in folder", &[("file_path", ArgKind::Path, true)], ALL),
+    ("shell.open_file_property", "Properties", &[("file_path", ArgKind::Path, true)], ALL),
+    ("shell.share", "Share", &[("file_paths", ArgKind::Paths, true), ("window_handle", ArgKind::WindowHandle, true)], ALL),
+    ("shell.add_to_recent", "Add to recent files", &[("file_path", ArgKind::Path, true)], ALL),
+    ("shell.set_folder_view_hints", "Customize folder", &[("directory", ArgKind::Directory, true), ("folder_type", ArgKind::Text, false), ("icon", ArgKind::Path, false)], ALL),
+    ("clipboard.write_uris", "Copy or cut", &[("window_handle", ArgKind::WindowHandle, true), ("paths", ArgKind::Paths, true), ("operation", ArgKind::Text, true)], ALL),
+    ("clipboard.write_file_names", "Copy as path", &[("window_handle", ArgKind::WindowHandle, true), ("paths", ArgKind::Paths, true), ("style", ArgKind::Text, true)], ALL),
+    (
+        "search.search",
+        "Search",
+        &[("location", ArgKind::Directory, true), ("pattern", ArgKind::Text, true), ("recursive", ArgKind::Bool, false), ("is_regex", ArgKind::Bool, false), ("content", ArgKind::Text, false)],
+        ALL,
+    ),
+    ("archive.compress", "Compress to", &[("paths", ArgKind::Paths, true), ("dest", ArgKind::Path, true), ("format", ArgKind::Text, true)], cfg!(feature = "archive")),
+    ("archive.extract", "Extract to", &[("archive", ArgKind::Path, true), ("dest", ArgKind::Directory, true)], cfg!(feature = "archive")),
+    ("device.eject", "Eject", &[("mount_point", ArgKind::Directory, true)], cfg!(feature = "device")),
+    ("media.extract_image_preview", "Preview image", &[("file_path", ArgKind::Path, true), ("width", ArgKind::Integer, false), ("height", ArgKind::Integer, false)], cfg!(feature = "media")),
+];
+
+/// Lists the actions available on this platform with the enabled features, in the order they are usually shown
+pub fn list() -> Vec<Action> {
+    ACTIONS.iter().filter(|(_, _, _, available)| *available).map(to_action).collect()
+}
+
+/// Finds an available action by id
+pub fn find(id: &str) -> Option<Action> {
+    ACTIONS.iter().find(|(action_id, _, _, available)| *available && *action_id == id).map(to_action)
+}
+
+fn to_action(entry: &Entry) -> Action {
+    let (id, label, args, _) = entry;
+    Action {
+        id: id.to_string(),
+        label: label.to_string(),
+        category: id.split('.').next().unwrap_or_default().to_string(),
+        args: args
+            .iter()
+            .map(|(name, kind, required)| ActionArg {
+                name: name.to_string(),
+                kind: *kind,
+                required: *required,
+            })
+            .collect(),
+    }
+}
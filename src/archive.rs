@@ -0,0 +1,780 @@
+use crate::{
+    fs::{OperationStatus, Response, Total},
+    Dirent, FileAttribute,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+const BUFFER_SIZE: usize = 1024 * 1024;
+/* Error message of a read stopped by Response::Cancel, told apart from real failures */
+const CANCELLED: &str = "Cancelled";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    // Requires the archive_7z feature
+    SevenZip,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Compression {
+    // Stored without compression, for files that are already compressed such as photos and videos
+    Store,
+    #[default]
+    Deflate,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompressOptions {
+    // Zip only. TarGz and SevenZip always compress
+    pub compression: Compression,
+    // 0 to 9, the default of the format if None
+    pub level: Option<u32>,
+}
+
+impl ArchiveFormat {
+    /// Format by the extension of the archive, such as ".zip", ".tar.gz", ".tgz" and ".7z"
+    pub fn from_path<P: AsRef<Path>>(file_path: P) -> Option<Self> {
+        let name = file_path.as_ref().file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".7z") {
+            Some(Self::SevenZip)
+        } else {
+            None
+        }
+    }
+}
+
+/* File or directory to add, with its name in the archive */
+struct Source {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+    size: u64,
+}
+
+/// Compresses the files and directories into a new archive, each directory with its contents under its own name
+///
+/// Reports progress as fs operations do and asks with `OperationStatus::Confirm` before replacing an existing archive.
+/// Cancelling removes the partially written archive
+pub fn compress<P1: AsRef<Path>, P2: AsRef<Path>>(paths: &[P1], dest: P2, format: ArchiveFormat, options: &CompressOptions, callback: impl FnMut(OperationStatus) -> Response) -> Result<(), String> {
+    let mut callback = crate::throttle::throttled(callback);
+    let dest = dest.as_ref();
+    crate::policy::check_transfer(paths, dest.parent().unwrap_or(dest))?;
+    let _guard = crate::lifecycle::begin_operation()?;
+
+    let mut sources = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let name = path.file_name().ok_or(format!("Invalid path:{}", path.to_string_lossy()))?.to_string_lossy().to_string();
+        collect_sources(path, name, &mut sources)?;
+    }
+
+    let total_size = sources.iter().map(|source| source.size).sum();
+    if callback(OperationStatus::Ready(Total {
+        total_size,
+        total_count: sources.len() as u64,
+    })) == Response::Cancel
+    {
+        return Ok(());
+    }
+
+    if dest.exists() && matches!(callback(OperationStatus::Confirm(dest.to_string_lossy().to_string())), Response::Skip | Response::Cancel) {
+        return Ok(());
+    }
+
+    // Removed unless the archive is completed
+    let partial = crate::cleanup::PartialFile::new(dest);
+    let mut progress = Progress {
+        callback: &mut callback,
+        done: 0,
+        total: total_size,
+        cancelled: false,
+    };
+
+    let result = match format {
+        ArchiveFormat::Zip => compress_zip(&sources, dest, options, &mut progress),
+        ArchiveFormat::TarGz => compress_tar_gz(&sources, dest, options, &mut progress),
+        ArchiveFormat::SevenZip => compress_7z(&sources, dest, &mut progress),
+    };
+
+    if progress.cancelled {
+        return Ok(());
+    }
+
+    result?;
+    partial.complete();
+    callback(OperationStatus::Finished);
+    Ok(())
+}
+
+/// Extracts the archive into the directory, creating it if missing. The format is chosen by the extension of the archive
+///
+/// Asks with `OperationStatus::Confirm` before replacing each existing file. Entries pointing outside the directory are skipped
+pub fn extract<P1: AsRef<Path>, P2: AsRef<Path>>(archive: P1, dest: P2, callback: impl FnMut(OperationStatus) -> Response) -> Result<(), String> {
+    let mut callback = crate::throttle::throttled(callback);
+    let archive = archive.as_ref();
+    let dest = dest.as_ref();
+    crate::policy::check_transfer(&[archive], dest)?;
+    let _guard = crate::lifecycle::begin_operation()?;
+
+    let format = ArchiveFormat::from_path(archive).ok_or(format!("Unsupported archive:{}", archive.to_string_lossy()))?;
+    std::fs::create_dir_all(dest).map_err(|e| e.to_string())?;
+
+    let mut progress = Progress {
+        callback: &mut callback,
+        done: 0,
+        total: 0,
+        cancelled: false,
+    };
+
+    let result = match format {
+        ArchiveFormat::Zip => extract_zip(archive, dest, &mut progress),
+        ArchiveFormat::TarGz => extract_tar_gz(archive, dest, &mut progress),
+        ArchiveFormat::SevenZip => extract_7z(archive, dest, &mut progress),
+    };
+
+    if progress.cancelled {
+        return Ok(());
+    }
+
+    result?;
+    callback(OperationStatus::Finished);
+    Ok(())
+}
+
+/// Lists every file and directory in the archive without extracting it, for browsing it like a directory
+///
+/// `full_path` of each entry is the archive path joined with the path inside it, which `read_entry` takes relative to the archive.
+/// Directories that are only implied by the paths of their files are listed as well
+pub fn list<P: AsRef<Path>>(archive: P) -> Result<Vec<Dirent>, String> {
+    let archive = archive.as_ref();
+    let format = ArchiveFormat::from_path(archive).ok_or(format!("Unsupported archive:{}", archive.to_string_lossy()))?;
+
+    let items = match format {
+        ArchiveFormat::Zip => list_zip(archive)?,
+        ArchiveFormat::TarGz => list_tar_gz(archive)?,
+        ArchiveFormat::SevenZip => list_7z(archive)?,
+    };
+
+    // Keyed by the path inside the archive, so that each directory is listed once and parents come before their children
+    let mut entries: BTreeMap<String, Item> = BTreeMap::new();
+    for item in items {
+        let mut parent = Path::new(&item.path).parent();
+        while let Some(directory) = parent.filter(|directory| !directory.as_os_str().is_empty()) {
+            let key = directory.to_string_lossy().to_string();
+            entries.entry(key.clone()).or_insert(Item {
+                path: key,
+                is_dir: true,
+                size: 0,
+                mtime_ms: 0,
+            });
+            parent = directory.parent();
+        }
+        entries.insert(item.path.clone(), item);
+    }
+
+    Ok(entries.into_values().map(|item| to_dirent(archive, item)).collect())
+}
+
+/// Writes the content of a file inside the archive to the writer, returning the number of bytes written
+pub fn read_entry<P1: AsRef<Path>, P2: AsRef<Path>, W: Write>(archive: P1, inner_path: P2, mut writer: W) -> Result<u64, String> {
+    let archive = archive.as_ref();
+    let inner_path = to_inner_path(inner_path.as_ref()).ok_or(format!("Invalid path:{}", inner_path.as_ref().to_string_lossy()))?;
+    let format = ArchiveFormat::from_path(archive).ok_or(format!("Unsupported archive:{}", archive.to_string_lossy()))?;
+    let not_found = || format!("{} not found in {}", inner_path, archive.to_string_lossy());
+
+    match format {
+        ArchiveFormat::Zip => {
+            let mut reader = zip::ZipArchive::new(File::open(archive).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            let mut entry = reader.by_name(&inner_path).map_err(|_| not_found())?;
+            std::io::copy(&mut entry, &mut writer).map_err(|e| e.to_string())
+        }
+        ArchiveFormat::TarGz => {
+            let mut reader = tar::Archive::new(flate2::read::GzDecoder::new(File::open(archive).map_err(|e| e.to_string())?));
+            for entry in reader.entries().map_err(|e| e.to_string())? {
+                let mut entry = entry.map_err(|e| e.to_string())?;
+                let is_match = entry.path().ok().and_then(|path| to_inner_path(&path)).is_some_and(|path| path == inner_path);
+                if is_match && entry.header().entry_type().is_file() {
+                    return std::io::copy(&mut entry, &mut writer).map_err(|e| e.to_string());
+                }
+            }
+            Err(not_found())
+        }
+        ArchiveFormat::SevenZip => read_7z_entry(archive, &inner_path, &mut writer)?.ok_or_else(not_found),
+    }
+}
+
+/* Entry as stored in the archive, the path separated by "/" */
+struct Item {
+    path: String,
+    is_dir: bool,
+    size: u64,
+    mtime_ms: u64,
+}
+
+/* "dir/file.txt" from "./dir/file.txt", "dir\\file.txt" or "dir/sub/", None if it is absolute or climbs out with ".." */
+fn to_inner_path(path: &Path) -> Option<String> {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut components = Vec::new();
+    for component in Path::new(&path).components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy().to_string()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!components.is_empty()).then(|| components.join("/"))
+}
+
+fn to_dirent(archive: &Path, item: Item) -> Dirent {
+    let full_path = archive.join(&item.path);
+    let name = full_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+    Dirent {
+        mime_type: if item.is_dir {
+            String::new()
+        } else {
+            crate::fs::get_mime_type(&name)
+        },
+        name,
+        parent_path: full_path.parent().unwrap_or(archive).to_string_lossy().to_string(),
+        full_path: full_path.to_string_lossy().to_string(),
+        attributes: FileAttribute {
+            is_directory: item.is_dir,
+            is_read_only: true,
+            is_hidden: false,
+            is_system: false,
+            is_device: false,
+            is_symbolic_link: false,
+            is_junction: false,
+            is_file: !item.is_dir,
+            is_not_indexed: false,
+            ctime_ms: item.mtime_ms,
+            mtime_ms: item.mtime_ms,
+            atime_ms: item.mtime_ms,
+            birthtime_ms: item.mtime_ms,
+            size: item.size,
+            link_path: String::new(),
+        },
+        icon: None,
+        link_target: None,
+        owner: None,
+    }
+}
+
+fn list_zip(archive: &Path) -> Result<Vec<Item>, String> {
+    let mut reader = zip::ZipArchive::new(File::open(archive).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let mut items = Vec::new();
+
+    for index in 0..reader.len() {
+        let entry = reader.by_index_raw(index).map_err(|e| e.to_string())?;
+        let Some(path) = to_inner_path(Path::new(entry.name())) else {
+            continue;
+        };
+
+        items.push(Item {
+            path,
+            is_dir: entry.is_dir(),
+            size: entry.size(),
+            mtime_ms: entry.last_modified().map(|time| to_msecs(time.year(), time.month(), time.day(), time.hour(), time.minute(), time.second())).unwrap_or_default(),
+        });
+    }
+
+    Ok(items)
+}
+
+fn list_tar_gz(archive: &Path) -> Result<Vec<Item>, String> {
+    let mut reader = tar::Archive::new(flate2::read::GzDecoder::new(File::open(archive).map_err(|e| e.to_string())?));
+    let mut items = Vec::new();
+
+    for entry in reader.entries().map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let Some(path) = entry.path().ok().and_then(|path| to_inner_path(&path)) else {
+            continue;
+        };
+
+        let header = entry.header();
+        items.push(Item {
+            path,
+            is_dir: header.entry_type().is_dir(),
+            size: header.size().unwrap_or_default(),
+            mtime_ms: header.mtime().unwrap_or_default() * 1000,
+        });
+    }
+
+    Ok(items)
+}
+
+/* Milliseconds since the Unix epoch of the date and time of a zip entry, which has no time zone and is taken as UTC */
+fn to_msecs(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> u64 {
+    // Days from 1970-01-01 to the date in the proleptic Gregorian calendar
+    let (year, month) = if month <= 2 {
+        (year as i64 - 1, month as i64 + 9)
+    } else {
+        (year as i64, month as i64 - 3)
+    };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = days * 86400 + hour as i64 * 3600 + minute as i64 * 60 + second as i64;
+    seconds.max(0) as u64 * 1000
+}
+
+fn collect_sources(path: &Path, name: String, sources: &mut Vec<Source>) -> Result<(), String> {
+    let metadata = std::fs::metadata(path).map_err(|e| format!("{}:{}", e, path.to_string_lossy()))?;
+
+    if !metadata.is_dir() {
+        sources.push(Source {
+            path: path.to_path_buf(),
+            name,
+            is_dir: false,
+            size: metadata.len(),
+        });
+        return Ok(());
+    }
+
+    sources.push(Source {
+        path: path.to_path_buf(),
+        name: name.clone(),
+        is_dir: true,
+        size: 0,
+    });
+
+    // Linked directories are added as empty directories so that a link to an ancestor cannot loop
+    if path.symlink_metadata().is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        return Ok(());
+    }
+
+    let mut children: Vec<PathBuf> = std::fs::read_dir(path).map_err(|e| e.to_string())?.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    children.sort();
+    for child in children {
+        let child_name = format!("{}/{}", name, child.file_name().unwrap_or_default().to_string_lossy());
+        collect_sources(&child, child_name, sources)?;
+    }
+
+    Ok(())
+}
+
+/* Progress of the whole operation, shared by every entry */
+struct Progress<'a> {
+    callback: &'a mut dyn FnMut(OperationStatus) -> Response,
+    done: u64,
+    total: u64,
+    cancelled: bool,
+}
+
+impl Progress<'_> {
+    /// Returns false if the user cancelled
+    fn report(&mut self, status: OperationStatus) -> bool {
+        if (self.callback)(status) == Response::Cancel {
+            self.cancelled = true;
+        }
+        !self.cancelled
+    }
+
+    fn advance(&mut self, len: u64) -> bool {
+        self.done += len;
+        self.report(OperationStatus::Progress(self.done as i64, self.total as i64))
+    }
+
+    /// Asks whether to replace an existing file, returning false to leave it
+    fn confirm(&mut self, file_path: &Path) -> bool {
+        if !file_path.exists() {
+            return true;
+        }
+
+        match (self.callback)(OperationStatus::Confirm(file_path.to_string_lossy().to_string())) {
+            Response::Cancel => {
+                self.cancelled = true;
+                false
+            }
+            Response::Skip => false,
+            _ => true,
+        }
+    }
+
+    /// Copies with a progress report after each chunk, failing with CANCELLED if the user cancelled
+    fn copy<R: Read + ?Sized, W: Write + ?Sized>(&mut self, reader: &mut R, writer: &mut W) -> std::io::Result<()> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            let len = reader.read(&mut buffer)?;
+            if len == 0 {
+                return Ok(());
+            }
+            writer.write_all(&buffer[..len])?;
+            if !self.advance(len as u64) {
+                return Err(std::io::Error::other(CANCELLED));
+            }
+        }
+    }
+}
+
+/* Joins the name in the archive to the destination, None if it is absolute or climbs out with ".." */
+fn enclosed_path(dest: &Path, name: &Path) -> Option<PathBuf> {
+    name.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)).then(|| dest.join(name))
+}
+
+fn compress_zip(sources: &[Source], dest: &Path, options: &CompressOptions, progress: &mut Progress) -> Result<(), String> {
+    let file = File::create(dest).map_err(|e| e.to_string())?;
+    let mut writer = zip::ZipWriter::new(file);
+
+    let method = match options.compression {
+        Compression::Store => zip::CompressionMethod::Stored,
+        Compression::Deflate => zip::CompressionMethod::Deflated,
+    };
+
+    for source in sources {
+        // Stored entries fail with a level
+        let level = options.level.filter(|_| method == zip::CompressionMethod::Deflated).map(|level| level as i64);
+        let file_options = zip::write::SimpleFileOptions::default().compression_method(method).compression_level(level).large_file(source.size >= u32::MAX as u64);
+        #[cfg(target_os = "linux")]
+        let file_options = match std::fs::metadata(&source.path) {
+            Ok(metadata) => file_options.unix_permissions(std::os::unix::fs::PermissionsExt::mode(&metadata.permissions())),
+            Err(_) => file_options,
+        };
+
+        if source.is_dir {
+            writer.add_directory(format!("{}/", source.name), file_options).map_err(|e| e.to_string())?;
+            continue;
+        }
+
+        if !progress.report(OperationStatus::Start(source.name.clone())) {
+            return Ok(());
+        }
+        writer.start_file(source.name.as_str(), file_options).map_err(|e| e.to_string())?;
+        let mut reader = File::open(&source.path).map_err(|e| e.to_string())?;
+        progress.copy(&mut reader, &mut writer).map_err(|e| e.to_string())?;
+        if !progress.report(OperationStatus::End) {
+            return Ok(());
+        }
+    }
+
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn compress_tar_gz(sources: &[Source], dest: &Path, options: &CompressOptions, progress: &mut Progress) -> Result<(), String> {
+    let file = File::create(dest).map_err(|e| e.to_string())?;
+    let level = options.level.map(flate2::Compression::new).unwrap_or_default();
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(file, level));
+
+    for source in sources {
+        if source.is_dir {
+            builder.append_dir(&source.name, &source.path).map_err(|e| e.to_string())?;
+            continue;
+        }
+
+        if !progress.report(OperationStatus::Start(source.name.clone())) {
+            return Ok(());
+        }
+
+        let file = File::open(&source.path).map_err(|e| e.to_string())?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&file.metadata().map_err(|e| e.to_string())?);
+
+        let mut reader = ProgressReader {
+            inner: file,
+            progress: &mut *progress,
+        };
+        builder.append_data(&mut header, &source.name, &mut reader).map_err(|e| e.to_string())?;
+        if !progress.report(OperationStatus::End) {
+            return Ok(());
+        }
+    }
+
+    builder.into_inner().and_then(|encoder| encoder.finish()).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/* Reader reporting each chunk, for archive writers that pull the data themselves */
+struct ProgressReader<'a, 'b, R: Read> {
+    inner: R,
+    progress: &'a mut Progress<'b>,
+}
+
+impl<R: Read> Read for ProgressReader<'_, '_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buffer)?;
+        if len > 0 && !self.progress.advance(len as u64) {
+            return Err(std::io::Error::other(CANCELLED));
+        }
+        Ok(len)
+    }
+}
+
+fn extract_zip(archive: &Path, dest: &Path, progress: &mut Progress) -> Result<(), String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    let mut reader = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+
+    let mut total_size = 0;
+    for index in 0..reader.len() {
+        total_size += reader.by_index(index).map_err(|e| e.to_string())?.size();
+    }
+    progress.total = total_size;
+    if !progress.report(OperationStatus::Ready(Total {
+        total_size,
+        total_count: reader.len() as u64,
+    })) {
+        return Ok(());
+    }
+
+    for index in 0..reader.len() {
+        let mut entry = reader.by_index(index).map_err(|e| e.to_string())?;
+        let Some(target) = entry.enclosed_name().map(|name| dest.join(name)) else {
+            continue;
+        };
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target).map_err(|e| e.to_string())?;
+            continue;
+        }
+
+        if !progress.confirm(&target) {
+            if progress.cancelled {
+                return Ok(());
+            }
+            progress.done += entry.size();
+            continue;
+        }
+
+        if !progress.report(OperationStatus::Start(entry.name().to_string())) {
+            return Ok(());
+        }
+        write_entry(&target, &mut entry, progress)?;
+
+        #[cfg(target_os = "linux")]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode));
+        }
+
+        if !progress.report(OperationStatus::End) {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_tar_gz(archive: &Path, dest: &Path, progress: &mut Progress) -> Result<(), String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    // The uncompressed size is unknown until the end, so progress is the compressed bytes read out of the archive size
+    progress.total = file.metadata().map_err(|e| e.to_string())?.len();
+    let read = std::rc::Rc::new(std::cell::Cell::new(0));
+    let counter = CountingReader {
+        inner: file,
+        read: read.clone(),
+    };
+
+    let mut reader = tar::Archive::new(flate2::read::GzDecoder::new(counter));
+    reader.set_preserve_permissions(cfg!(target_os = "linux"));
+
+    for entry in reader.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        let name = entry.path().map_err(|e| e.to_string())?.to_path_buf();
+        let Some(target) = enclosed_path(dest, &name) else {
+            continue;
+        };
+
+        // unpack_in also refuses to write through links pointing outside the destination
+        if entry.header().entry_type().is_dir() {
+            entry.unpack_in(dest).map_err(|e| e.to_string())?;
+            continue;
+        }
+
+        if !progress.confirm(&target) {
+            if progress.cancelled {
+                return Ok(());
+            }
+            continue;
+        }
+
+        if !progress.report(OperationStatus::Start(name.to_string_lossy().to_string())) {
+            return Ok(());
+        }
+        entry.unpack_in(dest).map_err(|e| e.to_string())?;
+
+        progress.done = read.get();
+        if !progress.advance(0) || !progress.report(OperationStatus::End) {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/* Counts the bytes read so that progress can be reported while the decoder owns the reader */
+struct CountingReader<R: Read> {
+    inner: R,
+    read: std::rc::Rc<std::cell::Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buffer)?;
+        self.read.set(self.read.get() + len as u64);
+        Ok(len)
+    }
+}
+
+/* Writes to the target through a partial file, so that a cancelled or failed entry does not leave a truncated file behind */
+fn write_entry<R: Read + ?Sized>(target: &Path, reader: &mut R, progress: &mut Progress) -> Result<(), String> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    let partial = crate::cleanup::PartialFile::new(target);
+    let mut file = File::create(target).map_err(|e| e.to_string())?;
+    progress.copy(reader, &mut file).map_err(|e| e.to_string())?;
+    partial.complete();
+    Ok(())
+}
+
+#[cfg(feature = "archive_7z")]
+fn compress_7z(sources: &[Source], dest: &Path, progress: &mut Progress) -> Result<(), String> {
+    let mut writer = sevenz_rust::SevenZWriter::create(dest).map_err(|e| e.to_string())?;
+
+    for source in sources {
+        let entry = sevenz_rust::SevenZArchiveEntry::from_path(&source.path, source.name.clone());
+        if source.is_dir {
+            writer.push_archive_entry::<File>(entry, None).map_err(|e| e.to_string())?;
+            continue;
+        }
+
+        if !progress.report(OperationStatus::Start(source.name.clone())) {
+            return Ok(());
+        }
+        let reader = ProgressReader {
+            inner: File::open(&source.path).map_err(|e| e.to_string())?,
+            progress: &mut *progress,
+        };
+        writer.push_archive_entry(entry, Some(reader)).map_err(|e| e.to_string())?;
+        if !progress.report(OperationStatus::End) {
+            return Ok(());
+        }
+    }
+
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(feature = "archive_7z")]
+fn extract_7z(archive: &Path, dest: &Path, progress: &mut Progress) -> Result<(), String> {
+    let reader = sevenz_rust::SevenZReader::open(archive, sevenz_rust::Password::empty()).map_err(|e| e.to_string())?;
+    let files = &reader.archive().files;
+    progress.total = files.iter().map(|file| file.size()).sum();
+    if !progress.report(OperationStatus::Ready(Total {
+        total_size: progress.total,
+        total_count: files.len() as u64,
+    })) {
+        return Ok(());
+    }
+
+    let mut error = None;
+    sevenz_rust::decompress_file_with_extract_fn(archive, dest, |entry, reader, _| {
+        let Some(target) = enclosed_path(dest, Path::new(entry.name())) else {
+            return Ok(true);
+        };
+
+        if entry.is_directory() {
+            let _ = std::fs::create_dir_all(&target);
+            return Ok(true);
+        }
+
+        if !progress.confirm(&target) {
+            progress.done += entry.size();
+            return Ok(!progress.cancelled);
+        }
+
+        if !progress.report(OperationStatus::Start(entry.name().to_string())) {
+            return Ok(false);
+        }
+        if let Err(e) = write_entry(&target, reader, progress) {
+            error = Some(e);
+            return Ok(false);
+        }
+        Ok(progress.report(OperationStatus::End))
+    })
+    .map_err(|e| e.to_string())?;
+
+    match error {
+        Some(e) if !progress.cancelled => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "archive_7z")]
+fn list_7z(archive: &Path) -> Result<Vec<Item>, String> {
+    let reader = sevenz_rust::SevenZReader::open(archive, sevenz_rust::Password::empty()).map_err(|e| e.to_string())?;
+    Ok(reader
+        .archive()
+        .files
+        .iter()
+        .filter_map(|entry| {
+            let mtime_ms = if entry.has_last_modified_date {
+                std::time::SystemTime::from(entry.last_modified_date).duration_since(std::time::UNIX_EPOCH).map(|duration| duration.as_millis() as u64).unwrap_or_default()
+            } else {
+                0
+            };
+
+            Some(Item {
+                path: to_inner_path(Path::new(entry.name()))?,
+                is_dir: entry.is_directory(),
+                size: entry.size(),
+                mtime_ms,
+            })
+        })
+        .collect())
+}
+
+#[cfg(feature = "archive_7z")]
+fn read_7z_entry(archive: &Path, inner_path: &str, writer: &mut dyn Write) -> Result<Option<u64>, String> {
+    let mut reader = sevenz_rust::SevenZReader::open(archive, sevenz_rust::Password::empty()).map_err(|e| e.to_string())?;
+    let mut result = None;
+
+    // Solid blocks are decompressed from their start, so entries are walked until the one asked for
+    reader
+        .for_each_entries(|entry, entry_reader| {
+            if entry.is_directory() || to_inner_path(Path::new(entry.name())).as_deref() != Some(inner_path) {
+                return Ok(true);
+            }
+            result = Some(std::io::copy(entry_reader, writer).map_err(|e| e.to_string()));
+            Ok(false)
+        })
+        .map_err(|e| e.to_string())?;
+
+    result.transpose()
+}
+
+#[cfg(not(feature = "archive_7z"))]
+fn list_7z(_: &Path) -> Result<Vec<Item>, String> {
+    Err("7z requires the archive_7z feature".to_string())
+}
+
+#[cfg(not(feature = "archive_7z"))]
+fn read_7z_entry(_: &Path, _: &str, _: &mut dyn Write) -> Result<Option<u64>, String> {
+    Err("7z requires the archive_7z feature".to_string())
+}
+
+#[cfg(not(feature = "archive_7z"))]
+fn compress_7z(_: &[Source], _: &Path, _: &mut Progress) -> Result<(), String> {
+    Err("7z requires the archive_7z feature".to_string())
+}
+
+#[cfg(not(feature = "archive_7z"))]
+fn extract_7z(_: &Path, _: &Path, _: &mut Progress) -> Result<(), String> {
+    Err("7z requires the archive_7z feature".to_string())
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// The oldest entries are dropped beyond this
+const MAX_ENTRIES: usize = 10_000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ENTRIES: Mutex<VecDeque<AuditEntry>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum AuditAction {
+    Delete,
+    Trash,
+    Overwrite,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp_ms: u64,
+    pub user: String,
+    pub application: String,
+    pub action: AuditAction,
+    pub path: String,
+    // Source item that replaced `path` on overwrite
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum AuditFormat {
+    Jsonl,
+    Csv,
+}
+
+/// Starts recording destructive file operations
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Stops recording. Recorded entries are kept until cleared
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Returns recorded entries, up to the latest 10,000. Export them periodically to keep older ones
+pub fn entries() -> Vec<AuditEntry> {
+    ENTRIES.lock().unwrap().iter().cloned().collect()
+}
+
+/// Removes all recorded entries
+pub fn clear() {
+    ENTRIES.lock().unwrap().clear();
+}
+
+/// Writes recorded entries to a file
+pub fn export<P: AsRef<Path>>(file_path: P, format: AuditFormat) -> Result<(), String> {
+    let mut file = std::fs::File::create(file_path).map_err(|e| e.to_string())?;
+
+    match format {
+        AuditFormat::Jsonl => {
+            for entry in ENTRIES.lock().unwrap().iter() {
+                let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+                writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+            }
+        }
+        AuditFormat::Csv => {
+            writeln!(file, "timestamp_ms,user,application,action,path,source").map_err(|e| e.to_string())?;
+            for entry in ENTRIES.lock().unwrap().iter() {
+                writeln!(file, "{},{},{},{:?},{},{}", entry.timestamp_ms, escape_csv(&entry.user), escape_csv(&entry.application), entry.action, escape_csv(&entry.path), escape_csv(&entry.source))
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub(crate) fn record<P: AsRef<Path>>(action: AuditAction, file_path: P) {
+    record_with_source(action, file_path, "");
+}
+
+pub(crate) fn record_with_source<P1: AsRef<Path>, P2: AsRef<Path>>(action: AuditAction, file_path: P1, source: P2) {
+    if !is_enabled() {
+        return;
+    }
+
+    let entry = AuditEntry {
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_millis() as u64).unwrap_or_default(),
+        user: std::env::var(if cfg!(target_os = "windows") {
+            "USERNAME"
+        } else {
+            "USER"
+        })
+        .unwrap_or_default(),
+        application: std::env::current_exe().ok().and_then(|exe| exe.file_name().map(|name| name.to_string_lossy().to_string())).unwrap_or_default(),
+        action,
+        path: file_path.as_ref().to_string_lossy().to_string(),
+        source: source.as_ref().to_string_lossy().to_string(),
+    };
+
+    let mut entries = ENTRIES.lock().unwrap();
+    if entries.len() >= MAX_ENTRIES {
+        entries.pop_front();
+    }
+    entries.push_back(entry);
+}
@@ -0,0 +1,77 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Mutex, Once},
+};
+
+// Destinations being written and temporary files removed at exit
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static AT_EXIT: Once = Once::new();
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> std::ffi::c_int;
+}
+
+extern "C" fn clean_up_at_exit() {
+    // Another thread may hold the lock while the process exits
+    if let Ok(mut pending) = PENDING.try_lock() {
+        pending.drain(..).for_each(|path| remove(&path));
+    }
+}
+
+fn remove(path: &Path) {
+    if path.is_dir() {
+        let _ = std::fs::remove_dir_all(path);
+    } else {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Removes the file or directory at the latest when the process exits
+pub(crate) fn register<P: AsRef<Path>>(path: P) {
+    AT_EXIT.call_once(|| unsafe {
+        atexit(clean_up_at_exit);
+    });
+    PENDING.lock().unwrap().push(path.as_ref().to_path_buf());
+}
+
+pub(crate) fn unregister<P: AsRef<Path>>(path: P) {
+    let mut pending = PENDING.lock().unwrap();
+    if let Some(index) = pending.iter().position(|pending| pending == path.as_ref()) {
+        pending.swap_remove(index);
+    }
+}
+
+/// Removes every registered path now
+pub(crate) fn clean_up() {
+    let paths: Vec<PathBuf> = PENDING.lock().unwrap().drain(..).collect();
+    paths.iter().for_each(|path| remove(path));
+}
+
+/// Destination written by an operation. Removed when dropped unless completed, so cancelled or failed operations do not leave a partial file
+pub(crate) struct PartialFile {
+    path: PathBuf,
+    completed: bool,
+}
+
+impl PartialFile {
+    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Self {
+        register(path.as_ref());
+        Self {
+            path: path.as_ref().to_path_buf(),
+            completed: false,
+        }
+    }
+
+    pub(crate) fn complete(mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        unregister(&self.path);
+        if !self.completed {
+            remove(&self.path);
+        }
+    }
+}
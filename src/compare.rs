@@ -0,0 +1,141 @@
+use crate::{CompareMode, FileComparison, LineDiffSummary};
+use std::io::Read;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Compares the sources byte by byte or line by line
+pub(crate) fn compare_readers<R1: Read, R2: Read>(a: R1, b: R2, mode: &CompareMode) -> Result<FileComparison, String> {
+    match mode {
+        CompareMode::Binary => compare_binary(a, b),
+        CompareMode::Text => compare_text(a, b),
+    }
+}
+
+fn compare_binary<R1: Read, R2: Read>(mut a: R1, mut b: R2) -> Result<FileComparison, String> {
+    let mut buffer_a = vec![0u8; CHUNK_SIZE];
+    let mut buffer_b = vec![0u8; CHUNK_SIZE];
+    let mut offset = 0;
+
+    loop {
+        let len_a = read_chunk(&mut a, &mut buffer_a)?;
+        let len_b = read_chunk(&mut b, &mut buffer_b)?;
+        let len = len_a.min(len_b);
+
+        if let Some(position) = buffer_a[..len].iter().zip(&buffer_b[..len]).position(|(x, y)| x != y) {
+            return Ok(FileComparison {
+                equal: false,
+                first_difference: Some(offset + position as u64),
+                lines: None,
+            });
+        }
+
+        // One of the sources ended
+        if len_a != len_b {
+            return Ok(FileComparison {
+                equal: false,
+                first_difference: Some(offset + len as u64),
+                lines: None,
+            });
+        }
+
+        if len == 0 {
+            return Ok(FileComparison {
+                equal: true,
+                first_difference: None,
+                lines: None,
+            });
+        }
+
+        offset += len as u64;
+    }
+}
+
+/* Fills the buffer unless the source ends, since read may return fewer bytes than available */
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, String> {
+    let mut len = 0;
+    while len < buffer.len() {
+        let read = reader.read(&mut buffer[len..]).map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        len += read;
+    }
+    Ok(len)
+}
+
+/* Line endings are ignored so that CRLF and LF files with the same lines are equal */
+fn compare_text<R1: Read, R2: Read>(mut a: R1, mut b: R2) -> Result<FileComparison, String> {
+    let mut bytes_a = Vec::new();
+    a.read_to_end(&mut bytes_a).map_err(|e| e.to_string())?;
+    let mut bytes_b = Vec::new();
+    b.read_to_end(&mut bytes_b).map_err(|e| e.to_string())?;
+
+    let text_a = String::from_utf8_lossy(&bytes_a);
+    let text_b = String::from_utf8_lossy(&bytes_b);
+    let lines_a: Vec<&str> = text_a.lines().collect();
+    let lines_b: Vec<&str> = text_b.lines().collect();
+
+    // Only the lines between the common prefix and suffix need to be diffed
+    let prefix = lines_a.iter().zip(&lines_b).take_while(|(x, y)| x == y).count();
+    let suffix = lines_a[prefix..].iter().rev().zip(lines_b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let middle_a = &lines_a[prefix..lines_a.len() - suffix];
+    let middle_b = &lines_b[prefix..lines_b.len() - suffix];
+
+    let distance = edit_distance(middle_a, middle_b);
+    let common = (middle_a.len() + middle_b.len() - distance) / 2;
+    let removed = middle_a.len() - common;
+    let added = middle_b.len() - common;
+    let equal = removed == 0 && added == 0;
+
+    Ok(FileComparison {
+        equal,
+        first_difference: None,
+        lines: Some(LineDiffSummary {
+            added,
+            removed,
+            unchanged: prefix + suffix + common,
+            first_different_line: if equal {
+                None
+            } else {
+                Some(prefix + 1)
+            },
+        }),
+    })
+}
+
+/* Myers' algorithm. Returns the number of inserted and deleted lines */
+fn edit_distance(a: &[&str], b: &[&str]) -> usize {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = a.len() + b.len();
+    if max == 0 {
+        return 0;
+    }
+
+    // Furthest x on each diagonal k, indexed by k + max
+    let mut v = vec![0isize; 2 * max + 2];
+
+    for d in 0..=max as isize {
+        for k in (-d..=d).step_by(2) {
+            let index = (k + max as isize) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[index] = x;
+
+            if x >= n && y >= m {
+                return d as usize;
+            }
+        }
+    }
+
+    max
+}
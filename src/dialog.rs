@@ -1,6 +1,46 @@
-use rfd::{AsyncFileDialog, AsyncMessageDialog, MessageButtons, MessageDialogResult, MessageLevel};
+#[cfg(target_os = "windows")]
+use raw_window_handle::{DisplayHandle, HandleError, HasDisplayHandle, Win32WindowHandle, WindowHandle};
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+#[cfg(target_os = "windows")]
+use rfd::AsyncFileDialog;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Where a dialog appears and how it gets attention
+///
+/// All dialogs are owned by `parent`, which on Linux works on X11 only
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DialogPlacement {
+    // HWND on Windows, X11 window ID on Linux
+    pub parent: Option<isize>,
+    // Index of the monitor to center on when there is no parent
+    pub monitor: Option<u32>,
+    pub keep_above: bool,
+    // Flashes the taskbar button of the parent until it is focused
+    pub urgent: bool,
+}
+
+impl DialogPlacement {
+    /// Placement owned by the window, taking the handle from winit, tao or any other crate implementing raw-window-handle
+    ///
+    /// Wayland and GTK handles are not supported and leave the dialog without a parent
+    pub fn for_window(window: &impl HasWindowHandle) -> Self {
+        let parent = window.window_handle().ok().and_then(|handle| match handle.as_raw() {
+            RawWindowHandle::Win32(handle) => Some(handle.hwnd.get()),
+            RawWindowHandle::Xlib(handle) => Some(handle.window as isize),
+            RawWindowHandle::Xcb(handle) => Some(handle.window.get() as isize),
+            _ => None,
+        });
+
+        Self {
+            parent,
+            ..Default::default()
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageDialogKind {
@@ -16,12 +56,24 @@ pub struct MessageDialogOptions {
     pub buttons: Vec<String>,
     pub message: String,
     pub cancel_id: Option<u32>,
+    // Pressed by Enter and focused first
+    pub default_id: Option<u32>,
+    pub placement: Option<DialogPlacement>,
+    // Checkbox below the message such as "Do this for all conflicts" or "Don't ask again"
+    pub checkbox_label: Option<String>,
+    pub checkbox_checked: bool,
+    // Longer text such as error details, hidden until the user expands it
+    pub detail: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct MessageResult {
+    // Index in `buttons`, which tells apart buttons with the same label. None when closed with Esc and there is no cancel button
+    pub button_index: Option<u32>,
     pub button: String,
     pub cancelled: bool,
+    // Final state of the checkbox, false when there is none
+    pub checked: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -37,6 +89,7 @@ pub struct OpenDialogOptions {
     pub default_path: Option<String>,
     pub filters: Option<Vec<FileFilter>>,
     pub properties: Option<Vec<OpenProperty>>,
+    pub placement: Option<DialogPlacement>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -44,6 +97,7 @@ pub struct SaveDialogOptions {
     pub title: Option<String>,
     pub default_path: Option<String>,
     pub filters: Option<Vec<FileFilter>>,
+    pub placement: Option<DialogPlacement>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -76,138 +130,124 @@ impl Default for FileDialogResult {
     }
 }
 
-fn get_level(kind: &Option<MessageDialogKind>) -> MessageLevel {
-    if let Some(kind) = kind {
-        match kind {
-            MessageDialogKind::Info => MessageLevel::Info,
-            MessageDialogKind::Warning => MessageLevel::Warning,
-            MessageDialogKind::Error => MessageLevel::Error,
-        }
-    } else {
-        MessageLevel::Info
-    }
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProgressDialogOptions {
+    pub title: Option<String>,
+    // Main text such as "Exporting 12 photos"
+    pub message: String,
+    // Secondary text below the progress bar such as the current file
+    pub detail: Option<String>,
+    // Shows a Pause button next to Cancel
+    pub pausable: bool,
+    pub placement: Option<DialogPlacement>,
 }
 
-const CANCEL: &str = "Cancel";
+type CancelCallback = Arc<dyn Fn() + Send + Sync>;
+type PauseCallback = Arc<dyn Fn(bool) + Send + Sync>;
+
+/* Shared between the handle and the dialog, which picks up changes on a timer */
+#[derive(Clone, Default)]
+pub(crate) struct ProgressState {
+    pub(crate) fraction: f64,
+    pub(crate) message: String,
+    pub(crate) detail: String,
+    pub(crate) paused: bool,
+    pub(crate) cancelled: bool,
+    pub(crate) closed: bool,
+    // Incremented by each change so that the dialog redraws only when needed
+    pub(crate) revision: u64,
+    on_cancel: Option<CancelCallback>,
+    on_pause: Option<PauseCallback>,
+}
 
-#[derive(Debug, Default)]
-struct CustomButtons {
-    ok: Option<String>,
-    cancel: Option<String>,
-    yes: Option<String>,
-    no: Option<String>,
+/// Handle of a progress dialog, which can be cloned and updated from any thread
+#[derive(Clone)]
+pub struct ProgressHandle {
+    state: Arc<Mutex<ProgressState>>,
 }
 
-fn parse_result(cancel_label: String, result: MessageDialogResult, buttons: CustomButtons) -> MessageResult {
-    match result {
-        MessageDialogResult::Ok => {
-            let button = if let Some(ok) = buttons.ok {
-                ok
-            } else {
-                "Ok".to_string()
-            };
-            let cancelled = button == cancel_label;
-            MessageResult {
-                button,
-                cancelled,
-            }
-        }
-        MessageDialogResult::Cancel => {
-            let button = if let Some(cancel) = buttons.cancel {
-                cancel
-            } else {
-                "Cancel".to_string()
-            };
-            let cancelled = button == cancel_label;
-            MessageResult {
-                button,
-                cancelled,
-            }
-        }
-        MessageDialogResult::Yes => {
-            let button = if let Some(yes) = buttons.yes {
-                yes
-            } else {
-                "Yes".to_string()
-            };
-            let cancelled = button == cancel_label;
-            MessageResult {
-                button,
-                cancelled,
-            }
-        }
-        MessageDialogResult::No => {
-            let button = if let Some(no) = buttons.no {
-                no
-            } else {
-                "No".to_string()
-            };
-            let cancelled = button == cancel_label;
-            MessageResult {
-                button,
-                cancelled,
-            }
+/* Window handle passed to rfd as the owner of the dialog */
+#[cfg(target_os = "windows")]
+struct ParentWindow(std::num::NonZeroIsize);
+
+#[cfg(target_os = "windows")]
+impl ParentWindow {
+    fn from_placement(placement: &Option<DialogPlacement>) -> Option<Self> {
+        let placement = placement.as_ref()?;
+        let parent = std::num::NonZeroIsize::new(placement.parent?)?;
+
+        if placement.urgent {
+            crate::platform::windows::window::flash(parent.get());
         }
-        MessageDialogResult::Custom(label) => MessageResult {
-            button: label.clone(),
-            cancelled: cancel_label == label,
-        },
+
+        Some(Self(parent))
     }
 }
 
-pub async fn message(options: MessageDialogOptions) -> MessageResult {
-    let dialog = AsyncMessageDialog::new().set_title(options.title.as_ref().unwrap_or(&String::new())).set_level(get_level(&options.kind)).set_description(&options.message);
+#[cfg(target_os = "windows")]
+impl HasWindowHandle for ParentWindow {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let raw = RawWindowHandle::Win32(Win32WindowHandle::new(self.0));
+        Ok(unsafe { WindowHandle::borrow_raw(raw) })
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl HasDisplayHandle for ParentWindow {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        Ok(DisplayHandle::windows())
+    }
+}
+
+const CANCEL: &str = "Cancel";
 
-    let cancel_label = if let Some(cancel_id) = options.cancel_id {
-        options.buttons.get(cancel_id as usize).unwrap_or(&String::new()).to_string()
+/// Shows a message dialog with the buttons in `buttons`, or a single "OK" button if empty
+///
+/// Labels may mark the access key with "&" as in "&Replace". Enter presses `default_id` and Esc presses `cancel_id`.
+/// The state of the checkbox is returned even when the dialog is cancelled. On Linux, must be awaited on the GTK main thread
+pub async fn message(options: MessageDialogOptions) -> MessageResult {
+    let buttons = if options.buttons.is_empty() {
+        vec!["OK".to_string()]
     } else {
-        let mut label = String::new();
-        for button in &options.buttons {
-            if button.to_lowercase() == CANCEL.to_lowercase() {
-                label = button.clone();
-                break;
-            }
-        }
-        label
+        options.buttons.clone()
     };
 
-    let (dialog, buttons) = if options.buttons.len() == 1 {
-        let buttons = CustomButtons {
-            ok: Some(options.buttons.first().unwrap().to_string()),
-            ..Default::default()
-        };
-        (dialog.set_buttons(MessageButtons::OkCustom(options.buttons.first().unwrap().to_string())), buttons)
-    } else if options.buttons.len() == 2 {
-        let buttons = CustomButtons {
-            ok: Some(options.buttons.first().unwrap().to_string()),
-            cancel: Some(options.buttons.get(1).unwrap().to_string()),
-            ..Default::default()
-        };
-        (dialog.set_buttons(MessageButtons::OkCancelCustom(options.buttons.first().unwrap().to_string(), options.buttons.get(1).unwrap().to_string())), buttons)
-    } else if options.buttons.len() == 3 {
-        let buttons = CustomButtons {
-            yes: Some(options.buttons.first().unwrap().to_string()),
-            no: Some(options.buttons.get(1).unwrap().to_string()),
-            cancel: Some(options.buttons.get(2).unwrap().to_string()),
-            ..Default::default()
-        };
-        (
-            dialog.set_buttons(MessageButtons::YesNoCancelCustom(
-                options.buttons.first().unwrap().to_string(),
-                options.buttons.get(1).unwrap().to_string(),
-                options.buttons.get(2).unwrap().to_string(),
-            )),
-            buttons,
-        )
-    } else {
-        (dialog.set_buttons(MessageButtons::Ok), CustomButtons::default())
+    let cancel_id = match options.cancel_id {
+        Some(cancel_id) => Some(cancel_id as usize),
+        None => buttons.iter().position(|button| strip_mnemonic(button).to_lowercase() == CANCEL.to_lowercase()),
     };
+    let default_id = options.default_id.map(|default_id| default_id as usize).filter(|default_id| *default_id < buttons.len());
+
+    #[cfg(target_os = "linux")]
+    let (pressed, checked) = crate::platform::linux::message_box::show(&options, &buttons, default_id).await;
+    #[cfg(target_os = "windows")]
+    let (pressed, checked) = crate::platform::windows::message_box::show(&options, &buttons, default_id).await;
+
+    // Esc and the close button press the cancel button
+    let index = pressed.or(cancel_id);
+    MessageResult {
+        button_index: index.map(|index| index as u32),
+        button: index.and_then(|index| buttons.get(index)).cloned().unwrap_or_default(),
+        cancelled: index.is_none() || index == cancel_id,
+        checked: checked && options.checkbox_label.is_some(),
+    }
+}
 
-    let result = dialog.show().await;
-    parse_result(cancel_label, result, buttons)
+fn strip_mnemonic(label: &str) -> String {
+    label.replace("&&", "\0").replace('&', "").replace('\0', "&")
 }
 
 pub async fn open(options: OpenDialogOptions) -> FileDialogResult {
+    #[cfg(target_os = "linux")]
+    let file_paths = crate::platform::linux::file_dialog::open(&options).await;
+    #[cfg(target_os = "windows")]
+    let file_paths = pick(options).await;
+
+    to_result(file_paths)
+}
+
+#[cfg(target_os = "windows")]
+async fn pick(options: OpenDialogOptions) -> Option<Vec<PathBuf>> {
     let dialog = AsyncFileDialog::new().set_title(options.title.as_ref().unwrap_or(&String::new())).set_directory(options.default_path.as_ref().unwrap_or(&String::new()));
     let dialog = if let Some(filters) = options.filters {
         let mut dialog_result = dialog;
@@ -219,6 +259,11 @@ pub async fn open(options: OpenDialogOptions) -> FileDialogResult {
         dialog
     };
 
+    let dialog = match ParentWindow::from_placement(&options.placement) {
+        Some(parent) => dialog.set_parent(&parent),
+        None => dialog,
+    };
+
     if let Some(properties) = options.properties {
         if properties.contains(&OpenProperty::MultiSelections) {
             pick_multiple(dialog, properties.contains(&OpenProperty::OpenFile)).await
@@ -230,43 +275,26 @@ pub async fn open(options: OpenDialogOptions) -> FileDialogResult {
     }
 }
 
-async fn pick_multiple(dialog: AsyncFileDialog, pic_file: bool) -> FileDialogResult {
+#[cfg(target_os = "windows")]
+async fn pick_multiple(dialog: AsyncFileDialog, pic_file: bool) -> Option<Vec<PathBuf>> {
     let results = if pic_file {
         dialog.pick_files().await
     } else {
         dialog.pick_folders().await
     };
 
-    if let Some(results) = results {
-        let mut file_paths = Vec::new();
-        for result in results {
-            file_paths.push(result.path().to_string_lossy().to_string());
-        }
-
-        return FileDialogResult {
-            canceled: false,
-            file_paths,
-        };
-    }
-
-    FileDialogResult::default()
+    results.map(|results| results.iter().map(|result| result.path().to_path_buf()).collect())
 }
 
-async fn pick_single(dialog: AsyncFileDialog, pic_file: bool) -> FileDialogResult {
+#[cfg(target_os = "windows")]
+async fn pick_single(dialog: AsyncFileDialog, pic_file: bool) -> Option<Vec<PathBuf>> {
     let result = if pic_file {
         dialog.pick_file().await
     } else {
         dialog.pick_folder().await
     };
 
-    if let Some(result) = result {
-        return FileDialogResult {
-            canceled: false,
-            file_paths: vec![result.path().to_string_lossy().to_string()],
-        };
-    }
-
-    FileDialogResult::default()
+    result.map(|result| vec![result.path().to_path_buf()])
 }
 
 pub async fn save(options: SaveDialogOptions) -> FileDialogResult {
@@ -281,14 +309,21 @@ pub async fn save(options: SaveDialogOptions) -> FileDialogResult {
         (None, None)
     };
 
-    let dialog = AsyncFileDialog::new()
-        .set_title(options.title.as_ref().unwrap_or(&String::new()))
-        .set_directory(directory.as_ref().unwrap_or(&Path::new("")))
-        .set_file_name(file_name.as_ref().unwrap_or(&String::new()));
-    let dialog = if let Some(filters) = options.filters {
+    #[cfg(target_os = "linux")]
+    let file_path = crate::platform::linux::file_dialog::save(&options, directory, file_name.as_deref()).await;
+    #[cfg(target_os = "windows")]
+    let file_path = save_file(&options, directory, file_name.as_deref()).await;
+
+    to_result(file_path.map(|file_path| vec![file_path]))
+}
+
+#[cfg(target_os = "windows")]
+async fn save_file(options: &SaveDialogOptions, directory: Option<&Path>, file_name: Option<&str>) -> Option<PathBuf> {
+    let dialog = AsyncFileDialog::new().set_title(options.title.as_ref().unwrap_or(&String::new())).set_directory(directory.unwrap_or(Path::new(""))).set_file_name(file_name.unwrap_or_default());
+    let dialog = if let Some(filters) = &options.filters {
         let mut dialog_result = dialog;
         for filter in filters {
-            dialog_result = dialog_result.add_filter(filter.name, &filter.extensions);
+            dialog_result = dialog_result.add_filter(&filter.name, &filter.extensions);
         }
         dialog_result
     } else {
@@ -296,14 +331,122 @@ pub async fn save(options: SaveDialogOptions) -> FileDialogResult {
         dialog.add_filter("All Files (*.*)", &extensions)
     };
 
-    let result = dialog.save_file().await;
+    let dialog = match ParentWindow::from_placement(&options.placement) {
+        Some(parent) => dialog.set_parent(&parent),
+        None => dialog,
+    };
 
-    if let Some(result) = result {
-        return FileDialogResult {
+    dialog.save_file().await.map(|result| result.path().to_path_buf())
+}
+
+fn to_result(file_paths: Option<Vec<PathBuf>>) -> FileDialogResult {
+    match file_paths {
+        Some(file_paths) => FileDialogResult {
             canceled: false,
-            file_paths: vec![result.path().to_string_lossy().to_string()],
+            file_paths: file_paths.iter().map(|file_path| file_path.to_string_lossy().to_string()).collect(),
+        },
+        None => FileDialogResult::default(),
+    }
+}
+
+/// Shows a modeless progress dialog with a Cancel button, and a Pause button if `pausable`, for any long task
+///
+/// The dialog stays open until the handle is closed or the user cancels. On Linux, must be called on the GTK main thread
+pub fn progress(options: ProgressDialogOptions) -> ProgressHandle {
+    let handle = ProgressHandle {
+        state: Arc::new(Mutex::new(ProgressState {
+            message: options.message.clone(),
+            detail: options.detail.clone().unwrap_or_default(),
+            ..Default::default()
+        })),
+    };
+
+    #[cfg(target_os = "linux")]
+    crate::platform::linux::progress_dialog::show(&options, handle.clone());
+    #[cfg(target_os = "windows")]
+    crate::platform::windows::progress_dialog::show(&options, handle.clone());
+
+    handle
+}
+
+impl ProgressHandle {
+    /// Sets the progress between 0.0 and 1.0
+    pub fn set_fraction(&self, fraction: f64) {
+        self.update(|state| state.fraction = fraction.clamp(0.0, 1.0));
+    }
+
+    pub fn set_message(&self, message: &str) {
+        self.update(|state| state.message = message.to_string());
+    }
+
+    pub fn set_detail(&self, detail: &str) {
+        self.update(|state| state.detail = detail.to_string());
+    }
+
+    /// Called on the UI thread when the user cancels, but not when the handle is closed
+    pub fn on_cancel<F: Fn() + Send + Sync + 'static>(&self, callback: F) {
+        self.state.lock().unwrap().on_cancel = Some(Arc::new(callback));
+    }
+
+    /// Called on the UI thread with true when the user pauses and false when the user resumes
+    pub fn on_pause<F: Fn(bool) + Send + Sync + 'static>(&self, callback: F) {
+        self.state.lock().unwrap().on_pause = Some(Arc::new(callback));
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().cancelled
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Closes the dialog once the task is done
+    pub fn close(&self) {
+        self.update(|state| state.closed = true);
+    }
+
+    pub(crate) fn state(&self) -> ProgressState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Marks the task cancelled by the user unless the dialog has already been closed
+    pub(crate) fn cancel(&self) {
+        let callback = {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+            state.cancelled = true;
+            state.closed = true;
+            state.on_cancel.clone()
         };
+
+        // Called without the lock so that the callback can use the handle
+        if let Some(callback) = callback {
+            callback();
+        }
     }
 
-    FileDialogResult::default()
+    /// Pauses or resumes as the user asked, returning whether the task is now paused
+    pub(crate) fn toggle_pause(&self) -> bool {
+        let (paused, callback) = {
+            let mut state = self.state.lock().unwrap();
+            state.paused = !state.paused;
+            state.revision += 1;
+            (state.paused, state.on_pause.clone())
+        };
+
+        if let Some(callback) = callback {
+            callback(paused);
+        }
+
+        paused
+    }
+
+    fn update<F: FnOnce(&mut ProgressState)>(&self, f: F) {
+        let mut state = self.state.lock().unwrap();
+        f(&mut state);
+        state.revision += 1;
+    }
 }
@@ -0,0 +1,366 @@
+use crate::fs::{OperationStatus, Response};
+use std::{
+    ffi::OsString,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+// First argument of the helper process, followed by the channel, the operation, the destination and the items
+const HELPER_ARG: &str = "--zouni-elevated-helper";
+// Channel argument for the helper to report to stdout instead of a named pipe
+const STDOUT_CHANNEL: &str = "-";
+#[cfg(target_os = "linux")]
+const PKEXEC_DISMISSED: i32 = 126;
+#[cfg(target_os = "linux")]
+const PKEXEC_NOT_AUTHORIZED: i32 = 127;
+
+#[derive(Clone, Copy)]
+pub(crate) enum ElevatedOperation {
+    Copy,
+    Delete,
+}
+
+impl ElevatedOperation {
+    fn name(&self) -> &'static str {
+        match self {
+            ElevatedOperation::Copy => "copy",
+            ElevatedOperation::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ElevatedOutcome {
+    Finished,
+    // The user declined the prompt or the callback returned Cancel
+    Cancelled,
+}
+
+/* Waits for the helper to exit and tells why it ended without reporting a result */
+type Finish = Box<dyn FnOnce() -> Result<ElevatedOutcome, String>>;
+
+/// Runs the operation in an elevated process of the current executable, relaying its progress to the callback
+///
+/// Returns Cancelled if the user declines the prompt. Returning Cancel closes the channel, which stops the helper at its next report
+pub(crate) fn run<P: AsRef<Path>>(operation: ElevatedOperation, items: &[P], to: Option<&Path>, mut callback: impl FnMut(OperationStatus) -> Response) -> Result<ElevatedOutcome, String> {
+    let program = std::env::current_exe().map_err(|e| e.to_string())?;
+    let mut args: Vec<OsString> = vec![operation.name().into(), to.map_or(OsString::new(), |to| to.as_os_str().to_os_string())];
+    args.extend(items.iter().map(|item| item.as_ref().as_os_str().to_os_string()));
+
+    let Some((reader, finish)) = spawn(&program, args)? else {
+        return Ok(ElevatedOutcome::Cancelled);
+    };
+    // The channel is closed before waiting for the helper, so that a cancelled helper stops
+    let outcome = relay(reader, &mut callback);
+    let exit = finish();
+
+    outcome.unwrap_or(exit)
+}
+
+/* None if the channel closed before the helper reported the result */
+fn relay<R: std::io::Read>(reader: R, callback: &mut impl FnMut(OperationStatus) -> Response) -> Option<Result<ElevatedOutcome, String>> {
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let (kind, value) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+
+        let status = match kind {
+            "start" => OperationStatus::Start(value.to_string()),
+            "progress" => {
+                let (processed, total) = value.split_once(' ').unwrap_or_default();
+                OperationStatus::Progress(processed.parse().unwrap_or_default(), total.parse().unwrap_or_default())
+            }
+            "end" => OperationStatus::End,
+            "error" => {
+                callback(OperationStatus::Error(value.to_string()));
+                return Some(Err(value.to_string()));
+            }
+            "finished" => {
+                callback(OperationStatus::Finished);
+                return Some(Ok(ElevatedOutcome::Finished));
+            }
+            _ => continue,
+        };
+
+        if callback(status) == Response::Cancel {
+            return Some(Ok(ElevatedOutcome::Cancelled));
+        }
+    }
+
+    None
+}
+
+/* Starts the helper with UAC and reads its reports from a named pipe, since a process started with runas cannot inherit pipes. None if the user declines the prompt */
+#[cfg(target_os = "windows")]
+fn spawn(program: &Path, args: Vec<OsString>) -> Result<Option<(std::fs::File, Finish)>, String> {
+    use std::{
+        ffi::OsStr,
+        os::windows::{ffi::OsStrExt, io::FromRawHandle},
+    };
+    use windows::{
+        core::{w, PCWSTR},
+        Win32::{
+            Foundation::{CloseHandle, LocalFree, ERROR_CANCELLED, ERROR_PIPE_CONNECTED, HANDLE, HLOCAL},
+            Security::SECURITY_ATTRIBUTES,
+            Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_INBOUND},
+            System::{
+                Com::CoCreateGuid,
+                Pipes::{ConnectNamedPipe, CreateNamedPipeW, GetNamedPipeClientProcessId, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT},
+                Threading::{GetProcessId, WaitForSingleObject, INFINITE},
+            },
+            UI::Shell::{ShellExecuteExW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW},
+        },
+    };
+
+    const BUFFER_SIZE: u32 = 4096;
+    let encode_wide = |value: &OsStr| value.encode_wide().chain(std::iter::once(0)).collect::<Vec<u16>>();
+
+    // A name that cannot be guessed, the first instance flag and the DACL keep other processes from creating or connecting to the pipe before the helper
+    let pipe_name = format!("\\\\.\\pipe\\zouni-elevated-{:?}", unsafe { CoCreateGuid() }.map_err(|e| e.message())?);
+    let pipe_name_wide = encode_wide(pipe_name.as_ref());
+    let descriptor = pipe_security_descriptor()?;
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0,
+        bInheritHandle: false.into(),
+    };
+    let pipe = unsafe {
+        CreateNamedPipeW(
+            PCWSTR::from_raw(pipe_name_wide.as_ptr()),
+            PIPE_ACCESS_INBOUND | FILE_FLAG_FIRST_PIPE_INSTANCE,
+            PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            0,
+            BUFFER_SIZE,
+            0,
+            Some(&attributes),
+        )
+    };
+    let _ = unsafe { LocalFree(Some(HLOCAL(descriptor.0))) };
+    if pipe.is_invalid() {
+        return Err(windows::core::Error::from_win32().message());
+    }
+    // Closes the pipe when the File is dropped
+    let reader = unsafe { std::fs::File::from_raw_handle(pipe.0) };
+
+    let mut helper_args = vec![OsString::from(HELPER_ARG), OsString::from(&pipe_name)];
+    helper_args.extend(args);
+    let parameters = encode_wide(helper_args.iter().map(|arg| quote(&arg.to_string_lossy())).collect::<Vec<_>>().join(" ").as_ref());
+    let file = encode_wide(program.as_os_str());
+
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: size_of::<SHELLEXECUTEINFOW>() as u32,
+        lpVerb: w!("runas"),
+        lpFile: PCWSTR::from_raw(file.as_ptr()),
+        lpParameters: PCWSTR::from_raw(parameters.as_ptr()),
+        fMask: SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC,
+        ..Default::default()
+    };
+    if let Err(e) = unsafe { ShellExecuteExW(&mut info) } {
+        // The user declined the prompt
+        if e.code() == ERROR_CANCELLED.to_hresult() {
+            return Ok(None);
+        }
+        return Err(e.message());
+    }
+    let helper_id = unsafe { GetProcessId(info.hProcess) };
+    let process = info.hProcess.0 as isize;
+
+    // The waiter owns the process handle. Connecting to the pipe after the helper exits wakes ConnectNamedPipe if the helper never connected
+    let waiter = std::thread::spawn(move || {
+        unsafe { WaitForSingleObject(HANDLE(process as _), INFINITE) };
+        let _ = unsafe { CloseHandle(HANDLE(process as _)) };
+        let _ = std::fs::OpenOptions::new().write(true).open(&pipe_name);
+    });
+    let finish: Finish = Box::new(move || {
+        let _ = waiter.join();
+        Err("The elevated helper exited without finishing".to_string())
+    });
+
+    if let Err(e) = unsafe { ConnectNamedPipe(HANDLE(pipe.0), None) } {
+        if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+            // Closing the pipe first makes a helper that has not connected yet exit, so that the waiter ends
+            drop(reader);
+            let _ = finish();
+            return Err(e.message());
+        }
+    }
+
+    let mut client_id = 0;
+    if unsafe { GetNamedPipeClientProcessId(HANDLE(pipe.0), &mut client_id) }.is_err() || client_id != helper_id {
+        drop(reader);
+        return finish().map(|_| None);
+    }
+
+    Ok(Some((reader, finish)))
+}
+
+/* Allows the current user and administrators, since the helper runs as another administrator when a standard user enters their credentials. Freed with LocalFree */
+#[cfg(target_os = "windows")]
+fn pipe_security_descriptor() -> Result<windows::Win32::Security::PSECURITY_DESCRIPTOR, String> {
+    use windows::{
+        core::{PCWSTR, PWSTR},
+        Win32::{
+            Foundation::{CloseHandle, LocalFree, HANDLE, HLOCAL},
+            Security::{
+                Authorization::{ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+                GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, TOKEN_QUERY, TOKEN_USER,
+            },
+            System::Threading::{GetCurrentProcess, OpenProcessToken},
+        },
+    };
+
+    let mut token = HANDLE::default();
+    unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) }.map_err(|e| e.message())?;
+    let mut size = 0;
+    let _ = unsafe { GetTokenInformation(token, TokenUser, None, 0, &mut size) };
+    // u64 keeps the buffer aligned for TOKEN_USER
+    let mut buffer = vec![0u64; (size as usize).div_ceil(size_of::<u64>())];
+    let result = unsafe { GetTokenInformation(token, TokenUser, Some(buffer.as_mut_ptr() as _), size, &mut size) };
+    let _ = unsafe { CloseHandle(token) };
+    result.map_err(|e| e.message())?;
+
+    let user = unsafe { &*(buffer.as_ptr() as *const TOKEN_USER) };
+    let mut sid = PWSTR::null();
+    unsafe { ConvertSidToStringSidW(user.User.Sid, &mut sid) }.map_err(|e| e.message())?;
+    let sddl = format!("D:P(A;;GA;;;{})(A;;GA;;;BA)", String::from_utf16_lossy(unsafe { sid.as_wide() }));
+    let _ = unsafe { LocalFree(Some(HLOCAL(sid.0 as _))) };
+
+    let sddl: Vec<u16> = sddl.encode_utf16().chain(std::iter::once(0)).collect();
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    unsafe { ConvertStringSecurityDescriptorToSecurityDescriptorW(PCWSTR::from_raw(sddl.as_ptr()), SDDL_REVISION_1, &mut descriptor, None) }.map_err(|e| e.message())?;
+    Ok(descriptor)
+}
+
+/* Quotes the argument for CommandLineToArgvW, doubling backslashes that would otherwise escape the closing quote */
+#[cfg(target_os = "windows")]
+fn quote(arg: &str) -> String {
+    let trailing_backslashes = arg.len() - arg.trim_end_matches('\\').len();
+    format!("\"{}{}\"", arg, "\\".repeat(trailing_backslashes))
+}
+
+/* Starts the helper with pkexec, which asks polkit for authorization and passes stdout through. A declined prompt is known when pkexec exits */
+#[cfg(target_os = "linux")]
+fn spawn(program: &Path, args: Vec<OsString>) -> Result<Option<(std::process::ChildStdout, Finish)>, String> {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("pkexec").arg(program).arg(HELPER_ARG).arg(STDOUT_CHANNEL).args(args).stdin(Stdio::null()).stdout(Stdio::piped()).spawn().map_err(|e| e.to_string())?;
+    let reader = child.stdout.take().ok_or("Failed to read the elevated helper")?;
+
+    let finish = move || match child.wait().map(|status| status.code()) {
+        Ok(Some(PKEXEC_DISMISSED)) => Ok(ElevatedOutcome::Cancelled),
+        Ok(Some(PKEXEC_NOT_AUTHORIZED)) => Err("Not authorized".to_string()),
+        Ok(_) => Err("The elevated helper exited without finishing".to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+
+    Ok(Some((reader, Box::new(finish))))
+}
+
+/// Runs the operation named by the arguments, reports to the channel and exits if the process was started by `run`
+pub(crate) fn run_helper() {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    if args.get(1).is_none_or(|arg| arg != HELPER_ARG) {
+        return;
+    }
+
+    let (Some(channel), Some(operation), Some(to)) = (args.get(2), args.get(3), args.get(4)) else {
+        std::process::exit(2);
+    };
+    let channel: Box<dyn Write> = if channel == STDOUT_CHANNEL {
+        Box::new(std::io::stdout())
+    } else {
+        match std::fs::OpenOptions::new().write(true).open(channel) {
+            Ok(pipe) => Box::new(pipe),
+            Err(_) => std::process::exit(2),
+        }
+    };
+    let items: Vec<PathBuf> = args[5..].iter().map(PathBuf::from).collect();
+
+    let mut reporter = Reporter {
+        channel,
+        processed: 0,
+        total: 0,
+    };
+    let result = match operation.to_str() {
+        Some("copy") => copy_items(&items, Path::new(to), &mut reporter),
+        Some("delete") => delete_items(&items, &mut reporter),
+        _ => Err("Unknown operation".to_string()),
+    };
+
+    let code = match result {
+        Ok(_) => reporter.send("finished").map_or(1, |_| 0),
+        // The parent closed the channel to cancel
+        Err(e) if e.is_empty() => 1,
+        Err(e) => {
+            let _ = reporter.send(&format!("error {}", e.replace(['\r', '\n'], " ")));
+            1
+        }
+    };
+    std::process::exit(code);
+}
+
+/* Writes a line per report. An empty error means the parent has gone and the helper should stop */
+struct Reporter {
+    channel: Box<dyn Write>,
+    processed: u64,
+    total: u64,
+}
+
+impl Reporter {
+    fn send(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.channel, "{}", line).and_then(|_| self.channel.flush()).map_err(|_| String::new())
+    }
+}
+
+fn copy_items(items: &[PathBuf], to: &Path, reporter: &mut Reporter) -> Result<(), String> {
+    reporter.total = items.iter().map(|item| measure(item)).sum();
+
+    for item in items {
+        reporter.send(&format!("start {}", item.file_name().unwrap_or_default().to_string_lossy()))?;
+        copy_item(item, to, reporter)?;
+        reporter.send("end")?;
+    }
+
+    Ok(())
+}
+
+fn copy_item(from: &Path, to: &Path, reporter: &mut Reporter) -> Result<(), String> {
+    let dest = to.join(from.file_name().ok_or(format!("Invalid path:{}", from.to_string_lossy()))?);
+
+    if from.symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+        std::fs::create_dir_all(&dest).map_err(|e| format!("{}:{}", e, dest.to_string_lossy()))?;
+        for entry in std::fs::read_dir(from).map_err(|e| format!("{}:{}", e, from.to_string_lossy()))? {
+            let entry = entry.map_err(|e| e.to_string())?;
+            copy_item(&entry.path(), &dest, reporter)?;
+        }
+        return Ok(());
+    }
+
+    reporter.processed += std::fs::copy(from, &dest).map_err(|e| format!("{}:{}", e, from.to_string_lossy()))?;
+    reporter.send(&format!("progress {} {}", reporter.processed, reporter.total))
+}
+
+fn measure(path: &Path) -> u64 {
+    match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => std::fs::read_dir(path).map(|entries| entries.flatten().map(|entry| measure(&entry.path())).sum()).unwrap_or_default(),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+fn delete_items(items: &[PathBuf], reporter: &mut Reporter) -> Result<(), String> {
+    for item in items {
+        reporter.send(&format!("start {}", item.file_name().unwrap_or_default().to_string_lossy()))?;
+        let result = if item.symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+            std::fs::remove_dir_all(item)
+        } else {
+            std::fs::remove_file(item)
+        };
+        result.map_err(|e| format!("{}:{}", e, item.to_string_lossy()))?;
+        reporter.send("end")?;
+    }
+
+    Ok(())
+}
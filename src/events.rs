@@ -0,0 +1,66 @@
+use crate::{
+    fs::{OperationStatus, Response},
+    OperationEvent,
+};
+use smol::channel::{Receiver, Sender};
+
+/// Turns the statuses of an operation into events, answering each conflict with the response sent through the handle
+pub(crate) fn forward(events: Sender<OperationEvent>, responses: Receiver<Response>) -> impl AsyncFnMut(OperationStatus) -> Response + 'static {
+    let mut started = false;
+    let mut name = String::new();
+
+    async move |status: OperationStatus| {
+        if !started {
+            started = true;
+            let (total_size, total_count) = match &status {
+                OperationStatus::Ready(total) => (Some(total.total_size), Some(total.total_count)),
+                _ => (None, None),
+            };
+            let _ = events
+                .send(OperationEvent::Started {
+                    total_size,
+                    total_count,
+                })
+                .await;
+        }
+
+        let event = match status {
+            OperationStatus::Ready(_) | OperationStatus::Retry(_) => None,
+            OperationStatus::Start(item) => {
+                name = item;
+                None
+            }
+            OperationStatus::Progress(processed, total) => Some(OperationEvent::ItemProgress {
+                name: name.clone(),
+                processed,
+                total,
+            }),
+            OperationStatus::End => Some(OperationEvent::ItemDone {
+                name: name.clone(),
+            }),
+            OperationStatus::InUseBy(processes) => Some(OperationEvent::InUseBy {
+                name: name.clone(),
+                processes,
+            }),
+            OperationStatus::Error(e) => Some(OperationEvent::Error(e)),
+            OperationStatus::Confirm(source) => {
+                let _ = events
+                    .send(OperationEvent::Conflict {
+                        source,
+                    })
+                    .await;
+                return responses.recv().await.unwrap_or(Response::Cancel);
+            }
+            OperationStatus::Skipped(source) => Some(OperationEvent::ItemSkipped {
+                source,
+            }),
+            OperationStatus::Finished => Some(OperationEvent::Finished),
+        };
+
+        if let Some(event) = event {
+            let _ = events.send(event).await;
+        }
+
+        Response::Proceed
+    }
+}
@@ -0,0 +1,27 @@
+use crate::NameStyle;
+use std::path::Path;
+
+#[cfg(target_os = "windows")]
+const LINE_SEPARATOR: &str = "\r\n";
+#[cfg(target_os = "linux")]
+const LINE_SEPARATOR: &str = "\n";
+
+/// Formats each path in the style, one per line
+pub(crate) fn format<P: AsRef<Path>>(paths: &[P], style: &NameStyle) -> String {
+    paths.iter().map(|path| format_one(path.as_ref(), style)).collect::<Vec<_>>().join(LINE_SEPARATOR)
+}
+
+fn format_one(path: &Path, style: &NameStyle) -> String {
+    let name = || path.file_name().map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy()).to_string();
+    // Relative paths cannot be URIs, so they are kept as they are
+    let uri = || url::Url::from_file_path(path).map_or_else(|_| path.to_string_lossy().to_string(), |uri| uri.to_string());
+
+    match style {
+        NameStyle::Name => name(),
+        NameStyle::FullPath => path.to_string_lossy().to_string(),
+        NameStyle::Quoted => format!("\"{}\"", path.to_string_lossy()),
+        NameStyle::Uri => uri(),
+        // Brackets in the name would end the link text early
+        NameStyle::MarkdownLink => format!("[{}]({})", name().replace('[', "\\[").replace(']', "\\]"), uri()),
+    }
+}
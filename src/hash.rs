@@ -0,0 +1,134 @@
+use crate::{
+    fs::{OperationStatus, Response},
+    FileHash, HashAlgorithm,
+};
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use sha2::Sha256;
+use std::{fs::File, io::Read, path::Path};
+use xxhash_rust::xxh3::Xxh3;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Xxh3(Box<Xxh3>),
+}
+
+impl Hasher {
+    fn new(algorithm: &HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => Self::Md5(Md5::new()),
+            HashAlgorithm::Sha1 => Self::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Xxh3 => Self::Xxh3(Box::new(Xxh3::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(hasher) => hasher.update(data),
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Self::Md5(hasher) => to_hex(&hasher.finalize()),
+            Self::Sha1(hasher) => to_hex(&hasher.finalize()),
+            Self::Sha256(hasher) => to_hex(&hasher.finalize()),
+            Self::Xxh3(hasher) => format!("{:016x}", hasher.digest()),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Reads the source in chunks and returns the hex digest, or None if the progress callback returns false
+fn hash_reader<R: Read>(mut reader: R, total: i64, algorithm: &HashAlgorithm, mut progress: impl FnMut(i64, i64) -> bool) -> Result<Option<String>, String> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut processed = 0;
+
+    loop {
+        let len = reader.read(&mut buffer).map_err(|e| e.to_string())?;
+        if len == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..len]);
+        processed += len as i64;
+
+        if !progress(processed, total) {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(hasher.finalize()))
+}
+
+/// Computes the hex digest of the opened file, reporting progress as `OperationStatus::Progress`
+pub(crate) fn hash_file(file: File, algorithm: &HashAlgorithm, callback: impl FnMut(OperationStatus) -> Response) -> Result<String, String> {
+    let mut callback = crate::throttle::throttled(callback);
+    let total = file.metadata().map_err(|e| e.to_string())?.len() as i64;
+
+    match hash_reader(file, total, algorithm, |processed, total| callback(OperationStatus::Progress(processed, total)) != Response::Cancel)? {
+        Some(hash) => Ok(hash),
+        None => Err("Operation cancelled".to_string()),
+    }
+}
+
+/// Computes the hex digests of files opened by `open`, reporting each file as `OperationStatus::Start` and `OperationStatus::End`
+pub(crate) fn hash_files<P: AsRef<Path>>(
+    file_paths: &[P],
+    algorithm: &HashAlgorithm,
+    mut callback: impl FnMut(OperationStatus) -> Response,
+    open: impl Fn(&Path) -> std::io::Result<File>,
+) -> Result<Vec<FileHash>, String> {
+    let mut hashes = Vec::new();
+
+    for file_path in file_paths {
+        let name = file_path.as_ref().file_name().unwrap_or_default().to_string_lossy().to_string();
+        match callback(OperationStatus::Start(name)) {
+            Response::Cancel => return Err("Operation cancelled".to_string()),
+            Response::Skip => continue,
+            _ => {}
+        }
+
+        let mut cancelled = false;
+        let result = open(file_path.as_ref()).map_err(|e| e.to_string()).and_then(|file| {
+            hash_file(file, algorithm, |status| {
+                let response = callback(status);
+                cancelled = response == Response::Cancel;
+                response
+            })
+        });
+
+        match result {
+            Ok(hash) => {
+                hashes.push(FileHash {
+                    full_path: file_path.as_ref().to_string_lossy().to_string(),
+                    hash,
+                });
+                callback(OperationStatus::End);
+            }
+            Err(e) => {
+                if cancelled {
+                    return Err(e);
+                }
+                if callback(OperationStatus::Error(e)) == Response::Cancel {
+                    return Err("Operation cancelled".to_string());
+                }
+            }
+        }
+    }
+
+    callback(OperationStatus::Finished);
+    Ok(hashes)
+}
@@ -0,0 +1,137 @@
+use crate::fs::FileOperation;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MAX_ENTRIES: usize = 256;
+
+static ENTRIES: Mutex<Vec<HistoryEntry>> = Mutex::new(Vec::new());
+static LISTENER: RwLock<Option<Arc<dyn Fn(&HistoryEntry) + Send + Sync>>> = RwLock::new(None);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum HistoryAction {
+    Move,
+    Copy,
+    Delete,
+    Trash,
+}
+
+impl From<FileOperation> for HistoryAction {
+    fn from(operation: FileOperation) -> Self {
+        match operation {
+            FileOperation::Move => HistoryAction::Move,
+            FileOperation::Copy => HistoryAction::Copy,
+            FileOperation::Delete => HistoryAction::Delete,
+            FileOperation::Trash => HistoryAction::Trash,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub timestamp_ms: u64,
+    pub action: HistoryAction,
+    pub sources: Vec<String>,
+    // Empty for Delete and Trash
+    pub destination: String,
+    // Description such as "Moved 12 items to D:\Photos" in the system language, English unless it is Japanese
+    pub description: String,
+}
+
+/// Get notification when a file operation is completed
+pub fn listen<F: Fn(&HistoryEntry) + Send + Sync + 'static>(callback: F) {
+    *LISTENER.write().unwrap() = Some(Arc::new(callback));
+}
+
+pub fn unlisten() {
+    *LISTENER.write().unwrap() = None;
+}
+
+/// Returns completed operations, oldest first. Only the latest 256 operations are kept
+pub fn entries() -> Vec<HistoryEntry> {
+    ENTRIES.lock().unwrap().clone()
+}
+
+/// Removes all entries
+pub fn clear() {
+    ENTRIES.lock().unwrap().clear();
+}
+
+/// Describes the operation in the language of `locale` such as "ja-JP"
+///
+/// Only English and Japanese are available. Every other locale falls back to English
+pub fn describe(action: HistoryAction, sources: &[String], destination: &str, locale: &str) -> String {
+    let is_japanese = locale.to_lowercase().starts_with("ja");
+    let items = match sources {
+        [source] => {
+            let name = Path::new(source).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| source.clone());
+            if is_japanese {
+                format!("「{}」", name)
+            } else {
+                format!("\"{}\"", name)
+            }
+        }
+        _ => {
+            if is_japanese {
+                format!("{} 個の項目", sources.len())
+            } else {
+                format!("{} items", sources.len())
+            }
+        }
+    };
+
+    if is_japanese {
+        match action {
+            HistoryAction::Move => format!("{}を {} に移動しました", items, destination),
+            HistoryAction::Copy => format!("{}を {} にコピーしました", items, destination),
+            HistoryAction::Delete => format!("{}を削除しました", items),
+            HistoryAction::Trash => format!("{}をごみ箱に移動しました", items),
+        }
+    } else {
+        match action {
+            HistoryAction::Move => format!("Moved {} to {}", items, destination),
+            HistoryAction::Copy => format!("Copied {} to {}", items, destination),
+            HistoryAction::Delete => format!("Deleted {}", items),
+            HistoryAction::Trash => {
+                if cfg!(target_os = "windows") {
+                    format!("Moved {} to the Recycle Bin", items)
+                } else {
+                    format!("Moved {} to the Trash", items)
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn record<P1: AsRef<Path>, P2: AsRef<Path>>(action: HistoryAction, sources: &[P1], destination: Option<P2>) {
+    if sources.is_empty() {
+        return;
+    }
+
+    let sources: Vec<String> = sources.iter().map(|source| source.as_ref().to_string_lossy().to_string()).collect();
+    let destination = destination.map(|destination| destination.as_ref().to_string_lossy().to_string()).unwrap_or_default();
+
+    let entry = HistoryEntry {
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_millis() as u64).unwrap_or_default(),
+        action,
+        description: describe(action, &sources, &destination, &crate::shell::get_locale()),
+        sources,
+        destination,
+    };
+
+    let listener = LISTENER.read().unwrap().clone();
+    if let Some(listener) = listener {
+        listener(&entry);
+    }
+
+    crate::hooks::after(action, &entry.sources, &entry.destination);
+
+    let mut entries = ENTRIES.lock().unwrap();
+    if entries.len() >= MAX_ENTRIES {
+        entries.remove(0);
+    }
+    entries.push(entry);
+}
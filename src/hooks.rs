@@ -0,0 +1,188 @@
+use crate::history::HistoryAction;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+static HOOKS: RwLock<Vec<(u32, HookEvent, Arc<Hook>)>> = RwLock::new(Vec::new());
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+// Replaced by each source as a separate argument
+const SOURCES_PLACEHOLDER: &str = "{sources}";
+const DESTINATION_PLACEHOLDER: &str = "{destination}";
+// Replaced by "move", "copy", "delete" or "trash"
+const ACTION_PLACEHOLDER: &str = "{action}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum HookEvent {
+    Before(HistoryAction),
+    After(HistoryAction),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum FailurePolicy {
+    // The operation proceeds as if the hook succeeded
+    #[default]
+    Ignore,
+    // The operation fails with the error of the hook. After hooks cannot undo the operation, so this is the same as Ignore for them
+    Abort,
+}
+
+/// Operation passed to a callback hook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookContext {
+    pub action: HistoryAction,
+    pub sources: Vec<String>,
+    // Empty for Delete and Trash
+    pub destination: String,
+}
+
+#[derive(Clone)]
+pub enum HookAction {
+    // Arguments can contain {sources}, {destination} and {action}
+    Command {
+        program: String,
+        args: Vec<String>,
+    },
+    Callback(Arc<dyn Fn(&HookContext) -> Result<(), String> + Send + Sync>),
+}
+
+/// Command or callback run before or after file operations
+#[derive(Clone)]
+pub struct Hook {
+    pub action: HookAction,
+    // Time after which the command is killed and the hook fails
+    pub timeout: Duration,
+    pub on_failure: FailurePolicy,
+}
+
+impl Hook {
+    /// Runs the program through the process module, failing with stderr if it exits with an error
+    pub fn command<S: Into<String>>(program: S, args: &[&str]) -> Self {
+        Self {
+            action: HookAction::Command {
+                program: program.into(),
+                args: args.iter().map(|arg| arg.to_string()).collect(),
+            },
+            timeout: DEFAULT_TIMEOUT,
+            on_failure: FailurePolicy::Ignore,
+        }
+    }
+
+    pub fn callback<F: Fn(&HookContext) -> Result<(), String> + Send + Sync + 'static>(callback: F) -> Self {
+        Self {
+            action: HookAction::Callback(Arc::new(callback)),
+            timeout: DEFAULT_TIMEOUT,
+            on_failure: FailurePolicy::Ignore,
+        }
+    }
+}
+
+/// Runs the hook on every move, copy, delete or trash of fs functions, returning the ID to unregister it
+///
+/// Before hooks run in registration order on the calling thread. After hooks run on a background thread once the operation has completed
+pub fn register(event: HookEvent, hook: Hook) -> u32 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    HOOKS.write().unwrap().push((id, event, Arc::new(hook)));
+    id
+}
+
+pub fn unregister(id: u32) {
+    HOOKS.write().unwrap().retain(|(hook_id, _, _)| *hook_id != id);
+}
+
+/// Removes all hooks
+pub fn clear() {
+    HOOKS.write().unwrap().clear();
+}
+
+/// Runs the Before hooks of the action, failing on the first hook that fails with the Abort policy
+pub(crate) fn before<P1: AsRef<Path>, P2: AsRef<Path>>(action: HistoryAction, sources: &[P1], destination: Option<P2>) -> Result<(), String> {
+    let hooks = hooks(HookEvent::Before(action));
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let context = HookContext {
+        action,
+        sources: sources.iter().map(|source| source.as_ref().to_string_lossy().to_string()).collect(),
+        destination: destination.map(|destination| destination.as_ref().to_string_lossy().to_string()).unwrap_or_default(),
+    };
+
+    for hook in hooks {
+        if let Err(e) = run(&hook, &context) {
+            if hook.on_failure == FailurePolicy::Abort {
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the After hooks of the recorded operation without blocking it
+pub(crate) fn after(action: HistoryAction, sources: &[String], destination: &str) {
+    let hooks = hooks(HookEvent::After(action));
+    if hooks.is_empty() {
+        return;
+    }
+
+    let context = HookContext {
+        action,
+        sources: sources.to_vec(),
+        destination: destination.to_string(),
+    };
+
+    std::thread::spawn(move || {
+        for hook in hooks {
+            let _ = run(&hook, &context);
+        }
+    });
+}
+
+fn hooks(event: HookEvent) -> Vec<Arc<Hook>> {
+    HOOKS.read().unwrap().iter().filter(|(_, hook_event, _)| *hook_event == event).map(|(_, _, hook)| hook.clone()).collect()
+}
+
+fn run(hook: &Hook, context: &HookContext) -> Result<(), String> {
+    match &hook.action {
+        HookAction::Command {
+            program,
+            args,
+        } => {
+            let args = expand_args(args, context);
+            crate::process::run_with_timeout(Path::new(program), &args, hook.timeout).map(|_| ())
+        }
+        HookAction::Callback(callback) => {
+            // The callback keeps running after the timeout, but the operation no longer waits for it
+            let (tx, rx) = std::sync::mpsc::channel();
+            let callback = callback.clone();
+            let thread_context = context.clone();
+            std::thread::spawn(move || {
+                let _ = tx.send(callback(&thread_context));
+            });
+            rx.recv_timeout(hook.timeout).unwrap_or_else(|_| Err(format!("Timed out after {}ms", hook.timeout.as_millis())))
+        }
+    }
+}
+
+fn expand_args(args: &[String], context: &HookContext) -> Vec<String> {
+    let action = format!("{:?}", context.action).to_lowercase();
+    let mut expanded = Vec::new();
+
+    for arg in args {
+        if arg == SOURCES_PLACEHOLDER {
+            expanded.extend(context.sources.iter().cloned());
+        } else {
+            expanded.push(arg.replace(DESTINATION_PLACEHOLDER, &context.destination).replace(ACTION_PLACEHOLDER, &action));
+        }
+    }
+
+    expanded
+}
@@ -1,7 +1,46 @@
+pub mod actions;
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(feature = "audit")]
+pub mod audit;
+mod cleanup;
+#[cfg(feature = "media")]
+mod color;
+mod compare;
 pub mod dialog;
+mod elevation;
+mod events;
+mod file_names;
+mod hash;
+pub mod history;
+pub mod hooks;
+mod lifecycle;
+#[cfg(feature = "listing")]
+mod listing;
+mod notice;
+pub mod path;
 mod platform;
+pub mod policy;
+#[cfg(feature = "media")]
+mod prefetch;
+mod preflight;
 pub mod process;
-use std::path::PathBuf;
+#[cfg(feature = "raw")]
+mod raw;
+mod resumable;
+#[cfg(feature = "media")]
+mod sandbox;
+pub mod search;
+#[cfg(feature = "drag_drop")]
+pub mod spring_load;
+pub mod storage;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod throttle;
+mod trace;
+mod transaction;
+pub mod worker;
+use std::path::{Path, PathBuf};
 
 #[cfg(target_os = "linux")]
 pub use platform::linux::*;
@@ -9,21 +48,181 @@ pub use platform::linux::*;
 pub use platform::windows::*;
 use serde::{Deserialize, Serialize};
 
+/// Cancels in-flight file operations, waits up to `timeout` for them to finish, then removes partially written files, listeners and drop targets
+///
+/// Returns false if operations are still running after `timeout`. File operations started afterwards fail
+pub fn shutdown(timeout: std::time::Duration) -> bool {
+    lifecycle::shutdown(timeout)
+}
+
+/// Releases thumb buttons, drop targets and the subclass kept for the window
+///
+/// Called automatically when the window is destroyed. Does nothing on Linux
+pub fn detach_window(window_handle: isize) {
+    #[cfg(target_os = "windows")]
+    platform::windows::window::detach(window_handle);
+    #[cfg(target_os = "linux")]
+    let _ = window_handle;
+}
+
+/// Reports the environment the crate runs in, for debugging issues on users' machines
+pub fn diagnostics() -> Diagnostics {
+    #[cfg(target_os = "windows")]
+    let mut diagnostics = platform::windows::diagnostics::diagnostics();
+    #[cfg(target_os = "linux")]
+    let mut diagnostics = platform::linux::diagnostics::diagnostics();
+
+    diagnostics.features = [
+        ("device", cfg!(feature = "device")),
+        ("media", cfg!(feature = "media")),
+        ("video", cfg!(feature = "video")),
+        ("drag_drop", cfg!(feature = "drag_drop")),
+        ("thumbbar", cfg!(feature = "thumbbar")),
+        ("webview2", cfg!(feature = "webview2")),
+        ("webkit2gtk", cfg!(feature = "webkit2gtk")),
+        ("testing", cfg!(feature = "testing")),
+        ("audit", cfg!(feature = "audit")),
+        ("instant_search", cfg!(feature = "instant_search")),
+        ("raw", cfg!(feature = "raw")),
+        ("heif", cfg!(feature = "heif")),
+        ("listing", cfg!(feature = "listing")),
+        ("archive", cfg!(feature = "archive")),
+        ("archive_7z", cfg!(feature = "archive_7z")),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(feature, _)| feature.to_string())
+    .collect();
+
+    diagnostics
+}
+
+/// Environment reported by `diagnostics`. Fields of the other platform are left empty
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Diagnostics {
+    // "STA", "MTA", "NA" or "None" for the calling thread
+    pub com_apartment: Option<String>,
+    // None if the runtime is not installed or the webview2 feature is disabled
+    pub webview2_version: Option<String>,
+    pub gtk_initialized: bool,
+    // "wayland" or "x11"
+    pub display_server: Option<String>,
+    pub portal_available: bool,
+    pub file_manager_available: bool,
+    pub notifications_available: bool,
+    // libavutil version, None if the video feature is disabled
+    pub ffmpeg_version: Option<String>,
+    // Which of "heic", "heif" and "avif" installed codecs can decode for extract_image_preview, empty if the media feature is disabled
+    pub decodable_image_formats: Vec<String>,
+    // Enabled cargo features
+    pub features: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Volume {
     pub mount_point: String,
     pub volume_label: String,
     pub available_units: u64,
     pub total_units: u64,
+    pub file_system: String,
+    pub is_removable: bool,
+    // Remote drive on Windows, NFS, SMB/CIFS, SSHFS or other remote file system mounted on Linux
+    pub is_network: bool,
+    pub is_read_only: bool,
+    pub device_path: String,
+    pub bus_type: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dirent {
     pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub parent_path: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub full_path: String,
     pub attributes: FileAttribute,
     pub mime_type: String,
+    // Set by `fs::readdir_with` when requested. "file,index" or the logo of a UWP app on Windows, the icon name on Linux
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    // Final target of a symbolic link, junction or shortcut with every link in between followed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+    // "DOMAIN\user" on Windows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+/// Values `fs::readdir_with` reads for each entry in addition to the attributes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReaddirOptions {
+    pub with_mime: bool,
+    pub with_icon: bool,
+    pub with_link_target: bool,
+    pub with_owner: bool,
+}
+
+/// Directory listing that stores the parent path once instead of in every entry
+///
+/// Entries directly under `parent_path` are serialized without `parent_path` and `full_path`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirListing {
+    pub parent_path: String,
+    pub entries: Vec<Dirent>,
+}
+
+impl DirListing {
+    pub fn new<P: AsRef<Path>>(parent_path: P, entries: Vec<Dirent>) -> Self {
+        let parent_path = parent_path.as_ref().to_string_lossy().to_string();
+        let entries = entries
+            .into_iter()
+            .map(|mut entry| {
+                if entry.parent_path == parent_path {
+                    entry.parent_path.clear();
+                    entry.full_path.clear();
+                }
+                entry
+            })
+            .collect();
+
+        Self {
+            parent_path,
+            entries,
+        }
+    }
+
+    /// Restores the omitted paths of each entry
+    pub fn into_dirents(self) -> Vec<Dirent> {
+        let parent_path = self.parent_path;
+        self.entries
+            .into_iter()
+            .map(|mut entry| {
+                if entry.parent_path.is_empty() {
+                    entry.full_path = Path::new(&parent_path).join(&entry.name).to_string_lossy().to_string();
+                    entry.parent_path = parent_path.clone();
+                }
+                entry
+            })
+            .collect()
+    }
+}
+
+/// Format of `fs::export_listing` and `fs::import_listing`
+#[cfg(feature = "listing")]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ListingFormat {
+    // One JSON object per line
+    JsonLines,
+    // One map per entry, written back to back
+    MessagePack,
+    // Header row followed by one row per entry with the attributes flattened into columns
+    Csv,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirPage {
+    pub entries: Vec<Dirent>,
+    pub cursor: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,7 +233,10 @@ pub struct FileAttribute {
     pub is_system: bool,
     pub is_device: bool,
     pub is_symbolic_link: bool,
+    pub is_junction: bool,
     pub is_file: bool,
+    // Excluded from search indexing
+    pub is_not_indexed: bool,
     pub ctime_ms: u64,
     pub mtime_ms: u64,
     pub atime_ms: u64,
@@ -43,6 +245,168 @@ pub struct FileAttribute {
     pub link_path: String,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MkdirOptions {
+    pub is_hidden: bool,
+    pub is_read_only: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SetAttributeOptions {
+    pub is_hidden: Option<bool>,
+    pub is_read_only: Option<bool>,
+    pub is_system: Option<bool>,
+    pub is_backup_excluded: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccessMask {
+    pub read: bool,
+    pub write: bool,
+    pub delete: bool,
+    pub execute: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryOptions {
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub chunk_size: usize,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10_000,
+            chunk_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Limits how often `OperationStatus::Progress` is reported. 0 disables each limit
+///
+/// The first and the final progress of each item are always reported
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProgressThrottle {
+    pub min_interval_ms: u64,
+    // Bytes, or work units of IFileOperation on Windows
+    pub min_delta: u64,
+}
+
+/// Event of a file operation started by `fs::operate_with_events`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OperationEvent {
+    // Totals are None on Windows, where the shell measures items as it goes
+    Started {
+        total_size: Option<u64>,
+        total_count: Option<u64>,
+    },
+    ItemProgress {
+        name: String,
+        processed: i64,
+        total: i64,
+    },
+    ItemDone {
+        name: String,
+    },
+    // Sent before Error when other processes have the item open
+    InUseBy {
+        name: String,
+        processes: Vec<crate::process::ProcessInfo>,
+    },
+    // The operation waits until answered by `OperationHandle::respond`
+    Conflict {
+        source: String,
+    },
+    // The conflict was answered with Skip
+    ItemSkipped {
+        source: String,
+    },
+    Error(String),
+    Finished,
+}
+
+/// Desktop notification and sound for a file operation that finishes while no window of the app is focused
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompletionNotice {
+    // "Copy completed", "Copy failed" and so on if None
+    pub title: Option<String>,
+    pub notification: bool,
+    pub sound: bool,
+    // Stays silent when the operation succeeds
+    pub failures_only: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreflightReport {
+    pub total_size: u64,
+    // Number of files, directories excluded
+    pub total_count: u64,
+    // Free space of the destination volume. None for Delete and Trash
+    pub available_space: Option<u64>,
+    pub has_enough_space: bool,
+    // Move within one volume, done as an instant rename
+    pub is_same_volume: bool,
+    // Destination paths exceeding the path or file name length limit
+    pub too_long_paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Xxh3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileHash {
+    pub full_path: String,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum CompareMode {
+    Binary,
+    // Compares lines ignoring line endings
+    Text,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileComparison {
+    pub equal: bool,
+    // Byte offset of the first difference in Binary mode
+    pub first_difference: Option<u64>,
+    // Line diff summary in Text mode
+    pub lines: Option<LineDiffSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineDiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    // 1-based
+    pub first_different_line: Option<usize>,
+}
+
+/// How `clipboard::write_file_names` writes each path
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum NameStyle {
+    // File name only
+    Name,
+    FullPath,
+    // Full path in double quotes, as a command line argument
+    Quoted,
+    // file:// URI
+    Uri,
+    // [name](file:// URI)
+    MarkdownLink,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Operation {
     None,
@@ -50,6 +414,14 @@ pub enum Operation {
     Move,
 }
 
+/// File dragged by `drag_drop::start_drag_virtual` that does not exist on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualFile {
+    pub name: String,
+    // Shown in the progress UI of the drop target if known
+    pub size: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardData {
     pub operation: Operation,
@@ -63,6 +435,117 @@ pub struct AppInfo {
     pub icon_path: String,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ShowState {
+    #[default]
+    Normal,
+    Hidden,
+    Minimized,
+    Maximized,
+}
+
+/// How shell::execute_with starts an application
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecuteOptions {
+    // Arguments passed before the file path, each quoted as needed
+    pub args: Vec<String>,
+    // Working directory, the current directory of the app if None
+    pub cwd: Option<String>,
+    // Variables added to or replacing those inherited from the app
+    pub env: Vec<(String, String)>,
+    // Initial window state, ignored on Linux
+    pub show: ShowState,
+    // Blocks until the application exits
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecuteResult {
+    // None if the file was handed to an already running instance
+    pub pid: Option<u32>,
+    // Set only when waiting for the application to exit
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellVerb {
+    // Passed to shell::invoke_verb such as "open", "edit" or "runas"
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellColumn {
+    // Passed to shell::column_value such as "dimensions" or "duration"
+    pub key: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PropertyValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    StringList(Vec<String>),
+}
+
+/// Per-folder view settings shared with Explorer through desktop.ini, and kept in GIO metadata on Linux
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FolderViewHints {
+    // FolderType such as "Generic", "Documents", "Pictures", "Music" or "Videos"
+    pub folder_type: Option<String>,
+    // Icon file followed by a comma and the icon index on Windows, icon file on Linux
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShellCapabilities {
+    pub can_rename: bool,
+    pub can_delete: bool,
+    pub can_copy: bool,
+    pub can_move: bool,
+    pub can_link: bool,
+    pub is_folder: bool,
+    // On removable media or is a removable device itself
+    pub is_removable_media: bool,
+    pub has_properties: bool,
+}
+
+/// What double-clicking the file would do
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DefaultAction {
+    // Opens in the associated application or browses the folder
+    Open,
+    // Runs the file itself as a program
+    Run,
+    // Asks the user first because there is no associated application or the program was downloaded
+    Prompt,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BreadcrumbKind {
+    // This PC, Network or Computer
+    Root,
+    Host,
+    Share,
+    Drive,
+    Folder,
+    // Archive file opened as a folder
+    Archive,
+    File,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Breadcrumb {
+    pub name: String,
+    // Path or URI to navigate to
+    pub path: String,
+    pub kind: BreadcrumbKind,
+    // Icon file path on Linux, "file,index" icon location on Windows
+    pub icon: Option<String>,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Icon {
     #[cfg(target_os = "linux")]
@@ -80,6 +563,23 @@ pub struct ThumbButton {
     pub icon: PathBuf,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JumpListItem {
+    pub title: String,
+    // Program or file to launch
+    pub path: PathBuf,
+    pub arguments: Option<String>,
+    pub icon: Option<PathBuf>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JumpListCategory {
+    // Empty for the Tasks category
+    pub name: String,
+    pub items: Vec<JumpListItem>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecycleBinDirent {
     pub name: String,
@@ -95,8 +595,82 @@ pub struct RecycleBinItem {
     pub deleted_time_ms: u64,
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecycleBinInfo {
+    // Number of items at the top level of Recycle Bin
+    pub item_count: u64,
+    // Total bytes of all items
+    pub total_size: u64,
+}
+
+/// What `undelete_with_conflict` does when an item already exists at the original path
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UndeleteConflict {
+    Overwrite,
+    // Restores the item as "name (2).ext"
+    KeepBoth,
+    Skip,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Size {
     pub width: u32,
     pub height: u32,
 }
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaInfo {
+    pub duration_ms: Option<u64>,
+    // Bits per second
+    pub bitrate: Option<u64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_rate: Option<f64>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    // Encoded image such as JPEG or PNG
+    pub album_art: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageInfo {
+    // As stored, before applying the orientation
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    // EXIF orientation from 1 to 8. 1 when the image is not rotated or flipped
+    pub orientation: u16,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub date_taken: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PrefetchPriority {
+    // Rows about to be visible, warmed before anything queued earlier
+    High,
+    // Rows further away, warmed after the others
+    Low,
+}
+
+/// Cancels work running on other threads. Clones share the same state
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+static IN_FLIGHT: Mutex<usize> = Mutex::new(0);
+static DRAINED: Condvar = Condvar::new();
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Counts a file operation as in flight until dropped
+pub(crate) struct OperationGuard;
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        let mut in_flight = IN_FLIGHT.lock().unwrap();
+        *in_flight = in_flight.saturating_sub(1);
+        if *in_flight == 0 {
+            DRAINED.notify_all();
+        }
+    }
+}
+
+/// Fails once shutdown has started
+pub(crate) fn begin_operation() -> Result<OperationGuard, String> {
+    if is_shutting_down() {
+        return Err("Shutting down".to_string());
+    }
+
+    *IN_FLIGHT.lock().unwrap() += 1;
+    Ok(OperationGuard)
+}
+
+pub(crate) fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+pub(crate) fn shutdown(timeout: Duration) -> bool {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+
+    #[cfg(target_os = "linux")]
+    crate::fs::cancel_operations();
+
+    let drained = wait_for_operations(timeout);
+    crate::cleanup::clean_up();
+
+    crate::history::unlisten();
+    crate::clipboard::stop_watching_cut();
+    crate::fs::unwatch_recycle_bin();
+
+    #[cfg(feature = "device")]
+    {
+        crate::device::unlisten();
+        crate::device::unlisten_volumes();
+    }
+
+    #[cfg(target_os = "windows")]
+    crate::platform::windows::window::detach_all();
+
+    drained
+}
+
+/* Operations on Windows run on other threads, so just wait until the last one is dropped */
+#[cfg(target_os = "windows")]
+fn wait_for_operations(timeout: Duration) -> bool {
+    let in_flight = IN_FLIGHT.lock().unwrap();
+    let (_in_flight, result) = DRAINED.wait_timeout_while(in_flight, timeout, |in_flight| *in_flight > 0).unwrap();
+    !result.timed_out()
+}
+
+/* Operations on Linux run on the main loop, so keep iterating it while waiting */
+#[cfg(target_os = "linux")]
+fn wait_for_operations(timeout: Duration) -> bool {
+    let deadline = std::time::Instant::now() + timeout;
+    let context = gtk::glib::MainContext::default();
+
+    while *IN_FLIGHT.lock().unwrap() > 0 {
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+
+        if !context.iteration(false) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    true
+}
@@ -0,0 +1,207 @@
+use crate::{Dirent, FileAttribute, ListingFormat};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+};
+
+const CSV_COLUMNS: [&str; 22] = [
+    "name",
+    "parent_path",
+    "full_path",
+    "mime_type",
+    "icon",
+    "link_target",
+    "owner",
+    "is_directory",
+    "is_read_only",
+    "is_hidden",
+    "is_system",
+    "is_device",
+    "is_symbolic_link",
+    "is_junction",
+    "is_file",
+    "is_not_indexed",
+    "ctime_ms",
+    "mtime_ms",
+    "atime_ms",
+    "birthtime_ms",
+    "size",
+    "link_path",
+];
+
+/// Writes the entries one at a time, so that a listing can be streamed to another process without building a single JSON value
+pub fn export_listing<W: Write>(entries: &[Dirent], format: ListingFormat, writer: W) -> Result<(), String> {
+    let mut writer = BufWriter::new(writer);
+
+    match format {
+        ListingFormat::JsonLines => {
+            for entry in entries {
+                serde_json::to_writer(&mut writer, entry).map_err(|e| e.to_string())?;
+                writeln!(writer).map_err(|e| e.to_string())?;
+            }
+        }
+        ListingFormat::MessagePack => {
+            // Maps instead of arrays, since empty paths and absent values are left out
+            let mut serializer = rmp_serde::Serializer::new(&mut writer).with_struct_map();
+            for entry in entries {
+                serde::Serialize::serialize(entry, &mut serializer).map_err(|e| e.to_string())?;
+            }
+        }
+        ListingFormat::Csv => {
+            writeln!(writer, "{}", CSV_COLUMNS.join(",")).map_err(|e| e.to_string())?;
+            for entry in entries {
+                writeln!(writer, "{}", to_csv_row(entry).join(",")).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    writer.flush().map_err(|e| e.to_string())
+}
+
+/// Reads entries written by `export_listing` in the same format
+pub fn import_listing<R: Read>(reader: R, format: ListingFormat) -> Result<Vec<Dirent>, String> {
+    let mut reader = BufReader::new(reader);
+    let mut entries = Vec::new();
+
+    match format {
+        ListingFormat::JsonLines => {
+            for line in reader.lines() {
+                let line = line.map_err(|e| e.to_string())?;
+                if !line.trim().is_empty() {
+                    entries.push(serde_json::from_str(&line).map_err(|e| e.to_string())?);
+                }
+            }
+        }
+        ListingFormat::MessagePack => {
+            let mut deserializer = rmp_serde::Deserializer::new(&mut reader);
+            while !reader_at_end(deserializer.get_mut())? {
+                entries.push(Dirent::deserialize(&mut deserializer).map_err(|e| e.to_string())?);
+            }
+        }
+        ListingFormat::Csv => {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).map_err(|e| e.to_string())?;
+            let mut rows = parse_csv(&text).into_iter();
+            let header = rows.next().unwrap_or_default();
+            for row in rows {
+                let values: HashMap<&str, &str> = header.iter().map(String::as_str).zip(row.iter().map(String::as_str)).collect();
+                entries.push(from_csv_row(&values)?);
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+fn reader_at_end<R: BufRead>(reader: &mut R) -> Result<bool, String> {
+    reader.fill_buf().map(|buffer| buffer.is_empty()).map_err(|e| e.to_string())
+}
+
+fn to_csv_row(entry: &Dirent) -> Vec<String> {
+    let attributes = &entry.attributes;
+    vec![
+        escape_csv(&entry.name),
+        escape_csv(&entry.parent_path),
+        escape_csv(&entry.full_path),
+        escape_csv(&entry.mime_type),
+        escape_csv(entry.icon.as_deref().unwrap_or_default()),
+        escape_csv(entry.link_target.as_deref().unwrap_or_default()),
+        escape_csv(entry.owner.as_deref().unwrap_or_default()),
+        attributes.is_directory.to_string(),
+        attributes.is_read_only.to_string(),
+        attributes.is_hidden.to_string(),
+        attributes.is_system.to_string(),
+        attributes.is_device.to_string(),
+        attributes.is_symbolic_link.to_string(),
+        attributes.is_junction.to_string(),
+        attributes.is_file.to_string(),
+        attributes.is_not_indexed.to_string(),
+        attributes.ctime_ms.to_string(),
+        attributes.mtime_ms.to_string(),
+        attributes.atime_ms.to_string(),
+        attributes.birthtime_ms.to_string(),
+        attributes.size.to_string(),
+        escape_csv(&attributes.link_path),
+    ]
+}
+
+fn from_csv_row(values: &HashMap<&str, &str>) -> Result<Dirent, String> {
+    let text = |column: &str| values.get(column).copied().unwrap_or_default().to_string();
+    let optional = |column: &str| Some(text(column)).filter(|value| !value.is_empty());
+    let flag = |column: &str| values.get(column).is_some_and(|value| *value == "true");
+    let number = |column: &str| -> Result<u64, String> {
+        match values.get(column) {
+            Some(value) if !value.is_empty() => value.parse().map_err(|_| format!("Invalid {}:{}", column, value)),
+            _ => Ok(0),
+        }
+    };
+
+    Ok(Dirent {
+        name: text("name"),
+        parent_path: text("parent_path"),
+        full_path: text("full_path"),
+        attributes: FileAttribute {
+            is_directory: flag("is_directory"),
+            is_read_only: flag("is_read_only"),
+            is_hidden: flag("is_hidden"),
+            is_system: flag("is_system"),
+            is_device: flag("is_device"),
+            is_symbolic_link: flag("is_symbolic_link"),
+            is_junction: flag("is_junction"),
+            is_file: flag("is_file"),
+            is_not_indexed: flag("is_not_indexed"),
+            ctime_ms: number("ctime_ms")?,
+            mtime_ms: number("mtime_ms")?,
+            atime_ms: number("atime_ms")?,
+            birthtime_ms: number("birthtime_ms")?,
+            size: number("size")?,
+            link_path: text("link_path"),
+        },
+        mime_type: text("mime_type"),
+        icon: optional("icon"),
+        link_target: optional("link_target"),
+        owner: optional("owner"),
+    })
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/* Splits into rows of fields, allowing line breaks inside quoted fields since names may contain them on Linux */
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => row.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
@@ -0,0 +1,54 @@
+use crate::{
+    fs::{FileOperation, OperationStatus},
+    CompletionNotice,
+};
+
+/// Keeps the first error reported by an operation
+#[derive(Default)]
+pub(crate) struct Outcome {
+    error: Option<String>,
+}
+
+impl Outcome {
+    pub(crate) fn observe(&mut self, status: &OperationStatus) {
+        if let OperationStatus::Error(e) = status {
+            self.fail(e);
+        }
+    }
+
+    pub(crate) fn fail(&mut self, error: &str) {
+        self.error.get_or_insert_with(|| error.to_string());
+    }
+
+    pub(crate) fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+/// Returns the title and body to notify with, or None if the app is focused or the notice does not cover the outcome
+pub(crate) fn compose(notice: &CompletionNotice, operation: FileOperation, count: usize, error: Option<&str>, is_app_focused: bool) -> Option<(String, String)> {
+    if is_app_focused || (!notice.notification && !notice.sound) || (notice.failures_only && error.is_none()) {
+        return None;
+    }
+
+    let name = match operation {
+        FileOperation::Copy => "Copy",
+        FileOperation::Move => "Move",
+        FileOperation::Delete => "Delete",
+        FileOperation::Trash => "Move to trash",
+    };
+
+    let title = match (&notice.title, error) {
+        (Some(title), _) => title.clone(),
+        (None, Some(_)) => format!("{} failed", name),
+        (None, None) => format!("{} completed", name),
+    };
+
+    let body = match error {
+        Some(e) => e.to_string(),
+        None if count == 1 => "1 item".to_string(),
+        None => format!("{} items", count),
+    };
+
+    Some((title, body))
+}
@@ -0,0 +1,168 @@
+use std::{
+    cmp::Ordering,
+    path::{Component, Path, PathBuf},
+};
+
+/// Resolves `.` and `..` and duplicate separators without touching the file system, so symlinks are not followed
+///
+/// `..` at the root is dropped, and kept at the start of a relative path
+pub fn normalize<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut components: Vec<Component> = Vec::new();
+
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => {
+                    components.pop();
+                }
+                Some(Component::Prefix(_)) | Some(Component::RootDir) => {}
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+
+    if components.is_empty() {
+        return PathBuf::from(".");
+    }
+
+    components.iter().collect()
+}
+
+/// Prefixes an absolute path with `\\?\`, or `\\?\UNC\` for a network path, so that Windows APIs accept it beyond MAX_PATH
+///
+/// Relative and already prefixed paths are returned as they are. Does nothing on Linux
+pub fn to_extended_length<P: AsRef<Path>>(path: P) -> PathBuf {
+    #[cfg(target_os = "windows")]
+    {
+        let path_str = path.as_ref().to_string_lossy();
+        if !path.as_ref().is_absolute() || path_str.starts_with("\\\\?\\") {
+            return path.as_ref().to_path_buf();
+        }
+
+        // Extended length paths are not normalized by Windows, so separators and dots have to be resolved here
+        let normalized = normalize(path.as_ref()).to_string_lossy().replace('/', "\\");
+        match normalized.strip_prefix("\\\\") {
+            Some(unc) => PathBuf::from(format!("\\\\?\\UNC\\{}", unc)),
+            None => PathBuf::from(format!("\\\\?\\{}", normalized)),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    path.as_ref().to_path_buf()
+}
+
+/// Whether the paths refer to the same file or directory, comparing the volume and file ID, or the device and inode on Linux
+///
+/// Hard links and symlinks to the same file are the same file. Fails if either does not exist
+pub fn is_same_file<P1: AsRef<Path>, P2: AsRef<Path>>(path_a: P1, path_b: P2) -> Result<bool, String> {
+    Ok(file_id(path_a.as_ref())? == file_id(path_b.as_ref())?)
+}
+
+#[cfg(target_os = "linux")]
+fn file_id(path: &Path) -> Result<(u64, u64), String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path).map_err(|e| format!("{}:{}", e, path.to_string_lossy()))?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(target_os = "windows")]
+fn file_id(path: &Path) -> Result<(u64, u64), String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::{
+        core::PCWSTR,
+        Win32::{
+            Foundation::CloseHandle,
+            Storage::FileSystem::{
+                CreateFileW, GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION, FILE_FLAG_BACKUP_SEMANTICS, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+            },
+        },
+    };
+
+    let wide: Vec<u16> = to_extended_length(path).as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    // No access is needed to read the IDs, and backup semantics are required to open directories
+    let handle = unsafe { CreateFileW(PCWSTR::from_raw(wide.as_ptr()), 0, FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE, None, OPEN_EXISTING, FILE_FLAG_BACKUP_SEMANTICS, None) }
+        .map_err(|e| format!("{}:{}", e.message(), path.to_string_lossy()))?;
+
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    let result = unsafe { GetFileInformationByHandle(handle, &mut info) };
+    let _ = unsafe { CloseHandle(handle) };
+    result.map_err(|e| e.message())?;
+
+    Ok((info.dwVolumeSerialNumber as u64, ((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64))
+}
+
+/// Returns the path relative to the base, with `..` for each level to go up from the base
+///
+/// Both are normalized first. Returns None if one is absolute and the other is not, or if they are on different drives
+pub fn relative_to<P1: AsRef<Path>, P2: AsRef<Path>>(path: P1, base: P2) -> Option<PathBuf> {
+    let path = normalize(path);
+    let base = normalize(base);
+    if path.is_absolute() != base.is_absolute() {
+        return None;
+    }
+
+    let path_components: Vec<Component> = path.components().filter(|component| *component != Component::CurDir).collect();
+    let base_components: Vec<Component> = base.components().filter(|component| *component != Component::CurDir).collect();
+
+    let common = path_components.iter().zip(&base_components).take_while(|(a, b)| compare_component(a, b) == Ordering::Equal).count();
+    // Absolute paths without a common root are on different drives or shares
+    if path.is_absolute() && common == 0 {
+        return None;
+    }
+    // `..` left in a relative base cannot be walked back without knowing the current directory
+    if base_components[common..].contains(&Component::ParentDir) {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &path_components[common..] {
+        relative.push(component);
+    }
+
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+
+    Some(relative)
+}
+
+/// Compares the paths after normalizing them, ignoring case on Windows where file names are case-insensitive
+pub fn compare<P1: AsRef<Path>, P2: AsRef<Path>>(path_a: P1, path_b: P2) -> Ordering {
+    let path_a = normalize(path_a);
+    let path_b = normalize(path_b);
+    let mut components_a = path_a.components();
+    let mut components_b = path_b.components();
+
+    loop {
+        match (components_a.next(), components_b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match compare_component(&a, &b) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            },
+        }
+    }
+}
+
+/// Whether the paths are the same after normalizing them, ignoring case on Windows. The file system is not accessed
+pub fn is_same_path<P1: AsRef<Path>, P2: AsRef<Path>>(path_a: P1, path_b: P2) -> bool {
+    compare(path_a, path_b) == Ordering::Equal
+}
+
+#[cfg(target_os = "windows")]
+fn compare_component(a: &Component, b: &Component) -> Ordering {
+    a.as_os_str().to_string_lossy().to_lowercase().cmp(&b.as_os_str().to_string_lossy().to_lowercase())
+}
+
+#[cfg(target_os = "linux")]
+fn compare_component(a: &Component, b: &Component) -> Ordering {
+    a.cmp(b)
+}
@@ -1,6 +1,24 @@
 use super::util::init;
-use crate::{platform::linux::util::path_to_uri, ClipboardData, Operation};
-use gtk::{gdk::SELECTION_CLIPBOARD, TargetEntry, TargetFlags};
+use crate::{platform::linux::util::path_to_uri, ClipboardData, NameStyle, Operation};
+use gtk::{
+    gdk::{Atom, SELECTION_CLIPBOARD},
+    gdk_pixbuf::{prelude::PixbufLoaderExt, PixbufLoader},
+    glib::{prelude::ObjectExt, ControlFlow, SignalHandlerId, SourceId},
+    TargetEntry, TargetFlags,
+};
+use std::{
+    cell::RefCell,
+    path::Path,
+    rc::{Rc, Weak},
+    sync::Mutex,
+    time::Duration,
+};
+
+static MOVE_COMPLETED_CALLBACK: Mutex<Option<Box<dyn FnMut(Vec<String>) + Send>>> = Mutex::new(None);
+
+thread_local! {
+    static PENDING_CUT: RefCell<Option<PendingCut>> = const { RefCell::new(None) };
+}
 
 /// Checks if text is available
 pub fn is_text_available() -> bool {
@@ -39,6 +57,11 @@ pub fn write_text(_window_handle: isize, text: String) -> Result<(), String> {
     Ok(())
 }
 
+/// Writes the paths as text, one per line, as names, full paths, quoted paths, URIs or Markdown links
+pub fn write_file_names(window_handle: isize, paths: &[String], style: NameStyle) -> Result<(), String> {
+    write_text(window_handle, crate::file_names::format(paths, &style))
+}
+
 /// Checks if URIs are available
 pub fn is_uris_available() -> bool {
     init();
@@ -63,10 +86,35 @@ pub fn read_uris(_window_handle: isize) -> Result<ClipboardData, String> {
 
     let clipboard = gtk::Clipboard::get(&SELECTION_CLIPBOARD);
 
+    // GNOME file managers put the verb on the first line followed by URIs
+    if let Some(selection) = clipboard.wait_for_contents(&Atom::intern("x-special/gnome-copied-files")) {
+        let payload = String::from_utf8_lossy(&selection.data()).to_string();
+        let mut lines = payload.lines();
+        let operation = match lines.next() {
+            Some("cut") => Operation::Move,
+            Some("copy") => Operation::Copy,
+            _ => Operation::None,
+        };
+        let urls: Vec<String> = lines.filter(|line| !line.is_empty()).map(|line| line.to_string()).collect();
+        if !urls.is_empty() {
+            return Ok(ClipboardData {
+                operation,
+                urls,
+            });
+        }
+    }
+
     let urls: Vec<String> = clipboard.wait_for_uris().iter().map(|gs| gs.to_string()).collect();
 
+    // KDE marks cut items with "1"
+    let operation = match clipboard.wait_for_contents(&Atom::intern("application/x-kde-cutselection")) {
+        Some(selection) if selection.data().starts_with(b"1") => Operation::Move,
+        Some(_) => Operation::Copy,
+        None => Operation::None,
+    };
+
     Ok(ClipboardData {
-        operation: Operation::None,
+        operation,
         urls,
     })
 }
@@ -95,24 +143,246 @@ pub fn write_uris(_window_handle: isize, paths: &[String], operation: Operation)
     }
     let payload = payloads.join("\n");
 
-    let _ = clipboard.set_with_data(&targets, move |_, selection, _| match selection.target().name().as_str() {
-        "x-special/gnome-copied-files" => {
-            selection.set(&selection.target(), 8, payload.as_bytes());
+    // Dropped with the closure when GTK clears the data, which tells that the clipboard is no longer ours
+    let ownership = Rc::new(());
+    let watched_ownership = Rc::downgrade(&ownership);
+
+    let _ = clipboard.set_with_data(&targets, move |_, selection, _| {
+        let _ = &ownership;
+        match selection.target().name().as_str() {
+            "x-special/gnome-copied-files" => {
+                selection.set(&selection.target(), 8, payload.as_bytes());
+            }
+            "application/x-kde-cutselection" => {
+                selection.set(
+                    &selection.target(),
+                    8,
+                    if operation == Operation::Move {
+                        b"1"
+                    } else {
+                        b"0"
+                    },
+                );
+            }
+            _ => {
+                let uris: Vec<&str> = payload.lines().skip(1).collect();
+                let _ = selection.set_uris(&uris);
+            }
         }
-        "application/x-kde-cutselection" => {
-            selection.set(
-                &selection.target(),
-                8,
-                if operation == Operation::Move {
-                    b"1"
-                } else {
-                    b"0"
-                },
-            );
+    });
+
+    // Stores the current clipboard data somewhere so that it will stay around after the application has quit.
+    clipboard.store();
+
+    if operation == Operation::Move {
+        watch_cut(paths, watched_ownership);
+    }
+
+    Ok(())
+}
+
+struct PendingCut {
+    paths: Vec<String>,
+    // Alive while the clipboard holds the items
+    ownership: Weak<()>,
+    handler: SignalHandlerId,
+    // Checks the sources after the items were pasted
+    poll: Option<SourceId>,
+}
+
+/// Notifies when the items written by `write_uris` with `Operation::Move` are moved by pasting them here or in other applications
+///
+/// The callback receives the moved paths so that the cut state can be cleared
+pub fn on_move_completed<F: FnMut(Vec<String>) + Send + 'static>(callback: F) {
+    *MOVE_COMPLETED_CALLBACK.lock().unwrap() = Some(Box::new(callback));
+}
+
+/// Stops watching the items written by `write_uris` with `Operation::Move` and keeps the callback for the next cut
+pub fn cancel_move_watch() {
+    if let Some(cut) = PENDING_CUT.with_borrow_mut(Option::take) {
+        gtk::Clipboard::get(&SELECTION_CLIPBOARD).disconnect(cut.handler);
+        if let Some(poll) = cut.poll {
+            poll.remove();
+        }
+    }
+}
+
+/// Stops watching the items written by `write_uris` with `Operation::Move` and removes the callback
+pub(crate) fn stop_watching_cut() {
+    cancel_move_watch();
+    MOVE_COMPLETED_CALLBACK.lock().unwrap().take();
+}
+
+/* File managers clear the clipboard when pasting cut items. Once the clipboard is emptied and all the sources are gone, the move is regarded as completed */
+fn watch_cut(paths: &[String], ownership: Weak<()>) {
+    cancel_move_watch();
+
+    let handler = gtk::Clipboard::get(&SELECTION_CLIPBOARD).connect_owner_change(|clipboard, _| on_owner_change(clipboard));
+    PENDING_CUT.with_borrow_mut(|pending| {
+        *pending = Some(PendingCut {
+            paths: paths.to_vec(),
+            ownership,
+            handler,
+            poll: None,
+        })
+    });
+}
+
+fn on_owner_change(clipboard: &gtk::Clipboard) {
+    let Some((is_owner, is_pasted)) = PENDING_CUT.with_borrow(|pending| pending.as_ref().map(|cut| (cut.ownership.strong_count() > 0, cut.poll.is_some()))) else {
+        return;
+    };
+
+    // The change made by write_uris itself
+    if is_owner {
+        return;
+    }
+
+    // The clipboard changed again after the items were pasted
+    if is_pasted {
+        cancel_move_watch();
+        return;
+    }
+
+    // Targets are requested asynchronously, since waiting for them runs a nested main loop
+    clipboard.request_targets(|_, targets| {
+        // Other data was copied, so the cut items can no longer be pasted
+        if !targets.is_empty() {
+            cancel_move_watch();
+            return;
+        }
+
+        if report_if_moved() {
+            return;
+        }
+
+        let poll = gtk::glib::timeout_add_local(Duration::from_millis(500), || {
+            if report_if_moved() {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        PENDING_CUT.with_borrow_mut(|pending| match pending.as_mut() {
+            Some(cut) => cut.poll = Some(poll),
+            None => poll.remove(),
+        });
+    });
+}
+
+/* Stops watching and notifies once all the sources are gone. Also true if the watch was stopped elsewhere */
+fn report_if_moved() -> bool {
+    let moved = PENDING_CUT.with_borrow_mut(|pending| {
+        if pending.as_ref().is_some_and(|cut| cut.paths.iter().all(|path| !Path::new(path).exists())) {
+            pending.take()
+        } else {
+            None
+        }
+    });
+
+    let Some(cut) = moved else {
+        return PENDING_CUT.with_borrow(|pending| pending.is_none());
+    };
+
+    // The poll ends by returning Break
+    gtk::Clipboard::get(&SELECTION_CLIPBOARD).disconnect(cut.handler);
+    if let Some(callback) = MOVE_COMPLETED_CALLBACK.lock().unwrap().as_mut() {
+        callback(cut.paths);
+    }
+
+    true
+}
+
+/// Checks if image is available
+pub fn is_image_available() -> bool {
+    init();
+
+    let clipboard = gtk::Clipboard::get(&SELECTION_CLIPBOARD);
+    clipboard.wait_is_image_available()
+}
+
+/// Reads image from clipboard as PNG
+///
+/// `window_handle` is ignored
+pub fn read_image(_window_handle: isize) -> Result<Vec<u8>, String> {
+    init();
+
+    let clipboard = gtk::Clipboard::get(&SELECTION_CLIPBOARD);
+    match clipboard.wait_for_image() {
+        Some(pixbuf) => pixbuf.save_to_bufferv("png", &[]).map_err(|e| e.message().to_string()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Writes PNG image to clipboard
+///
+/// `window_handle` is ignored
+pub fn write_image(_window_handle: isize, png: &[u8]) -> Result<(), String> {
+    init();
+
+    let loader = PixbufLoader::new();
+    loader.write(png).map_err(|e| e.message().to_string())?;
+    loader.close().map_err(|e| e.message().to_string())?;
+    let pixbuf = loader.pixbuf().ok_or("Failed to decode image".to_string())?;
+
+    let clipboard = gtk::Clipboard::get(&SELECTION_CLIPBOARD);
+    clipboard.set_image(&pixbuf);
+
+    // Stores the current clipboard data somewhere so that it will stay around after the application has quit.
+    clipboard.store();
+
+    Ok(())
+}
+
+/// Checks if HTML is available
+pub fn is_html_available() -> bool {
+    init();
+
+    let clipboard = gtk::Clipboard::get(&SELECTION_CLIPBOARD);
+    clipboard.wait_is_target_available(&Atom::intern("text/html"))
+}
+
+/// Reads HTML from clipboard
+///
+/// `window_handle` is ignored
+pub fn read_html(_window_handle: isize) -> Result<String, String> {
+    init();
+
+    let clipboard = gtk::Clipboard::get(&SELECTION_CLIPBOARD);
+    let data = match clipboard.wait_for_contents(&Atom::intern("text/html")) {
+        Some(selection) => selection.data(),
+        None => return Ok(String::new()),
+    };
+
+    // Some browsers provide UTF-16 with BOM
+    if let Some(utf16) = data.strip_prefix(&[0xFF, 0xFE]) {
+        let wide: Vec<u16> = utf16.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        return Ok(String::from_utf16_lossy(&wide).trim_end_matches('\0').to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&data).trim_end_matches('\0').to_string())
+}
+
+/// Writes HTML to clipboard
+///
+/// `window_handle` is ignored. `alt_text` is written as plain text for applications which do not accept HTML
+pub fn write_html(_window_handle: isize, html: String, alt_text: Option<String>) -> Result<(), String> {
+    init();
+
+    let clipboard = gtk::Clipboard::get(&SELECTION_CLIPBOARD);
+
+    let mut target_names = vec!["text/html"];
+    if alt_text.is_some() {
+        target_names.extend(["UTF8_STRING", "text/plain;charset=utf-8", "text/plain"]);
+    }
+    let targets: Vec<TargetEntry> = target_names.iter().map(|target| TargetEntry::new(target, TargetFlags::empty(), 0)).collect();
+
+    let _ = clipboard.set_with_data(&targets, move |_, selection, _| match selection.target().name().as_str() {
+        "text/html" => {
+            selection.set(&selection.target(), 8, html.as_bytes());
         }
         _ => {
-            let uris: Vec<&str> = payload.lines().skip(1).collect();
-            let _ = selection.set_uris(&uris);
+            selection.set_text(alt_text.as_deref().unwrap_or_default());
         }
     });
 
@@ -1,6 +1,16 @@
-use rusb::{Context, Device, Interfaces, Registration, UsbContext};
+use gtk::{
+    gio::{
+        prelude::{FileExt, MountExt, VolumeMonitorExt},
+        IOErrorEnum, Mount, MountOperation, MountUnmountFlags, VolumeMonitor,
+    },
+    glib::{prelude::ObjectExt, SignalHandlerId},
+};
+use rusb::{Context, Device, Registration, UsbContext};
 use serde::{Deserialize, Serialize};
 use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, LazyLock, Mutex,
@@ -11,52 +21,139 @@ use std::{
 static LISTENER: LazyLock<Mutex<Listener>> = LazyLock::new(|| Mutex::new(Listener::default()));
 static WATCHING: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
 
+thread_local! {
+    static VOLUME_LISTENER: RefCell<Option<(VolumeMonitor, Vec<SignalHandlerId>)>> = const { RefCell::new(None) };
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceEvent {
     name: String,
     event: String,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceFilter {
+    /// Classes such as "MassStorage", "HID", "Printer", "Audio", "COMM", "PTP" and "Hub". Empty for all classes
+    pub classes: Vec<String>,
+    /// Pairs of vendor ID and product ID. Empty for all devices
+    pub ids: Vec<(u16, u16)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeEvent {
+    pub mount_point: String,
+    pub volume_label: String,
+    pub event: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemInfo {
+    // None if no battery is present
+    pub battery_percent: Option<u8>,
+    pub charging: bool,
+    pub os_version: String,
+    pub cpu_count: usize,
+    pub total_memory: u64,
+    pub available_memory: u64,
+    pub uptime_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum EjectError {
+    NotFound,
+    Busy,
+    Failed(String),
+}
+
+impl std::fmt::Display for EjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EjectError::NotFound => write!(f, "Volume not found"),
+            EjectError::Busy => write!(f, "Volume is in use"),
+            EjectError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
 struct HotPlugHandler {
     callback: Box<dyn FnMut(DeviceEvent) + 'static + Send>,
+    filter: DeviceFilter,
 }
 
-fn get_class_name(interfaces: Interfaces) -> String {
-    let mut class_name = String::new();
-
-    for interface in interfaces {
-        for descriptor in interface.descriptors() {
-            class_name = match descriptor.class_code() {
-                1 => "Audio",
-                2 => "COMM",
-                3 => "HID",
-                5 => "Physical",
-                6 => "PTP",
-                7 => "Printer",
-                8 => "MassStorage",
-                9 => "Hub",
-                10 => "Data",
-                _ => "Unknown",
+impl HotPlugHandler {
+    fn notify<T: UsbContext>(&mut self, device: Device<T>, config_descriptor: rusb::Result<rusb::ConfigDescriptor>, event: &str) {
+        let descriptor = device.device_descriptor().ok();
+        if !self.filter.ids.is_empty() {
+            let Some(descriptor) = &descriptor else {
+                return;
+            };
+            if !self.filter.ids.contains(&(descriptor.vendor_id(), descriptor.product_id())) {
+                return;
             }
-            .to_string();
+        }
+
+        let class_names = get_class_names(descriptor.map(|descriptor| descriptor.class_code()), config_descriptor.ok().as_ref());
+        // The class that matched the filter, otherwise the device class or the first interface class
+        let name = if self.filter.classes.is_empty() {
+            class_names.first().cloned().unwrap_or_default()
+        } else {
+            match class_names.into_iter().find(|name| self.filter.classes.contains(name)) {
+                Some(name) => name,
+                None => return,
+            }
+        };
+
+        (self.callback)(DeviceEvent {
+            name,
+            event: event.to_string(),
+        });
+    }
+}
+
+/* A composite device such as a headset with buttons reports Audio and HID on separate interfaces, so the device class and every interface class are listed */
+fn get_class_names(device_class: Option<u8>, config_descriptor: Option<&rusb::ConfigDescriptor>) -> Vec<String> {
+    // 0 means that each interface specifies its own class
+    let mut codes: Vec<u8> = device_class.filter(|code| *code != 0).into_iter().collect();
+    if let Some(config_descriptor) = config_descriptor {
+        for interface in config_descriptor.interfaces() {
+            codes.extend(interface.descriptors().map(|descriptor| descriptor.class_code()));
+        }
+    }
+
+    let mut class_names: Vec<String> = Vec::new();
+    for code in codes {
+        let class_name = to_class_name(code).to_string();
+        if !class_names.contains(&class_name) {
+            class_names.push(class_name);
         }
     }
-    class_name
+    class_names
+}
+
+fn to_class_name(code: u8) -> &'static str {
+    match code {
+        1 => "Audio",
+        2 => "COMM",
+        3 => "HID",
+        5 => "Physical",
+        6 => "PTP",
+        7 => "Printer",
+        8 => "MassStorage",
+        9 => "Hub",
+        10 => "Data",
+        _ => "Unknown",
+    }
 }
 
 impl<T: UsbContext> rusb::Hotplug<T> for HotPlugHandler {
     fn device_arrived(&mut self, device: Device<T>) {
-        (self.callback)(DeviceEvent {
-            name: get_class_name(device.active_config_descriptor().unwrap().interfaces()),
-            event: "Added".to_string(),
-        });
+        let config_descriptor = device.active_config_descriptor();
+        self.notify(device, config_descriptor, "Added");
     }
 
     fn device_left(&mut self, device: Device<T>) {
-        (self.callback)(DeviceEvent {
-            name: get_class_name(device.config_descriptor(0).unwrap().interfaces()),
-            event: "Removed".to_string(),
-        });
+        let config_descriptor = device.config_descriptor(0);
+        self.notify(device, config_descriptor, "Removed");
     }
 }
 
@@ -67,17 +164,26 @@ struct Listener {
 }
 
 /// Get notification when any device is conneced
-pub fn listen<F: FnMut(DeviceEvent) + 'static + Send>(callback: F) -> bool {
+///
+/// Only the devices matching `filter` are notified
+pub fn listen<F: FnMut(DeviceEvent) + 'static + Send>(filter: Option<DeviceFilter>, callback: F) -> bool {
     if !rusb::has_hotplug() {
         return false;
     }
 
     if let Ok(context) = Context::new() {
         let callback = Box::new(callback);
-        if let Ok(registration) = rusb::HotplugBuilder::new().register(
+        let filter = filter.unwrap_or_default();
+        let mut builder = rusb::HotplugBuilder::new();
+        // Let libusb skip other devices when only one device is watched
+        if let [(vendor_id, product_id)] = filter.ids.as_slice() {
+            builder.vendor_id(*vendor_id).product_id(*product_id);
+        }
+        if let Ok(registration) = builder.register(
             &context,
             Box::new(HotPlugHandler {
                 callback,
+                filter,
             }),
         ) {
             unlisten();
@@ -124,3 +230,150 @@ pub fn unlisten() {
 pub fn is_listening() -> bool {
     WATCHING.load(Ordering::SeqCst)
 }
+
+fn to_volume_event(mount: &Mount, event: &str) -> VolumeEvent {
+    VolumeEvent {
+        mount_point: mount.root().path().unwrap_or_default().to_string_lossy().to_string(),
+        volume_label: mount.name().to_string(),
+        event: event.to_string(),
+    }
+}
+
+/// Get notification when a volume is mounted, unmounted or renamed
+///
+/// Events are "Mounted", "Unmounted" and "Renamed". Must be called on the main thread running the GLib main loop
+pub fn listen_volumes<F: FnMut(VolumeEvent) + Send + 'static>(callback: F) -> bool {
+    unlisten_volumes();
+
+    let monitor = VolumeMonitor::get();
+    let callback = Rc::new(RefCell::new(callback));
+    let labels: Rc<RefCell<HashMap<String, String>>> = Rc::new(RefCell::new(monitor.mounts().iter().map(|mount| (mount.root().uri().to_string(), mount.name().to_string())).collect()));
+
+    let mut handlers = Vec::new();
+
+    let added_callback = callback.clone();
+    let added_labels = labels.clone();
+    handlers.push(monitor.connect_mount_added(move |_, mount| {
+        added_labels.borrow_mut().insert(mount.root().uri().to_string(), mount.name().to_string());
+        (added_callback.borrow_mut())(to_volume_event(mount, "Mounted"));
+    }));
+
+    let removed_callback = callback.clone();
+    let removed_labels = labels.clone();
+    handlers.push(monitor.connect_mount_removed(move |_, mount| {
+        removed_labels.borrow_mut().remove(&mount.root().uri().to_string());
+        (removed_callback.borrow_mut())(to_volume_event(mount, "Unmounted"));
+    }));
+
+    // mount-changed is also emitted for unrelated property changes, so report only label changes
+    handlers.push(monitor.connect_mount_changed(move |_, mount| {
+        let name = mount.name().to_string();
+        let previous = labels.borrow_mut().insert(mount.root().uri().to_string(), name.clone());
+        if previous.is_some_and(|previous| previous != name) {
+            (callback.borrow_mut())(to_volume_event(mount, "Renamed"));
+        }
+    }));
+
+    VOLUME_LISTENER.with(|listener| *listener.borrow_mut() = Some((monitor, handlers)));
+
+    true
+}
+
+pub fn unlisten_volumes() {
+    VOLUME_LISTENER.with(|listener| {
+        if let Some((monitor, handlers)) = listener.borrow_mut().take() {
+            for handler in handlers {
+                monitor.disconnect(handler);
+            }
+        }
+    });
+}
+
+pub fn is_listening_volumes() -> bool {
+    VOLUME_LISTENER.with(|listener| listener.borrow().is_some())
+}
+
+/// Ejects the removable volume mounted at `mount_point`, or unmounts it if it cannot be ejected
+pub async fn eject(mount_point: &str) -> Result<(), EjectError> {
+    let mount = VolumeMonitor::get().mounts().into_iter().find(|mount| mount.root().path().is_some_and(|path| path.to_string_lossy() == mount_point)).ok_or(EjectError::NotFound)?;
+
+    let result = if mount.can_eject() {
+        mount.eject_with_operation_future(MountUnmountFlags::NONE, None::<&MountOperation>).await
+    } else {
+        mount.unmount_with_operation_future(MountUnmountFlags::NONE, None::<&MountOperation>).await
+    };
+
+    result.map_err(|e| match e.kind::<IOErrorEnum>() {
+        Some(IOErrorEnum::Busy) => EjectError::Busy,
+        _ => EjectError::Failed(e.message().to_string()),
+    })
+}
+
+/// Returns battery, OS, CPU, memory and uptime information
+pub fn system_info() -> Result<SystemInfo, String> {
+    let (battery_percent, charging) = get_battery();
+    let (total_memory, available_memory) = get_memory()?;
+
+    let uptime = std::fs::read_to_string("/proc/uptime").map_err(|e| e.to_string())?;
+    let uptime_secs = uptime.split_whitespace().next().and_then(|secs| secs.parse::<f64>().ok()).unwrap_or_default() as u64;
+
+    Ok(SystemInfo {
+        battery_percent,
+        charging,
+        os_version: get_os_version(),
+        cpu_count: std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1),
+        total_memory,
+        available_memory,
+        uptime_secs,
+    })
+}
+
+fn get_battery() -> (Option<u8>, bool) {
+    let Ok(entries) = std::fs::read_dir("/sys/class/power_supply") else {
+        return (None, false);
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_battery = std::fs::read_to_string(path.join("type")).is_ok_and(|supply_type| supply_type.trim() == "Battery");
+        if !is_battery {
+            continue;
+        }
+
+        let Some(capacity) = std::fs::read_to_string(path.join("capacity")).ok().and_then(|capacity| capacity.trim().parse::<u8>().ok()) else {
+            continue;
+        };
+        let charging = std::fs::read_to_string(path.join("status")).is_ok_and(|status| status.trim() == "Charging");
+        return (Some(capacity), charging);
+    }
+
+    (None, false)
+}
+
+/* Reads MemTotal and MemAvailable in bytes */
+fn get_memory() -> Result<(u64, u64), String> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").map_err(|e| e.to_string())?;
+
+    let read_value = |name: &str| -> u64 {
+        meminfo.lines().find_map(|line| line.strip_prefix(name)).and_then(|value| value.trim_start_matches(':').split_whitespace().next()).and_then(|kb| kb.parse::<u64>().ok()).unwrap_or_default()
+            * 1024
+    };
+
+    Ok((read_value("MemTotal"), read_value("MemAvailable")))
+}
+
+/* Such as "Ubuntu 24.04.1 LTS (Linux 6.8.0-45-generic)" */
+fn get_os_version() -> String {
+    let name = std::fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|os_release| os_release.lines().find_map(|line| line.strip_prefix("PRETTY_NAME=").map(|name| name.trim_matches('"').to_string())))
+        .unwrap_or_else(|| "Linux".to_string());
+
+    let mut utsname: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut utsname) } != 0 {
+        return name;
+    }
+
+    let release = unsafe { std::ffi::CStr::from_ptr(utsname.release.as_ptr()) }.to_string_lossy().to_string();
+    format!("{} (Linux {})", name, release)
+}
@@ -0,0 +1,82 @@
+use crate::Diagnostics;
+use zbus::blocking::{fdo::DBusProxy, Connection};
+
+const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+const FILE_MANAGER_NAME: &str = "org.freedesktop.FileManager1";
+const NOTIFICATIONS_NAME: &str = "org.freedesktop.Notifications";
+
+pub(crate) fn diagnostics() -> Diagnostics {
+    let bus_names = list_bus_names();
+
+    Diagnostics {
+        gtk_initialized: gtk::is_initialized(),
+        display_server: display_server(),
+        portal_available: bus_names.iter().any(|name| name == PORTAL_NAME),
+        file_manager_available: bus_names.iter().any(|name| name == FILE_MANAGER_NAME),
+        notifications_available: bus_names.iter().any(|name| name == NOTIFICATIONS_NAME),
+        ffmpeg_version: ffmpeg_version(),
+        decodable_image_formats: decodable_image_formats(),
+        ..Default::default()
+    }
+}
+
+fn display_server() -> Option<String> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        Some("wayland".to_string())
+    } else if std::env::var_os("DISPLAY").is_some() {
+        Some("x11".to_string())
+    } else {
+        None
+    }
+}
+
+/* Names owned on the session bus and those started on demand */
+fn list_bus_names() -> Vec<String> {
+    let Ok(connection) = Connection::session() else {
+        return Vec::new();
+    };
+    let Ok(proxy) = DBusProxy::new(&connection) else {
+        return Vec::new();
+    };
+
+    let mut names = proxy.list_names().unwrap_or_default();
+    names.extend(proxy.list_activatable_names().unwrap_or_default());
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[cfg(feature = "video")]
+fn ffmpeg_version() -> Option<String> {
+    let version = ffmpeg_next::util::version();
+    Some(format!("{}.{}.{}", version >> 16, (version >> 8) & 0xff, version & 0xff))
+}
+
+#[cfg(not(feature = "video"))]
+fn ffmpeg_version() -> Option<String> {
+    None
+}
+
+/* libheif with the heif feature, or the gdk-pixbuf loaders that libheif and libavif install */
+#[cfg(feature = "media")]
+fn decodable_image_formats() -> Vec<String> {
+    let mut extensions: Vec<String> = gtk::gdk_pixbuf::Pixbuf::formats().iter().flat_map(|format| format.extensions()).map(|extension| extension.to_lowercase()).collect();
+
+    #[cfg(feature = "heif")]
+    {
+        use libheif_rs::{CompressionFormat, LibHeif};
+
+        let lib_heif = LibHeif::new();
+        if !lib_heif.decoder_descriptors(1, Some(CompressionFormat::Hevc)).is_empty() {
+            extensions.extend(["heic".to_string(), "heif".to_string()]);
+        }
+        if !lib_heif.decoder_descriptors(1, Some(CompressionFormat::Av1)).is_empty() {
+            extensions.push("avif".to_string());
+        }
+    }
+
+    ["heic", "heif", "avif"].iter().filter(|format| extensions.iter().any(|extension| extension == *format)).map(|format| format.to_string()).collect()
+}
+
+#[cfg(not(feature = "media"))]
+fn decodable_image_formats() -> Vec<String> {
+    Vec::new()
+}
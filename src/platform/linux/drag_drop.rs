@@ -1,8 +1,14 @@
 use crate::{
     platform::linux::util::{init, path_to_uri},
-    Operation,
+    Operation, VirtualFile,
 };
-use gtk::{gdk::DragAction, prelude::WidgetExt, TargetEntry, TargetFlags};
+use gtk::{
+    gdk::DragAction,
+    glib::{prelude::ObjectExt, SignalHandlerId},
+    prelude::WidgetExt,
+    SelectionData, TargetEntry, TargetFlags,
+};
+use std::{cell::RefCell, rc::Rc};
 
 /// Starts dragging
 pub fn start_drag(file_paths: Vec<String>, operation: Operation) -> Result<(), String> {
@@ -23,13 +29,116 @@ pub fn start_drag(file_paths: Vec<String>, operation: Operation) -> Result<(), S
     };
     widget.drag_begin_with_coordinates(&targets, action, 1, None, -1, -1);
 
-    widget.connect_drag_data_get(move |_, _context, selection_data, info, _time| {
+    connect_for_drag(widget, move |selection_data, info| {
         if info == 0 {
-            let uris = file_paths.iter().filter_map(|path| path_to_uri(path).ok()).map(|url| url.to_string()).collect::<Vec<_>>();
-            let uris_ref: Vec<&str> = uris.iter().map(|uri| uri.as_str()).collect();
-            selection_data.set_uris(&uris_ref);
+            set_uris(selection_data, &file_paths);
         }
     });
 
     Ok(())
 }
+
+/// Starts dragging files that do not exist on disk yet
+///
+/// `writer` is called with each file when the drop target requests the data. The files are written to a temporary directory.
+/// If writing fails, nothing is dropped and the error is passed to `on_error`. Names must be plain file names without path separators
+pub fn start_drag_virtual<F, E>(files: Vec<VirtualFile>, operation: Operation, writer: F, on_error: E) -> Result<(), String>
+where
+    F: Fn(&VirtualFile, &mut dyn std::io::Write) -> Result<(), String> + 'static,
+    E: Fn(String) + 'static,
+{
+    if let Some(file) = files.iter().find(|file| !is_plain_name(&file.name)) {
+        return Err(format!("Invalid file name:{}", file.name));
+    }
+
+    init();
+
+    let widgets = gtk::Window::list_toplevels();
+    if widgets.is_empty() {
+        return Ok(());
+    }
+    let widget = widgets.first().unwrap();
+
+    let targets = gtk::TargetList::new(&[TargetEntry::new("text/uri-list", TargetFlags::OTHER_APP, 0)]);
+
+    let action = match operation {
+        Operation::Copy => DragAction::COPY,
+        Operation::Move => DragAction::MOVE,
+        Operation::None => DragAction::DEFAULT,
+    };
+    widget.drag_begin_with_coordinates(&targets, action, 1, None, -1, -1);
+
+    // Written once on the first request
+    let file_paths: RefCell<Option<Vec<String>>> = RefCell::new(None);
+
+    connect_for_drag(widget, move |selection_data, info| {
+        if info != 0 {
+            return;
+        }
+
+        if file_paths.borrow().is_none() {
+            match write_virtual_files(&files, &writer) {
+                Ok(paths) => *file_paths.borrow_mut() = Some(paths),
+                // Leaving the selection empty fails the drop
+                Err(e) => {
+                    on_error(e);
+                    return;
+                }
+            }
+        }
+
+        if let Some(file_paths) = file_paths.borrow().as_ref() {
+            set_uris(selection_data, file_paths);
+        }
+    });
+
+    Ok(())
+}
+
+/* Connects drag-data-get for the drag just started, disconnecting it with drag-end so that handlers of earlier drags do not pile up */
+fn connect_for_drag<F: Fn(&SelectionData, u32) + 'static>(widget: &gtk::Widget, on_data_get: F) {
+    let handlers: Rc<RefCell<Vec<SignalHandlerId>>> = Rc::new(RefCell::new(Vec::new()));
+
+    let data_get_handler = widget.connect_drag_data_get(move |_, _context, selection_data, info, _time| on_data_get(selection_data, info));
+
+    let end_handlers = handlers.clone();
+    let end_handler = widget.connect_drag_end(move |widget, _context| {
+        for handler in end_handlers.borrow_mut().drain(..) {
+            widget.disconnect(handler);
+        }
+    });
+
+    handlers.borrow_mut().extend([data_get_handler, end_handler]);
+}
+
+fn set_uris(selection_data: &SelectionData, file_paths: &[String]) {
+    let uris = file_paths.iter().filter_map(|path| path_to_uri(path).ok()).map(|url| url.to_string()).collect::<Vec<_>>();
+    let uris_ref: Vec<&str> = uris.iter().map(|uri| uri.as_str()).collect();
+    selection_data.set_uris(&uris_ref);
+}
+
+/* Rejects names that would be written outside the temporary directory */
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+fn write_virtual_files<F>(files: &[VirtualFile], writer: &F) -> Result<Vec<String>, String>
+where
+    F: Fn(&VirtualFile, &mut dyn std::io::Write) -> Result<(), String>,
+{
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|duration| duration.as_nanos()).unwrap_or_default();
+    let dir = std::env::temp_dir().join(format!("zouni-drag-{}-{}", std::process::id(), nanos));
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    crate::cleanup::register(&dir);
+
+    let mut paths = Vec::new();
+    for file in files {
+        let path = dir.join(&file.name);
+        let mut output = std::io::BufWriter::new(std::fs::File::create(&path).map_err(|e| e.to_string())?);
+        writer(file, &mut output)?;
+        std::io::Write::flush(&mut output).map_err(|e| e.to_string())?;
+        paths.push(path.to_string_lossy().to_string());
+    }
+
+    Ok(paths)
+}
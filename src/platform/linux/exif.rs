@@ -0,0 +1,134 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+const ORIENTATION: u16 = 0x0112;
+const MAKE: u16 = 0x010F;
+const MODEL: u16 = 0x0110;
+const EXIF_IFD: u16 = 0x8769;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+
+/// Tags read from the EXIF segment of a JPEG file
+#[derive(Debug, Default)]
+pub(crate) struct Exif {
+    pub orientation: Option<u16>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub date_taken: Option<String>,
+}
+
+/// Reads the APP1 segment without reading the image data. Returns None for files other than JPEG or without EXIF
+pub(crate) fn read_exif<P: AsRef<Path>>(path: P) -> Option<Exif> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+
+    let mut soi = [0u8; 2];
+    reader.read_exact(&mut soi).ok()?;
+    if soi != [0xFF, 0xD8] {
+        return None;
+    }
+
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).ok()?;
+        // Start of scan. Metadata segments come before it
+        if header[0] != 0xFF || header[1] == 0xDA {
+            return None;
+        }
+
+        let len = (u16::from_be_bytes([header[2], header[3]]) as usize).checked_sub(2)?;
+        let mut segment = vec![0u8; len];
+        reader.read_exact(&mut segment).ok()?;
+
+        if header[1] == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return parse_tiff(&segment[6..]);
+        }
+    }
+}
+
+fn parse_tiff(data: &[u8]) -> Option<Exif> {
+    let little_endian = match data.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let tiff = Tiff {
+        data,
+        little_endian,
+    };
+
+    let mut exif = Exif::default();
+    let ifd0 = tiff.u32(4)? as usize;
+    for (tag, field_type, count, offset) in tiff.entries(ifd0)? {
+        match tag {
+            ORIENTATION if field_type == TYPE_SHORT => exif.orientation = tiff.u16(offset + 8),
+            MAKE if field_type == TYPE_ASCII => exif.make = tiff.ascii(offset, count),
+            MODEL if field_type == TYPE_ASCII => exif.model = tiff.ascii(offset, count),
+            EXIF_IFD => {
+                let exif_ifd = tiff.u32(offset + 8)? as usize;
+                exif.date_taken = tiff
+                    .entries(exif_ifd)
+                    .and_then(|entries| entries.into_iter().find(|(tag, field_type, _, _)| *tag == DATE_TIME_ORIGINAL && *field_type == TYPE_ASCII))
+                    .and_then(|(_, _, count, offset)| tiff.ascii(offset, count));
+            }
+            _ => {}
+        }
+    }
+
+    Some(exif)
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = [*self.data.get(offset)?, *self.data.get(offset + 1)?];
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /* Tag, type, count and the offset of each 12 byte entry */
+    fn entries(&self, ifd: usize) -> Option<Vec<(u16, u16, u32, usize)>> {
+        let count = self.u16(ifd)? as usize;
+        (0..count)
+            .map(|index| {
+                let offset = ifd + 2 + index * 12;
+                Some((self.u16(offset)?, self.u16(offset + 2)?, self.u32(offset + 4)?, offset))
+            })
+            .collect()
+    }
+
+    /* Values up to 4 bytes are stored in the entry itself */
+    fn ascii(&self, entry: usize, count: u32) -> Option<String> {
+        let start = if count <= 4 {
+            entry + 8
+        } else {
+            self.u32(entry + 8)? as usize
+        };
+        let bytes = self.data.get(start..start + count as usize)?;
+        let value = String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string();
+        if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use super::util::{init, place_window};
+use crate::dialog::{DialogPlacement, FileFilter, OpenDialogOptions, OpenProperty, SaveDialogOptions};
+use gtk::{
+    prelude::{DialogExtManual, FileChooserExt, GtkWindowExt},
+    FileChooserAction, FileChooserDialog, ResponseType,
+};
+use std::path::{Path, PathBuf};
+
+/// Shows a GTK file chooser to open files or folders and returns the chosen paths, or None if cancelled
+///
+/// Unlike the chooser of rfd, the dialog is attached to the parent of the placement. Must be awaited on the GTK main thread
+pub(crate) async fn open(options: &OpenDialogOptions) -> Option<Vec<PathBuf>> {
+    init();
+
+    let (pick_file, multiple) = match &options.properties {
+        Some(properties) => (properties.contains(&OpenProperty::OpenFile), properties.contains(&OpenProperty::MultiSelections)),
+        None => (true, false),
+    };
+
+    let action = if pick_file {
+        FileChooserAction::Open
+    } else {
+        FileChooserAction::SelectFolder
+    };
+
+    let dialog = create(options.title.as_deref(), action, "_Open", &options.placement);
+    dialog.set_select_multiple(multiple);
+
+    if let Some(default_path) = options.default_path.as_ref().filter(|default_path| !default_path.is_empty()) {
+        let _ = dialog.set_current_folder(default_path);
+    }
+
+    if pick_file {
+        add_filters(&dialog, &options.filters);
+    }
+
+    let file_paths = run(&dialog).await.then(|| dialog.filenames());
+    dialog.close();
+    file_paths
+}
+
+/// Shows a GTK file chooser to save a file and returns the chosen path, or None if cancelled
+///
+/// Must be awaited on the GTK main thread
+pub(crate) async fn save(options: &SaveDialogOptions, directory: Option<&Path>, file_name: Option<&str>) -> Option<PathBuf> {
+    init();
+
+    let dialog = create(options.title.as_deref(), FileChooserAction::Save, "_Save", &options.placement);
+    dialog.set_do_overwrite_confirmation(true);
+
+    if let Some(directory) = directory.filter(|directory| !directory.as_os_str().is_empty()) {
+        let _ = dialog.set_current_folder(directory);
+    }
+
+    if let Some(file_name) = file_name {
+        dialog.set_current_name(file_name);
+    }
+
+    add_filters(&dialog, &options.filters);
+
+    let file_path = if run(&dialog).await {
+        dialog.filename()
+    } else {
+        None
+    };
+    dialog.close();
+    file_path
+}
+
+fn create(title: Option<&str>, action: FileChooserAction, accept_label: &str, placement: &Option<DialogPlacement>) -> FileChooserDialog {
+    let dialog = FileChooserDialog::with_buttons(title, gtk::Window::NONE, action, &[("_Cancel", ResponseType::Cancel), (accept_label, ResponseType::Accept)]);
+    dialog.set_modal(true);
+
+    if let Some(placement) = placement {
+        place_window(&dialog, placement);
+    }
+
+    dialog
+}
+
+/* Patterns of GTK are case-sensitive, so each extension is matched in lower and upper case as Windows would */
+fn add_filters(dialog: &FileChooserDialog, filters: &Option<Vec<FileFilter>>) {
+    let Some(filters) = filters else {
+        return;
+    };
+
+    for filter in filters {
+        let file_filter = gtk::FileFilter::new();
+        file_filter.set_name(Some(&filter.name));
+        for extension in &filter.extensions {
+            file_filter.add_pattern(&format!("*.{}", extension.to_lowercase()));
+            file_filter.add_pattern(&format!("*.{}", extension.to_uppercase()));
+        }
+        dialog.add_filter(file_filter);
+    }
+}
+
+async fn run(dialog: &FileChooserDialog) -> bool {
+    dialog.run_future().await == ResponseType::Accept
+}
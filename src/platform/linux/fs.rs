@@ -1,17 +1,60 @@
-use crate::{platform::linux::fs_ext::execute_file_operation, Dirent, FileAttribute, RecycleBinDirent, RecycleBinItem, Volume};
-use gtk::gio::{self, traits::FileExt, Cancellable, File, FileCopyFlags, FileEnumerator, FileInfo, FileQueryInfoFlags, FileType};
-use libc::{timespec, utimensat, AT_FDCWD};
+pub(crate) use super::fs_ext::cancel_operations;
+#[cfg(feature = "listing")]
+pub use crate::listing::{export_listing, import_listing};
+pub use crate::transaction::Transaction;
+use crate::{
+    compare::compare_readers,
+    platform::linux::fs_ext::{execute_file_operation, execute_file_operation_headless, execute_traced_file_operation},
+    AccessMask, CompareMode, CompletionNotice, DirListing, DirPage, Dirent, FileAttribute, FileComparison, FileHash, HashAlgorithm, MkdirOptions, OperationEvent, PreflightReport, ProgressThrottle,
+    ReaddirOptions, RecycleBinDirent, RecycleBinInfo, RecycleBinItem, RetryOptions, SetAttributeOptions, UndeleteConflict, Volume,
+};
+use gtk::{
+    gio::{
+        self, prelude::FileMonitorExt, traits::CancellableExt, traits::FileExt, Cancellable, File, FileCopyFlags, FileEnumerator, FileInfo, FileMonitor, FileMonitorEvent, FileMonitorFlags,
+        FileQueryInfoFlags, FileType,
+    },
+    glib::{prelude::ObjectExt, SignalHandlerId},
+};
+use libc::{faccessat, geteuid, timespec, utimensat, AT_EACCESS, AT_FDCWD, NAME_MAX, PATH_MAX, R_OK, S_ISVTX, W_OK, X_OK};
 use serde_json::Value;
-use std::{collections::HashMap, ffi::CString, path::Path};
-
-const ATTRIBUTES: &str = "filesystem::readonly,standard::is-hidden,standard::is-symlink,standard::name,standard::size,standard::type,time::*,dos::is-system,standard::symlink-target";
+use smol::channel::{Receiver, Sender};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    io::Write,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+};
+
+const ATTRIBUTES: &str =
+    "filesystem::readonly,access::can-write,standard::is-hidden,standard::is-symlink,standard::name,standard::size,standard::type,time::*,dos::is-system,standard::symlink-target,xattr::xdg.robots.index";
 const ATTRIBUTES_FOR_RECYCLE: &str =
-    "trash::orig-path,trash::deletion-date,filesystem::readonly,standard::is-hidden,standard::is-symlink,standard::name,standard::size,standard::type,time::*,dos::is-system,standard::symlink-target";
+    "trash::orig-path,trash::deletion-date,filesystem::readonly,access::can-write,standard::is-hidden,standard::is-symlink,standard::name,standard::size,standard::type,time::*,dos::is-system,standard::symlink-target";
+
+static NEXT_CURSOR: AtomicU64 = AtomicU64::new(1);
+/* Started on first use of readdir_paged and kept for the life of the process */
+static DIRECTORY_THREAD: LazyLock<Result<std::sync::mpsc::Sender<DirectoryJob>, String>> = LazyLock::new(start_directory_thread);
+
+type DirectoryJob = Box<dyn FnOnce() + Send>;
+
+thread_local! {
+    // GIO enumerators must stay on the thread that created them, so they live on the directory thread
+    static OPEN_DIRECTORIES: RefCell<HashMap<String, OpenDirectory>> = RefCell::new(HashMap::new());
+    static RECYCLE_BIN_WATCHER: RefCell<Option<(FileMonitor, SignalHandlerId)>> = const { RefCell::new(None) };
+}
 
 /// Lists volumes
 pub fn list_volumes() -> Result<Vec<Volume>, String> {
     let mut volumes = Vec::new();
-    let output = std::process::Command::new("lsblk").args(["-ba", "--json", "-o", "NAME,TYPE,FSTYPE,LABEL,VENDOR,MODEL,SIZE,MOUNTPOINT,FSAVAIL"]).output().map_err(|e| e.to_string())?;
+    let output = std::process::Command::new("lsblk")
+        .args(["-ba", "--json", "-o", "NAME,PATH,TYPE,FSTYPE,LABEL,VENDOR,MODEL,SIZE,MOUNTPOINT,FSAVAIL,RM,RO,TRAN"])
+        .output()
+        .map_err(|e| e.to_string())?;
     let data: Value = serde_json::from_str(std::str::from_utf8(&output.stdout).unwrap()).map_err(|e| e.to_string())?;
     let drives: Vec<&Value> = data["blockdevices"].as_array().unwrap().iter().filter(|dev| dev["type"].as_str().unwrap_or_default() == "disk").collect();
     let exclude_mount_points = ["boot", "[SWAP]", "swap"];
@@ -20,10 +63,15 @@ pub fn list_volumes() -> Result<Vec<Volume>, String> {
         let mut available_units = 0;
         let mut total_units = 0;
         let mut mount_point = String::new();
+        let mut file_system = String::new();
+        let mut device_path = String::new();
+        let mut is_read_only = to_bool(&drive["ro"]);
 
         if drive["children"].is_null() {
             let drive_mount_point = drive["mountpoint"].as_str().unwrap_or_default();
             mount_point = drive_mount_point.to_string();
+            file_system = drive["fstype"].as_str().unwrap_or_default().to_string();
+            device_path = drive["path"].as_str().unwrap_or_default().to_string();
             total_units += drive["size"].as_u64().unwrap_or_default();
             available_units += drive["fsavail"].as_u64().unwrap_or_default();
         } else {
@@ -31,6 +79,9 @@ pub fn list_volumes() -> Result<Vec<Volume>, String> {
                 let child_mount_point = child["mountpoint"].as_str().unwrap_or_default();
                 if !exclude_mount_points.iter().any(|p| child_mount_point.contains(p)) {
                     mount_point = child_mount_point.to_string();
+                    file_system = child["fstype"].as_str().unwrap_or_default().to_string();
+                    device_path = child["path"].as_str().unwrap_or_default().to_string();
+                    is_read_only |= to_bool(&child["ro"]);
                 }
                 total_units += child["size"].as_u64().unwrap_or_default();
                 available_units += child["fsavail"].as_u64().unwrap_or_default();
@@ -65,88 +116,384 @@ pub fn list_volumes() -> Result<Vec<Volume>, String> {
             volume_label,
             available_units,
             total_units,
+            is_network: is_network_file_system(&file_system),
+            file_system,
+            is_removable: to_bool(&drive["rm"]),
+            is_read_only,
+            device_path,
+            bus_type: drive["tran"].as_str().unwrap_or_default().to_string(),
         });
     }
 
+    volumes.extend(list_network_volumes()?);
+
     Ok(volumes)
 }
 
+/* Network mounts have no block device, so lsblk does not list them */
+fn list_network_volumes() -> Result<Vec<Volume>, String> {
+    let mount_info = std::fs::read_to_string("/proc/self/mountinfo").map_err(|e| e.to_string())?;
+    let mut volumes: Vec<Volume> = Vec::new();
+
+    // Each line is "id parent major:minor root mount-point options [optional fields] - fstype source super-options"
+    for line in mount_info.lines() {
+        let Some((mount, file_system)) = line.split_once(" - ") else {
+            continue;
+        };
+        let mount: Vec<&str> = mount.split(' ').collect();
+        let file_system: Vec<&str> = file_system.split(' ').collect();
+        if mount.len() < 6 || file_system.len() < 2 || !is_network_file_system(file_system[0]) {
+            continue;
+        }
+
+        let mount_point = unescape_mount_field(mount[4]);
+        if volumes.iter().any(|volume| volume.mount_point == mount_point) {
+            continue;
+        }
+
+        let source = unescape_mount_field(file_system[1]);
+        let (available_units, total_units) = file_system_size(&mount_point);
+        volumes.push(Volume {
+            volume_label: source.clone(),
+            available_units,
+            total_units,
+            file_system: file_system[0].to_string(),
+            is_removable: false,
+            is_network: true,
+            is_read_only: mount[5].split(',').any(|option| option == "ro"),
+            device_path: source,
+            bus_type: String::new(),
+            mount_point,
+        });
+    }
+
+    Ok(volumes)
+}
+
+/* mountinfo escapes space, tab, newline and backslash as octal */
+fn unescape_mount_field(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'\\' && tail.len() >= 3 && tail[..3].iter().all(|digit| (b'0'..=b'7').contains(digit)) {
+            bytes.push((tail[0] - b'0') * 64 + (tail[1] - b'0') * 8 + (tail[2] - b'0'));
+            rest = &tail[3..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/* Returns (available, total) bytes, zero if the mount does not respond */
+fn file_system_size(mount_point: &str) -> (u64, u64) {
+    let Ok(path) = CString::new(mount_point) else {
+        return (0, 0);
+    };
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return (0, 0);
+    }
+    (stat.f_bavail as u64 * stat.f_frsize as u64, stat.f_blocks as u64 * stat.f_frsize as u64)
+}
+
+/// Returns true if the Linux file system type, as in /proc/self/mountinfo, is backed by remote storage, such as NFS, SMB/CIFS, SSHFS or 9P
+pub fn is_network_file_system(file_system: &str) -> bool {
+    const NETWORK_FILE_SYSTEMS: [&str; 13] = ["cifs", "smb3", "smbfs", "ncpfs", "9p", "afs", "ceph", "glusterfs", "lustre", "davfs", "fuse.sshfs", "fuse.rclone", "fuse.s3fs"];
+    let file_system = file_system.to_lowercase();
+    file_system.starts_with("nfs") || NETWORK_FILE_SYSTEMS.contains(&file_system.as_str())
+}
+
+/* Older lsblk prints flags as "0"/"1" instead of booleans */
+fn to_bool(value: &Value) -> bool {
+    value.as_bool().unwrap_or_else(|| value.as_str() == Some("1"))
+}
+
 /// Lists all files/directories under the specified directory
 pub fn readdir<P: AsRef<Path>>(directory: P, recursive: bool, with_mime_type: bool) -> Result<Vec<Dirent>, String> {
+    readdir_with(
+        directory,
+        recursive,
+        &ReaddirOptions {
+            with_mime: with_mime_type,
+            ..Default::default()
+        },
+    )
+}
+
+/// Lists all files/directories under the specified directory, reading the values requested in the options in the same pass
+pub fn readdir_with<P: AsRef<Path>>(directory: P, recursive: bool, options: &ReaddirOptions) -> Result<Vec<Dirent>, String> {
     if !directory.as_ref().is_dir() {
         return Ok(Vec::new());
     }
 
     let file = File::for_path(directory.as_ref());
 
+    let mut attributes = ATTRIBUTES.to_string();
+    if options.with_icon {
+        attributes.push_str(",standard::icon");
+    }
+    if options.with_owner {
+        attributes.push_str(",owner::user");
+    }
+
     let mut entries = Vec::new();
-    try_readdir(file, &mut entries, recursive, with_mime_type)?;
+    try_readdir(file, &attributes, &mut entries, recursive, options)?;
 
     Ok(entries)
 }
 
-fn try_readdir(dir: File, entries: &mut Vec<Dirent>, recursive: bool, with_mime_type: bool) -> Result<&mut Vec<Dirent>, String> {
-    for info in dir.enumerate_children(ATTRIBUTES, FileQueryInfoFlags::NOFOLLOW_SYMLINKS, Cancellable::NONE).unwrap().flatten() {
-        let name = info.name();
-        let mut full_path = dir.path().unwrap().to_path_buf();
-        full_path.push(name.clone());
-
-        let full_path_string = full_path.to_string_lossy().to_string();
-        let attributes = to_file_attribute(&info);
-
-        let mime_type = if with_mime_type {
-            get_mime_type(if attributes.is_symbolic_link {
-                &attributes.link_path
-            } else {
-                &full_path_string
-            })
-        } else {
-            String::new()
-        };
+/// Lists entries in the directory as a compact `DirListing`
+pub fn readdir_listing<P: AsRef<Path>>(directory: P, recursive: bool, with_mime_type: bool) -> Result<DirListing, String> {
+    let entries = readdir(directory.as_ref(), recursive, with_mime_type)?;
+    Ok(DirListing::new(directory, entries))
+}
 
-        entries.push(Dirent {
-            name: name.file_name().unwrap_or_default().to_string_lossy().to_string(),
-            parent_path: dir.path().unwrap().to_string_lossy().to_string(),
-            full_path: full_path_string,
-            attributes,
-            mime_type,
-        });
+fn try_readdir<'a>(dir: File, attributes: &str, entries: &'a mut Vec<Dirent>, recursive: bool, options: &ReaddirOptions) -> Result<&'a mut Vec<Dirent>, String> {
+    for info in dir.enumerate_children(attributes, FileQueryInfoFlags::NOFOLLOW_SYMLINKS, Cancellable::NONE).unwrap().flatten() {
+        let entry = info_to_dirent(&dir.path().unwrap(), &info, options);
+        let full_path = entry.full_path.clone();
+        entries.push(entry);
 
         if info.file_type() == FileType::Directory && recursive {
             let next_dir = File::for_path(full_path);
-            try_readdir(next_dir, entries, recursive, with_mime_type)?;
+            try_readdir(next_dir, attributes, entries, recursive, options)?;
         }
     }
 
     Ok(entries)
 }
 
+fn info_to_dirent(parent: &Path, info: &FileInfo, options: &ReaddirOptions) -> Dirent {
+    let name = info.name();
+    let full_path = parent.join(&name);
+
+    let full_path_string = full_path.to_string_lossy().to_string();
+    let attributes = to_file_attribute(info, Some(parent));
+
+    let mime_type = if options.with_mime {
+        get_mime_type(if attributes.is_symbolic_link {
+            &attributes.link_path
+        } else {
+            &full_path_string
+        })
+    } else {
+        String::new()
+    };
+
+    let link_target = if options.with_link_target && attributes.is_symbolic_link {
+        Some(resolve_link_target(parent, &attributes.link_path))
+    } else {
+        None
+    };
+
+    Dirent {
+        name: name.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        parent_path: parent.to_string_lossy().to_string(),
+        full_path: full_path_string,
+        attributes,
+        mime_type,
+        // Getters of attributes that were not queried print warnings
+        icon: options.with_icon.then(|| info.icon().and_then(|icon| to_icon_name(&icon))).flatten(),
+        link_target,
+        owner: options.with_owner.then(|| info.attribute_string("owner::user").map(|owner| owner.to_string())).flatten(),
+    }
+}
+
+/* Themed icons are named by the most specific name, file icons by the path */
+fn to_icon_name(icon: &gio::Icon) -> Option<String> {
+    use gtk::glib::Cast;
+
+    if let Some(icon) = icon.downcast_ref::<gio::ThemedIcon>() {
+        return icon.names().first().map(|name| name.to_string());
+    }
+    icon.downcast_ref::<gio::FileIcon>().and_then(|icon| icon.file().path()).map(|path| path.to_string_lossy().to_string())
+}
+
+/* Relative targets are relative to the directory of the link. A broken link resolves to its target as it is */
+fn resolve_link_target(parent: &Path, link_path: &str) -> String {
+    let target = parent.join(link_path);
+    std::fs::canonicalize(&target).unwrap_or(target).to_string_lossy().to_string()
+}
+
+struct OpenDirectory {
+    directory: PathBuf,
+    enumerator: FileEnumerator,
+}
+
+/// Lists entries in the directory a page at a time, keeping the directory open between calls
+///
+/// Pass `None` as the cursor to start listing and the returned cursor to continue. The cursor is `None` when the listing is complete.
+/// Cursors can be continued or closed from any thread
+pub fn readdir_paged<P: AsRef<Path>>(directory: P, cursor: Option<&str>, page_size: usize, with_mime_type: bool) -> Result<DirPage, String> {
+    let directory = directory.as_ref().to_path_buf();
+    let cursor = cursor.map(|cursor| cursor.to_string());
+    on_directory_thread(move || read_page(&directory, cursor.as_deref(), page_size, with_mime_type))?
+}
+
+fn read_page(directory: &Path, cursor: Option<&str>, page_size: usize, with_mime_type: bool) -> Result<DirPage, String> {
+    let (cursor, open) = match cursor {
+        Some(cursor) => {
+            let open = OPEN_DIRECTORIES.with_borrow_mut(|directories| directories.remove(cursor)).ok_or("Invalid cursor")?;
+            if open.directory != directory {
+                OPEN_DIRECTORIES.with_borrow_mut(|directories| directories.insert(cursor.to_string(), open));
+                return Err("Cursor does not belong to the directory".to_string());
+            }
+            (cursor.to_string(), open)
+        }
+        None => {
+            let enumerator = File::for_path(directory).enumerate_children(ATTRIBUTES, FileQueryInfoFlags::NOFOLLOW_SYMLINKS, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+            (
+                NEXT_CURSOR.fetch_add(1, Ordering::SeqCst).to_string(),
+                OpenDirectory {
+                    directory: directory.to_path_buf(),
+                    enumerator,
+                },
+            )
+        }
+    };
+
+    let options = ReaddirOptions {
+        with_mime: with_mime_type,
+        ..Default::default()
+    };
+    let mut entries = Vec::new();
+    let mut finished = false;
+
+    while entries.len() < page_size.max(1) {
+        match open.enumerator.next_file(Cancellable::NONE) {
+            Ok(Some(info)) => entries.push(info_to_dirent(&open.directory, &info, &options)),
+            Ok(None) => {
+                finished = true;
+                break;
+            }
+            Err(e) => {
+                let _ = open.enumerator.close(Cancellable::NONE);
+                return Err(e.message().to_string());
+            }
+        }
+    }
+
+    if finished {
+        open.enumerator.close(Cancellable::NONE).map_err(|e| e.message().to_string())?;
+        return Ok(DirPage {
+            entries,
+            cursor: None,
+        });
+    }
+
+    OPEN_DIRECTORIES.with_borrow_mut(|directories| directories.insert(cursor.clone(), open));
+
+    Ok(DirPage {
+        entries,
+        cursor: Some(cursor),
+    })
+}
+
+/// Closes the directory kept open by `readdir_paged` before the listing is complete
+pub fn close_readdir_cursor(cursor: &str) -> Result<(), String> {
+    let cursor = cursor.to_string();
+    on_directory_thread(move || {
+        if let Some(open) = OPEN_DIRECTORIES.with_borrow_mut(|directories| directories.remove(&cursor)) {
+            open.enumerator.close(Cancellable::NONE).map_err(|e| e.message().to_string())?;
+        }
+        Ok(())
+    })?
+}
+
+fn start_directory_thread() -> Result<std::sync::mpsc::Sender<DirectoryJob>, String> {
+    let (tx, rx) = std::sync::mpsc::channel::<DirectoryJob>();
+
+    std::thread::Builder::new()
+        .name("zouni-readdir".to_string())
+        .spawn(move || {
+            for job in rx {
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+            }
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(tx)
+}
+
+/* Runs the closure on the thread that owns the open directories and waits for its result */
+fn on_directory_thread<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, String> {
+    let jobs = DIRECTORY_THREAD.as_ref().map_err(|e| e.clone())?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    jobs.send(Box::new(move || {
+        let _ = tx.send(f());
+    }))
+    .map_err(|_| "The directory thread has stopped".to_string())?;
+    rx.recv().map_err(|_| "The job panicked on the directory thread".to_string())
+}
+
 /// Gets file/directory attributes
+///
+/// For a symbolic link, the attributes are those of its target except is_symbolic_link and link_path, which describe the link
 pub fn stat<P: AsRef<Path>>(file_path: P) -> Result<FileAttribute, String> {
     let file = File::for_path(file_path.as_ref());
-    let info = file.query_info(ATTRIBUTES, FileQueryInfoFlags::NONE, Cancellable::NONE).map_err(|e| e.message().to_string())?;
-    Ok(to_file_attribute(&info))
+    let parent = file_path.as_ref().parent();
+    let link_info = file.query_info(ATTRIBUTES, FileQueryInfoFlags::NOFOLLOW_SYMLINKS, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    if !link_info.is_symlink() {
+        return Ok(to_file_attribute(&link_info, parent));
+    }
+
+    // Size, times and read-only state come from the link target. A broken link has only the link itself
+    let Ok(target_info) = file.query_info(ATTRIBUTES, FileQueryInfoFlags::NONE, Cancellable::NONE) else {
+        return Ok(to_file_attribute(&link_info, parent));
+    };
+
+    let mut attributes = to_file_attribute(&target_info, parent);
+    attributes.is_symbolic_link = true;
+    attributes.link_path = resolve_symlink_target(&link_info, parent);
+    Ok(attributes)
 }
 
-fn to_file_attribute(info: &FileInfo) -> FileAttribute {
+fn to_file_attribute(info: &FileInfo, parent: Option<&Path>) -> FileAttribute {
+    let link_path = if info.is_symlink() {
+        resolve_symlink_target(info, parent)
+    } else {
+        String::new()
+    };
+
+    // Report the type of the link target as on Windows
+    let (is_directory, is_file) = if info.is_symlink() {
+        (Path::new(&link_path).is_dir(), Path::new(&link_path).is_file())
+    } else {
+        (info.file_type() == FileType::Directory, info.file_type() == FileType::Regular)
+    };
+
     FileAttribute {
-        is_directory: info.file_type() == FileType::Directory,
-        is_read_only: info.boolean("filesystem::readonly"),
+        is_directory,
+        is_read_only: info.boolean("filesystem::readonly") || (info.has_attribute("access::can-write") && !info.boolean("access::can-write")),
         is_hidden: info.is_hidden(),
         is_system: info.boolean("dos::is-system"),
         is_device: info.file_type() == FileType::Mountable,
-        is_file: info.file_type() == FileType::Regular,
+        is_file,
         is_symbolic_link: info.is_symlink(),
+        is_junction: false,
+        // The "user." prefix is dropped in the xattr namespace of gio
+        is_not_indexed: info.attribute_as_string("xattr::xdg.robots.index").is_some_and(|value| value.as_str() == "false"),
         ctime_ms: to_msecs(info.attribute_uint64("time::changed"), info.attribute_uint32("time::changed-usec")),
         mtime_ms: to_msecs(info.attribute_uint64("time::modified"), info.attribute_uint32("time::modified-usec")),
         atime_ms: to_msecs(info.attribute_uint64("time::access"), info.attribute_uint32("time::access-usec")),
         birthtime_ms: to_msecs(info.attribute_uint64("time::created"), info.attribute_uint32("time::created-usec")),
         size: info.size() as u64,
-        link_path: if info.is_symlink() {
-            info.symlink_target().unwrap_or_default().to_string_lossy().to_string()
-        } else {
-            String::new()
-        },
+        link_path,
+    }
+}
+
+/* standard::symlink-target may be relative to the directory containing the link */
+fn resolve_symlink_target(info: &FileInfo, parent: Option<&Path>) -> String {
+    let target = info.symlink_target().unwrap_or_default();
+    if target.is_absolute() {
+        return target.to_string_lossy().to_string();
+    }
+
+    match parent {
+        Some(parent) => parent.join(&target).to_string_lossy().to_string(),
+        None => target.to_string_lossy().to_string(),
     }
 }
 
@@ -155,11 +502,302 @@ fn to_msecs(secs: u64, microsecs: u32) -> u64 {
 }
 
 /// Create shortcut
+///
+/// Creates a symbolic link at `full_path` pointing to `link_path`
 pub fn create_symlink<P1: AsRef<Path>, P2: AsRef<Path>>(full_path: P1, link_path: P2) -> Result<(), String> {
+    if full_path.as_ref().symlink_metadata().is_ok() {
+        return Err(format!("File already exists:{}", full_path.as_ref().to_string_lossy()));
+    }
+
     let file = gio::File::for_path(full_path);
     file.make_symbolic_link(link_path, Cancellable::NONE).map_err(|e| e.message().to_string())
 }
 
+/// Creates a hard link at `link_path` to the existing file
+pub fn create_hardlink<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, link_path: P2) -> Result<(), String> {
+    std::fs::hard_link(file_path, link_path).map_err(|e| e.to_string())
+}
+
+const USER_NAMESPACE: &str = "user.";
+// Common extended attribute defined by freedesktop.org
+const ROBOTS_INDEX: &str = "user.xdg.robots.index";
+const TRACKER_IGNORE: &str = ".trackerignore";
+
+/// Lists the names of the extended attributes of the file with their namespace such as "user.xdg.origin.url"
+pub fn list_xattrs<P: AsRef<Path>>(file_path: P) -> Result<Vec<String>, String> {
+    let path = CString::new(file_path.as_ref().to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+
+    let size = unsafe { libc::listxattr(path.as_ptr(), std::ptr::null_mut(), 0) };
+    if size < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    let mut buffer = vec![0u8; size as usize];
+    let size = unsafe { libc::listxattr(path.as_ptr(), buffer.as_mut_ptr() as _, buffer.len()) };
+    if size < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    // Null separated names
+    Ok(buffer[..size as usize].split(|byte| *byte == 0).filter(|name| !name.is_empty()).map(|name| String::from_utf8_lossy(name).to_string()).collect())
+}
+
+/// Reads the value of the extended attribute
+pub fn get_xattr<P: AsRef<Path>>(file_path: P, name: &str) -> Result<Vec<u8>, String> {
+    let path = CString::new(file_path.as_ref().to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    let name = CString::new(name).map_err(|e| e.to_string())?;
+
+    let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+    if size < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    let mut buffer = vec![0u8; size as usize];
+    let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buffer.as_mut_ptr() as _, buffer.len()) };
+    if size < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    buffer.truncate(size as usize);
+    Ok(buffer)
+}
+
+/// Creates or replaces the extended attribute
+///
+/// Only the "user" namespace is writable without privileges
+pub fn set_xattr<P: AsRef<Path>>(file_path: P, name: &str, value: &[u8]) -> Result<(), String> {
+    let path = CString::new(file_path.as_ref().to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    let name = CString::new(name).map_err(|e| e.to_string())?;
+
+    if unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr() as _, value.len(), 0) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    Ok(())
+}
+
+/// Removes the extended attribute
+pub fn remove_xattr<P: AsRef<Path>>(file_path: P, name: &str) -> Result<(), String> {
+    let path = CString::new(file_path.as_ref().to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    let name = CString::new(name).map_err(|e| e.to_string())?;
+
+    if unsafe { libc::removexattr(path.as_ptr(), name.as_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    Ok(())
+}
+
+/// Excludes the item from or includes it back in search indexing, as "Allow files in this folder to have contents indexed" does
+///
+/// On Linux, sets "user.xdg.robots.index" to "false" and, for a directory, adds `.trackerignore` honored by Tracker and LocalSearch
+pub fn set_no_index<P: AsRef<Path>>(file_path: P, no_index: bool) -> Result<(), String> {
+    let tracker_ignore = file_path.as_ref().join(TRACKER_IGNORE);
+
+    if no_index {
+        set_xattr(&file_path, ROBOTS_INDEX, b"false")?;
+        if file_path.as_ref().is_dir() && !tracker_ignore.exists() {
+            std::fs::write(&tracker_ignore, b"").map_err(|e| e.to_string())?;
+        }
+    } else {
+        if get_xattr(&file_path, ROBOTS_INDEX).is_ok() {
+            remove_xattr(&file_path, ROBOTS_INDEX)?;
+        }
+        if tracker_ignore.is_file() {
+            std::fs::remove_file(&tracker_ignore).map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the names of the data streams stored with the file
+///
+/// On Linux, the extended attributes in the "user" namespace without the "user." prefix. On Windows, NTFS alternate data streams such as "Zone.Identifier"
+pub fn list_streams<P: AsRef<Path>>(file_path: P) -> Result<Vec<String>, String> {
+    Ok(list_xattrs(file_path)?.into_iter().filter_map(|name| name.strip_prefix(USER_NAMESPACE).map(|name| name.to_string())).collect())
+}
+
+/// Reads the data stream stored with the file
+pub fn read_stream<P: AsRef<Path>>(file_path: P, name: &str) -> Result<Vec<u8>, String> {
+    get_xattr(file_path, &format!("{}{}", USER_NAMESPACE, name))
+}
+
+/// Creates or replaces the data stream stored with the file
+///
+/// On Linux, the size is limited by the file system, typically to 4KB on ext4
+pub fn write_stream<P: AsRef<Path>>(file_path: P, name: &str, data: &[u8]) -> Result<(), String> {
+    set_xattr(file_path, &format!("{}{}", USER_NAMESPACE, name), data)
+}
+
+/// Removes the data stream stored with the file
+pub fn remove_stream<P: AsRef<Path>>(file_path: P, name: &str) -> Result<(), String> {
+    remove_xattr(file_path, &format!("{}{}", USER_NAMESPACE, name))
+}
+
+/// Creates a directory
+///
+/// Fails if the parent directory does not exist
+pub fn mkdir<P: AsRef<Path>>(directory: P, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+    let file = File::for_path(directory.as_ref());
+    file.make_directory(Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    apply_mkdir_options(directory.as_ref(), &options.unwrap_or_default())?;
+    to_dirent(directory)
+}
+
+/// Creates a directory and all of its missing parent directories
+///
+/// Options are applied to the last directory only
+pub fn mkdir_all<P: AsRef<Path>>(directory: P, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+    if directory.as_ref().is_dir() {
+        return to_dirent(directory);
+    }
+
+    let file = File::for_path(directory.as_ref());
+    file.make_directory_with_parents(Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    apply_mkdir_options(directory.as_ref(), &options.unwrap_or_default())?;
+    to_dirent(directory)
+}
+
+fn apply_mkdir_options(directory: &Path, options: &MkdirOptions) -> Result<(), String> {
+    set_attributes(
+        directory,
+        SetAttributeOptions {
+            is_hidden: Some(options.is_hidden).filter(|hidden| *hidden),
+            is_read_only: Some(options.is_read_only).filter(|read_only| *read_only),
+            ..Default::default()
+        },
+    )
+}
+
+/// Changes file/directory attributes
+///
+/// On Linux, an item is hidden by listing it in the ".hidden" file of its parent directory and `is_system` has no effect
+pub fn set_attributes<P: AsRef<Path>>(file_path: P, options: SetAttributeOptions) -> Result<(), String> {
+    let file = File::for_path(file_path.as_ref());
+
+    if let Some(hidden) = options.is_hidden {
+        set_hidden(file_path.as_ref(), hidden)?;
+    }
+
+    if let Some(read_only) = options.is_read_only {
+        let info = file.query_info("unix::mode", FileQueryInfoFlags::NONE, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+        let mode = info.attribute_uint32("unix::mode");
+        let mode = if read_only {
+            mode & !0o222
+        } else {
+            mode | 0o200
+        };
+        file.set_attribute_uint32("unix::mode", mode, FileQueryInfoFlags::NONE, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    }
+
+    if let Some(backup_excluded) = options.is_backup_excluded {
+        // https://www.freedesktop.org/wiki/CommonExtendedAttributes/
+        let value = if backup_excluded {
+            "false"
+        } else {
+            "true"
+        };
+        file.set_attribute_string("xattr::xdg.robots.backup", value, FileQueryInfoFlags::NONE, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Checks if the current user has all the requested access to the file/directory without performing the operation
+pub fn access<P: AsRef<Path>>(file_path: P, mask: AccessMask) -> Result<bool, String> {
+    let mut mode = 0;
+    if mask.read {
+        mode |= R_OK;
+    }
+    if mask.write {
+        mode |= W_OK;
+    }
+    if mask.execute {
+        mode |= X_OK;
+    }
+
+    if mode != 0 && !has_access(file_path.as_ref(), mode)? {
+        return Ok(false);
+    }
+
+    if mask.delete {
+        return can_delete(file_path.as_ref());
+    }
+
+    Ok(true)
+}
+
+fn has_access(file_path: &Path, mode: i32) -> Result<bool, String> {
+    let path = CString::new(file_path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    // AT_EACCESS checks with the effective user instead of the real user
+    if unsafe { faccessat(AT_FDCWD, path.as_ptr(), mode, AT_EACCESS) } == 0 {
+        return Ok(true);
+    }
+
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EROFS) | Some(libc::ETXTBSY) | Some(libc::EPERM) => Ok(false),
+        _ => Err(error.to_string()),
+    }
+}
+
+/* Deleting an entry requires write and search permission on its parent. In a sticky directory, only the owner of the entry or the directory can delete it */
+fn can_delete(file_path: &Path) -> Result<bool, String> {
+    let metadata = std::fs::symlink_metadata(file_path).map_err(|e| e.to_string())?;
+    let parent = file_path.parent().ok_or("No parent directory found")?;
+
+    if !has_access(parent, W_OK | X_OK)? {
+        return Ok(false);
+    }
+
+    let parent_metadata = std::fs::metadata(parent).map_err(|e| e.to_string())?;
+    if parent_metadata.mode() & S_ISVTX != 0 {
+        let uid = unsafe { geteuid() };
+        return Ok(uid == 0 || uid == metadata.uid() || uid == parent_metadata.uid());
+    }
+
+    Ok(true)
+}
+
+/* Items listed in the parent's ".hidden" file are reported as hidden by GIO */
+fn set_hidden(file_path: &Path, hidden: bool) -> Result<(), String> {
+    let name = file_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    if name.starts_with('.') {
+        return Ok(());
+    }
+
+    let hidden_file_path = file_path.parent().ok_or("No parent directory found")?.join(".hidden");
+    let content = std::fs::read_to_string(&hidden_file_path).unwrap_or_default();
+    let mut names: Vec<&str> = content.lines().filter(|line| !line.is_empty() && *line != name).collect();
+    if hidden {
+        names.push(&name);
+    }
+
+    let mut hidden_file = std::fs::File::create(&hidden_file_path).map_err(|e| e.to_string())?;
+    names.iter().try_for_each(|name| writeln!(hidden_file, "{}", name)).map_err(|e| e.to_string())
+}
+
+fn to_dirent<P: AsRef<Path>>(file_path: P) -> Result<Dirent, String> {
+    let attributes = stat(file_path.as_ref())?;
+    let mime_type = if attributes.is_file {
+        get_mime_type(file_path.as_ref())
+    } else {
+        String::new()
+    };
+
+    Ok(Dirent {
+        name: file_path.as_ref().file_name().unwrap_or_default().to_string_lossy().to_string(),
+        parent_path: file_path.as_ref().parent().unwrap_or(Path::new("")).to_string_lossy().to_string(),
+        full_path: file_path.as_ref().to_string_lossy().to_string(),
+        attributes,
+        mime_type,
+        icon: None,
+        link_target: None,
+        owner: None,
+    })
+}
+
 /// Gets mime type of the file
 pub fn get_mime_type<P: AsRef<Path>>(file_path: P) -> String {
     match mime_guess::from_path(file_path).first() {
@@ -197,9 +835,9 @@ fn handle_directory<P1: AsRef<Path>, P2: AsRef<Path>>(is_copy: bool, from: P1, t
             let from_file = from.as_ref().to_path_buf().join(info.name());
             println!("here:{:?} vs {:?}", from_file, to_dr);
             if is_copy {
-                copy(from_file, to_dr.clone())
+                copy_item(&from_file, &to_dr)
             } else {
-                mv(from_file, to_dr.clone())
+                move_item(&from_file, &to_dr)
             }
         })
     } else {
@@ -209,113 +847,318 @@ fn handle_directory<P1: AsRef<Path>, P2: AsRef<Path>>(is_copy: bool, from: P1, t
 
 /// Moves an item
 pub fn mv<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2) -> Result<(), String> {
-    let source = File::for_path(from.as_ref());
-    let dest_path = to.as_ref().join(from.as_ref().file_name().unwrap());
+    crate::policy::check_transfer(&[from.as_ref()], to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Move, &[from.as_ref()], Some(to.as_ref()))?;
+    move_item(from.as_ref(), to.as_ref())?;
+    crate::history::record(crate::history::HistoryAction::Move, &[from.as_ref()], Some(to.as_ref()));
+    Ok(())
+}
+
+fn move_item(from: &Path, to: &Path) -> Result<(), String> {
+    let source = File::for_path(from);
+    let dest_path = to.join(from.file_name().unwrap());
     let dest = File::for_path(&dest_path);
 
-    if from.as_ref().is_dir() {
+    if from.is_dir() {
         handle_directory(false, from, to)
     } else {
-        source.move_(&dest, FileCopyFlags::ALL_METADATA | FileCopyFlags::NOFOLLOW_SYMLINKS | FileCopyFlags::OVERWRITE, Cancellable::NONE, None).map_err(|e| e.message().to_string())
+        #[cfg(feature = "audit")]
+        let overwrites = dest_path.exists();
+        source.move_(&dest, FileCopyFlags::ALL_METADATA | FileCopyFlags::NOFOLLOW_SYMLINKS | FileCopyFlags::OVERWRITE, Cancellable::NONE, None).map_err(|e| e.message().to_string())?;
+        #[cfg(feature = "audit")]
+        if overwrites {
+            crate::audit::record_with_source(crate::audit::AuditAction::Overwrite, &dest_path, from);
+        }
+        Ok(())
     }
 }
 
 /// Moves an item
-pub fn mv_async<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
+pub fn mv_async<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
     execute_file_operation(FileOperation::Move, &[from], Some(to), callback)
 }
 
 /// Moves multiple items
 pub fn mv_all<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2) -> Result<(), String> {
-    froms.iter().try_for_each(|from| mv(from, to.as_ref()))
+    crate::policy::check_transfer(froms, to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Move, froms, Some(to.as_ref()))?;
+    froms.iter().try_for_each(|from| move_item(from.as_ref(), to.as_ref()))?;
+    crate::history::record(crate::history::HistoryAction::Move, froms, Some(to.as_ref()));
+    Ok(())
 }
 
 /// Moves multiple items
-pub fn mv_all_async<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
+pub fn mv_all_async<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
     execute_file_operation(FileOperation::Move, froms, Some(to), callback)
 }
 
 /// Copies an item
 pub fn copy<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2) -> Result<(), String> {
-    let source = File::for_path(from.as_ref());
-    let dest_path = to.as_ref().join(from.as_ref().file_name().unwrap());
+    crate::policy::check_transfer(&[from.as_ref()], to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Copy, &[from.as_ref()], Some(to.as_ref()))?;
+    copy_item(from.as_ref(), to.as_ref())?;
+    crate::history::record(crate::history::HistoryAction::Copy, &[from.as_ref()], Some(to.as_ref()));
+    Ok(())
+}
+
+fn copy_item(from: &Path, to: &Path) -> Result<(), String> {
+    let source = File::for_path(from);
+    let dest_path = to.join(from.file_name().unwrap());
     let dest = File::for_path(&dest_path);
 
-    if from.as_ref().is_dir() {
+    if from.is_dir() {
         handle_directory(true, from, to)
     } else {
-        source.copy(&dest, FileCopyFlags::ALL_METADATA | FileCopyFlags::NOFOLLOW_SYMLINKS | FileCopyFlags::OVERWRITE, Cancellable::NONE, None).map_err(|e| e.message().to_string())
+        #[cfg(feature = "audit")]
+        let overwrites = dest_path.exists();
+        source.copy(&dest, FileCopyFlags::ALL_METADATA | FileCopyFlags::NOFOLLOW_SYMLINKS | FileCopyFlags::OVERWRITE, Cancellable::NONE, None).map_err(|e| e.message().to_string())?;
+        #[cfg(feature = "audit")]
+        if overwrites {
+            crate::audit::record_with_source(crate::audit::AuditAction::Overwrite, &dest_path, from);
+        }
+        Ok(())
     }
 }
 
 /// Copies an item
-pub fn copy_async<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
+pub fn copy_async<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
     execute_file_operation(FileOperation::Copy, &[from], Some(to), callback)
 }
 
 /// Copies multiple items
 pub fn copy_all<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2) -> Result<(), String> {
-    froms.iter().try_for_each(|from| copy(from, to.as_ref()))
+    crate::policy::check_transfer(froms, to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Copy, froms, Some(to.as_ref()))?;
+    froms.iter().try_for_each(|from| copy_item(from.as_ref(), to.as_ref()))?;
+    crate::history::record(crate::history::HistoryAction::Copy, froms, Some(to.as_ref()));
+    Ok(())
 }
 
 /// Copies multiple items
-pub fn copy_all_async<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
+pub fn copy_all_async<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
     execute_file_operation(FileOperation::Copy, froms, Some(to), callback)
 }
 
+/// Measures the operation before starting it so that a lack of space can be reported up front
+///
+/// Stages multiple operations to be completed all together or rolled back on failure
+pub fn transaction() -> Transaction {
+    Transaction::default()
+}
+
+/// Checks the free space of the destination volume, whether a move is a rename within one volume and the length of each destination path
+pub fn preflight<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>) -> Result<PreflightReport, String> {
+    if !matches!(operation, FileOperation::Copy | FileOperation::Move) {
+        return crate::preflight::measure(froms, None, |_| false);
+    }
+
+    let to = to.ok_or("No destination specified")?;
+    let report = crate::preflight::measure(froms, Some(to.as_ref()), |path| path.as_os_str().len() >= PATH_MAX as usize || path.file_name().is_some_and(|name| name.len() > NAME_MAX as usize))?;
+
+    let device = to.as_ref().metadata().map_err(|e| e.to_string())?.dev();
+    let is_same_volume = operation == FileOperation::Move && froms.iter().all(|from| from.as_ref().symlink_metadata().is_ok_and(|metadata| metadata.dev() == device));
+
+    let path = CString::new(to.as_ref().to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    Ok(crate::preflight::complete(report, Some(stat.f_bavail as u64 * stat.f_frsize as u64), is_same_volume))
+}
+
+/// Coalesces `OperationStatus::Progress` of file operations, copy_resumable and hash_file. None reports every progress
+///
+/// Takes effect from the next operation
+pub fn set_progress_throttle(throttle: Option<ProgressThrottle>) {
+    crate::throttle::set(throttle);
+}
+
+/// Copies a file, resuming from the last copied offset when reading or writing fails
+///
+/// Each retry is reported as `OperationStatus::Retry` after waiting for the backoff. `max_retries` limits consecutive failures without progress
+pub fn copy_resumable<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2, options: RetryOptions, callback: impl FnMut(OperationStatus) -> Response) -> Result<(), String> {
+    crate::resumable::copy_resumable(from.as_ref(), to.as_ref(), options, callback, Path::to_path_buf)
+}
+
+/// Computes the hex digest of a file, reporting progress as `OperationStatus::Progress`
+///
+/// Returns an error if the callback cancels the operation
+pub fn hash_file<P: AsRef<Path>>(file_path: P, algorithm: HashAlgorithm, callback: impl FnMut(OperationStatus) -> Response) -> Result<String, String> {
+    let file = std::fs::File::open(file_path.as_ref()).map_err(|e| e.to_string())?;
+    crate::hash::hash_file(file, &algorithm, callback)
+}
+
+/// Computes the hex digests of files, reporting each file as `OperationStatus::Start` and `OperationStatus::End`
+///
+/// Files that fail to hash are reported as `OperationStatus::Error` and skipped
+pub fn hash_files<P: AsRef<Path>>(file_paths: &[P], algorithm: HashAlgorithm, callback: impl FnMut(OperationStatus) -> Response) -> Result<Vec<FileHash>, String> {
+    crate::hash::hash_files(file_paths, &algorithm, callback, |file_path| std::fs::File::open(file_path))
+}
+
+/// Compares two files byte by byte, returning the offset of the first difference, or line by line, returning a line diff summary
+pub fn compare_files<P1: AsRef<Path>, P2: AsRef<Path>>(a: P1, b: P2, mode: CompareMode) -> Result<FileComparison, String> {
+    let file_a = std::fs::File::open(a.as_ref()).map_err(|e| e.to_string())?;
+    let file_b = std::fs::File::open(b.as_ref()).map_err(|e| e.to_string())?;
+    compare_readers(file_a, file_b, &mode)
+}
+
 /// Deletes an item
 pub fn delete<P: AsRef<Path>>(file: P) -> Result<(), String> {
-    if file.as_ref().is_dir() {
-        let children = crate::fs::readdir(file.as_ref(), false, false)?;
-        if children.is_empty() {
-            File::for_path(file).delete(Cancellable::NONE).map_err(|e| e.message().to_string())
-        } else {
-            children.iter().try_for_each(|child| delete(child.full_path.clone()))?;
-            File::for_path(file).delete(Cancellable::NONE).map_err(|e| e.message().to_string())
-        }
-    } else {
-        File::for_path(file).delete(Cancellable::NONE).map_err(|e| e.message().to_string())
+    crate::hooks::before(crate::history::HistoryAction::Delete, &[file.as_ref()], None::<&Path>)?;
+    delete_item(file.as_ref())?;
+    crate::history::record(crate::history::HistoryAction::Delete, &[file.as_ref()], None::<&Path>);
+    Ok(())
+}
+
+fn delete_item(file: &Path) -> Result<(), String> {
+    if file.is_dir() {
+        let children = crate::fs::readdir(file, false, false)?;
+        children.iter().try_for_each(|child| delete_item(Path::new(&child.full_path)))?;
     }
+
+    File::for_path(file).delete(Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    #[cfg(feature = "audit")]
+    crate::audit::record(crate::audit::AuditAction::Delete, file);
+    Ok(())
 }
 
 /// Deletes an item
-pub fn delete_async<P: AsRef<Path>>(file: P, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
+pub fn delete_async<P: AsRef<Path>>(file: P, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
     execute_file_operation(FileOperation::Delete, &[file], None::<String>, callback)
 }
 
+/// Copies the items into the directory in an elevated helper process once the user approves the polkit prompt of pkexec
+///
+/// For when `copy_all` fails with access denied. The helper is the app's own executable, which must call `run_elevated_helper` at the start of `main`.
+/// Returning Cancel from the callback stops the helper after the file being copied.
+/// Returns false if the user declined the prompt or the callback cancelled, in which case nothing is recorded in history
+pub fn copy_elevated<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2, callback: impl FnMut(OperationStatus) -> Response) -> Result<bool, String> {
+    crate::policy::check_transfer(froms, to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Copy, froms, Some(to.as_ref()))?;
+    if crate::elevation::run(crate::elevation::ElevatedOperation::Copy, froms, Some(to.as_ref()), callback)? == crate::elevation::ElevatedOutcome::Cancelled {
+        return Ok(false);
+    }
+    crate::history::record(crate::history::HistoryAction::Copy, froms, Some(to.as_ref()));
+    Ok(true)
+}
+
+/// Deletes the items permanently in an elevated helper process once the user approves the polkit prompt of pkexec
+///
+/// For when `delete_all` fails with access denied. See `copy_elevated` for the helper and the result
+pub fn delete_elevated<P: AsRef<Path>>(file_paths: &[P], callback: impl FnMut(OperationStatus) -> Response) -> Result<bool, String> {
+    crate::hooks::before(crate::history::HistoryAction::Delete, file_paths, None::<&Path>)?;
+    if crate::elevation::run(crate::elevation::ElevatedOperation::Delete, file_paths, None, callback)? == crate::elevation::ElevatedOutcome::Cancelled {
+        return Ok(false);
+    }
+    crate::history::record(crate::history::HistoryAction::Delete, file_paths, None::<&Path>);
+    Ok(true)
+}
+
+/// Runs the operation and exits if the process was started as the helper of `copy_elevated` or `delete_elevated`. Returns if it was not
+pub fn run_elevated_helper() {
+    crate::elevation::run_helper();
+}
+
 /// Deletes multiple items
 pub fn delete_all<P: AsRef<Path>>(files: &[P]) -> Result<(), String> {
-    files.iter().try_for_each(|file| delete(file.as_ref()))
+    crate::hooks::before(crate::history::HistoryAction::Delete, files, None::<&Path>)?;
+    files.iter().try_for_each(|file| delete_item(file.as_ref()))?;
+    crate::history::record(crate::history::HistoryAction::Delete, files, None::<&Path>);
+    Ok(())
 }
 
 /// Deletes multiple items
-pub fn delete_all_async<P: AsRef<Path>>(files: &[P], callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
+pub fn delete_all_async<P: AsRef<Path>>(files: &[P], callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
     execute_file_operation(FileOperation::Delete, files, None::<String>, callback)
 }
 
 /// Moves an item to the OS-specific trash location
 pub fn trash<P: AsRef<Path>>(file: P) -> Result<(), String> {
-    File::for_path(file).trash(Cancellable::NONE).map_err(|e| e.message().to_string())
+    crate::hooks::before(crate::history::HistoryAction::Trash, &[file.as_ref()], None::<&Path>)?;
+    trash_item(file.as_ref())?;
+    crate::history::record(crate::history::HistoryAction::Trash, &[file.as_ref()], None::<&Path>);
+    Ok(())
+}
+
+fn trash_item(file: &Path) -> Result<(), String> {
+    File::for_path(file).trash(Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    #[cfg(feature = "audit")]
+    crate::audit::record(crate::audit::AuditAction::Trash, file);
+    Ok(())
 }
 
 /// Moves an item to the OS-specific trash location
-pub fn trash_async<P: AsRef<Path>>(file: P, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
+pub fn trash_async<P: AsRef<Path>>(file: P, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
     execute_file_operation(FileOperation::Trash, &[file], None::<String>, callback)
 }
 
 /// Moves multiple items to the OS-specific trash location
 pub fn trash_all<P: AsRef<Path>>(files: &[P]) -> Result<(), String> {
-    files.iter().try_for_each(|file| trash(file.as_ref()))
+    crate::hooks::before(crate::history::HistoryAction::Trash, files, None::<&Path>)?;
+    files.iter().try_for_each(|file| trash_item(file.as_ref()))?;
+    crate::history::record(crate::history::HistoryAction::Trash, files, None::<&Path>);
+    Ok(())
 }
 
 /// Moves multiple items to the OS-specific trash location
-pub fn trash_all_async<P: AsRef<Path>>(files: &[P], callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
+pub fn trash_all_async<P: AsRef<Path>>(files: &[P], callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
     execute_file_operation(FileOperation::Trash, files, None::<String>, callback)
 }
 
 /// Execute file operation
-pub fn operate<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) {
-    super::fs_ext::execute_file_operation(operation, froms, to, callback)
+pub fn operate<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
+    execute_file_operation(operation, froms, to, callback)
+}
+
+/// Execute file operation, recording the gio calls made for it
+///
+/// The calls are complete once `OperationStatus::Finished` is reported
+pub fn operate_traced<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> OperationHandle {
+    execute_traced_file_operation(operation, froms, to, callback)
+}
+
+/// Execute file operation, reporting it as events received from `OperationHandle::events`
+pub fn operate_with_events<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>) -> OperationHandle {
+    let (event_tx, event_rx) = smol::channel::unbounded();
+    let (response_tx, response_rx) = smol::channel::bounded(1);
+
+    let mut handle = execute_file_operation(operation, froms, to, crate::events::forward(event_tx, response_rx));
+    handle.events = Some(event_rx);
+    handle.responses = Some(response_tx);
+    handle
+}
+
+/// Execute file operation on a worker thread without initializing GTK or needing its main loop
+///
+/// Progress and conflicts are reported to the callback on the worker thread, so the app can drive its own UI. `OperationStatus::Confirm` is answered by the returned response
+pub fn operate_headless<P1: AsRef<Path>, P2: AsRef<Path>>(
+    operation: FileOperation,
+    froms: &[P1],
+    to: Option<P2>,
+    callback: impl FnMut(OperationStatus) -> Response + Send + 'static,
+) -> OperationHandle {
+    execute_file_operation_headless(operation, froms, to, callback)
+}
+
+/// Execute file operation, then shows a desktop notification and/or plays a sound as the notice specifies if no window of the app is active
+pub fn operate_with_notice<P1: AsRef<Path>, P2: AsRef<Path>>(
+    operation: FileOperation,
+    froms: &[P1],
+    to: Option<P2>,
+    notice: CompletionNotice,
+    mut callback: impl AsyncFnMut(OperationStatus) -> Response + 'static,
+) -> OperationHandle {
+    let count = froms.len();
+    let mut outcome = crate::notice::Outcome::default();
+
+    execute_file_operation(operation, froms, to, async move |status: OperationStatus| {
+        outcome.observe(&status);
+        if matches!(status, OperationStatus::Finished) {
+            super::util::notify_completion(&notice, operation, count, outcome.error());
+        }
+        callback(status).await
+    })
 }
 
 struct TrashData {
@@ -349,7 +1192,7 @@ pub fn read_recycle_bin() -> Result<Vec<RecycleBinDirent>, String> {
                 0
             };
 
-            let attributes = to_file_attribute(&info);
+            let attributes = to_file_attribute(&info, Path::new(&original_path).parent());
             let mime_type = get_mime_type(&original_path);
 
             let bin_item = RecycleBinDirent {
@@ -369,32 +1212,68 @@ pub fn read_recycle_bin() -> Result<Vec<RecycleBinDirent>, String> {
 pub fn undelete<P: AsRef<Path>>(file_paths: &[P]) -> Result<(), String> {
     let trash_file = File::for_uri(TRASH_PATH_STR);
 
-    if let Ok(mut children) = trash_file.enumerate_children("trash::orig-path,trash::deletion-date,standard::name", FileQueryInfoFlags::NONE, Cancellable::NONE) {
-        let file_paths: Vec<String> = file_paths.iter().map(|f| f.as_ref().to_string_lossy().to_string()).collect();
-        let mut map: HashMap<String, TrashData> = HashMap::new();
-        while let Some(Ok(info)) = children.next() {
-            let orig_path = if let Some(path) = info.attribute_as_string("trash::orig-path") {
-                path.to_string()
-            } else {
-                String::new()
-            };
+    if let Ok(children) = trash_file.enumerate_children("trash::orig-path,trash::deletion-date,standard::name", FileQueryInfoFlags::NONE, Cancellable::NONE) {
+        let map = find_latest_items_in_recycle_bin(children, file_paths);
+
+        for (orig_path, trash_data) in map.iter() {
+            let mut trash_path = String::from(TRASH_PATH_STR);
+            trash_path.push_str(&trash_data.name);
+
+            File::for_uri(&trash_path).move_(&File::for_parse_name(orig_path), FileCopyFlags::OVERWRITE | FileCopyFlags::ALL_METADATA, Cancellable::NONE, None).map_err(|e| e.message().to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Undos a trash operation, asking the resolver what to do with each item whose original path is already taken
+///
+/// Pass a closure returning a fixed choice such as `|_| UndeleteConflict::Skip` to apply it to all items
+pub fn undelete_with_conflict<P: AsRef<Path>>(file_paths: &[P], mut resolve: impl FnMut(&str) -> UndeleteConflict) -> Result<(), String> {
+    let trash_file = File::for_uri(TRASH_PATH_STR);
+    let children = trash_file.enumerate_children("trash::orig-path,trash::deletion-date,standard::name", FileQueryInfoFlags::NONE, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    let map = find_latest_items_in_recycle_bin(children, file_paths);
 
-            let date_string = info.attribute_as_string("trash::deletion-date").unwrap();
-            let date = gtk::glib::DateTime::from_iso8601(&date_string, Some(&gtk::glib::TimeZone::local())).unwrap().to_unix();
-
-            if file_paths.contains(&orig_path) {
-                if map.contains_key(&orig_path) {
-                    let trash_data = map.get(&orig_path).unwrap();
-                    if trash_data.date < date {
-                        let _ = map.insert(
-                            orig_path,
-                            TrashData {
-                                date,
-                                name: info.name().to_string_lossy().to_string(),
-                            },
-                        );
-                    }
-                } else {
+    for (orig_path, trash_data) in map.iter() {
+        let dest_path = if Path::new(orig_path).symlink_metadata().is_err() {
+            PathBuf::from(orig_path)
+        } else {
+            match resolve(orig_path) {
+                UndeleteConflict::Overwrite => PathBuf::from(orig_path),
+                UndeleteConflict::KeepBoth => get_available_path(Path::new(orig_path)),
+                UndeleteConflict::Skip => continue,
+            }
+        };
+
+        // Moving a directory onto an existing one fails even with OVERWRITE, so remove it first
+        if dest_path.is_dir() && !dest_path.is_symlink() {
+            std::fs::remove_dir_all(&dest_path).map_err(|e| e.to_string())?;
+        }
+
+        let trash_path = format!("{}{}", TRASH_PATH_STR, trash_data.name);
+        File::for_uri(&trash_path).move_(&File::for_path(&dest_path), FileCopyFlags::OVERWRITE | FileCopyFlags::ALL_METADATA, Cancellable::NONE, None).map_err(|e| e.message().to_string())?;
+    }
+
+    Ok(())
+}
+
+fn find_latest_items_in_recycle_bin<P: AsRef<Path>>(mut children: FileEnumerator, file_paths: &[P]) -> HashMap<String, TrashData> {
+    let file_paths: Vec<String> = file_paths.iter().map(|f| f.as_ref().to_string_lossy().to_string()).collect();
+    let mut map: HashMap<String, TrashData> = HashMap::new();
+    while let Some(Ok(info)) = children.next() {
+        let orig_path = if let Some(path) = info.attribute_as_string("trash::orig-path") {
+            path.to_string()
+        } else {
+            String::new()
+        };
+
+        let date_string = info.attribute_as_string("trash::deletion-date").unwrap();
+        let date = gtk::glib::DateTime::from_iso8601(&date_string, Some(&gtk::glib::TimeZone::local())).unwrap().to_unix();
+
+        if file_paths.contains(&orig_path) {
+            if map.contains_key(&orig_path) {
+                let trash_data = map.get(&orig_path).unwrap();
+                if trash_data.date < date {
                     let _ = map.insert(
                         orig_path,
                         TrashData {
@@ -403,18 +1282,33 @@ pub fn undelete<P: AsRef<Path>>(file_paths: &[P]) -> Result<(), String> {
                         },
                     );
                 }
+            } else {
+                let _ = map.insert(
+                    orig_path,
+                    TrashData {
+                        date,
+                        name: info.name().to_string_lossy().to_string(),
+                    },
+                );
             }
         }
+    }
+    map
+}
 
-        for (orig_path, trash_data) in map.iter() {
-            let mut trash_path = String::from(TRASH_PATH_STR);
-            trash_path.push_str(&trash_data.name);
+/* Finds a free name like "name (2).ext" next to the path */
+fn get_available_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
 
-            File::for_uri(&trash_path).move_(&File::for_parse_name(orig_path), FileCopyFlags::OVERWRITE | FileCopyFlags::ALL_METADATA, Cancellable::NONE, None).map_err(|e| e.message().to_string())?;
+    let mut index = 2;
+    loop {
+        let candidate = path.with_file_name(format!("{} ({}){}", stem, index, extension));
+        if candidate.symlink_metadata().is_err() {
+            return candidate;
         }
+        index += 1;
     }
-
-    Ok(())
 }
 
 /// Undos a trash operation by deleted time
@@ -422,10 +1316,9 @@ pub fn undelete_by_time(targets: &[RecycleBinItem]) -> Result<(), String> {
     let trash_file = File::for_uri(TRASH_PATH_STR);
 
     if let Ok(children) = trash_file.enumerate_children("trash::orig-path,trash::deletion-date,standard::name", FileQueryInfoFlags::NONE, Cancellable::NONE) {
-        let args: HashMap<String, u64> = targets.iter().map(|target| (target.original_path.clone(), target.deleted_time_ms)).collect();
-        let map = find_items_in_recycle_bin(children, args)?;
+        let items = find_items_in_recycle_bin(children, &to_recycle_bin_keys(targets))?;
 
-        for (orig_path, trash_data) in map.iter() {
+        for (orig_path, trash_data) in items.iter() {
             let mut trash_path = String::from(TRASH_PATH_STR);
             trash_path.push_str(&trash_data.name);
 
@@ -436,15 +1329,34 @@ pub fn undelete_by_time(targets: &[RecycleBinItem]) -> Result<(), String> {
     Ok(())
 }
 
+/// Restores items in Recycle Bin into the destination directory instead of their original paths
+///
+/// Items trashed from the same path are told apart by the deleted time. Fails if an item with the same name exists in the destination
+pub fn undelete_to<P: AsRef<Path>>(targets: &[RecycleBinItem], destination: P) -> Result<(), String> {
+    let original_paths: Vec<&str> = targets.iter().map(|target| target.original_path.as_str()).collect();
+    crate::policy::check_transfer(&original_paths, destination.as_ref())?;
+
+    let trash_file = File::for_uri(TRASH_PATH_STR);
+    let children = trash_file.enumerate_children("trash::orig-path,trash::deletion-date,standard::name", FileQueryInfoFlags::NONE, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    let items = find_items_in_recycle_bin(children, &to_recycle_bin_keys(targets))?;
+
+    for (orig_path, trash_data) in items.iter() {
+        let name = Path::new(orig_path).file_name().ok_or(format!("Invalid original path:{}", orig_path))?;
+        let dest_file = File::for_path(destination.as_ref().join(name));
+        File::for_uri(&format!("{}{}", TRASH_PATH_STR, trash_data.name)).move_(&dest_file, FileCopyFlags::ALL_METADATA, Cancellable::NONE, None).map_err(|e| e.message().to_string())?;
+    }
+
+    Ok(())
+}
+
 /// Delete files in Recycle Bin
 pub fn delete_from_recycle_bin(targets: &[RecycleBinItem]) -> Result<(), String> {
     let trash_file = File::for_uri(TRASH_PATH_STR);
 
     if let Ok(children) = trash_file.enumerate_children("trash::orig-path,trash::deletion-date,standard::name", FileQueryInfoFlags::NONE, Cancellable::NONE) {
-        let args: HashMap<String, u64> = targets.iter().map(|target| (target.original_path.clone(), target.deleted_time_ms)).collect();
-        let map = find_items_in_recycle_bin(children, args)?;
+        let items = find_items_in_recycle_bin(children, &to_recycle_bin_keys(targets))?;
 
-        for (_, trash_data) in map.iter() {
+        for (_, trash_data) in items.iter() {
             let mut trash_path = String::from(TRASH_PATH_STR);
             trash_path.push_str(&trash_data.name);
 
@@ -455,8 +1367,33 @@ pub fn delete_from_recycle_bin(targets: &[RecycleBinItem]) -> Result<(), String>
     Ok(())
 }
 
-fn find_items_in_recycle_bin(mut children: FileEnumerator, map: HashMap<String, u64>) -> Result<HashMap<String, TrashData>, String> {
-    let mut items: HashMap<String, TrashData> = HashMap::new();
+/// Permanently deletes the items from the trash, removing their trash info files as well
+///
+/// Fails without deleting anything if any item is not found. Items trashed from the same path are told apart by the deleted time
+pub fn purge_from_recycle_bin(targets: &[RecycleBinItem]) -> Result<(), String> {
+    let trash_file = File::for_uri(TRASH_PATH_STR);
+    let children = trash_file.enumerate_children("trash::orig-path,trash::deletion-date,standard::name", FileQueryInfoFlags::NONE, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+
+    let keys = to_recycle_bin_keys(targets);
+    let items = find_items_in_recycle_bin(children, &keys)?;
+    if items.len() < keys.len() {
+        return Err("Some items are not in trash".to_string());
+    }
+
+    // The trash backend deletes directories recursively along with the info file
+    for (_, trash_data) in items.iter() {
+        File::for_uri(&format!("{}{}", TRASH_PATH_STR, trash_data.name)).delete(Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    }
+
+    Ok(())
+}
+
+fn to_recycle_bin_keys(targets: &[RecycleBinItem]) -> HashSet<(String, u64)> {
+    targets.iter().map(|target| (target.original_path.clone(), target.deleted_time_ms)).collect()
+}
+
+fn find_items_in_recycle_bin(mut children: FileEnumerator, keys: &HashSet<(String, u64)>) -> Result<Vec<(String, TrashData)>, String> {
+    let mut items: Vec<(String, TrashData)> = Vec::new();
     while let Some(Ok(info)) = children.next() {
         let orig_path = if let Some(path) = info.attribute_as_string("trash::orig-path") {
             path.to_string()
@@ -467,14 +1404,14 @@ fn find_items_in_recycle_bin(mut children: FileEnumerator, map: HashMap<String,
         let date_string = info.attribute_as_string("trash::deletion-date").unwrap();
         let date = gtk::glib::DateTime::from_iso8601(&date_string, Some(&gtk::glib::TimeZone::local())).unwrap().to_unix();
 
-        if map.contains_key(&orig_path) && *map.get(&orig_path).unwrap() == date as u64 {
-            let _ = items.insert(
+        if keys.contains(&(orig_path.clone(), date as u64)) {
+            items.push((
                 orig_path,
                 TrashData {
                     date,
                     name: info.name().to_string_lossy().to_string(),
                 },
-            );
+            ));
         }
     }
     Ok(items)
@@ -495,6 +1432,75 @@ pub fn empty_recycle_bin(root: Option<String>) -> Result<(), String> {
     Ok(())
 }
 
+#[allow(unused_variables)]
+/// Gets the number of items and total bytes in Recycle Bin
+/// Parameter "root" has no effect on Linux
+pub fn recycle_bin_info(root: Option<String>) -> Result<RecycleBinInfo, String> {
+    let trash_file = File::for_uri(TRASH_PATH_STR);
+    let children = trash_file.enumerate_children("standard::name,standard::type,standard::size", FileQueryInfoFlags::NOFOLLOW_SYMLINKS, Cancellable::NONE).map_err(|e| e.message().to_string())?;
+
+    let mut info = RecycleBinInfo::default();
+    for child in children.flatten() {
+        info.item_count += 1;
+        info.total_size += get_trash_item_size(&trash_file.child(child.name()), &child);
+    }
+
+    Ok(info)
+}
+
+fn get_trash_item_size(file: &File, info: &FileInfo) -> u64 {
+    if info.file_type() != FileType::Directory {
+        return info.size() as u64;
+    }
+
+    match file.enumerate_children("standard::name,standard::type,standard::size", FileQueryInfoFlags::NOFOLLOW_SYMLINKS, Cancellable::NONE) {
+        Ok(children) => children.flatten().map(|child| get_trash_item_size(&file.child(child.name()), &child)).sum(),
+        Err(_) => 0,
+    }
+}
+
+/// Get notification when items are added to or removed from Recycle Bin
+///
+/// The callback receives the new item count and total bytes. Must be called on the main thread running the GLib main loop
+pub fn watch_recycle_bin<F: FnMut(RecycleBinInfo) + Send + 'static>(callback: F) -> bool {
+    unwatch_recycle_bin();
+
+    let monitor = match File::for_uri(TRASH_PATH_STR).monitor_directory(FileMonitorFlags::NONE, Cancellable::NONE) {
+        Ok(monitor) => monitor,
+        Err(_) => return false,
+    };
+
+    let callback = RefCell::new(callback);
+    let last = RefCell::new(recycle_bin_info(None).unwrap_or_default());
+    // The trash backend emits several events for a single deletion, so report only actual changes
+    let handler = monitor.connect_changed(move |_, _, _, event| {
+        if !matches!(event, FileMonitorEvent::Created | FileMonitorEvent::Deleted | FileMonitorEvent::MovedIn | FileMonitorEvent::MovedOut | FileMonitorEvent::Changed) {
+            return;
+        }
+
+        if let Ok(info) = recycle_bin_info(None) {
+            if *last.borrow() != info {
+                *last.borrow_mut() = info.clone();
+                (callback.borrow_mut())(info);
+            }
+        }
+    });
+
+    RECYCLE_BIN_WATCHER.with(|watcher| *watcher.borrow_mut() = Some((monitor, handler)));
+
+    true
+}
+
+/// Stops notification started by watch_recycle_bin
+pub fn unwatch_recycle_bin() {
+    RECYCLE_BIN_WATCHER.with(|watcher| {
+        if let Some((monitor, handler)) = watcher.borrow_mut().take() {
+            monitor.disconnect(handler);
+            monitor.cancel();
+        }
+    });
+}
+
 /// Changes the modification and access timestamps of a file
 pub fn utimes<P: AsRef<Path>>(file: P, atime_ms: u64, mtime_ms: u64) -> Result<(), String> {
     let path = CString::new(file.as_ref().to_string_lossy().to_string()).map_err(|e| e.to_string())?;
@@ -536,8 +1542,14 @@ pub enum OperationStatus {
     // proccessed size and total size
     Progress(i64, i64),
     End,
+    // Sent before Error when the item failed because other processes have it open
+    InUseBy(Vec<crate::process::ProcessInfo>),
     Error(String),
     Confirm(String),
+    // Source of an item skipped after Confirm
+    Skipped(String),
+    // number of retries so far
+    Retry(u32),
     Finished,
 }
 
@@ -549,8 +1561,88 @@ pub enum Response {
     Skip,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Total {
     pub total_size: u64,
     pub total_count: u64,
 }
+
+#[derive(Debug, Clone, Default)]
+pub struct OperationProgress {
+    pub processed_size: u64,
+    pub total_size: u64,
+    pub processed_count: u64,
+    pub total_count: u64,
+}
+
+/// Controls a running file operation
+///
+/// Pausing takes effect between items, so an item being transferred is always completed first
+#[derive(Debug, Clone)]
+pub struct OperationHandle {
+    pub(crate) cancellable: Cancellable,
+    pub(crate) paused: Arc<AtomicBool>,
+    pub(crate) progress: Arc<Mutex<OperationProgress>>,
+    events: Option<Receiver<OperationEvent>>,
+    responses: Option<Sender<Response>>,
+    pub(crate) trace: crate::trace::CallTrace,
+}
+
+impl OperationHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            cancellable: Cancellable::new(),
+            paused: Arc::new(AtomicBool::new(false)),
+            progress: Arc::new(Mutex::new(OperationProgress::default())),
+            events: None,
+            responses: None,
+            trace: crate::trace::CallTrace::default(),
+        }
+    }
+
+    /// Suspends the operation before the next item
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resumes the paused operation
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Cancels the operation and removes a halfway copied item
+    pub fn cancel(&self) {
+        self.resume();
+        self.cancellable.cancel();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellable.is_cancelled()
+    }
+
+    /// Gets the current progress of the operation
+    pub fn progress(&self) -> OperationProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Receives the events of an operation started by `operate_with_events`
+    pub fn events(&self) -> Option<Receiver<OperationEvent>> {
+        self.events.clone()
+    }
+
+    /// Answers `OperationEvent::Conflict` with Replace, Skip or Cancel
+    pub fn respond(&self, response: Response) {
+        if let Some(responses) = &self.responses {
+            let _ = responses.try_send(response);
+        }
+    }
+
+    /// Gets the gio calls recorded so far by an operation started by `operate_traced`
+    pub fn trace(&self) -> Vec<String> {
+        self.trace.calls()
+    }
+}
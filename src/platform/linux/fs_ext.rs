@@ -1,26 +1,98 @@
 use crate::{
-    fs::{readdir, FileOperation, OperationStatus, Response, Total},
+    cleanup::PartialFile,
+    fs::{readdir, FileOperation, OperationHandle, OperationStatus, Response, Total},
     platform::linux::util::init,
+    trace::CallTrace,
 };
 use gtk::{
-    gio::{prelude::CancellableExtManual, prelude::FileExtManual, traits::CancellableExt, traits::FileExt, Cancellable, File, FileCopyFlags, FileMeasureFlags, FileQueryInfoFlags, IOErrorEnum},
-    glib::Priority,
+    gio::{prelude::CancellableExtManual, prelude::FileExtManual, traits::FileExt, Cancellable, File, FileCopyFlags, FileMeasureFlags, FileQueryInfoFlags, IOErrorEnum},
+    glib::{MainContext, Priority},
 };
 use smol::{
     channel::{Receiver, Sender},
     stream::StreamExt,
 };
 use std::{
+    future::Future,
     path::{Path, PathBuf},
     pin::Pin,
+    sync::Mutex,
+    time::Duration,
 };
 
-pub(crate) fn execute_file_operation<F, P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, mut callback: F)
+const COPY_FLAGS: FileCopyFlags = FileCopyFlags::ALL_METADATA.union(FileCopyFlags::NOFOLLOW_SYMLINKS).union(FileCopyFlags::OVERWRITE);
+
+// Handles of the running operations, including those running on worker threads
+static RUNNING: Mutex<Vec<OperationHandle>> = Mutex::new(Vec::new());
+
+/// Cancels all running operations
+pub(crate) fn cancel_operations() {
+    RUNNING.lock().unwrap().iter().for_each(|handle| handle.cancel());
+}
+
+pub(crate) fn execute_file_operation<F, P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, callback: F) -> OperationHandle
+where
+    F: AsyncFnMut(OperationStatus) -> Response + 'static,
+{
+    spawn_operation(OperationHandle::new(), operation, froms, to, callback)
+}
+
+pub(crate) fn execute_traced_file_operation<F, P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, callback: F) -> OperationHandle
+where
+    F: AsyncFnMut(OperationStatus) -> Response + 'static,
+{
+    let mut handle = OperationHandle::new();
+    handle.trace = CallTrace::enabled();
+    spawn_operation(handle, operation, froms, to, callback)
+}
+
+fn spawn_operation<F, P1: AsRef<Path>, P2: AsRef<Path>>(handle: OperationHandle, operation: FileOperation, froms: &[P1], to: Option<P2>, callback: F) -> OperationHandle
 where
     F: AsyncFnMut(OperationStatus) -> Response + 'static,
 {
     init();
 
+    let (forward, run) = build_operation(operation, froms, to, &handle, callback);
+
+    gtk::glib::spawn_future_local(forward);
+    gtk::glib::spawn_future_local(run);
+
+    handle
+}
+
+/// Runs the operation on a worker thread iterating its own main context, so GTK is neither initialized nor required to run
+pub(crate) fn execute_file_operation_headless<F, P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, mut callback: F) -> OperationHandle
+where
+    F: FnMut(OperationStatus) -> Response + Send + 'static,
+{
+    let froms = froms.iter().map(|a| a.as_ref().to_path_buf()).collect::<Vec<_>>();
+    let to = to.map(|to| to.as_ref().to_path_buf());
+
+    let handle = OperationHandle::new();
+    let thread_handle = handle.clone();
+
+    std::thread::spawn(move || {
+        let context = MainContext::new();
+        let _ = context.with_thread_default(|| {
+            let (forward, run) = build_operation(operation, &froms, to, &thread_handle, async move |status| callback(status));
+            context.block_on(smol::future::zip(forward, run));
+        });
+    });
+
+    handle
+}
+
+/* Returns the future forwarding statuses to the callback and the future running the operation */
+fn build_operation<F, P1: AsRef<Path>, P2: AsRef<Path>>(
+    operation: FileOperation,
+    froms: &[P1],
+    to: Option<P2>,
+    handle: &OperationHandle,
+    mut callback: F,
+) -> (impl Future<Output = ()> + 'static, impl Future<Output = ()> + 'static)
+where
+    F: AsyncFnMut(OperationStatus) -> Response + 'static,
+{
     let froms = froms.iter().map(|a| a.as_ref().to_path_buf()).collect::<Vec<_>>();
     let to = if let Some(to) = to {
         to.as_ref().to_path_buf()
@@ -31,70 +103,142 @@ where
     let (tx, rx) = smol::channel::unbounded::<OperationStatus>();
     let (confirm_tx, confirm_rx) = smol::channel::bounded::<Response>(1);
 
-    let cancellable = Cancellable::new();
-    let ref_cancellable = cancellable.clone();
-
-    gtk::glib::spawn_future_local(async move {
-        loop {
-            if let Ok(result) = rx.recv().await {
-                match result {
-                    OperationStatus::Confirm(_) => {
-                        let response = callback(result).await;
-                        match response {
-                            Response::Cancel => {
-                                cancellable.cancel();
-                                break;
-                            }
-                            Response::Proceed => {
-                                let _ = confirm_tx.send(Response::Replace).await;
-                            }
-                            _ => {
-                                let _ = confirm_tx.send(response).await;
-                            }
+    let callback_handle = handle.clone();
+    let operation_handle = handle.clone();
+
+    let forward = async move {
+        let mut completed_size = 0;
+        let mut current_size = 0;
+
+        while let Ok(result) = rx.recv().await {
+            update_progress(&callback_handle, &result, &mut completed_size, &mut current_size);
+
+            match result {
+                OperationStatus::Confirm(_) => {
+                    let response = callback(result).await;
+                    match response {
+                        Response::Cancel => {
+                            callback_handle.cancel();
+                            break;
+                        }
+                        Response::Proceed => {
+                            let _ = confirm_tx.send(Response::Replace).await;
+                        }
+                        _ => {
+                            let _ = confirm_tx.send(response).await;
                         }
                     }
-                    OperationStatus::Finished => {
-                        let _ = callback(result).await;
+                }
+                OperationStatus::Finished => {
+                    let _ = callback(result).await;
+                    break;
+                }
+                _ => {
+                    if callback(result).await == Response::Cancel {
+                        callback_handle.cancel();
                         break;
                     }
-                    _ => {
-                        if callback(result).await == Response::Cancel {
-                            cancellable.cancel();
-                            break;
-                        }
-                    }
                 }
             }
         }
-    });
+    };
 
-    gtk::glib::spawn_future_local(async move {
+    let run = async move {
+        let handle = operation_handle;
         let mut total = Total::default();
 
+        let _guard = match crate::lifecycle::begin_operation() {
+            Ok(guard) => guard,
+            Err(e) => {
+                let _ = tx.send(OperationStatus::Error(e)).await;
+                let _ = tx.send(OperationStatus::Finished).await;
+                return;
+            }
+        };
+
+        if matches!(operation, FileOperation::Copy | FileOperation::Move) {
+            if let Err(e) = crate::policy::check_transfer(&froms, &to) {
+                let _ = tx.send(OperationStatus::Error(e)).await;
+                let _ = tx.send(OperationStatus::Finished).await;
+                return;
+            }
+        }
+
+        let destination = matches!(operation, FileOperation::Copy | FileOperation::Move).then_some(&to);
+        if let Err(e) = crate::hooks::before(operation.into(), &froms, destination) {
+            let _ = tx.send(OperationStatus::Error(e)).await;
+            let _ = tx.send(OperationStatus::Finished).await;
+            return;
+        }
+
+        RUNNING.lock().unwrap().push(handle.clone());
+
         if measure_size(&froms, &mut total).await.is_err() {
+            unregister(&handle);
             let _ = tx.send(OperationStatus::Error("Calculation failed".to_string())).await;
             return;
         }
 
         tx.send(OperationStatus::Ready(total)).await.expect("Cannot start operation");
 
-        for from in froms {
-            if ref_cancellable.is_cancelled() {
+        for from in froms.clone() {
+            wait_while_paused(&handle).await;
+
+            if handle.is_cancelled() {
                 break;
             }
 
             let _ = tx.send(OperationStatus::Start(from.file_name().unwrap().to_string_lossy().to_string())).await;
 
             match operation {
-                FileOperation::Copy => execute_copy(from, to.clone(), &ref_cancellable, &tx, &confirm_rx).await,
-                FileOperation::Move => execute_move(from, to.clone(), &ref_cancellable, &tx, None, &confirm_rx).await,
-                FileOperation::Delete => execute_delete(from, &ref_cancellable, &tx).await,
-                FileOperation::Trash => execute_trash(from, &ref_cancellable, &tx).await,
+                FileOperation::Copy => execute_copy(from, to.clone(), &handle, &tx, &confirm_rx).await,
+                FileOperation::Move => execute_move(from, to.clone(), &handle, &tx, None, &confirm_rx).await,
+                FileOperation::Delete => execute_delete(from, &handle, &tx).await,
+                FileOperation::Trash => execute_trash(from, &handle, &tx).await,
             }
         }
 
+        if !handle.is_cancelled() {
+            crate::history::record(operation.into(), &froms, destination);
+        }
+
+        unregister(&handle);
+
         let _ = tx.send(OperationStatus::Finished).await;
-    });
+    };
+
+    (forward, run)
+}
+
+fn unregister(handle: &OperationHandle) {
+    RUNNING.lock().unwrap().retain(|running| running.cancellable != handle.cancellable);
+}
+
+fn update_progress(handle: &OperationHandle, status: &OperationStatus, completed_size: &mut u64, current_size: &mut u64) {
+    let mut progress = handle.progress.lock().unwrap();
+    match status {
+        OperationStatus::Ready(total) => {
+            progress.total_size = total.total_size;
+            progress.total_count = total.total_count;
+        }
+        OperationStatus::Progress(current, total) => {
+            *current_size = *total as u64;
+            progress.processed_size = *completed_size + *current as u64;
+        }
+        OperationStatus::End => {
+            *completed_size += *current_size;
+            *current_size = 0;
+            progress.processed_size = *completed_size;
+            progress.processed_count += 1;
+        }
+        _ => {}
+    }
+}
+
+async fn wait_while_paused(handle: &OperationHandle) {
+    while handle.is_paused() && !handle.is_cancelled() {
+        gtk::glib::timeout_future(Duration::from_millis(100)).await;
+    }
 }
 
 async fn measure_size(entries: &[PathBuf], data: &mut Total) -> Result<(), String> {
@@ -118,17 +262,22 @@ async fn run_with_cancellable<F, T>(
     progress_stream: Option<Pin<Box<dyn smol::prelude::Stream<Item = (i64, i64)>>>>,
     cancellable: &Cancellable,
     tx: &Sender<OperationStatus>,
-    cleanup_file: Option<File>,
+    partial: Option<PartialFile>,
     parent_dir: Option<PathBuf>,
-) where
+    source: &Path,
+) -> bool
+where
     F: smol::future::FutureExt<Output = Result<T, gtk::glib::Error>>,
 {
     let progress_tx = tx.clone();
 
     if let Some(mut progress) = progress_stream {
         gtk::glib::spawn_future_local(async move {
+            let mut filter = crate::throttle::ProgressFilter::new();
             while let Some((current, total)) = progress.next().await {
-                let _ = progress_tx.try_send(OperationStatus::Progress(current, total));
+                if filter.pass(current, total) {
+                    let _ = progress_tx.try_send(OperationStatus::Progress(current, total));
+                }
             }
         });
     }
@@ -140,37 +289,44 @@ async fn run_with_cancellable<F, T>(
 
     match operation.race(cancellation_signal).await {
         Ok(_) => {
+            if let Some(partial) = partial {
+                partial.complete();
+            }
             let _ = tx.try_send(OperationStatus::End);
+            true
         }
         Err(e) => {
-            // If cancelled, delete destination file that may be halfway
-            if e.matches(IOErrorEnum::Cancelled) {
-                if let Some(file) = cleanup_file {
-                    file.delete_async(Priority::DEFAULT, Cancellable::NONE, |_| {});
-                }
-            }
+            // Delete destination file that may be halfway
+            drop(partial);
 
             // If move, delete the remaining empty source directory
             if let Some(parent) = parent_dir {
                 File::for_path(parent).delete_async(Priority::DEFAULT, Cancellable::NONE, |_| {});
             }
 
+            if e.matches(IOErrorEnum::Busy) {
+                if let Some(processes) = crate::process::in_use_by(source) {
+                    let _ = tx.try_send(OperationStatus::InUseBy(processes));
+                }
+            }
             let _ = tx.try_send(OperationStatus::Error(e.message().to_string()));
+            false
         }
     }
 }
 
-async fn execute_move(from: PathBuf, to: PathBuf, cancellable: &Cancellable, tx: &Sender<OperationStatus>, parent: Option<PathBuf>, confirm_rx: &Receiver<Response>) {
+async fn execute_move(from: PathBuf, to: PathBuf, handle: &OperationHandle, tx: &Sender<OperationStatus>, parent: Option<PathBuf>, confirm_rx: &Receiver<Response>) {
     let source = File::for_path(&from);
     let dest_path = to.join(from.file_name().unwrap());
     let dest = File::for_path(&dest_path);
 
     // The native implementation may support moving directories (for instance on moves inside the same filesystem), but the fallback code does not.
     if from.is_dir() {
-        return handle_directory(false, from, to, cancellable, tx, confirm_rx).await;
+        return handle_directory(false, from, to, handle, tx, confirm_rx).await;
     }
 
-    if dest_path.exists() {
+    let overwrites = dest_path.exists();
+    if overwrites {
         let _ = tx.send(OperationStatus::Confirm(from.to_string_lossy().to_string())).await;
         let result = if let Ok(response) = confirm_rx.recv().await {
             response
@@ -178,25 +334,33 @@ async fn execute_move(from: PathBuf, to: PathBuf, cancellable: &Cancellable, tx:
             Response::Skip
         };
         if result == Response::Skip {
+            let _ = tx.send(OperationStatus::Skipped(from.to_string_lossy().to_string())).await;
             return;
         }
     }
 
-    let (output, progress_stream) = source.move_future(&dest, FileCopyFlags::ALL_METADATA | FileCopyFlags::NOFOLLOW_SYMLINKS | FileCopyFlags::OVERWRITE, Priority::DEFAULT);
-    run_with_cancellable(output, Some(progress_stream), cancellable, tx, Some(dest), parent).await;
+    // The existing file is kept if replacing it fails
+    let partial = (!overwrites).then(|| PartialFile::new(&dest_path));
+    handle.trace.record(|| format!("g_file_move_async({}, {}, {:?})", source.uri(), dest.uri(), COPY_FLAGS));
+    let (output, progress_stream) = source.move_future(&dest, COPY_FLAGS, Priority::DEFAULT);
+    if run_with_cancellable(output, Some(progress_stream), &handle.cancellable, tx, partial, parent, &from).await && overwrites {
+        #[cfg(feature = "audit")]
+        crate::audit::record_with_source(crate::audit::AuditAction::Overwrite, &dest_path, &from);
+    }
 }
 
-async fn execute_copy(from: PathBuf, to: PathBuf, cancellable: &Cancellable, tx: &Sender<OperationStatus>, confirm_rx: &Receiver<Response>) {
+async fn execute_copy(from: PathBuf, to: PathBuf, handle: &OperationHandle, tx: &Sender<OperationStatus>, confirm_rx: &Receiver<Response>) {
     let source = File::for_path(&from);
     let dest_path = to.join(from.file_name().unwrap());
     let dest = File::for_path(&dest_path);
 
     // Can not handle recursive copies of directories
     if from.is_dir() {
-        return handle_directory(true, from, to, cancellable, tx, confirm_rx).await;
+        return handle_directory(true, from, to, handle, tx, confirm_rx).await;
     }
 
-    if dest_path.exists() {
+    let overwrites = dest_path.exists();
+    if overwrites {
         let _ = tx.send(OperationStatus::Confirm(from.to_string_lossy().to_string())).await;
         let result = if let Ok(response) = confirm_rx.recv().await {
             response
@@ -204,20 +368,27 @@ async fn execute_copy(from: PathBuf, to: PathBuf, cancellable: &Cancellable, tx:
             Response::Skip
         };
         if result == Response::Skip {
+            let _ = tx.send(OperationStatus::Skipped(from.to_string_lossy().to_string())).await;
             return;
         }
     }
 
-    let (output, progress_stream) = source.copy_future(&dest, FileCopyFlags::ALL_METADATA | FileCopyFlags::NOFOLLOW_SYMLINKS | FileCopyFlags::OVERWRITE, Priority::DEFAULT);
-    run_with_cancellable(output, Some(progress_stream), cancellable, tx, Some(dest), None).await;
+    let partial = (!overwrites).then(|| PartialFile::new(&dest_path));
+    handle.trace.record(|| format!("g_file_copy_async({}, {}, {:?})", source.uri(), dest.uri(), COPY_FLAGS));
+    let (output, progress_stream) = source.copy_future(&dest, COPY_FLAGS, Priority::DEFAULT);
+    if run_with_cancellable(output, Some(progress_stream), &handle.cancellable, tx, partial, None, &from).await && overwrites {
+        #[cfg(feature = "audit")]
+        crate::audit::record_with_source(crate::audit::AuditAction::Overwrite, &dest_path, &from);
+    }
 }
 
-async fn handle_directory(is_copy: bool, from: PathBuf, to: PathBuf, cancellable: &Cancellable, sender: &Sender<OperationStatus>, confirm_rx: &Receiver<Response>) {
+async fn handle_directory(is_copy: bool, from: PathBuf, to: PathBuf, handle: &OperationHandle, sender: &Sender<OperationStatus>, confirm_rx: &Receiver<Response>) {
     let source = File::for_path(&from);
     let to_dr = to.join(from.file_name().unwrap());
     let dest = File::for_path(&to_dr);
 
     if !dest.query_exists(Cancellable::NONE) {
+        handle.trace.record(|| format!("g_file_make_directory({})", dest.uri()));
         match dest.make_directory(Cancellable::NONE) {
             Ok(()) => {}
             Err(e) => {
@@ -234,32 +405,47 @@ async fn handle_directory(is_copy: bool, from: PathBuf, to: PathBuf, cancellable
 
     if let Ok(mut children) = source.enumerate_children("standard:name", FileQueryInfoFlags::NONE, Cancellable::NONE) {
         while let Some(Ok(info)) = children.next() {
+            wait_while_paused(handle).await;
+
+            if handle.is_cancelled() {
+                break;
+            }
+
             let from_file = from.to_path_buf().join(info.name());
             if is_copy {
-                Box::pin(execute_copy(from_file, to_dr.clone(), cancellable, sender, confirm_rx)).await;
+                Box::pin(execute_copy(from_file, to_dr.clone(), handle, sender, confirm_rx)).await;
             } else {
-                Box::pin(execute_move(from_file, to_dr.clone(), cancellable, sender, Some(from.to_path_buf()), confirm_rx)).await;
+                Box::pin(execute_move(from_file, to_dr.clone(), handle, sender, Some(from.to_path_buf()), confirm_rx)).await;
             }
         }
     }
 }
 
-async fn execute_delete(file_path: PathBuf, cancellable: &Cancellable, tx: &Sender<OperationStatus>) {
+async fn execute_delete(file_path: PathBuf, handle: &OperationHandle, tx: &Sender<OperationStatus>) {
     if file_path.is_dir() {
         if let Ok(files) = readdir(&file_path, false, false) {
             for file in files {
-                Box::pin(execute_delete(PathBuf::from(file.full_path), cancellable, tx)).await;
+                wait_while_paused(handle).await;
+                Box::pin(execute_delete(PathBuf::from(file.full_path), handle, tx)).await;
             }
         }
     }
 
-    let file = File::for_path(file_path);
+    let file = File::for_path(&file_path);
+    handle.trace.record(|| format!("g_file_delete_async({})", file.uri()));
     let output = file.delete_future(Priority::DEFAULT);
-    run_with_cancellable(output, None, cancellable, tx, None, None).await;
+    if run_with_cancellable(output, None, &handle.cancellable, tx, None, None, &file_path).await {
+        #[cfg(feature = "audit")]
+        crate::audit::record(crate::audit::AuditAction::Delete, &file_path);
+    }
 }
 
-async fn execute_trash(file_path: PathBuf, cancellable: &Cancellable, tx: &Sender<OperationStatus>) {
-    let file = File::for_path(file_path);
+async fn execute_trash(file_path: PathBuf, handle: &OperationHandle, tx: &Sender<OperationStatus>) {
+    let file = File::for_path(&file_path);
+    handle.trace.record(|| format!("g_file_trash_async({})", file.uri()));
     let output = file.trash_future(Priority::DEFAULT);
-    run_with_cancellable(output, None, cancellable, tx, None, None).await;
+    if run_with_cancellable(output, None, &handle.cancellable, tx, None, None, &file_path).await {
+        #[cfg(feature = "audit")]
+        crate::audit::record(crate::audit::AuditAction::Trash, &file_path);
+    }
 }
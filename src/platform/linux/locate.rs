@@ -0,0 +1,34 @@
+use std::path::Path;
+
+/// Asks plocate for the full paths of the entries under the location whose names match the wildcard or regex pattern
+///
+/// Fails if plocate is not installed or its database cannot be read
+pub(crate) fn locate(location: &Path, pattern: &str, is_regex: bool, recursive: bool) -> Result<Vec<String>, String> {
+    let mut command = std::process::Command::new("plocate");
+    // Matches only the base name, ignoring case, with NUL separated output so that names may contain newlines
+    command.args(["-i", "-b", "-0"]);
+    if is_regex {
+        command.arg("--regex");
+    }
+    let output = command.arg(pattern).output().map_err(|e| e.to_string())?;
+
+    // plocate exits with 1 when nothing is found
+    if !output.status.success() && !output.stderr.is_empty() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(output
+        .stdout
+        .split(|byte| *byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| String::from_utf8_lossy(path).to_string())
+        .filter(|path| {
+            let path = Path::new(path);
+            if recursive {
+                path != location && path.starts_with(location)
+            } else {
+                path.parent() == Some(location)
+            }
+        })
+        .collect())
+}
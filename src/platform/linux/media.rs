@@ -1,32 +1,442 @@
-use crate::Size;
+#[cfg(feature = "video")]
+use crate::MediaInfo;
+use crate::{CancelToken, ImageInfo, PrefetchPriority, Size};
 use gtk::gio::{self, traits::FileExt, Cancellable, FileQueryInfoFlags};
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+use zbus::blocking::Connection;
+
+static SESSION: LazyLock<Option<Connection>> = LazyLock::new(|| Connection::session().ok());
 
-#[allow(unused_variables)]
 pub fn extract_video_thumbnail<P: AsRef<Path>>(file_path: P, size: Option<Size>) -> Result<Vec<u8>, String> {
-    get_video_thumbnail(file_path).map_err(|e| e.to_string())
+    extract_thumbnail(file_path.as_ref(), size)
 }
 
-#[allow(unused_variables)]
 pub fn extract_video_thumbnails<P: AsRef<Path>>(file_paths: &[P], size: Option<Size>) -> Result<HashMap<String, Vec<u8>>, String> {
     let mut result = HashMap::new();
     for file_path in file_paths {
-        let thumbnail = get_video_thumbnail(file_path).map_err(|e| e.to_string())?;
+        let thumbnail = extract_thumbnail(file_path.as_ref(), size.clone())?;
         let _ = result.insert(file_path.as_ref().to_string_lossy().to_string(), thumbnail);
     }
 
     Ok(result)
 }
 
+/// Extracts thumbnails on up to `max_concurrency` worker threads, calling `callback` with the path of each file as soon as it is done
+///
+/// Returns immediately. Files not started yet are skipped once `cancel_token` is cancelled
+pub fn extract_video_thumbnails_batch<P: AsRef<Path>, F: Fn(String, Result<Vec<u8>, String>) + Send + Sync + 'static>(
+    file_paths: &[P],
+    size: Option<Size>,
+    max_concurrency: usize,
+    cancel_token: CancelToken,
+    callback: F,
+) {
+    let queue: Arc<Mutex<VecDeque<PathBuf>>> = Arc::new(Mutex::new(file_paths.iter().map(|file_path| file_path.as_ref().to_path_buf()).collect()));
+    let callback = Arc::new(callback);
+
+    for _ in 0..max_concurrency.clamp(1, file_paths.len().max(1)) {
+        let queue = queue.clone();
+        let size = size.clone();
+        let cancel_token = cancel_token.clone();
+        let callback = callback.clone();
+
+        std::thread::spawn(move || {
+            while !cancel_token.is_cancelled() {
+                let Some(file_path) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+
+                let result = extract_thumbnail(&file_path, size.clone());
+                callback(file_path.to_string_lossy().to_string(), result);
+            }
+        });
+    }
+}
+
+/// Warms the thumbnail cache for files about to be shown, on worker threads shared by all prefetches
+///
+/// Returns immediately. Files already queued are not queued again. Cancel the returned token when the files are scrolled away.
+/// Thumbnails are generated by the freedesktop thumbnailer service
+pub fn prefetch<P: AsRef<Path>>(file_paths: &[P], size: Option<Size>, priority: PrefetchPriority) -> CancelToken {
+    let cancel_token = CancelToken::new();
+    crate::prefetch::enqueue(file_paths, size, priority, &cancel_token, warm_thumbnail);
+    cancel_token
+}
+
+/// Extracts thumbnails in a helper process that is killed after `timeout`, so that a malformed file cannot crash or hang the app. None extracts them in process
+///
+/// The helper is the app's own executable, which must call `run_sandbox_helper` at the start of `main`
+pub fn set_sandbox(timeout: Option<Duration>) {
+    crate::sandbox::set(timeout);
+}
+
+/// Extracts the thumbnail and exits if the process was started as the helper of `set_sandbox`. Returns if it was not
+pub fn run_sandbox_helper() {
+    crate::sandbox::run_helper(|path, _| get_video_thumbnail(path));
+}
+
+fn extract_thumbnail(path: &Path, size: Option<Size>) -> Result<Vec<u8>, String> {
+    match crate::sandbox::timeout() {
+        Some(timeout) => crate::sandbox::extract(path, size, timeout),
+        None => get_video_thumbnail(path),
+    }
+}
+
+fn warm_thumbnail(file_path: &Path, size: Option<&Size>) {
+    let file = gio::File::for_path(file_path);
+    let Ok(info) = file.query_info("standard::content-type,thumbnail::path,thumbnail::failed", FileQueryInfoFlags::NONE, Cancellable::NONE) else {
+        return;
+    };
+
+    if info.attribute_byte_string("thumbnail::path").is_some() || info.boolean("thumbnail::failed") {
+        return;
+    }
+
+    let Some(mime_type) = info.content_type().and_then(|content_type| gio::content_type_get_mime_type(&content_type)) else {
+        return;
+    };
+
+    // Flavors of the thumbnail specification by the longer side
+    let flavor = match size.map_or(0, |size| size.width.max(size.height)) {
+        0..=128 => "normal",
+        129..=256 => "large",
+        257..=512 => "x-large",
+        _ => "xx-large",
+    };
+
+    if let Some(connection) = SESSION.as_ref() {
+        if let Ok(proxy) = Thumbnailer1Proxy::new(connection) {
+            let _ = proxy.queue(&[file.uri().as_str()], &[mime_type.as_str()], flavor, "background", 0);
+        }
+    }
+}
+
+/// Extracts the frame at the timestamp as JPEG, scaled down to fit in `size`
+///
+/// Decodes from the keyframe before the timestamp, so the frame is exact rather than the nearest keyframe
+#[cfg(feature = "video")]
+pub fn extract_video_frame<P: AsRef<Path>>(file_path: P, timestamp: Duration, size: Option<Size>) -> Result<Vec<u8>, String> {
+    use ffmpeg_next::{codec, format::Pixel, media::Type, rescale, software::scaling, util::frame::video::Video, Rescale};
+
+    ffmpeg_next::init().map_err(|e| e.to_string())?;
+    let mut context = ffmpeg_next::format::input(&file_path.as_ref()).map_err(|e| e.to_string())?;
+
+    let stream = context.streams().best(Type::Video).ok_or_else(|| "No video stream".to_string())?;
+    let stream_index = stream.index();
+    let time_base = stream.time_base();
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters()).and_then(|context| context.decoder().video()).map_err(|e| e.to_string())?;
+
+    // In AV_TIME_BASE(microseconds). Seeks to the keyframe before the position
+    let position = timestamp.as_micros() as i64;
+    context.seek(position, ..position).map_err(|e| e.to_string())?;
+    let target = position.rescale(rescale::TIME_BASE, time_base);
+
+    let (width, height) = fit_size(decoder.width(), decoder.height(), size);
+    let mut scaler = scaling::Context::get(decoder.format(), decoder.width(), decoder.height(), Pixel::RGB24, width, height, scaling::Flags::BILINEAR).map_err(|e| e.to_string())?;
+
+    let mut decoded = Video::empty();
+    let mut last_frame = None;
+    for (stream, packet) in context.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+
+        decoder.send_packet(&packet).map_err(|e| e.to_string())?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            if decoded.timestamp().is_none_or(|timestamp| timestamp >= target) {
+                return encode_frame(&mut scaler, &decoded);
+            }
+            last_frame = Some(decoded.clone());
+        }
+    }
+
+    // Uses the last frame if the timestamp is beyond the end
+    let _ = decoder.send_eof();
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        last_frame = Some(decoded.clone());
+    }
+
+    match last_frame {
+        Some(frame) => encode_frame(&mut scaler, &frame),
+        None => Err("No video frames".to_string()),
+    }
+}
+
+fn fit_size(width: u32, height: u32, size: Option<Size>) -> (u32, u32) {
+    match size {
+        Some(size) if width > size.width || height > size.height => {
+            let scale = (size.width as f64 / width as f64).min(size.height as f64 / height as f64);
+            (((width as f64 * scale) as u32).max(1), ((height as f64 * scale) as u32).max(1))
+        }
+        _ => (width, height),
+    }
+}
+
+#[cfg(feature = "video")]
+fn encode_frame(scaler: &mut ffmpeg_next::software::scaling::Context, frame: &ffmpeg_next::util::frame::video::Video) -> Result<Vec<u8>, String> {
+    use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+
+    let mut rgb = ffmpeg_next::util::frame::video::Video::empty();
+    scaler.run(frame, &mut rgb).map_err(|e| e.to_string())?;
+
+    let pixbuf = Pixbuf::from_mut_slice(rgb.data(0).to_vec(), Colorspace::Rgb, false, 8, rgb.width() as i32, rgb.height() as i32, rgb.stride(0) as i32);
+    pixbuf.save_to_bufferv("jpeg", &[]).map_err(|e| e.message().to_string())
+}
+
+/// Reads duration, bitrate, codecs, dimensions, frame rate and tags with FFmpeg
+#[cfg(feature = "video")]
+pub fn read_media_info<P: AsRef<Path>>(file_path: P) -> Result<MediaInfo, String> {
+    use ffmpeg_next::{codec, format::stream::Disposition, media::Type};
+
+    ffmpeg_next::init().map_err(|e| e.to_string())?;
+    let mut context = ffmpeg_next::format::input(&file_path.as_ref()).map_err(|e| e.to_string())?;
+
+    let mut info = MediaInfo {
+        // In AV_TIME_BASE(microseconds)
+        duration_ms: Some(context.duration()).filter(|duration| *duration > 0).map(|duration| duration as u64 / 1000),
+        bitrate: Some(context.bit_rate()).filter(|bitrate| *bitrate > 0).map(|bitrate| bitrate as u64),
+        ..Default::default()
+    };
+
+    let metadata = context.metadata();
+    info.title = metadata.get("title").map(|title| title.to_string());
+    info.artist = metadata.get("artist").map(|artist| artist.to_string());
+    info.album = metadata.get("album").map(|album| album.to_string());
+
+    let mut album_art_index = None;
+    for stream in context.streams() {
+        let parameters = stream.parameters();
+        match parameters.medium() {
+            // Album art is stored as a video stream with a single picture
+            Type::Video if stream.disposition().contains(Disposition::ATTACHED_PIC) => album_art_index = Some(stream.index()),
+            Type::Video if info.video_codec.is_none() => {
+                info.video_codec = Some(parameters.id().name().to_string());
+                if let Ok(video) = codec::context::Context::from_parameters(parameters).and_then(|context| context.decoder().video()) {
+                    info.width = Some(video.width());
+                    info.height = Some(video.height());
+                }
+                let frame_rate = stream.avg_frame_rate();
+                if frame_rate.numerator() > 0 && frame_rate.denominator() > 0 {
+                    info.frame_rate = Some(f64::from(frame_rate));
+                }
+            }
+            Type::Audio if info.audio_codec.is_none() => {
+                info.audio_codec = Some(parameters.id().name().to_string());
+                if let Ok(audio) = codec::context::Context::from_parameters(parameters).and_then(|context| context.decoder().audio()) {
+                    info.sample_rate = Some(audio.rate());
+                    info.channels = Some(audio.ch_layout().channels());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(index) = album_art_index {
+        info.album_art = context.packets().find(|(stream, _)| stream.index() == index).and_then(|(_, packet)| packet.data().map(|data| data.to_vec()));
+    }
+
+    Ok(info)
+}
+
+/// Reads dimensions, EXIF orientation, camera and the date taken
+pub fn read_image_info<P: AsRef<Path>>(file_path: P) -> Result<ImageInfo, String> {
+    let (_, width, height) = gtk::gdk_pixbuf::Pixbuf::file_info(file_path.as_ref()).ok_or_else(|| "Unsupported image".to_string())?;
+    let exif = super::exif::read_exif(file_path.as_ref()).unwrap_or_default();
+
+    Ok(ImageInfo {
+        width: Some(width as u32),
+        height: Some(height as u32),
+        orientation: read_orientation(exif.orientation),
+        camera_make: exif.make,
+        camera_model: exif.model,
+        date_taken: exif.date_taken,
+    })
+}
+
+/// Decodes the image and encodes it as JPEG, scaled down to fit in `size` and rotated to the EXIF orientation
+///
+/// HEIC and AVIF are decoded by libheif with the `heif` feature, and otherwise need the gdk-pixbuf loaders that libheif and libavif install.
+/// `diagnostics()` reports whether they can be decoded
+pub fn extract_image_preview<P: AsRef<Path>>(file_path: P, size: Option<Size>) -> Result<Vec<u8>, String> {
+    let path = file_path.as_ref();
+    // libheif already applies the rotation and mirroring stored in the file
+    #[cfg(feature = "heif")]
+    if is_heif(path) {
+        return encode_preview(decode_heif(path)?, size);
+    }
+
+    let pixbuf = gtk::gdk_pixbuf::Pixbuf::from_file(path).map_err(|e| e.message().to_string())?;
+    let orientation = read_orientation(super::exif::read_exif(path).and_then(|exif| exif.orientation));
+    encode_preview(orient(&pixbuf, orientation).ok_or("Failed to rotate image".to_string())?, size)
+}
+
+fn encode_preview(pixbuf: gtk::gdk_pixbuf::Pixbuf, size: Option<Size>) -> Result<Vec<u8>, String> {
+    let (width, height) = fit_size(pixbuf.width() as u32, pixbuf.height() as u32, size);
+    let pixbuf = if width != pixbuf.width() as u32 || height != pixbuf.height() as u32 {
+        pixbuf.scale_simple(width as i32, height as i32, gtk::gdk_pixbuf::InterpType::Bilinear).ok_or("Failed to scale image".to_string())?
+    } else {
+        pixbuf
+    };
+
+    pixbuf.save_to_bufferv("jpeg", &[]).map_err(|e| e.message().to_string())
+}
+
+#[cfg(feature = "heif")]
+fn is_heif(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| ["heic", "heif", "avif"].contains(&extension.to_string_lossy().to_lowercase().as_str()))
+}
+
+/* Decodes the primary image of a HEIF container, which holds HEVC for HEIC and AV1 for AVIF */
+#[cfg(feature = "heif")]
+fn decode_heif(path: &Path) -> Result<gtk::gdk_pixbuf::Pixbuf, String> {
+    use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let context = HeifContext::read_from_file(&path.to_string_lossy()).map_err(|e| e.to_string())?;
+    let handle = context.primary_image_handle().map_err(|e| e.to_string())?;
+    let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None).map_err(|e| e.to_string())?;
+    let plane = image.planes().interleaved.ok_or("Failed to decode image".to_string())?;
+
+    Ok(Pixbuf::from_mut_slice(plane.data.to_vec(), Colorspace::Rgb, false, 8, plane.width as i32, plane.height as i32, plane.stride as i32))
+}
+
+fn read_orientation(orientation: Option<u16>) -> u16 {
+    orientation.filter(|orientation| (1..=8).contains(orientation)).unwrap_or(1)
+}
+
 fn get_video_thumbnail<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, String> {
+    #[cfg(feature = "raw")]
+    if crate::raw::is_raw(path.as_ref()) {
+        if let Some(thumbnail) = get_raw_thumbnail(path.as_ref()) {
+            return Ok(thumbnail);
+        }
+    }
+
     let attributes = "thumbnail::path-normal,thumbnail::path-large,thumbnail::path-xlarge";
     let file = gio::File::for_path(path.as_ref());
     let info = file.query_info(attributes, FileQueryInfoFlags::NONE, Cancellable::NONE).map_err(|e| e.message().to_string())?;
     for attribute in attributes.split(",") {
         if let Some(thumbnail) = info.attribute_byte_string(attribute) {
-            return std::fs::read(thumbnail).map_err(|e| e.to_string());
+            let thumbnail = std::fs::read(thumbnail).map_err(|e| e.to_string())?;
+            // Thumbnailers keep the pixel values of the source, so they are converted with its profile. Images without one are left as they are
+            let thumbnail = if crate::color::is_enabled() {
+                convert_to_srgb(path.as_ref(), &thumbnail).unwrap_or(thumbnail)
+            } else {
+                thumbnail
+            };
+            return apply_orientation(path.as_ref(), thumbnail);
         }
     }
 
     Err("No thumbnails available".to_string())
 }
+
+/* Converts the thumbnail with the ICC profile that gdk-pixbuf reads from the source image to sRGB */
+fn convert_to_srgb(path: &Path, thumbnail: &[u8]) -> Option<Vec<u8>> {
+    use gtk::gdk_pixbuf::{prelude::PixbufLoaderExt, Pixbuf, PixbufLoader};
+
+    // Loading at a small size is enough to read the profile, which loaders keep as a base64 option
+    let source = Pixbuf::from_file_at_scale(path, 16, 16, true).ok()?;
+    let icc = gtk::glib::base64_decode(&source.option("icc-profile")?);
+    let profile = qcms::Profile::new_from_slice(&icc, false)?;
+    let mut srgb = qcms::Profile::new_sRGB();
+    srgb.precache_output_transform();
+
+    let loader = PixbufLoader::new();
+    loader.write(thumbnail).ok()?;
+    loader.close().ok()?;
+    let pixbuf = loader.pixbuf()?;
+
+    let data_type = if pixbuf.has_alpha() {
+        qcms::DataType::RGBA8
+    } else {
+        qcms::DataType::RGB8
+    };
+    let transform = qcms::Transform::new(&profile, &srgb, data_type, qcms::Intent::Perceptual)?;
+
+    // Rows are transformed one by one since they may be padded
+    let (width, channels, rowstride) = (pixbuf.width() as usize, pixbuf.n_channels() as usize, pixbuf.rowstride() as usize);
+    let pixels = unsafe { pixbuf.pixels() };
+    for row in pixels.chunks_mut(rowstride) {
+        if let Some(row) = row.get_mut(..width * channels) {
+            transform.apply(row);
+        }
+    }
+
+    pixbuf.save_to_bufferv("png", &[]).ok()
+}
+
+/* Rotates the thumbnail of a photo to the EXIF orientation unless the aspect ratio shows the thumbnailer already rotated it */
+fn apply_orientation(path: &Path, thumbnail: Vec<u8>) -> Result<Vec<u8>, String> {
+    use gtk::gdk_pixbuf::{prelude::PixbufLoaderExt, Pixbuf, PixbufLoader};
+
+    let orientation = read_orientation(super::exif::read_exif(path).and_then(|exif| exif.orientation));
+    if orientation == 1 {
+        return Ok(thumbnail);
+    }
+
+    let loader = PixbufLoader::new();
+    loader.write(&thumbnail).map_err(|e| e.message().to_string())?;
+    loader.close().map_err(|e| e.message().to_string())?;
+    let pixbuf = loader.pixbuf().ok_or("Failed to decode thumbnail".to_string())?;
+
+    if orientation >= 5 {
+        if let Some((_, width, height)) = Pixbuf::file_info(path) {
+            if width != height && (width > height) != (pixbuf.width() > pixbuf.height()) {
+                return Ok(thumbnail);
+            }
+        }
+    }
+
+    let oriented = orient(&pixbuf, orientation).ok_or("Failed to rotate thumbnail".to_string())?;
+
+    oriented.save_to_bufferv("png", &[]).map_err(|e| e.message().to_string())
+}
+
+fn orient(pixbuf: &gtk::gdk_pixbuf::Pixbuf, orientation: u16) -> Option<gtk::gdk_pixbuf::Pixbuf> {
+    use gtk::gdk_pixbuf::PixbufRotation;
+
+    match orientation {
+        1 => Some(pixbuf.clone()),
+        2 => pixbuf.flip(true),
+        3 => pixbuf.rotate_simple(PixbufRotation::Upsidedown),
+        4 => pixbuf.flip(false),
+        5 => pixbuf.rotate_simple(PixbufRotation::Clockwise).and_then(|pixbuf| pixbuf.flip(true)),
+        6 => pixbuf.rotate_simple(PixbufRotation::Clockwise),
+        7 => pixbuf.rotate_simple(PixbufRotation::Clockwise).and_then(|pixbuf| pixbuf.flip(false)),
+        _ => pixbuf.rotate_simple(PixbufRotation::Counterclockwise),
+    }
+}
+
+/* Rotates the JPEG preview embedded in a RAW photo, which is faster than a RAW thumbnailer and works without one */
+#[cfg(feature = "raw")]
+fn get_raw_thumbnail(path: &Path) -> Option<Vec<u8>> {
+    use gtk::gdk_pixbuf::{prelude::PixbufLoaderExt, PixbufLoader};
+
+    let preview = crate::raw::read_preview(path)?;
+    if preview.orientation == 1 {
+        return Some(preview.jpeg);
+    }
+
+    let loader = PixbufLoader::new();
+    loader.write(&preview.jpeg).ok()?;
+    loader.close().ok()?;
+    orient(&loader.pixbuf()?, preview.orientation)?.save_to_bufferv("jpeg", &[]).ok()
+}
+
+/// # D-Bus interface proxy for `org.freedesktop.thumbnails.Thumbnailer1` interface.
+// https://wiki.gnome.org/DraftSpecs/ThumbnailerSpec
+#[zbus::proxy(
+    gen_async = false,
+    interface = "org.freedesktop.thumbnails.Thumbnailer1",
+    default_service = "org.freedesktop.thumbnails.Thumbnailer1",
+    default_path = "/org/freedesktop/thumbnails/Thumbnailer1"
+)]
+trait Thumbnailer1 {
+    fn queue(&self, uris: &[&str], mime_types: &[&str], flavor: &str, scheduler: &str, handle_to_unqueue: u32) -> zbus::Result<u32>;
+}
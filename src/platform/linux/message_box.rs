@@ -0,0 +1,89 @@
+use super::util::{init, place_window};
+use crate::dialog::{MessageDialogKind, MessageDialogOptions};
+use gtk::{
+    prelude::{BoxExt, Cast, ContainerExt, DialogExt, DialogExtManual, GtkWindowExt, LabelExt, MessageDialogExt, ToggleButtonExt, WidgetExt},
+    ButtonsType, CheckButton, DialogFlags, Expander, Label, MessageDialog, MessageType, ResponseType,
+};
+
+/// Shows a modal message dialog and returns the index of the pressed button, or None if closed with Esc, with the state of the checkbox
+///
+/// Must be awaited on the GTK main thread
+pub(crate) async fn show(options: &MessageDialogOptions, buttons: &[String], default_id: Option<usize>) -> (Option<usize>, bool) {
+    init();
+
+    let message_type = match options.kind {
+        Some(MessageDialogKind::Warning) => MessageType::Warning,
+        Some(MessageDialogKind::Error) => MessageType::Error,
+        _ => MessageType::Info,
+    };
+
+    let dialog = MessageDialog::new(gtk::Window::NONE, DialogFlags::MODAL, message_type, ButtonsType::None, &options.message);
+    dialog.set_title(options.title.as_deref().unwrap_or_default());
+
+    // Buttons are focused in the order they are added
+    for (index, label) in buttons.iter().enumerate() {
+        dialog.add_button(&to_mnemonic(label), ResponseType::Other(index as u16));
+    }
+
+    // The message area is a vertical box below the text, where the detail and the checkbox are added in the order of a task dialog
+    let message_area = dialog.message_area().downcast::<gtk::Box>().ok();
+
+    if let (Some(detail), Some(message_area)) = (&options.detail, &message_area) {
+        let label = Label::new(Some(detail));
+        label.set_selectable(true);
+        label.set_line_wrap(true);
+        label.set_xalign(0.0);
+        let expander = Expander::with_mnemonic("_Details");
+        expander.add(&label);
+        message_area.pack_start(&expander, false, false, 0);
+    }
+
+    let checkbox = options.checkbox_label.as_ref().map(|checkbox_label| CheckButton::with_mnemonic(&to_mnemonic(checkbox_label)));
+    if let (Some(checkbox), Some(message_area)) = (&checkbox, &message_area) {
+        checkbox.set_active(options.checkbox_checked);
+        message_area.pack_start(checkbox, false, false, 0);
+    }
+
+    if let Some(message_area) = &message_area {
+        message_area.show_all();
+    }
+
+    if let Some(default_id) = default_id {
+        let response = ResponseType::Other(default_id as u16);
+        dialog.set_default_response(response);
+        if let Some(button) = dialog.widget_for_response(response) {
+            button.grab_focus();
+        }
+    }
+
+    if let Some(placement) = &options.placement {
+        place_window(&dialog, placement);
+    }
+
+    let response = dialog.run_future().await;
+    let checked = checkbox.map(|checkbox| checkbox.is_active()).unwrap_or_default();
+    dialog.close();
+
+    match response {
+        ResponseType::Other(index) => (Some(index as usize), checked),
+        _ => (None, checked),
+    }
+}
+
+/* "&Replace" to "_Replace". "&&" is a literal ampersand */
+fn to_mnemonic(label: &str) -> String {
+    let mut mnemonic = String::new();
+    let mut chars = label.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' if chars.peek() == Some(&'&') => {
+                chars.next();
+                mnemonic.push('&');
+            }
+            '&' => mnemonic.push('_'),
+            '_' => mnemonic.push_str("__"),
+            _ => mnemonic.push(c),
+        }
+    }
+    mnemonic
+}
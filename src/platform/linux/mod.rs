@@ -1,9 +1,20 @@
 pub mod clipboard;
+#[cfg(feature = "device")]
 pub mod device;
+pub(crate) mod diagnostics;
+#[cfg(feature = "drag_drop")]
 pub mod drag_drop;
+#[cfg(feature = "media")]
+mod exif;
+pub(crate) mod file_dialog;
 pub mod fs;
 mod fs_ext;
+#[cfg(feature = "instant_search")]
+pub(crate) mod locate;
+#[cfg(feature = "media")]
 pub mod media;
+pub(crate) mod message_box;
+pub(crate) mod progress_dialog;
 pub mod shell;
 mod util;
 #[cfg(feature = "webkit2gtk")]
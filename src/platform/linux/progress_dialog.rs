@@ -0,0 +1,83 @@
+use super::util::{init, place_window};
+use crate::dialog::{ProgressDialogOptions, ProgressHandle};
+use gtk::{
+    glib::{self, ControlFlow},
+    prelude::{BoxExt, ButtonExt, Cast, ContainerExt, DialogExt, GtkWindowExt, LabelExt, ProgressBarExt, WidgetExt},
+    Dialog, DialogFlags, Label, Orientation, ProgressBar, ResponseType,
+};
+use std::time::Duration;
+
+const PAUSE_RESPONSE: ResponseType = ResponseType::Other(0);
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shows a GTK dialog with a progress bar that follows the handle until it is closed or cancelled
+///
+/// Must be called on the GTK main thread
+pub(crate) fn show(options: &ProgressDialogOptions, handle: ProgressHandle) {
+    init();
+
+    let dialog = Dialog::with_buttons(options.title.as_deref(), gtk::Window::NONE, DialogFlags::DESTROY_WITH_PARENT, &[]);
+    dialog.set_default_width(420);
+    dialog.set_resizable(false);
+
+    let message = Label::new(None);
+    message.set_xalign(0.0);
+    message.set_line_wrap(true);
+    let progress_bar = ProgressBar::new();
+    let detail = Label::new(None);
+    detail.set_xalign(0.0);
+    detail.set_ellipsize(gtk::pango::EllipsizeMode::Middle);
+
+    let content = gtk::Box::new(Orientation::Vertical, 8);
+    content.set_border_width(12);
+    content.pack_start(&message, false, false, 0);
+    content.pack_start(&progress_bar, false, false, 0);
+    content.pack_start(&detail, false, false, 0);
+    dialog.content_area().pack_start(&content, true, true, 0);
+
+    let pause_button = options.pausable.then(|| dialog.add_button("_Pause", PAUSE_RESPONSE)).and_then(|button| button.downcast::<gtk::Button>().ok());
+    dialog.add_button("_Cancel", ResponseType::Cancel);
+
+    if let Some(placement) = &options.placement {
+        place_window(&dialog, placement);
+    }
+
+    let response_handle = handle.clone();
+    dialog.connect_response(move |_, response| {
+        if response != PAUSE_RESPONSE {
+            // Cancel, Esc and the close button. Closing through the handle also ends up here, when it does nothing
+            response_handle.cancel();
+            return;
+        }
+
+        let paused = response_handle.toggle_pause();
+        if let Some(pause_button) = &pause_button {
+            pause_button.set_label(if paused {
+                "_Resume"
+            } else {
+                "_Pause"
+            });
+        }
+    });
+
+    dialog.show_all();
+
+    let mut revision = u64::MAX;
+    glib::timeout_add_local(UPDATE_INTERVAL, move || {
+        let state = handle.state();
+        // Closed by the handle or after Cancel
+        if state.closed {
+            dialog.close();
+            return ControlFlow::Break;
+        }
+
+        if revision != state.revision {
+            revision = state.revision;
+            message.set_text(&state.message);
+            progress_bar.set_fraction(state.fraction);
+            detail.set_text(&state.detail);
+        }
+
+        ControlFlow::Continue
+    });
+}
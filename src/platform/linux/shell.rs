@@ -1,36 +1,62 @@
 use super::{fs::get_mime_type, util::init};
 use crate::{
     fs::get_mime_type_fallback,
-    platform::linux::util::{reveal_with_dbus, show_item_properties},
-    AppInfo, Icon, Size, ThumbButton,
+    platform::linux::util::{path_to_uri, reveal_with_dbus, share_with_open_uri_portal, show_item_properties},
+    AppInfo, Breadcrumb, BreadcrumbKind, DefaultAction, ExecuteOptions, ExecuteResult, FolderViewHints, Icon, JumpListCategory, PropertyValue, ShellCapabilities, ShellColumn, ShellVerb, Size,
 };
 use gio::glib::clone;
 use gtk::{
     gio::{
         self,
-        glib::{Cast, GString},
-        prelude::{AppInfoExt, FileExt},
-        AppInfoCreateFlags, AppLaunchContext, File, FileIcon, ThemedIcon,
+        glib::{Cast, GString, KeyFile, Pid, SpawnFlags},
+        prelude::{AppInfoExt, AppLaunchContextExt, FileExt},
+        AppInfoCreateFlags, AppLaunchContext, DesktopAppInfo, File, FileIcon, ThemedIcon,
     },
     prelude::{AppChooserExt, IconThemeExt, WidgetExt},
     traits::{AppChooserDialogExt, AppChooserWidgetExt, DialogExt, GtkWindowExt},
     AppChooserDialog, DialogFlags, IconLookupFlags, IconSize, IconTheme, ResponseType,
 };
-use std::path::Path;
+use std::{cell::RefCell, collections::HashMap, path::Path, rc::Rc};
+
+/* Column key and display name */
+const COLUMNS: [(&str, &str); 10] = [
+    ("size", "Size"),
+    ("modified", "Date modified"),
+    ("type", "Type"),
+    ("owner", "Owner"),
+    ("dimensions", "Dimensions"),
+    ("duration", "Length"),
+    ("title", "Title"),
+    ("artist", "Contributing artists"),
+    ("album", "Album"),
+    ("bitrate", "Bit rate"),
+];
+/* Windows property name and the extended attribute used by freedesktop and Baloo */
+const PROPERTY_ATTRIBUTES: [(&str, &str); 4] =
+    [("System.Title", "xattr::xdg.title"), ("System.Comment", "xattr::xdg.comment"), ("System.Keywords", "xattr::xdg.tags"), ("System.Rating", "xattr::baloo.rating")];
+#[cfg(feature = "video")]
+const MEDIA_COLUMNS: [&str; 5] = ["duration", "title", "artist", "album", "bitrate"];
+const FOLDER_TYPE_ATTRIBUTE: &str = "metadata::folder-type";
+// URI of the folder icon, as Nautilus and Nemo store it
+const CUSTOM_ICON_ATTRIBUTE: &str = "metadata::custom-icon";
+const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
 
 /// Opens the file with the default/associated application
 pub fn open_path<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
+    crate::policy::check_execute(file_path.as_ref())?;
     let uri = format!("file://{}", file_path.as_ref().to_str().unwrap());
     gtk::gio::AppInfo::launch_default_for_uri(&uri, AppLaunchContext::NONE).map_err(|e| e.message().to_string())
 }
 
 /// Opens the file with the specified application
 pub fn open_path_with<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2) -> Result<(), String> {
+    crate::policy::check_execute(app_path.as_ref())?;
     let info = gtk::gio::AppInfo::create_from_commandline(app_path.as_ref(), None, AppInfoCreateFlags::NONE).map_err(|e| e.message().to_string())?;
     info.launch(&[File::for_path(file_path)], AppLaunchContext::NONE).map_err(|e| e.message().to_string())
 }
 
 pub fn execute<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2) -> Result<(), String> {
+    crate::policy::check_execute(app_path.as_ref())?;
     let info = gtk::gio::AppInfo::create_from_commandline(app_path.as_ref(), None, AppInfoCreateFlags::NEEDS_TERMINAL).map_err(|e| e.message().to_string())?;
     info.launch(&[File::for_path(file_path)], AppLaunchContext::NONE).map_err(|e| e.message().to_string())
 }
@@ -39,6 +65,70 @@ pub fn execute_as<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2)
     execute(file_path, app_path)
 }
 
+/// Starts the application with the file, passing the arguments, working directory and environment in the options
+///
+/// The show state is ignored since the window manager decides it
+pub fn execute_with<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2, options: &ExecuteOptions) -> Result<ExecuteResult, String> {
+    crate::policy::check_execute(app_path.as_ref())?;
+
+    // A desktop entry lets GIO split the arguments and change the working directory
+    let exec = std::iter::once(app_path.as_ref().to_string_lossy().to_string()).chain(options.args.iter().cloned()).map(|arg| quote_exec_arg(&arg)).collect::<Vec<_>>().join(" ");
+    let key_file = KeyFile::new();
+    key_file.set_string(DESKTOP_ENTRY_GROUP, "Type", "Application");
+    key_file.set_string(DESKTOP_ENTRY_GROUP, "Name", &app_path.as_ref().file_name().unwrap_or_default().to_string_lossy());
+    key_file.set_string(DESKTOP_ENTRY_GROUP, "Exec", &format!("{} %f", exec));
+    if let Some(cwd) = &options.cwd {
+        key_file.set_string(DESKTOP_ENTRY_GROUP, "Path", cwd);
+    }
+    let info = DesktopAppInfo::from_keyfile(&key_file).ok_or_else(|| format!("Failed to launch:{}", app_path.as_ref().to_string_lossy()))?;
+
+    let context = AppLaunchContext::new();
+    for (name, value) in &options.env {
+        context.setenv(name, value);
+    }
+
+    // The child has to be left unreaped for waitpid to get its exit status
+    let flags = if options.wait {
+        SpawnFlags::SEARCH_PATH | SpawnFlags::DO_NOT_REAP_CHILD
+    } else {
+        SpawnFlags::SEARCH_PATH
+    };
+    let mut pid = None;
+    let uri = File::for_path(file_path).uri();
+    info.launch_uris_as_manager(&[uri.as_str()], Some(&context), flags, None, Some(&mut |_: &DesktopAppInfo, child: Pid| pid = Some(child.0))).map_err(|e| e.message().to_string())?;
+
+    let mut exit_code = None;
+    if let Some(pid) = pid.filter(|_| options.wait) {
+        let mut status = 0;
+        if unsafe { libc::waitpid(pid, &mut status, 0) } == pid && libc::WIFEXITED(status) {
+            exit_code = Some(libc::WEXITSTATUS(status));
+        }
+    }
+
+    Ok(ExecuteResult {
+        pid: pid.map(|pid| pid as u32),
+        exit_code,
+    })
+}
+
+/* Quotes the argument for the Exec key, where % starts a field code */
+fn quote_exec_arg(arg: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        match c {
+            '"' | '`' | '$' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
 /// Shows the application chooser dialog
 pub fn show_open_with_dialog<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
     use gtk::glib;
@@ -87,6 +177,43 @@ pub fn show_open_with_dialog<P: AsRef<Path>>(file_path: P) -> Result<(), String>
     Ok(())
 }
 
+/// Shows the application chooser dialog without launching the chosen application
+///
+/// `callback` receives None when the dialog is cancelled
+pub fn choose_open_with_app<P: AsRef<Path>, F: FnOnce(Option<AppInfo>) + 'static>(file_path: P, callback: F) -> Result<(), String> {
+    init();
+
+    let file = File::for_path(file_path.as_ref());
+    let dialog = AppChooserDialog::new(gtk::Window::NONE, DialogFlags::DESTROY_WITH_PARENT, &file);
+
+    // Closing the dialog emits another response
+    let callback = RefCell::new(Some(callback));
+    dialog.connect_response(move |dialog, response_type| {
+        if let Some(callback) = callback.take() {
+            let app_info = if response_type == ResponseType::Ok {
+                dialog.app_info().map(|app_info| to_app_info(&app_info))
+            } else {
+                None
+            };
+            callback(app_info);
+        }
+
+        dialog.close();
+    });
+
+    dialog.show();
+
+    Ok(())
+}
+
+fn to_app_info(app_info: &gio::AppInfo) -> AppInfo {
+    AppInfo {
+        path: app_info.commandline().unwrap_or_default().to_string_lossy().to_string(),
+        name: app_info.display_name().to_string(),
+        icon_path: to_path_from_gicon(app_info.icon(), None),
+    }
+}
+
 fn to_path_from_gicon(icon: Option<gio::Icon>, size: Option<i32>) -> String {
     init();
     if let Some(icon) = icon {
@@ -136,6 +263,47 @@ pub fn get_open_with<P: AsRef<Path>>(file_path: P) -> Vec<AppInfo> {
     apps
 }
 
+/// Returns the application that opens files with the extension such as "txt" or ".txt"
+pub fn get_default_app(extension: &str) -> Option<AppInfo> {
+    let content_type = get_content_type_for_extension(extension);
+    let app_info = gtk::gio::AppInfo::default_for_type(&content_type, false)?;
+
+    Some(to_app_info(&app_info))
+}
+
+/// Sets the application listed by `get_open_with` as the default for the extension
+pub fn set_default_app<P: AsRef<Path>>(extension: &str, app_path: P) -> Result<(), String> {
+    let content_type = get_content_type_for_extension(extension);
+    let app_info = find_app_info(&content_type, app_path.as_ref())?;
+    app_info.set_as_default_for_type(&content_type).map_err(|e| e.message().to_string())
+}
+
+/// Makes the application chosen by `choose_open_with_app` the default for the extension such as "txt" or MIME type such as "text/plain"
+pub fn set_always_open_with(extension_or_mime: &str, app: &AppInfo) -> Result<(), String> {
+    let content_type = if extension_or_mime.contains('/') {
+        extension_or_mime.to_string()
+    } else {
+        get_content_type_for_extension(extension_or_mime)
+    };
+
+    let app_info = find_app_info(&content_type, Path::new(&app.path))?;
+    app_info.set_as_default_for_type(&content_type).map_err(|e| e.message().to_string())
+}
+
+/* Applications are identified by their command line */
+fn find_app_info(content_type: &str, app_path: &Path) -> Result<gio::AppInfo, String> {
+    gtk::gio::AppInfo::all_for_type(content_type)
+        .into_iter()
+        .find(|app_info| app_info.commandline().is_some_and(|commandline| commandline == app_path))
+        .map(Ok)
+        .unwrap_or_else(|| gtk::gio::AppInfo::create_from_commandline(app_path, None, AppInfoCreateFlags::NONE).map_err(|e| e.message().to_string()))
+}
+
+fn get_content_type_for_extension(extension: &str) -> String {
+    let (content_type, _) = gtk::gio::content_type_guess(Some(format!("file.{}", extension.trim_start_matches('.'))), &[0]);
+    content_type.to_string()
+}
+
 /// Extracts an icon from executable/icon file or an icon stored in a file's associated executable file
 pub fn extract_icon<P: AsRef<Path>>(path_or_name: P, size: Size) -> Result<Icon, String> {
     init();
@@ -167,12 +335,555 @@ pub fn show_item_in_folder<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
     reveal_with_dbus(file_path)
 }
 
+/// Opens the app chooser of the desktop portal to send the file to another app
+///
+/// Only a single file can be shared on Linux
+pub fn share<P: AsRef<Path>>(file_paths: &[P], _window_handle: isize) -> Result<(), String> {
+    match file_paths {
+        [] => Ok(()),
+        [file_path] => share_with_open_uri_portal(file_path),
+        _ => Err("Sharing several files is not supported".to_string()),
+    }
+}
+
+#[cfg(feature = "thumbbar")]
 #[allow(unused_variables)]
 /// Does nothing on Linux
-pub fn set_thumbar_buttons<F: Fn(String) + 'static>(window_handle: isize, buttons: &[ThumbButton], callback: F) -> Result<(), String> {
+pub fn set_thumbar_buttons<F: Fn(String) + 'static>(window_handle: isize, buttons: &[crate::ThumbButton], callback: F) -> Result<(), String> {
     Ok(())
 }
 
+/// Shows the context menu of the files at the position in client coordinates
+///
+/// `callback` receives the verb of the invoked command such as "open", "copy" or "properties".
+/// Blocks until the menu is closed, returning the error if the command fails
+pub fn show_context_menu<P: AsRef<Path>, F: FnOnce(String) + 'static>(_window_handle: isize, file_paths: &[P], x: i32, y: i32, callback: F) -> Result<(), String> {
+    use gtk::prelude::{GtkMenuExt, GtkMenuItemExt, MenuShellExt, WidgetExtManual};
+
+    init();
+
+    let paths: Vec<String> = file_paths.iter().map(|path| path.as_ref().to_string_lossy().to_string()).collect();
+    let Some(first_path) = paths.first() else {
+        return Err("No files specified".to_string());
+    };
+
+    let widgets = gtk::Window::list_toplevels();
+    let Some(widget) = widgets.first() else {
+        return Err("No window found".to_string());
+    };
+    let Some(window) = widget.window() else {
+        return Err("Window is not realized".to_string());
+    };
+
+    // Verb and result of the activated item
+    let invoked: Rc<RefCell<Option<(String, Result<(), String>)>>> = Rc::new(RefCell::new(None));
+
+    let menu = gtk::Menu::new();
+    menu.set_attach_widget(Some(widget));
+
+    let add_item = |menu: &gtk::Menu, label: &str, verb: String, action: Box<dyn Fn(&[String]) -> Result<(), String>>| {
+        let item = gtk::MenuItem::with_label(label);
+        let paths = paths.clone();
+        let invoked = invoked.clone();
+        item.connect_activate(move |_| {
+            *invoked.borrow_mut() = Some((verb.clone(), action(&paths)));
+        });
+        menu.append(&item);
+    };
+
+    add_item(&menu, "Open", "open".to_string(), Box::new(|paths| paths.iter().try_for_each(open_path)));
+
+    let open_with_menu = gtk::Menu::new();
+    for app_info in gtk::gio::AppInfo::all_for_type(&get_mime_type(first_path)) {
+        let id = app_info.id().unwrap_or_default().to_string();
+        add_item(
+            &open_with_menu,
+            &app_info.display_name(),
+            format!("openwith:{}", id),
+            Box::new(move |paths| {
+                let files: Vec<File> = paths.iter().map(File::for_path).collect();
+                app_info.launch(&files, AppLaunchContext::NONE).map_err(|e| e.message().to_string())
+            }),
+        );
+    }
+    open_with_menu.append(&gtk::SeparatorMenuItem::new());
+    add_item(&open_with_menu, "Other Application...", "openwith".to_string(), Box::new(|paths| paths.first().map_or(Ok(()), show_open_with_dialog)));
+    let open_with_item = gtk::MenuItem::with_label("Open With");
+    open_with_item.set_submenu(Some(&open_with_menu));
+    menu.append(&open_with_item);
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+    add_item(&menu, "Cut", "cut".to_string(), Box::new(|paths| crate::clipboard::write_uris(0, paths, crate::Operation::Move)));
+    add_item(&menu, "Copy", "copy".to_string(), Box::new(|paths| crate::clipboard::write_uris(0, paths, crate::Operation::Copy)));
+
+    let send_to_menu = gtk::Menu::new();
+    for (label, destination) in get_send_to_destinations() {
+        add_item(&send_to_menu, &label, format!("sendto:{}", destination), Box::new(move |paths| crate::fs::copy_all(paths, &destination)));
+    }
+    let send_to_item = gtk::MenuItem::with_label("Send To");
+    send_to_item.set_submenu(Some(&send_to_menu));
+    menu.append(&send_to_item);
+
+    add_item(&menu, "Move to Trash", "trash".to_string(), Box::new(|paths| crate::fs::trash_all(paths)));
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+    add_item(&menu, "Properties", "properties".to_string(), Box::new(|paths| paths.first().map_or(Ok(()), open_file_property)));
+
+    // Runs until the menu is closed, like TrackPopupMenuEx on Windows
+    let main_loop = gtk::glib::MainLoop::new(None, false);
+    let deactivate_loop = main_loop.clone();
+    menu.connect_deactivate(move |_| {
+        let main_loop = deactivate_loop.clone();
+        // Quit after the activated item is handled
+        gtk::glib::idle_add_local_once(move || main_loop.quit());
+    });
+
+    menu.show_all();
+    menu.popup_at_rect(&window, &gtk::gdk::Rectangle::new(x, y, 1, 1), gtk::gdk::Gravity::NorthWest, gtk::gdk::Gravity::NorthWest, None);
+    main_loop.run();
+    unsafe { menu.destroy() };
+
+    let invoked = invoked.borrow_mut().take();
+    if let Some((verb, result)) = invoked {
+        result.map_err(|e| format!("Failed to run {}: {}", verb, e))?;
+        callback(verb);
+    }
+
+    Ok(())
+}
+
+/// Lists the verbs of the file such as "open", "openwith:<app id>", desktop actions of the default application and "properties"
+pub fn list_verbs<P: AsRef<Path>>(file_path: P) -> Result<Vec<ShellVerb>, String> {
+    let content_type = get_mime_type_fallback(file_path.as_ref())?;
+    let default_app = gtk::gio::AppInfo::default_for_type(&content_type, false);
+
+    let mut verbs = vec![ShellVerb {
+        id: "open".to_string(),
+        name: match &default_app {
+            Some(app_info) => format!("Open With {}", app_info.display_name()),
+            None => "Open".to_string(),
+        },
+    }];
+
+    let default_id = default_app.as_ref().and_then(|app_info| app_info.id());
+    for app_info in gtk::gio::AppInfo::all_for_type(&content_type) {
+        let Some(id) = app_info.id() else {
+            continue;
+        };
+        if Some(&id) == default_id.as_ref() {
+            continue;
+        }
+        verbs.push(ShellVerb {
+            id: format!("openwith:{}", id),
+            name: format!("Open With {}", app_info.display_name()),
+        });
+    }
+
+    // Actions such as "New Window" defined in the desktop file
+    if let Some(desktop_app_info) = default_id.as_ref().and_then(|id| gtk::gio::DesktopAppInfo::new(id)) {
+        for action in desktop_app_info.list_actions() {
+            verbs.push(ShellVerb {
+                id: format!("action:{}:{}", default_id.as_ref().unwrap(), action),
+                name: desktop_app_info.action_name(&action).to_string(),
+            });
+        }
+    }
+
+    verbs.push(ShellVerb {
+        id: "properties".to_string(),
+        name: "Properties".to_string(),
+    });
+
+    Ok(verbs)
+}
+
+/// Invokes the verb returned by `list_verbs`
+pub fn invoke_verb<P: AsRef<Path>>(file_path: P, verb: &str) -> Result<(), String> {
+    match verb {
+        "open" => open_path(file_path),
+        "properties" => open_file_property(file_path),
+        _ => {
+            if let Some(id) = verb.strip_prefix("openwith:") {
+                let app_info = gtk::gio::DesktopAppInfo::new(id).ok_or_else(|| format!("Application not found: {}", id))?;
+                crate::policy::check_execute(app_info.filename().unwrap_or_default())?;
+                return app_info.launch(&[File::for_path(file_path)], AppLaunchContext::NONE).map_err(|e| e.message().to_string());
+            }
+
+            if let Some((id, action)) = verb.strip_prefix("action:").and_then(|action| action.split_once(':')) {
+                let app_info = gtk::gio::DesktopAppInfo::new(id).ok_or_else(|| format!("Application not found: {}", id))?;
+                app_info.launch_action(action, AppLaunchContext::NONE);
+                return Ok(());
+            }
+
+            Err(format!("Unknown verb: {}", verb))
+        }
+    }
+}
+
+/// Queries what the file allows from its GIO access attributes
+pub fn capabilities<P: AsRef<Path>>(path: P) -> Result<ShellCapabilities, String> {
+    use gtk::gio::prelude::{DriveExt, MountExt};
+
+    let file = File::for_path(path.as_ref());
+    let info = file
+        .query_info("access::can-read,access::can-rename,access::can-delete,standard::type", gio::FileQueryInfoFlags::NOFOLLOW_SYMLINKS, gio::Cancellable::NONE)
+        .map_err(|e| e.message().to_string())?;
+
+    let is_removable_media = match file.find_enclosing_mount(gio::Cancellable::NONE) {
+        Ok(mount) => mount.can_eject() || mount.drive().map(|drive| drive.is_removable() || drive.is_media_removable()).unwrap_or(false),
+        Err(_) => false,
+    };
+
+    Ok(ShellCapabilities {
+        can_rename: info.boolean("access::can-rename"),
+        can_delete: info.boolean("access::can-delete"),
+        can_copy: info.boolean("access::can-read"),
+        // Moving removes the file from its parent
+        can_move: info.boolean("access::can-delete"),
+        can_link: file.is_native(),
+        is_folder: info.file_type() == gio::FileType::Directory,
+        is_removable_media,
+        // Properties are shown by the file manager over D-Bus
+        has_properties: file.is_native(),
+    })
+}
+
+/// Tells whether double-clicking the file would open it, run it or ask the user
+///
+/// Executable scripts, programs with the "xdg.origin.url" attribute set by browsers and files without a default application are Prompt
+pub fn get_default_action<P: AsRef<Path>>(file_path: P) -> Result<DefaultAction, String> {
+    let file = File::for_path(file_path.as_ref());
+    let info = file.query_info("standard::type,standard::content-type,access::can-execute", gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE).map_err(|e| e.message().to_string())?;
+
+    if info.file_type() == gio::FileType::Directory {
+        return Ok(DefaultAction::Open);
+    }
+
+    let content_type = info.content_type().map(|content_type| content_type.to_string()).unwrap_or_default();
+
+    if info.boolean("access::can-execute") && gio::content_type_can_be_executable(&content_type) {
+        // File managers ask whether to run or display executable text files
+        let is_script = gio::content_type_is_a(&content_type, "text/plain");
+        let is_downloaded = crate::fs::read_stream(file_path.as_ref(), "xdg.origin.url").is_ok();
+        return Ok(if is_script || is_downloaded {
+            DefaultAction::Prompt
+        } else {
+            DefaultAction::Run
+        });
+    }
+
+    if gtk::gio::AppInfo::default_for_type(&content_type, false).is_some() {
+        Ok(DefaultAction::Open)
+    } else {
+        Ok(DefaultAction::Prompt)
+    }
+}
+
+/// Splits the path or URI into segments from Computer or the server down to the location itself
+///
+/// Archive files in the middle of the path and the roots of archive:// locations are returned as Archive
+pub fn breadcrumbs(path_or_uri: &str) -> Result<Vec<Breadcrumb>, String> {
+    init();
+
+    let file = if path_or_uri.contains("://") {
+        File::for_uri(path_or_uri)
+    } else {
+        File::for_path(path_or_uri)
+    };
+
+    let mut files = vec![file];
+    while let Some(parent) = files.last().and_then(|file| file.parent()) {
+        files.push(parent);
+    }
+    files.reverse();
+
+    let mut breadcrumbs = Vec::new();
+    if files[0].is_native() {
+        breadcrumbs.push(to_breadcrumb(&File::for_uri("computer:///"), BreadcrumbKind::Root, "Computer"));
+    }
+
+    let root_kind = if files[0].is_native() {
+        BreadcrumbKind::Drive
+    } else if files[0].has_uri_scheme("archive") {
+        BreadcrumbKind::Archive
+    } else {
+        BreadcrumbKind::Host
+    };
+
+    for (index, file) in files.iter().enumerate() {
+        let kind = if index == 0 {
+            root_kind.clone()
+        } else if index == 1 && root_kind == BreadcrumbKind::Host && file.has_uri_scheme("smb") {
+            BreadcrumbKind::Share
+        } else {
+            match file.query_file_type(gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE) {
+                gio::FileType::Regular if index < files.len() - 1 => BreadcrumbKind::Archive,
+                gio::FileType::Regular => BreadcrumbKind::File,
+                _ => BreadcrumbKind::Folder,
+            }
+        };
+        let fallback_name = file.basename().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| file.uri().to_string());
+        breadcrumbs.push(to_breadcrumb(file, kind, &fallback_name));
+    }
+
+    Ok(breadcrumbs)
+}
+
+fn to_breadcrumb(file: &File, kind: BreadcrumbKind, fallback_name: &str) -> Breadcrumb {
+    let info = file.query_info("standard::display-name,standard::icon", gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE).ok();
+    let icon = to_path_from_gicon(info.as_ref().and_then(|info| info.icon()), None);
+
+    Breadcrumb {
+        name: info.as_ref().map(|info| info.display_name().to_string()).filter(|name| !name.is_empty()).unwrap_or_else(|| fallback_name.to_string()),
+        path: if file.is_native() {
+            file.path().unwrap_or_default().to_string_lossy().to_string()
+        } else {
+            file.uri().to_string()
+        },
+        kind,
+        icon: if icon.is_empty() {
+            None
+        } else {
+            Some(icon)
+        },
+    }
+}
+
+/// Lists the columns supported by `column_value`
+pub fn list_columns() -> Vec<ShellColumn> {
+    COLUMNS
+        .iter()
+        .map(|(key, name)| ShellColumn {
+            key: key.to_string(),
+            name: name.to_string(),
+        })
+        .collect()
+}
+
+/// Returns the value of the column listed by `list_columns` from GIO attributes, or None if the file has no value
+///
+/// Media columns such as "duration" require the `media` feature
+pub fn column_value<P: AsRef<Path>>(file_path: P, column_key: &str) -> Result<Option<String>, String> {
+    validate_columns(&[column_key])?;
+    Ok(read_columns(file_path.as_ref(), &[column_key]).remove(column_key))
+}
+
+/// Returns the values of the columns for each file, querying each file once
+///
+/// Columns without values are omitted
+pub fn column_values<P: AsRef<Path>>(file_paths: &[P], column_keys: &[&str]) -> Result<HashMap<String, HashMap<String, String>>, String> {
+    validate_columns(column_keys)?;
+    Ok(file_paths.iter().map(|file_path| (file_path.as_ref().to_string_lossy().to_string(), read_columns(file_path.as_ref(), column_keys))).collect())
+}
+
+fn validate_columns(column_keys: &[&str]) -> Result<(), String> {
+    match column_keys.iter().find(|column_key| !COLUMNS.iter().any(|(key, _)| key == *column_key)) {
+        Some(column_key) => Err(format!("Unknown column: {}", column_key)),
+        None => Ok(()),
+    }
+}
+
+fn read_columns(file_path: &Path, column_keys: &[&str]) -> HashMap<String, String> {
+    let info = File::for_path(file_path).query_info("standard::size,standard::content-type,time::modified,owner::user", gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE).ok();
+
+    #[cfg(feature = "video")]
+    let media_info = if column_keys.iter().any(|column_key| MEDIA_COLUMNS.contains(column_key)) {
+        crate::media::read_media_info(file_path).ok()
+    } else {
+        None
+    };
+    #[cfg(not(feature = "video"))]
+    let media_info: Option<crate::MediaInfo> = None;
+
+    let mut values = HashMap::new();
+    for column_key in column_keys {
+        let value = match *column_key {
+            "size" => info.as_ref().map(|info| gtk::glib::format_size(info.size() as u64).to_string()),
+            "modified" => info.as_ref().and_then(|info| info.modification_date_time()).and_then(|date| date.to_local().ok()).and_then(|date| date.format("%x %X").ok()).map(|date| date.to_string()),
+            "type" => info.as_ref().and_then(|info| info.content_type()).map(|content_type| gio::content_type_get_description(&content_type).to_string()),
+            "owner" => info.as_ref().and_then(|info| info.attribute_string("owner::user")).map(|owner| owner.to_string()),
+            "dimensions" => gtk::gdk_pixbuf::Pixbuf::file_info(file_path).map(|(_, width, height)| format!("{} x {}", width, height)),
+            "duration" => media_info.as_ref().and_then(|media_info| media_info.duration_ms).map(format_duration),
+            "title" => media_info.as_ref().and_then(|media_info| media_info.title.clone()),
+            "artist" => media_info.as_ref().and_then(|media_info| media_info.artist.clone()),
+            "album" => media_info.as_ref().and_then(|media_info| media_info.album.clone()),
+            "bitrate" => media_info.as_ref().and_then(|media_info| media_info.bitrate).map(|bitrate| format!("{}kbps", bitrate / 1000)),
+            _ => None,
+        };
+
+        if let Some(value) = value {
+            values.insert(column_key.to_string(), value);
+        }
+    }
+
+    values
+}
+
+/* Same as Explorer such as 00:03:25 */
+/// Reads the properties by Windows name such as "System.Rating" or by GIO attribute name such as "metadata::custom"
+///
+/// Title, Comment, Keywords and Rating are stored in extended attributes. Properties without values are omitted
+pub fn get_properties<P: AsRef<Path>>(file_path: P, keys: &[&str]) -> Result<HashMap<String, PropertyValue>, String> {
+    let attributes = keys.iter().map(|key| to_property_attribute(key)).collect::<Result<Vec<_>, String>>()?;
+    let info = File::for_path(file_path.as_ref()).query_info(&attributes.join(","), gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE).map_err(|e| e.message().to_string())?;
+
+    let mut result = HashMap::new();
+    for (key, attribute) in keys.iter().zip(&attributes) {
+        let value = match info.attribute_type(attribute) {
+            gio::FileAttributeType::String => info.attribute_string(attribute).map(|value| to_property_value(key, value.to_string())),
+            gio::FileAttributeType::ByteString => info.attribute_byte_string(attribute).map(|value| to_property_value(key, value.to_string())),
+            gio::FileAttributeType::Boolean => Some(PropertyValue::Bool(info.boolean(attribute))),
+            gio::FileAttributeType::Uint32 => Some(PropertyValue::Integer(info.attribute_uint32(attribute) as i64)),
+            gio::FileAttributeType::Int32 => Some(PropertyValue::Integer(info.attribute_int32(attribute) as i64)),
+            gio::FileAttributeType::Uint64 => Some(PropertyValue::Integer(info.attribute_uint64(attribute) as i64)),
+            gio::FileAttributeType::Int64 => Some(PropertyValue::Integer(info.attribute_int64(attribute))),
+            gio::FileAttributeType::Stringv => Some(PropertyValue::StringList(info.attribute_stringv(attribute).iter().map(|value| value.to_string()).collect())),
+            _ => None,
+        };
+
+        if let Some(value) = value {
+            result.insert(key.to_string(), value);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Writes the properties by Windows name or by GIO attribute name
+///
+/// Extended attributes hold text only, so their values are written as text
+pub fn set_properties<P: AsRef<Path>>(file_path: P, values: &HashMap<String, PropertyValue>) -> Result<(), String> {
+    let info = gio::FileInfo::new();
+    for (key, value) in values {
+        let attribute = to_property_attribute(key)?;
+        if attribute.starts_with("xattr::") {
+            info.set_attribute_string(&attribute, &to_property_text(value));
+            continue;
+        }
+
+        match value {
+            PropertyValue::String(value) => info.set_attribute_string(&attribute, value),
+            PropertyValue::Integer(value) => info.set_attribute_int64(&attribute, *value),
+            PropertyValue::Float(value) => info.set_attribute_string(&attribute, &value.to_string()),
+            PropertyValue::Bool(value) => info.set_attribute_boolean(&attribute, *value),
+            PropertyValue::StringList(values) => info.set_attribute_stringv(&attribute, values.as_slice()),
+        }
+    }
+
+    File::for_path(file_path.as_ref()).set_attributes_from_info(&info, gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE).map_err(|e| e.message().to_string())
+}
+
+fn to_property_attribute(key: &str) -> Result<String, String> {
+    if let Some((_, attribute)) = PROPERTY_ATTRIBUTES.iter().find(|(name, _)| *name == key) {
+        Ok(attribute.to_string())
+    } else if key.contains("::") {
+        Ok(key.to_string())
+    } else {
+        Err(format!("Unsupported property: {}", key))
+    }
+}
+
+/* Extended attributes are text, so convert the known properties to their types */
+fn to_property_value(key: &str, value: String) -> PropertyValue {
+    match key {
+        "System.Keywords" => PropertyValue::StringList(value.split(',').map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect()),
+        "System.Rating" => value.trim().parse().map(PropertyValue::Integer).unwrap_or(PropertyValue::String(value)),
+        _ => PropertyValue::String(value),
+    }
+}
+
+/* Tags are separated by commas as Baloo and Dolphin do */
+fn to_property_text(value: &PropertyValue) -> String {
+    match value {
+        PropertyValue::String(value) => value.clone(),
+        PropertyValue::Integer(value) => value.to_string(),
+        PropertyValue::Float(value) => value.to_string(),
+        PropertyValue::Bool(value) => value.to_string(),
+        PropertyValue::StringList(values) => values.join(","),
+    }
+}
+
+fn format_duration(duration_ms: u64) -> String {
+    let seconds = duration_ms / 1000;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/* Desktop and mounted removable drives */
+fn get_send_to_destinations() -> Vec<(String, String)> {
+    use gtk::gio::prelude::{MountExt, VolumeMonitorExt};
+
+    let mut destinations = Vec::new();
+
+    if let Some(desktop) = gtk::glib::user_special_dir(gtk::glib::UserDirectory::Desktop) {
+        destinations.push(("Desktop".to_string(), desktop.to_string_lossy().to_string()));
+    }
+
+    for mount in gtk::gio::VolumeMonitor::get().mounts() {
+        if !mount.can_eject() && !mount.can_unmount() {
+            continue;
+        }
+        if let Some(path) = mount.root().path() {
+            destinations.push((mount.name().to_string(), path.to_string_lossy().to_string()));
+        }
+    }
+
+    destinations
+}
+
+/// Adds the file to the recently used files
+pub fn add_to_recent<P: AsRef<Path>>(file_path: P) {
+    use gtk::prelude::RecentManagerExt;
+
+    init();
+
+    if let Ok(uri) = path_to_uri(file_path) {
+        if let Some(manager) = gtk::RecentManager::default() {
+            manager.add_item(uri.as_str());
+        }
+    }
+}
+
+/// Lists the recently used local files, newest first
+pub fn get_recent_files() -> Result<Vec<String>, String> {
+    use gtk::prelude::RecentManagerExt;
+
+    init();
+
+    let manager = gtk::RecentManager::default().ok_or_else(|| "Recent manager not available".to_string())?;
+    let mut items: Vec<_> = manager.items().into_iter().filter(|item| item.is_local() && item.exists()).collect();
+    items.sort_by_key(|item| std::cmp::Reverse(item.modified()));
+
+    Ok(items.iter().filter_map(|item| item.uri()).filter_map(|uri| File::for_uri(&uri).path()).map(|path| path.to_string_lossy().to_string()).collect())
+}
+
+#[allow(unused_variables)]
+/// Does nothing on Linux
+pub fn set_jump_list(categories: &[JumpListCategory]) -> Result<(), String> {
+    Ok(())
+}
+
+/// Reads the folder type and icon kept in GIO metadata of the directory
+pub fn get_folder_view_hints<P: AsRef<Path>>(directory: P) -> Result<FolderViewHints, String> {
+    let attributes = format!("{},{}", FOLDER_TYPE_ATTRIBUTE, CUSTOM_ICON_ATTRIBUTE);
+    let info = File::for_path(directory.as_ref()).query_info(&attributes, gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE).map_err(|e| e.message().to_string())?;
+    // Metadata cannot be removed through FileInfo, so an empty value means none
+    let read = |attribute: &str| info.attribute_string(attribute).map(|value| value.to_string()).filter(|value| !value.is_empty());
+
+    Ok(FolderViewHints {
+        folder_type: read(FOLDER_TYPE_ATTRIBUTE),
+        icon: read(CUSTOM_ICON_ATTRIBUTE).map(|uri| File::for_uri(&uri).path().map_or(uri, |path| path.to_string_lossy().to_string())),
+    })
+}
+
+/// Writes the folder type and icon to GIO metadata of the directory. The icon is the custom icon that Nautilus and Nemo show. None removes the value
+pub fn set_folder_view_hints<P: AsRef<Path>>(directory: P, hints: &FolderViewHints) -> Result<(), String> {
+    let info = gio::FileInfo::new();
+    info.set_attribute_string(FOLDER_TYPE_ATTRIBUTE, hints.folder_type.as_deref().unwrap_or_default());
+    info.set_attribute_string(CUSTOM_ICON_ATTRIBUTE, &hints.icon.as_ref().map(|icon| File::for_path(icon).uri().to_string()).unwrap_or_default());
+
+    File::for_path(directory.as_ref()).set_attributes_from_info(&info, gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE).map_err(|e| e.message().to_string())
+}
+
 pub fn get_locale() -> String {
     if let Some(language) = gtk::default_language() {
         language.to_string()
@@ -1,3 +1,12 @@
+use crate::{dialog::DialogPlacement, fs::FileOperation, CompletionNotice};
+use gtk::{
+    gdk,
+    glib::{
+        object::{Cast, IsA, ObjectExt},
+        translate::{from_glib_full, ToGlibPtr},
+    },
+    prelude::{GtkWindowExt, MonitorExt, WidgetExt},
+};
 use std::{
     collections::HashMap,
     fs::File,
@@ -30,6 +39,16 @@ pub(crate) fn show_item_properties<P: AsRef<Path>>(path: P) -> Result<(), String
     proxy.show_item_properties(&[uri], "").map_err(|e| e.to_string())
 }
 
+/* The portal has no share sheet, so the file is offered to the app the user chooses */
+pub(crate) fn share_with_open_uri_portal<P: AsRef<Path>>(path: P) -> Result<(), String> {
+    let connection = Connection::session().map_err(|e| e.to_string())?;
+    let file = File::open(path.as_ref()).map_err(|e| e.to_string())?;
+    let proxy = OpenURIProxy::new(&connection).map_err(|e| e.to_string())?;
+    let ask = zbus::zvariant::Value::from(true);
+    proxy.open_file("", file.as_fd().into(), HashMap::from([("ask", &ask)])).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 fn reveal_with_filemanager1(path: PathBuf, connection: &Connection) -> Result<(), String> {
     let uri = path_to_uri(path)?;
     let proxy = FileManager1Proxy::new(connection).map_err(|e| e.to_string())?;
@@ -43,6 +62,87 @@ fn reveal_with_open_uri_portal(path: PathBuf, connection: &Connection) -> Result
     Ok(())
 }
 
+/// Applies the placement to a window created by this crate
+///
+/// A parent given as an X11 window ID is attached on X11 only, since Wayland has no way to refer to a window of another client
+pub(crate) fn place_window(window: &impl IsA<gtk::Window>, placement: &DialogPlacement) {
+    window.set_keep_above(placement.keep_above);
+    window.set_urgency_hint(placement.urgent);
+
+    if let Some(parent) = placement.parent {
+        attach_to_x11_parent(window, parent);
+    }
+
+    let Some(monitor) = placement.monitor else {
+        return;
+    };
+
+    if let Some(area) = gdk::Display::default().and_then(|display| display.monitor(monitor as i32)).map(|monitor| monitor.workarea()) {
+        window.connect_map(move |window| {
+            let (width, height) = window.size();
+            window.move_(area.x() + (area.width() - width) / 2, area.y() + (area.height() - height) / 2);
+        });
+    }
+}
+
+/* GDK wraps the X11 window of the parent, which may belong to another toolkit, so that the window manager keeps the window above it */
+fn attach_to_x11_parent(window: &impl IsA<gtk::Window>, parent: isize) {
+    extern "C" {
+        fn gdk_x11_window_foreign_new_for_display(display: *mut gdk::ffi::GdkDisplay, window: std::ffi::c_ulong) -> *mut gdk::ffi::GdkWindow;
+    }
+
+    let Some(display) = gdk::Display::default() else {
+        return;
+    };
+
+    if display.type_().name() != "GdkX11Display" {
+        return;
+    }
+
+    window.connect_realize(move |window| {
+        let foreign = unsafe { gdk_x11_window_foreign_new_for_display(display.to_glib_none().0, parent as std::ffi::c_ulong) };
+        if foreign.is_null() {
+            return;
+        }
+
+        let foreign: gdk::Window = unsafe { from_glib_full(foreign) };
+        if let Some(gdk_window) = window.window() {
+            gdk_window.set_transient_for(&foreign);
+        }
+    });
+}
+
+/// Shows a desktop notification and beeps as the notice specifies unless a window of the app is active
+pub(crate) fn notify_completion(notice: &CompletionNotice, operation: FileOperation, count: usize, error: Option<&str>) {
+    let is_app_focused = gtk::Window::list_toplevels().iter().filter_map(|window| window.downcast_ref::<gtk::Window>()).any(|window| window.is_active());
+    let Some((title, body)) = crate::notice::compose(notice, operation, count, error, is_app_focused) else {
+        return;
+    };
+
+    if notice.sound {
+        if let Some(display) = gdk::Display::default() {
+            display.beep();
+        }
+    }
+
+    if notice.notification {
+        let _ = show_notification(&title, &body, error.is_some());
+    }
+}
+
+fn show_notification(title: &str, body: &str, is_error: bool) -> Result<(), String> {
+    let connection = Connection::session().map_err(|e| e.to_string())?;
+    let proxy = NotificationsProxy::new(&connection).map_err(|e| e.to_string())?;
+    let app_name = gtk::glib::application_name().map(|name| name.to_string()).unwrap_or_default();
+    let icon = if is_error {
+        "dialog-error"
+    } else {
+        "dialog-information"
+    };
+    proxy.notify(&app_name, 0, icon, title, body, &[], HashMap::new(), -1).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 pub(crate) fn path_to_uri<P: AsRef<Path>>(path: P) -> Result<Url, String> {
     let path = path.as_ref().canonicalize().map_err(|e| e.to_string())?;
     Ok(Url::from_file_path(path).unwrap())
@@ -60,4 +160,23 @@ trait FileManager1 {
 #[zbus::proxy(gen_async = false, interface = "org.freedesktop.portal.OpenURI", default_service = "org.freedesktop.portal.Desktop", default_path = "/org/freedesktop/portal/desktop")]
 pub trait OpenURI {
     fn open_directory(&self, parent_window: &str, fd: zbus::zvariant::Fd<'_>, options: HashMap<&str, &zbus::zvariant::Value<'_>>) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+    fn open_file(&self, parent_window: &str, fd: zbus::zvariant::Fd<'_>, options: HashMap<&str, &zbus::zvariant::Value<'_>>) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+}
+
+/// # D-Bus interface proxy for `org.freedesktop.Notifications` interface.
+// https://specifications.freedesktop.org/notification-spec/latest/
+#[zbus::proxy(gen_async = false, interface = "org.freedesktop.Notifications", default_service = "org.freedesktop.Notifications", default_path = "/org/freedesktop/Notifications")]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, &zbus::zvariant::Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
 }
@@ -1,15 +1,57 @@
-use super::util::{decode_wide, encode_wide, GlobalMemory};
-use crate::{ClipboardData, Operation};
-use windows::Win32::{
-    Foundation::{HANDLE, HGLOBAL, HWND},
-    System::{
-        DataExchange::{CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard, RegisterClipboardFormatW, SetClipboardData},
-        Memory::{GlobalLock, GlobalUnlock},
-        Ole::{CF_HDROP, CF_TEXT, CF_UNICODETEXT, DROPEFFECT_COPY, DROPEFFECT_MOVE, DROPEFFECT_NONE},
+use super::util::{decode_wide, encode_wide, ComGuard, GlobalMemory};
+use crate::{ClipboardData, NameStyle, Operation};
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{HANDLE, HGLOBAL, HWND, LPARAM, LRESULT, WPARAM},
+        Graphics::{
+            Gdi::BITMAPINFOHEADER,
+            Imaging::{
+                CLSID_WICImagingFactory, GUID_ContainerFormatPng, GUID_WICPixelFormat32bppBGRA, IWICBitmapFrameEncode, IWICImagingFactory, WICBitmapDitherTypeNone, WICBitmapEncoderNoCache,
+                WICBitmapPaletteTypeCustom, WICDecodeMetadataCacheOnDemand,
+            },
+        },
+        System::{
+            Com::{
+                CoCreateInstance,
+                StructuredStorage::{CreateStreamOnHGlobal, IPropertyBag2},
+                CLSCTX_INPROC_SERVER, STATFLAG_NONAME, STATSTG, STREAM_SEEK_SET,
+            },
+            DataExchange::{
+                AddClipboardFormatListener, CloseClipboard, CountClipboardFormats, EmptyClipboard, GetClipboardData, GetClipboardSequenceNumber, IsClipboardFormatAvailable, OpenClipboard,
+                RegisterClipboardFormatW, RemoveClipboardFormatListener, SetClipboardData,
+            },
+            Memory::{GlobalLock, GlobalSize, GlobalUnlock},
+            Ole::{CF_DIB, CF_HDROP, CF_TEXT, CF_UNICODETEXT, DROPEFFECT_COPY, DROPEFFECT_MOVE, DROPEFFECT_NONE},
+        },
+        UI::{
+            Shell::{DragQueryFileW, CFSTR_PREFERREDDROPEFFECT, DROPFILES, HDROP},
+            WindowsAndMessaging::{
+                CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, KillTimer, PostMessageW, PostQuitMessage, RegisterClassW, SetTimer, TranslateMessage, HWND_MESSAGE, MSG,
+                WINDOW_EX_STYLE, WM_CLIPBOARDUPDATE, WM_CLOSE, WM_DESTROY, WM_TIMER, WNDCLASSW, WS_OVERLAPPED,
+            },
+        },
     },
-    UI::Shell::{DragQueryFileW, CFSTR_PREFERREDDROPEFFECT, DROPFILES, HDROP},
 };
 
+static MOVE_COMPLETED_CALLBACK: Mutex<Option<Box<dyn FnMut(Vec<String>) + Send>>> = Mutex::new(None);
+static CUT_WATCH: Mutex<CutWatch> = Mutex::new(CutWatch {
+    pending: None,
+    window: 0,
+});
+
+const CUT_TIMER_ID: usize = 1;
+// Interval of checking the sources after the items were pasted
+const CUT_POLL_MS: u32 = 500;
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const FRAGMENT_START: &str = "<html><body>\r\n<!--StartFragment-->";
+const FRAGMENT_END: &str = "<!--EndFragment-->\r\n</body></html>";
+
 /// Checks if text is available
 pub fn is_text_available() -> bool {
     is_ansi_text_available() || is_unicode_text_available()
@@ -98,6 +140,11 @@ pub fn write_text(window_handle: isize, text: String) -> Result<(), String> {
     Ok(())
 }
 
+/// Writes the paths as text, one per line, as names, full paths, quoted paths, URIs or Markdown links
+pub fn write_file_names(window_handle: isize, paths: &[String], style: NameStyle) -> Result<(), String> {
+    write_text(window_handle, crate::file_names::format(paths, &style))
+}
+
 /// Checks if URIs are available
 pub fn is_uris_available() -> bool {
     unsafe { IsClipboardFormatAvailable(CF_HDROP.0 as u32).is_ok() }
@@ -224,9 +271,184 @@ pub fn write_uris(window_handle: isize, paths: &[String], operation: Operation)
 
     unsafe { CloseClipboard().map_err(|e| e.message()) }?;
 
+    if operation == Operation::Move {
+        watch_cut(paths);
+    }
+
     Ok(())
 }
 
+struct CutWatch {
+    pending: Option<PendingCut>,
+    // Message-only window listening for clipboard updates, 0 if not watching
+    window: isize,
+}
+
+struct PendingCut {
+    paths: Vec<String>,
+    sequence: u32,
+    // The clipboard was emptied by pasting the items
+    pasted: bool,
+}
+
+/// Notifies when the items written by `write_uris` with `Operation::Move` are moved by pasting them here or in other applications
+///
+/// The callback receives the moved paths so that the cut state can be cleared
+pub fn on_move_completed<F: FnMut(Vec<String>) + Send + 'static>(callback: F) {
+    *MOVE_COMPLETED_CALLBACK.lock().unwrap() = Some(Box::new(callback));
+}
+
+/// Stops watching the items written by `write_uris` with `Operation::Move` and keeps the callback for the next cut
+pub fn cancel_move_watch() {
+    stop_watch(CUT_WATCH.lock().unwrap());
+}
+
+/// Stops watching the items written by `write_uris` with `Operation::Move` and removes the callback
+pub(crate) fn stop_watching_cut() {
+    cancel_move_watch();
+    MOVE_COMPLETED_CALLBACK.lock().unwrap().take();
+}
+
+/* Explorer empties the clipboard when pasting cut items. Once the clipboard is emptied and all the sources are gone, the move is regarded as completed */
+fn watch_cut(paths: &[String]) {
+    let mut watch = CUT_WATCH.lock().unwrap();
+    watch.pending = Some(PendingCut {
+        paths: paths.to_vec(),
+        sequence: unsafe { GetClipboardSequenceNumber() },
+        pasted: false,
+    });
+
+    if watch.window != 0 {
+        return;
+    }
+
+    // The window procedure does not lock until the window is handed out, so waiting here does not block it
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let class_name = encode_wide("ZouniCutWatcher");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(cut_window_proc),
+            lpszClassName: PCWSTR::from_raw(class_name.as_ptr()),
+            ..Default::default()
+        };
+        unsafe { RegisterClassW(&class) };
+
+        let Ok(hwnd) = (unsafe { CreateWindowExW(WINDOW_EX_STYLE::default(), PCWSTR::from_raw(class_name.as_ptr()), PCWSTR::null(), WS_OVERLAPPED, 0, 0, 0, 0, Some(HWND_MESSAGE), None, None, None) })
+        else {
+            let _ = sender.send(0);
+            return;
+        };
+
+        if unsafe { AddClipboardFormatListener(hwnd) }.is_err() {
+            let _ = unsafe { DestroyWindow(hwnd) };
+            let _ = sender.send(0);
+            return;
+        }
+        let _ = sender.send(hwnd.0 as isize);
+
+        let mut msg = MSG::default();
+        while unsafe { GetMessageW(&mut msg, None, 0, 0) }.as_bool() {
+            unsafe {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+    });
+
+    watch.window = receiver.recv().unwrap_or(0);
+    if watch.window == 0 {
+        watch.pending = None;
+    }
+}
+
+/* Forgets the cut and closes the window. The lock is released first, since the window procedure takes it */
+fn stop_watch(mut watch: MutexGuard<CutWatch>) {
+    watch.pending = None;
+    let window = std::mem::take(&mut watch.window);
+    drop(watch);
+
+    if window != 0 {
+        let _ = unsafe { PostMessageW(Some(HWND(window as _)), WM_CLOSE, WPARAM(0), LPARAM(0)) };
+    }
+}
+
+fn on_clipboard_update(window: HWND) {
+    let mut watch = CUT_WATCH.lock().unwrap();
+    let Some(cut) = watch.pending.as_mut() else {
+        return;
+    };
+
+    // The update made by write_uris itself
+    if cut.sequence == unsafe { GetClipboardSequenceNumber() } {
+        return;
+    }
+
+    // Other data was copied, so the cut items can no longer be pasted, or the clipboard changed again after they were pasted
+    if cut.pasted || unsafe { CountClipboardFormats() } != 0 {
+        stop_watch(watch);
+        return;
+    }
+
+    cut.pasted = true;
+    drop(watch);
+
+    unsafe { SetTimer(Some(window), CUT_TIMER_ID, CUT_POLL_MS, None) };
+    report_if_moved(window);
+}
+
+/* Stops watching and notifies once all the sources of the pasted items are gone */
+fn report_if_moved(window: HWND) {
+    let mut watch = CUT_WATCH.lock().unwrap();
+    let Some(cut) = watch.pending.as_ref() else {
+        return;
+    };
+
+    // A new cut replaced the pasted one
+    if !cut.pasted {
+        drop(watch);
+        let _ = unsafe { KillTimer(Some(window), CUT_TIMER_ID) };
+        return;
+    }
+
+    if cut.paths.iter().any(|path| Path::new(path).exists()) {
+        return;
+    }
+
+    let paths = watch.pending.take().map(|cut| cut.paths).unwrap_or_default();
+    stop_watch(watch);
+    if let Some(callback) = MOVE_COMPLETED_CALLBACK.lock().unwrap().as_mut() {
+        callback(paths);
+    }
+}
+
+unsafe extern "system" fn cut_window_proc(window: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_CLIPBOARDUPDATE => {
+            on_clipboard_update(window);
+            LRESULT(0)
+        }
+
+        WM_TIMER if wparam.0 == CUT_TIMER_ID => {
+            report_if_moved(window);
+            LRESULT(0)
+        }
+
+        WM_CLOSE => {
+            let _ = DestroyWindow(window);
+            LRESULT(0)
+        }
+
+        WM_DESTROY => {
+            let _ = KillTimer(Some(window), CUT_TIMER_ID);
+            let _ = RemoveClipboardFormatListener(window);
+            PostQuitMessage(0);
+            LRESULT(0)
+        }
+
+        _ => DefWindowProcW(window, msg, wparam, lparam),
+    }
+}
+
 fn get_preferred_drop_effect() -> Operation {
     let cf_format = unsafe { RegisterClipboardFormatW(CFSTR_PREFERREDDROPEFFECT) };
     if cf_format == 0 {
@@ -255,3 +477,306 @@ fn get_preferred_drop_effect() -> Operation {
 
     Operation::None
 }
+
+fn png_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(w!("PNG")) }
+}
+
+fn html_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(w!("HTML Format")) }
+}
+
+/// Copies the data of the format from the opened clipboard
+fn get_clipboard_bytes(format: u32) -> Option<Vec<u8>> {
+    let handle = unsafe { GetClipboardData(format) }.ok()?;
+    let hglobal = HGLOBAL(handle.0);
+    let ptr = unsafe { GlobalLock(hglobal) } as *const u8;
+    if ptr.is_null() {
+        return None;
+    }
+
+    let size = unsafe { GlobalSize(hglobal) };
+    let data = unsafe { std::slice::from_raw_parts(ptr, size) }.to_vec();
+    let _ = unsafe { GlobalUnlock(hglobal) };
+
+    Some(data)
+}
+
+/// Sets the data of the format to the opened clipboard
+fn set_clipboard_bytes(format: u32, data: &[u8]) -> Result<(), String> {
+    let hglobal = GlobalMemory::new(data.len())?;
+
+    let ptr = hglobal.lock()?;
+
+    unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len()) };
+
+    hglobal.unlock();
+
+    if unsafe { SetClipboardData(format, Some(HANDLE(hglobal.handle().0))).is_err() } {
+        return Err("Failed to write clipboard".to_string());
+    }
+
+    std::mem::forget(hglobal);
+
+    Ok(())
+}
+
+/// Checks if image is available
+pub fn is_image_available() -> bool {
+    unsafe { IsClipboardFormatAvailable(png_format()).is_ok() || IsClipboardFormatAvailable(CF_DIB.0 as u32).is_ok() }
+}
+
+/// Reads image from clipboard as PNG
+pub fn read_image(window_handle: isize) -> Result<Vec<u8>, String> {
+    if !is_image_available() {
+        return Ok(Vec::new());
+    }
+
+    unsafe { OpenClipboard(Some(HWND(window_handle as _))).map_err(|e| e.message()) }?;
+
+    let png = get_clipboard_bytes(png_format());
+    let dib = if png.is_none() {
+        get_clipboard_bytes(CF_DIB.0 as u32)
+    } else {
+        None
+    };
+
+    unsafe { CloseClipboard().map_err(|e| e.message()) }?;
+
+    match (png, dib) {
+        (Some(png), _) => Ok(png),
+        (None, Some(dib)) => dib_to_png(&dib),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Writes PNG image to clipboard
+///
+/// The image is written as both PNG and DIB
+pub fn write_image(window_handle: isize, png: &[u8]) -> Result<(), String> {
+    let dib = png_to_dib(png)?;
+
+    unsafe { OpenClipboard(Some(HWND(window_handle as _))).map_err(|e| e.message()) }?;
+
+    unsafe { EmptyClipboard().map_err(|e| e.message()) }?;
+
+    let result = set_clipboard_bytes(CF_DIB.0 as u32, &dib).and_then(|_| set_clipboard_bytes(png_format(), png));
+
+    unsafe { CloseClipboard().map_err(|e| e.message()) }?;
+
+    result
+}
+
+fn dib_to_png(dib: &[u8]) -> Result<Vec<u8>, String> {
+    if dib.len() < std::mem::size_of::<BITMAPINFOHEADER>() {
+        return Err("Invalid bitmap".to_string());
+    }
+
+    let header = unsafe { std::ptr::read_unaligned(dib.as_ptr() as *const BITMAPINFOHEADER) };
+
+    if (header.biBitCount != 24 && header.biBitCount != 32) || (header.biCompression != BI_RGB && header.biCompression != BI_BITFIELDS) {
+        return Err("Unsupported bitmap format".to_string());
+    }
+
+    let width = header.biWidth.unsigned_abs() as usize;
+    let height = header.biHeight.unsigned_abs() as usize;
+    let bytes_per_pixel = header.biBitCount as usize / 8;
+    let src_stride = (width * header.biBitCount as usize).div_ceil(32) * 4;
+
+    // Color masks follow BITMAPINFOHEADER when BI_BITFIELDS is used
+    let mut offset = header.biSize as usize + header.biClrUsed as usize * 4;
+    if header.biCompression == BI_BITFIELDS && header.biSize as usize == std::mem::size_of::<BITMAPINFOHEADER>() {
+        offset += 12;
+    }
+
+    if dib.len() < offset + src_stride * height {
+        return Err("Invalid bitmap".to_string());
+    }
+
+    let mut pixels = vec![0u8; width * height * 4];
+    for y in 0..height {
+        // DIB is bottom-up unless the height is negative
+        let src_y = if header.biHeight > 0 {
+            height - 1 - y
+        } else {
+            y
+        };
+        let src = &dib[offset + src_y * src_stride..];
+        for x in 0..width {
+            let pixel = &src[x * bytes_per_pixel..x * bytes_per_pixel + bytes_per_pixel];
+            let dest = (y * width + x) * 4;
+            pixels[dest..dest + 3].copy_from_slice(&pixel[..3]);
+            pixels[dest + 3] = if bytes_per_pixel == 4 {
+                pixel[3]
+            } else {
+                255
+            };
+        }
+    }
+
+    // Most applications leave the alpha channel of 32bit DIB empty
+    if bytes_per_pixel == 4 && pixels.chunks(4).all(|pixel| pixel[3] == 0) {
+        pixels.chunks_mut(4).for_each(|pixel| pixel[3] = 255);
+    }
+
+    encode_png(width as u32, height as u32, &pixels)
+}
+
+fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Result<Vec<u8>, String> {
+    let _guard = ComGuard::new();
+
+    let factory: IWICImagingFactory = unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER) }.map_err(|e| e.message())?;
+    let bitmap = unsafe { factory.CreateBitmapFromMemory(width, height, &GUID_WICPixelFormat32bppBGRA, width * 4, pixels) }.map_err(|e| e.message())?;
+
+    let stream = unsafe { CreateStreamOnHGlobal(HGLOBAL::default(), true) }.map_err(|e| e.message())?;
+
+    let encoder = unsafe { factory.CreateEncoder(&GUID_ContainerFormatPng, std::ptr::null()) }.map_err(|e| e.message())?;
+    unsafe { encoder.Initialize(&stream, WICBitmapEncoderNoCache) }.map_err(|e| e.message())?;
+
+    let mut frame: Option<IWICBitmapFrameEncode> = None;
+    let mut bag: Option<IPropertyBag2> = None;
+    unsafe { encoder.CreateNewFrame(&mut frame, &mut bag) }.map_err(|e| e.message())?;
+    let frame = frame.ok_or("Failed to create frame".to_string())?;
+
+    unsafe { frame.Initialize(None) }.map_err(|e| e.message())?;
+    unsafe { frame.SetSize(width, height) }.map_err(|e| e.message())?;
+    unsafe { frame.WriteSource(&bitmap, std::ptr::null()) }.map_err(|e| e.message())?;
+    unsafe { frame.Commit() }.map_err(|e| e.message())?;
+    unsafe { encoder.Commit() }.map_err(|e| e.message())?;
+
+    let mut stat = STATSTG::default();
+    unsafe { stream.Stat(&mut stat, STATFLAG_NONAME) }.map_err(|e| e.message())?;
+
+    let mut png = vec![0u8; stat.cbSize as usize];
+    unsafe { stream.Seek(0, STREAM_SEEK_SET, None) }.map_err(|e| e.message())?;
+    let _ = unsafe { stream.Read(png.as_mut_ptr() as _, stat.cbSize as _, None) };
+
+    Ok(png)
+}
+
+fn png_to_dib(png: &[u8]) -> Result<Vec<u8>, String> {
+    let _guard = ComGuard::new();
+
+    let factory: IWICImagingFactory = unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER) }.map_err(|e| e.message())?;
+
+    let stream = unsafe { factory.CreateStream() }.map_err(|e| e.message())?;
+    unsafe { stream.InitializeFromMemory(png) }.map_err(|e| e.message())?;
+
+    let decoder = unsafe { factory.CreateDecoderFromStream(&stream, std::ptr::null(), WICDecodeMetadataCacheOnDemand) }.map_err(|e| e.message())?;
+    let frame = unsafe { decoder.GetFrame(0) }.map_err(|e| e.message())?;
+
+    let converter = unsafe { factory.CreateFormatConverter() }.map_err(|e| e.message())?;
+    unsafe { converter.Initialize(&frame, &GUID_WICPixelFormat32bppBGRA, WICBitmapDitherTypeNone, None, 0.0, WICBitmapPaletteTypeCustom) }.map_err(|e| e.message())?;
+
+    let mut width = 0;
+    let mut height = 0;
+    unsafe { converter.GetSize(&mut width, &mut height) }.map_err(|e| e.message())?;
+
+    let stride = width as usize * 4;
+    let mut pixels = vec![0u8; stride * height as usize];
+    unsafe { converter.CopyPixels(std::ptr::null(), stride as u32, &mut pixels) }.map_err(|e| e.message())?;
+
+    let header = BITMAPINFOHEADER {
+        biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+        biWidth: width as i32,
+        biHeight: height as i32,
+        biPlanes: 1,
+        biBitCount: 32,
+        biCompression: BI_RGB,
+        biSizeImage: pixels.len() as u32,
+        biXPelsPerMeter: 0,
+        biYPelsPerMeter: 0,
+        biClrUsed: 0,
+        biClrImportant: 0,
+    };
+
+    let header_bytes = unsafe { std::slice::from_raw_parts(&header as *const BITMAPINFOHEADER as *const u8, std::mem::size_of::<BITMAPINFOHEADER>()) };
+    let mut dib = header_bytes.to_vec();
+    // Write rows bottom-up
+    for row in pixels.chunks(stride).rev() {
+        dib.extend_from_slice(row);
+    }
+
+    Ok(dib)
+}
+
+/// Checks if HTML is available
+pub fn is_html_available() -> bool {
+    unsafe { IsClipboardFormatAvailable(html_format()).is_ok() }
+}
+
+/// Reads HTML fragment from clipboard
+pub fn read_html(window_handle: isize) -> Result<String, String> {
+    if !is_html_available() {
+        return Ok(String::new());
+    }
+
+    unsafe { OpenClipboard(Some(HWND(window_handle as _))).map_err(|e| e.message()) }?;
+
+    let data = get_clipboard_bytes(html_format());
+
+    unsafe { CloseClipboard().map_err(|e| e.message()) }?;
+
+    Ok(data.map(|data| parse_cf_html(&data)).unwrap_or_default())
+}
+
+/// Writes HTML fragment to clipboard
+///
+/// `alt_text` is written as plain text for applications which do not accept HTML
+pub fn write_html(window_handle: isize, html: String, alt_text: Option<String>) -> Result<(), String> {
+    let mut cf_html = build_cf_html(&html).into_bytes();
+    cf_html.push(0);
+
+    unsafe { OpenClipboard(Some(HWND(window_handle as _))).map_err(|e| e.message()) }?;
+
+    unsafe { EmptyClipboard().map_err(|e| e.message()) }?;
+
+    let result = set_clipboard_bytes(html_format(), &cf_html).and_then(|_| match alt_text {
+        Some(text) => {
+            let utf16: Vec<u8> = encode_wide(text).iter().flat_map(|c| c.to_le_bytes()).collect();
+            set_clipboard_bytes(CF_UNICODETEXT.0 as u32, &utf16)
+        }
+        None => Ok(()),
+    });
+
+    unsafe { CloseClipboard().map_err(|e| e.message()) }?;
+
+    result
+}
+
+fn cf_html_header(start_html: usize, end_html: usize, start_fragment: usize, end_fragment: usize) -> String {
+    format!("Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\n", start_html, end_html, start_fragment, end_fragment)
+}
+
+/// Builds CF_HTML data. Offsets are byte offsets of UTF-8 string
+fn build_cf_html(html: &str) -> String {
+    let start_html = cf_html_header(0, 0, 0, 0).len();
+    let start_fragment = start_html + FRAGMENT_START.len();
+    let end_fragment = start_fragment + html.len();
+    let end_html = end_fragment + FRAGMENT_END.len();
+
+    format!("{}{}{}{}", cf_html_header(start_html, end_html, start_fragment, end_fragment), FRAGMENT_START, html, FRAGMENT_END)
+}
+
+fn parse_cf_html(data: &[u8]) -> String {
+    let len = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    let data = &data[..len];
+    let text = String::from_utf8_lossy(data);
+
+    // Optional offsets are -1 which fail to parse
+    let offset = |name: &str| text.lines().find_map(|line| line.strip_prefix(name)).and_then(|value| value.trim().parse::<usize>().ok());
+
+    let (start, end) = match (offset("StartFragment:"), offset("EndFragment:")) {
+        (Some(start), Some(end)) => (start, end),
+        _ => match (offset("StartHTML:"), offset("EndHTML:")) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return String::new(),
+        },
+    };
+
+    if start > end || end > data.len() {
+        return String::new();
+    }
+
+    String::from_utf8_lossy(&data[start..end]).to_string()
+}
@@ -1,19 +1,46 @@
-use super::util::decode_wide;
+use super::util::{decode_wide, encode_wide};
+use crate::{fs::list_volumes, Volume};
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
 use windows::{
-    core::{Error, GUID},
+    core::{w, Error, GUID, PCWSTR},
     Win32::{
         Devices::DeviceAndDriverInstallation::{
             CM_Register_Notification, CM_Unregister_Notification, SetupDiEnumDeviceInfo, SetupDiGetClassDevsW, SetupDiGetDeviceRegistryPropertyW, CM_NOTIFY_ACTION,
             CM_NOTIFY_ACTION_DEVICEINTERFACEARRIVAL, CM_NOTIFY_ACTION_DEVICEINTERFACEREMOVAL, CM_NOTIFY_EVENT_DATA, CM_NOTIFY_FILTER, CM_NOTIFY_FILTER_FLAG_ALL_INTERFACE_CLASSES,
             CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE, CR_SUCCESS, DIGCF_DEVICEINTERFACE, HCMNOTIFICATION, SPDRP_CLASS, SP_DEVINFO_DATA,
         },
-        Foundation::{ERROR_SUCCESS, MAX_PATH},
+        Foundation::{CloseHandle, ERROR_ACCESS_DENIED, ERROR_FILE_NOT_FOUND, ERROR_PATH_NOT_FOUND, ERROR_SHARING_VIOLATION, ERROR_SUCCESS, GENERIC_READ, GENERIC_WRITE, HANDLE, HWND, LPARAM, LRESULT, MAX_PATH, WPARAM},
+        Storage::FileSystem::{CreateFileW, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING},
+        System::{
+            Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS},
+            Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ},
+            SystemInformation::{GetTickCount64, GlobalMemoryStatusEx, MEMORYSTATUSEX},
+            IO::DeviceIoControl,
+        },
+        UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, GetWindowLongPtrW, PostMessageW, PostQuitMessage, RegisterClassW, SetWindowLongPtrW, TranslateMessage,
+            GWLP_USERDATA, MSG, WINDOW_EX_STYLE, WM_CLOSE, WM_DESTROY, WM_DEVICECHANGE, WNDCLASSW, WS_OVERLAPPED,
+        },
     },
 };
 
-static CONFIG: Mutex<isize> = Mutex::new(-1);
+static LISTENER: Mutex<Option<Listener>> = Mutex::new(None);
+static VOLUME_WINDOW: Mutex<isize> = Mutex::new(0);
+
+const FSCTL_LOCK_VOLUME: u32 = 0x00090018;
+const FSCTL_DISMOUNT_VOLUME: u32 = 0x00090020;
+const IOCTL_STORAGE_MEDIA_REMOVAL: u32 = 0x002D4804;
+const IOCTL_STORAGE_EJECT_MEDIA: u32 = 0x002D4808;
+const LOCK_RETRIES: u32 = 5;
+const BATTERY_FLAG_CHARGING: u8 = 8;
+const BATTERY_FLAG_NO_BATTERY: u8 = 128;
+const BATTERY_FLAG_UNKNOWN: u8 = 255;
+const BATTERY_PERCENTAGE_UNKNOWN: u8 = 255;
+
+const DBT_DEVNODES_CHANGED: usize = 0x0007;
+const DBT_DEVICEARRIVAL: usize = 0x8000;
+const DBT_DEVICEREMOVECOMPLETE: usize = 0x8004;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceEvent {
@@ -21,23 +48,154 @@ pub struct DeviceEvent {
     event: String,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceFilter {
+    /// Classes such as "MassStorage", "HID", "Printer", "Audio", "COMM", "PTP" and "Hub". Empty for all classes
+    pub classes: Vec<String>,
+    /// Pairs of vendor ID and product ID. Empty for all devices
+    pub ids: Vec<(u16, u16)>,
+}
+
+struct NotifyContext<F> {
+    callback: F,
+    ids: Vec<(u16, u16)>,
+}
+
+struct Listener {
+    configs: Vec<isize>,
+    // NotifyContext shared by the registrations, freed by free_context after they are unregistered
+    context: isize,
+    free_context: unsafe fn(isize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeEvent {
+    pub mount_point: String,
+    pub volume_label: String,
+    pub event: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemInfo {
+    // None if no battery is present
+    pub battery_percent: Option<u8>,
+    pub charging: bool,
+    pub os_version: String,
+    pub cpu_count: usize,
+    pub total_memory: u64,
+    pub available_memory: u64,
+    pub uptime_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum EjectError {
+    NotFound,
+    Busy,
+    Failed(String),
+}
+
+impl std::fmt::Display for EjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EjectError::NotFound => write!(f, "Volume not found"),
+            EjectError::Busy => write!(f, "Volume is in use"),
+            EjectError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
 /// Get notification when any device is conneced
-pub fn listen<F: FnMut(DeviceEvent) + 'static>(callback: F) -> bool {
-    let notify_type = CM_NOTIFY_FILTER {
-        cbSize: size_of::<CM_NOTIFY_FILTER>() as _,
-        FilterType: CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE,
-        Flags: CM_NOTIFY_FILTER_FLAG_ALL_INTERFACE_CLASSES,
-        ..Default::default()
-    };
-    let mut config = HCMNOTIFICATION::default();
-    let result = unsafe { CM_Register_Notification(&notify_type, Some(Box::into_raw(Box::new(callback)) as _), Some(on_notify::<F>), &mut config) };
-    if result.0 == CR_SUCCESS.0 {
-        unlisten();
-        *CONFIG.lock().unwrap() = config.0 as _;
-        true
+///
+/// Only the devices matching `filter` are notified
+pub fn listen<F: FnMut(DeviceEvent) + 'static>(filter: Option<DeviceFilter>, callback: F) -> bool {
+    let filter = filter.unwrap_or_default();
+
+    let mut notify_filters = Vec::new();
+    if filter.classes.is_empty() {
+        notify_filters.push(CM_NOTIFY_FILTER {
+            cbSize: size_of::<CM_NOTIFY_FILTER>() as _,
+            FilterType: CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE,
+            Flags: CM_NOTIFY_FILTER_FLAG_ALL_INTERFACE_CLASSES,
+            ..Default::default()
+        });
     } else {
-        false
+        for class in &filter.classes {
+            let Some(guid) = to_interface_class_guid(class) else {
+                return false;
+            };
+            let mut notify_filter = CM_NOTIFY_FILTER {
+                cbSize: size_of::<CM_NOTIFY_FILTER>() as _,
+                FilterType: CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE,
+                ..Default::default()
+            };
+            notify_filter.u.DeviceInterface.ClassGuid = guid;
+            notify_filters.push(notify_filter);
+        }
+    }
+
+    // Shared by all registrations
+    let context = Box::into_raw(Box::new(NotifyContext {
+        callback,
+        ids: filter.ids,
+    }));
+
+    let mut configs = Vec::new();
+    for notify_filter in &notify_filters {
+        let mut config = HCMNOTIFICATION::default();
+        let result = unsafe { CM_Register_Notification(notify_filter, Some(context as _), Some(on_notify::<F>), &mut config) };
+        if result.0 != CR_SUCCESS.0 {
+            configs.iter().for_each(|config: &HCMNOTIFICATION| {
+                let _ = unsafe { CM_Unregister_Notification(*config) };
+            });
+            unsafe { free_context::<F>(context as isize) };
+            return false;
+        }
+        configs.push(config);
     }
+
+    unlisten();
+    *LISTENER.lock().unwrap() = Some(Listener {
+        configs: configs.iter().map(|config| config.0 as isize).collect(),
+        context: context as isize,
+        free_context: free_context::<F>,
+    });
+    true
+}
+
+/* Only called once no registration can invoke on_notify with the context */
+unsafe fn free_context<F>(context: isize) {
+    drop(Box::from_raw(context as *mut NotifyContext<F>));
+}
+
+/* Device interface class GUIDs for the class names reported on Linux */
+fn to_interface_class_guid(class: &str) -> Option<GUID> {
+    match class {
+        // GUID_DEVINTERFACE_DISK
+        "MassStorage" => Some(GUID::from_u128(0x53f56307_b6bf_11d0_94f2_00a0c91efb8b)),
+        // GUID_DEVINTERFACE_HID
+        "HID" => Some(GUID::from_u128(0x4d1e55b2_f16f_11cf_88cb_001111000030)),
+        // GUID_DEVINTERFACE_USBPRINT
+        "Printer" => Some(GUID::from_u128(0x28d78fad_5a12_11d1_ae5b_0000f803a8c2)),
+        // KSCATEGORY_AUDIO
+        "Audio" => Some(GUID::from_u128(0x6994ad04_93ef_11d0_a3cc_00a0c9223196)),
+        // GUID_DEVINTERFACE_COMPORT
+        "COMM" => Some(GUID::from_u128(0x86e0d1e0_8089_11d0_9ce4_08003e301f73)),
+        // GUID_DEVINTERFACE_WPD
+        "PTP" => Some(GUID::from_u128(0x6ac27878_a6fa_4155_ba85_f98f491d4f33)),
+        // GUID_DEVINTERFACE_USB_HUB
+        "Hub" => Some(GUID::from_u128(0xf18a0e88_c30c_11d0_8815_00a0c906bed8)),
+        _ => None,
+    }
+}
+
+/* Symbolic links of USB and HID devices contain "VID_xxxx&PID_xxxx" */
+fn get_ids(symbolic_link: &str) -> Option<(u16, u16)> {
+    let upper = symbolic_link.to_uppercase();
+    let vid_start = upper.find("VID_")? + 4;
+    let pid_start = upper.find("PID_")? + 4;
+    let vendor_id = u16::from_str_radix(upper.get(vid_start..vid_start + 4)?, 16).ok()?;
+    let product_id = u16::from_str_radix(upper.get(pid_start..pid_start + 4)?, 16).ok()?;
+    Some((vendor_id, product_id))
 }
 
 unsafe extern "system" fn on_notify<F: FnMut(DeviceEvent)>(
@@ -54,9 +212,18 @@ unsafe extern "system" fn on_notify<F: FnMut(DeviceEvent)>(
                 return 0;
             }
 
-            let callback = &mut *(context as *mut F);
+            let context = &mut *(context as *mut NotifyContext<F>);
+            if !context.ids.is_empty() {
+                let ptr = data.u.DeviceInterface.SymbolicLink.as_ptr();
+                let len = (0..).take_while(|&i| *ptr.add(i) != 0).count();
+                let symbolic_link = decode_wide(std::slice::from_raw_parts(ptr, len));
+                if !get_ids(&symbolic_link).is_some_and(|ids| context.ids.contains(&ids)) {
+                    return ERROR_SUCCESS.0;
+                }
+            }
+
             let name = get_device_type(data.u.DeviceInterface.ClassGuid).unwrap_or_default();
-            callback(DeviceEvent {
+            (context.callback)(DeviceEvent {
                 name,
                 event: if action == CM_NOTIFY_ACTION_DEVICEINTERFACEARRIVAL {
                     "Added".to_string()
@@ -95,17 +262,263 @@ fn get_device_type(guid: GUID) -> Result<String, Error> {
 }
 
 pub fn unlisten() {
-    if let Ok(config) = CONFIG.try_lock() {
-        if *config != 0 {
-            let _ = unsafe { CM_Unregister_Notification(HCMNOTIFICATION(*config as _)) };
+    if let Ok(mut listener) = LISTENER.try_lock() {
+        if let Some(listener) = listener.take() {
+            // CM_Unregister_Notification waits for running callbacks to return
+            for config in listener.configs {
+                let _ = unsafe { CM_Unregister_Notification(HCMNOTIFICATION(config as _)) };
+            }
+            unsafe { (listener.free_context)(listener.context) };
         }
     }
 }
 
 pub fn is_listening() -> bool {
-    if let Ok(config) = CONFIG.try_lock() {
-        *config != 0
+    if let Ok(listener) = LISTENER.try_lock() {
+        listener.is_some()
     } else {
         false
     }
 }
+
+struct VolumeWatcher {
+    callback: Box<dyn FnMut(VolumeEvent) + Send>,
+    volumes: HashMap<String, Volume>,
+}
+
+/// Get notification when a volume is mounted, unmounted or renamed
+///
+/// Events are "Mounted", "Unmounted" and "Renamed". Renames are detected only when a device change is broadcast
+pub fn listen_volumes<F: FnMut(VolumeEvent) + Send + 'static>(callback: F) -> bool {
+    unlisten_volumes();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        let class_name = encode_wide("ZouniVolumeWatcher");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(volume_window_proc),
+            lpszClassName: PCWSTR::from_raw(class_name.as_ptr()),
+            ..Default::default()
+        };
+        unsafe { RegisterClassW(&class) };
+
+        // WM_DEVICECHANGE is broadcast only to top-level windows, so a message-only window cannot be used
+        let hwnd = match unsafe { CreateWindowExW(WINDOW_EX_STYLE::default(), PCWSTR::from_raw(class_name.as_ptr()), PCWSTR::null(), WS_OVERLAPPED, 0, 0, 0, 0, None, None, None, None) } {
+            Ok(hwnd) => hwnd,
+            Err(_) => {
+                let _ = sender.send(false);
+                return;
+            }
+        };
+
+        let watcher = VolumeWatcher {
+            callback: Box::new(callback),
+            volumes: snapshot_volumes(),
+        };
+        unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(Box::new(watcher)) as _) };
+
+        *VOLUME_WINDOW.lock().unwrap() = hwnd.0 as _;
+        let _ = sender.send(true);
+
+        let mut msg = MSG::default();
+        while unsafe { GetMessageW(&mut msg, None, 0, 0) }.as_bool() {
+            unsafe {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+    });
+
+    receiver.recv().unwrap_or(false)
+}
+
+pub fn unlisten_volumes() {
+    if let Ok(mut window) = VOLUME_WINDOW.lock() {
+        if *window != 0 {
+            let _ = unsafe { PostMessageW(Some(HWND(*window as _)), WM_CLOSE, WPARAM(0), LPARAM(0)) };
+            *window = 0;
+        }
+    }
+}
+
+pub fn is_listening_volumes() -> bool {
+    if let Ok(window) = VOLUME_WINDOW.lock() {
+        *window != 0
+    } else {
+        false
+    }
+}
+
+fn snapshot_volumes() -> HashMap<String, Volume> {
+    list_volumes().unwrap_or_default().into_iter().map(|volume| (volume.mount_point.clone(), volume)).collect()
+}
+
+fn notify_volume_changes(watcher: &mut VolumeWatcher) {
+    let volumes = snapshot_volumes();
+
+    for (mount_point, volume) in &volumes {
+        let event = match watcher.volumes.get(mount_point) {
+            None => "Mounted",
+            Some(old) if old.volume_label != volume.volume_label => "Renamed",
+            _ => continue,
+        };
+        (watcher.callback)(VolumeEvent {
+            mount_point: mount_point.clone(),
+            volume_label: volume.volume_label.clone(),
+            event: event.to_string(),
+        });
+    }
+
+    for (mount_point, volume) in &watcher.volumes {
+        if !volumes.contains_key(mount_point) {
+            (watcher.callback)(VolumeEvent {
+                mount_point: mount_point.clone(),
+                volume_label: volume.volume_label.clone(),
+                event: "Unmounted".to_string(),
+            });
+        }
+    }
+
+    watcher.volumes = volumes;
+}
+
+unsafe extern "system" fn volume_window_proc(window: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_DEVICECHANGE => {
+            if matches!(wparam.0, DBT_DEVICEARRIVAL | DBT_DEVICEREMOVECOMPLETE | DBT_DEVNODES_CHANGED) {
+                let watcher = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut VolumeWatcher;
+                if !watcher.is_null() {
+                    notify_volume_changes(&mut *watcher);
+                }
+            }
+            LRESULT(1)
+        }
+
+        WM_CLOSE => {
+            let _ = DestroyWindow(window);
+            LRESULT(0)
+        }
+
+        WM_DESTROY => {
+            let watcher = SetWindowLongPtrW(window, GWLP_USERDATA, 0) as *mut VolumeWatcher;
+            if !watcher.is_null() {
+                drop(Box::from_raw(watcher));
+            }
+            PostQuitMessage(0);
+            LRESULT(0)
+        }
+
+        _ => DefWindowProcW(window, msg, wparam, lparam),
+    }
+}
+
+/// Ejects the removable volume mounted at `mount_point`
+///
+/// Fails with `EjectError::Busy` if the volume cannot be locked because files are in use
+pub async fn eject(mount_point: &str) -> Result<(), EjectError> {
+    let drive = mount_point.trim_end_matches(['\\', '/']);
+    let wide = encode_wide(format!("\\\\.\\{}", drive));
+
+    let handle = unsafe {
+        CreateFileW(PCWSTR::from_raw(wide.as_ptr()), GENERIC_READ.0 | GENERIC_WRITE.0, FILE_SHARE_READ | FILE_SHARE_WRITE, None, OPEN_EXISTING, FILE_FLAGS_AND_ATTRIBUTES(0), None).map_err(|e| {
+            if e.code() == ERROR_FILE_NOT_FOUND.to_hresult() || e.code() == ERROR_PATH_NOT_FOUND.to_hresult() {
+                EjectError::NotFound
+            } else {
+                EjectError::Failed(e.message())
+            }
+        })
+    }?;
+
+    let result = eject_volume(handle).await;
+    let _ = unsafe { CloseHandle(handle) };
+    result
+}
+
+async fn eject_volume(handle: HANDLE) -> Result<(), EjectError> {
+    let mut bytes_returned = 0;
+    let mut retries = 0;
+    loop {
+        match unsafe { DeviceIoControl(handle, FSCTL_LOCK_VOLUME, None, 0, None, 0, Some(&mut bytes_returned), None) } {
+            Ok(_) => break,
+            Err(e) if e.code() == ERROR_ACCESS_DENIED.to_hresult() || e.code() == ERROR_SHARING_VIOLATION.to_hresult() => {
+                retries += 1;
+                if retries >= LOCK_RETRIES {
+                    return Err(EjectError::Busy);
+                }
+                smol::Timer::after(Duration::from_millis(500)).await;
+            }
+            Err(e) => return Err(EjectError::Failed(e.message())),
+        }
+    }
+
+    unsafe { DeviceIoControl(handle, FSCTL_DISMOUNT_VOLUME, None, 0, None, 0, Some(&mut bytes_returned), None) }.map_err(|e| EjectError::Failed(e.message()))?;
+
+    // PREVENT_MEDIA_REMOVAL with PreventMediaRemoval set to false
+    let prevent_removal = [0u8];
+    unsafe { DeviceIoControl(handle, IOCTL_STORAGE_MEDIA_REMOVAL, Some(prevent_removal.as_ptr() as _), prevent_removal.len() as _, None, 0, Some(&mut bytes_returned), None) }.map_err(|e| EjectError::Failed(e.message()))?;
+
+    unsafe { DeviceIoControl(handle, IOCTL_STORAGE_EJECT_MEDIA, None, 0, None, 0, Some(&mut bytes_returned), None) }.map_err(|e| EjectError::Failed(e.message()))
+}
+
+/// Returns battery, OS, CPU, memory and uptime information
+pub fn system_info() -> Result<SystemInfo, String> {
+    let mut power_status = SYSTEM_POWER_STATUS::default();
+    unsafe { GetSystemPowerStatus(&mut power_status) }.map_err(|e| e.message())?;
+
+    let has_battery = power_status.BatteryFlag != BATTERY_FLAG_UNKNOWN && power_status.BatteryFlag & BATTERY_FLAG_NO_BATTERY == 0;
+    let battery_percent = if has_battery && power_status.BatteryLifePercent != BATTERY_PERCENTAGE_UNKNOWN {
+        Some(power_status.BatteryLifePercent)
+    } else {
+        None
+    };
+
+    let mut memory_status = MEMORYSTATUSEX {
+        dwLength: size_of::<MEMORYSTATUSEX>() as _,
+        ..Default::default()
+    };
+    unsafe { GlobalMemoryStatusEx(&mut memory_status) }.map_err(|e| e.message())?;
+
+    Ok(SystemInfo {
+        battery_percent,
+        charging: has_battery && power_status.BatteryFlag & BATTERY_FLAG_CHARGING != 0,
+        os_version: get_os_version(),
+        cpu_count: std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1),
+        total_memory: memory_status.ullTotalPhys,
+        available_memory: memory_status.ullAvailPhys,
+        uptime_secs: unsafe { GetTickCount64() } / 1000,
+    })
+}
+
+/* Such as "Windows 11 Pro 23H2 (Build 22631)" */
+fn get_os_version() -> String {
+    let product_name = read_version_value(w!("ProductName")).unwrap_or_else(|| "Windows".to_string());
+    let build = read_version_value(w!("CurrentBuild")).unwrap_or_default();
+
+    // ProductName remains "Windows 10" on Windows 11
+    let product_name = if build.parse::<u32>().is_ok_and(|build| build >= 22000) {
+        product_name.replace("Windows 10", "Windows 11")
+    } else {
+        product_name
+    };
+
+    match read_version_value(w!("DisplayVersion")) {
+        Some(display_version) => format!("{} {} (Build {})", product_name, display_version, build),
+        None => format!("{} (Build {})", product_name, build),
+    }
+}
+
+fn read_version_value(name: PCWSTR) -> Option<String> {
+    let key = w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion");
+    let mut size = 0;
+    if unsafe { RegGetValueW(HKEY_LOCAL_MACHINE, key, name, RRF_RT_REG_SZ, None, None, Some(&mut size)) } != ERROR_SUCCESS {
+        return None;
+    }
+
+    let mut buffer = vec![0u16; size as usize / 2];
+    if unsafe { RegGetValueW(HKEY_LOCAL_MACHINE, key, name, RRF_RT_REG_SZ, None, Some(buffer.as_mut_ptr() as _), Some(&mut size)) } != ERROR_SUCCESS {
+        return None;
+    }
+
+    Some(decode_wide(&buffer))
+}
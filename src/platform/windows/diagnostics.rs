@@ -0,0 +1,101 @@
+use crate::Diagnostics;
+use windows::Win32::System::Com::{CoGetApartmentType, APTTYPE, APTTYPEQUALIFIER, APTTYPE_MAINSTA, APTTYPE_MTA, APTTYPE_NA, APTTYPE_STA};
+
+pub(crate) fn diagnostics() -> Diagnostics {
+    Diagnostics {
+        com_apartment: Some(com_apartment()),
+        webview2_version: webview2_version(),
+        decodable_image_formats: decodable_image_formats(),
+        ..Default::default()
+    }
+}
+
+/* Apartment of the calling thread */
+fn com_apartment() -> String {
+    let mut apartment = APTTYPE::default();
+    let mut qualifier = APTTYPEQUALIFIER::default();
+
+    if unsafe { CoGetApartmentType(&mut apartment, &mut qualifier) }.is_err() {
+        return "None".to_string();
+    }
+
+    match apartment {
+        APTTYPE_STA | APTTYPE_MAINSTA => "STA",
+        APTTYPE_MTA => "MTA",
+        APTTYPE_NA => "NA",
+        _ => "Unknown",
+    }
+    .to_string()
+}
+
+#[cfg(feature = "webview2")]
+fn webview2_version() -> Option<String> {
+    use webview2_com::Microsoft::Web::WebView2::Win32::GetAvailableCoreWebView2BrowserVersionString;
+    use windows::{
+        core::{PCWSTR, PWSTR},
+        Win32::System::Com::CoTaskMemFree,
+    };
+
+    let mut version = PWSTR::null();
+    unsafe { GetAvailableCoreWebView2BrowserVersionString(PCWSTR::null(), &mut version) }.ok()?;
+    if version.is_null() {
+        return None;
+    }
+
+    let result = unsafe { version.to_string() }.ok();
+    unsafe { CoTaskMemFree(Some(version.0 as _)) };
+    result
+}
+
+#[cfg(not(feature = "webview2"))]
+fn webview2_version() -> Option<String> {
+    None
+}
+
+/* HEIC and AVIF decoders come with the HEIF and AV1 Video Extensions, which register their file extensions with WIC */
+#[cfg(feature = "media")]
+fn decodable_image_formats() -> Vec<String> {
+    use super::util::{decode_wide, ComGuard};
+    use windows::{
+        core::{IUnknown, Interface},
+        Win32::{
+            Foundation::S_OK,
+            Graphics::Imaging::{CLSID_WICImagingFactory, IWICBitmapDecoderInfo, IWICImagingFactory, WICComponentEnumerateDefault, WICDecoder},
+            System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER},
+        },
+    };
+
+    let _guard = ComGuard::new();
+    let Ok(factory) = (unsafe { CoCreateInstance::<_, IWICImagingFactory>(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER) }) else {
+        return Vec::new();
+    };
+    let Ok(components) = (unsafe { factory.CreateComponentEnumerator(WICDecoder.0 as u32, WICComponentEnumerateDefault.0 as u32) }) else {
+        return Vec::new();
+    };
+
+    let mut extensions: Vec<String> = Vec::new();
+    let mut component: [Option<IUnknown>; 1] = [None];
+    while unsafe { components.Next(&mut component, None) } == S_OK {
+        let Some(info) = component[0].take().and_then(|component| component.cast::<IWICBitmapDecoderInfo>().ok()) else {
+            continue;
+        };
+
+        // Such as ".heic,.heif"
+        let mut len = 0;
+        let _ = unsafe { info.GetFileExtensions(&mut [], &mut len) };
+        if len == 0 {
+            continue;
+        }
+        let mut buffer = vec![0u16; len as usize];
+        if unsafe { info.GetFileExtensions(&mut buffer, &mut len) }.is_ok() {
+            extensions.extend(decode_wide(&buffer).split(',').map(|extension| extension.trim_start_matches('.').to_lowercase()));
+        }
+    }
+
+    ["heic", "heif", "avif"].iter().filter(|format| extensions.iter().any(|extension| extension == *format)).map(|format| format.to_string()).collect()
+}
+
+#[cfg(not(feature = "media"))]
+fn decodable_image_formats() -> Vec<String> {
+    Vec::new()
+}
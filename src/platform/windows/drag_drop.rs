@@ -1,22 +1,53 @@
-use super::util::{encode_wide, ComGuard, GlobalMemory};
-use crate::Operation;
-use std::mem::ManuallyDrop;
+use super::util::{decode_wide, encode_wide, ComGuard, GlobalMemory};
+use crate::{Operation, VirtualFile};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, mem::ManuallyDrop};
 use windows::{
-    core::{implement, Ref, BOOL, HRESULT, PCWSTR},
+    core::{implement, w, Ref, BOOL, HRESULT, PCWSTR},
     Win32::{
         Foundation::*,
+        Graphics::Gdi::ScreenToClient,
         System::{
-            Com::{CoTaskMemFree, IDataObject, DVASPECT_CONTENT, FORMATETC, STGMEDIUM, STGMEDIUM_0, TYMED_HGLOBAL},
+            Com::{CoTaskMemFree, IAdviseSink, IDataObject, IDataObject_Impl, IEnumFORMATETC, IEnumSTATDATA, DVASPECT_CONTENT, FORMATETC, STGMEDIUM, STGMEDIUM_0, TYMED_HGLOBAL, TYMED_ISTREAM},
+            DataExchange::RegisterClipboardFormatW,
             Ole::{
                 DoDragDrop, IDropSource, IDropSource_Impl, IDropTarget, IDropTarget_Impl, RegisterDragDrop, ReleaseStgMedium, RevokeDragDrop, CF_HDROP, DROPEFFECT, DROPEFFECT_COPY, DROPEFFECT_MOVE,
                 DROPEFFECT_NONE,
             },
-            SystemServices::{MK_LBUTTON, MODIFIERKEYS_FLAGS},
+            SystemServices::{MK_ALT, MK_CONTROL, MK_LBUTTON, MK_SHIFT, MODIFIERKEYS_FLAGS},
         },
-        UI::Shell::{Common::ITEMIDLIST, SHCreateDataObject, SHParseDisplayName, DROPFILES},
+        UI::Shell::{Common::ITEMIDLIST, DragQueryFileW, SHCreateDataObject, SHCreateMemStream, SHCreateStdEnumFmtEtc, SHParseDisplayName, DROPFILES, FILEDESCRIPTORW, HDROP},
     },
 };
 
+const FD_FILESIZE: u32 = 0x40;
+const FD_PROGRESSUI: u32 = 0x4000;
+const DATADIR_GET: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DropTargetEvent {
+    pub paths: Vec<String>,
+    /// Cursor position in client coordinates
+    pub x: i32,
+    pub y: i32,
+    pub ctrl_key: bool,
+    pub shift_key: bool,
+    pub alt_key: bool,
+    /// Drop effect suggested by the modifier keys. On drop, the one last returned by `on_enter` or `on_over`
+    pub operation: Operation,
+}
+
+/// Callbacks for a native drop target
+///
+/// `on_enter` and `on_over` return the drop effect to show. Files are copied unless they return another operation
+#[derive(Default)]
+pub struct DropTargetHandlers {
+    pub on_enter: Option<Box<dyn Fn(&DropTargetEvent) -> Operation>>,
+    pub on_over: Option<Box<dyn Fn(&DropTargetEvent) -> Operation>>,
+    pub on_leave: Option<Box<dyn Fn()>>,
+    pub on_drop: Option<Box<dyn Fn(&DropTargetEvent)>>,
+}
+
 /// Starts dragging
 pub fn start_drag(file_paths: Vec<String>, operation: Operation) -> Result<(), String> {
     let _guard = ComGuard::new();
@@ -114,6 +145,207 @@ pub fn start_drag(file_paths: Vec<String>, operation: Operation) -> Result<(), S
     Ok(())
 }
 
+/// Starts dragging files that do not exist on disk yet
+///
+/// `writer` is called with each file when the drop target requests its content and writes the bytes to the given stream.
+/// If writing fails, the drop target is told so and the error is passed to `on_error`
+pub fn start_drag_virtual<F, E>(files: Vec<VirtualFile>, operation: Operation, writer: F, on_error: E) -> Result<(), String>
+where
+    F: Fn(&VirtualFile, &mut dyn std::io::Write) -> Result<(), String> + 'static,
+    E: Fn(String) + 'static,
+{
+    let _guard = ComGuard::new();
+
+    // Holds other formats such as drag images set by the shell
+    let inner: IDataObject = unsafe { SHCreateDataObject(None, None, None).map_err(|e| e.message()) }?;
+
+    let data_object: IDataObject = VirtualDataObject {
+        inner,
+        files,
+        writer: Box::new(writer),
+        on_error: Box::new(on_error),
+        descriptor_format: unsafe { RegisterClipboardFormatW(w!("FileGroupDescriptorW")) } as u16,
+        contents_format: unsafe { RegisterClipboardFormatW(w!("FileContents")) } as u16,
+    }
+    .into();
+
+    let drop_source: IDropSource = DragDropTarget.into();
+
+    let mut effects = to_drop_effect(operation);
+
+    let _ = unsafe { DoDragDrop(&data_object, &drop_source, effects, &mut effects) };
+
+    Ok(())
+}
+
+#[implement(IDataObject)]
+struct VirtualDataObject {
+    inner: IDataObject,
+    files: Vec<VirtualFile>,
+    writer: Box<dyn Fn(&VirtualFile, &mut dyn std::io::Write) -> Result<(), String>>,
+    on_error: Box<dyn Fn(String)>,
+    descriptor_format: u16,
+    contents_format: u16,
+}
+
+impl VirtualDataObject {
+    fn formats(&self) -> [FORMATETC; 2] {
+        [
+            FORMATETC {
+                cfFormat: self.descriptor_format,
+                ptd: std::ptr::null_mut(),
+                dwAspect: DVASPECT_CONTENT.0,
+                lindex: -1,
+                tymed: TYMED_HGLOBAL.0 as _,
+            },
+            FORMATETC {
+                cfFormat: self.contents_format,
+                ptd: std::ptr::null_mut(),
+                dwAspect: DVASPECT_CONTENT.0,
+                lindex: -1,
+                tymed: (TYMED_ISTREAM.0 | TYMED_HGLOBAL.0) as _,
+            },
+        ]
+    }
+
+    /* FILEGROUPDESCRIPTORW is the item count followed by FILEDESCRIPTORW for each file */
+    fn get_descriptor(&self) -> Result<STGMEDIUM, String> {
+        let descriptor_size = std::mem::size_of::<FILEDESCRIPTORW>();
+        let hglobal = GlobalMemory::new(std::mem::size_of::<u32>() + descriptor_size * self.files.len())?;
+        let ptr = hglobal.lock()?;
+
+        unsafe { std::ptr::write_unaligned(ptr as *mut u32, self.files.len() as u32) };
+
+        for (i, file) in self.files.iter().enumerate() {
+            let mut descriptor = FILEDESCRIPTORW {
+                dwFlags: FD_PROGRESSUI,
+                ..Default::default()
+            };
+
+            if let Some(size) = file.size {
+                descriptor.dwFlags |= FD_FILESIZE;
+                descriptor.nFileSizeHigh = (size >> 32) as u32;
+                descriptor.nFileSizeLow = size as u32;
+            }
+
+            let name: Vec<u16> = file.name.encode_utf16().take(descriptor.cFileName.len() - 1).collect();
+            descriptor.cFileName[..name.len()].copy_from_slice(&name);
+
+            unsafe { std::ptr::write_unaligned(ptr.add(std::mem::size_of::<u32>() + descriptor_size * i) as *mut FILEDESCRIPTORW, descriptor) };
+        }
+
+        hglobal.unlock();
+
+        let handle = hglobal.handle();
+        // The receiver frees the memory
+        std::mem::forget(hglobal);
+
+        Ok(STGMEDIUM {
+            tymed: TYMED_HGLOBAL.0 as _,
+            u: STGMEDIUM_0 {
+                hGlobal: handle,
+            },
+            pUnkForRelease: ManuallyDrop::new(None),
+        })
+    }
+
+    fn get_contents(&self, index: i32, tymed: u32) -> Result<STGMEDIUM, String> {
+        let file = self.files.get(index as usize).ok_or_else(|| "Invalid file index".to_string())?;
+
+        let mut bytes = Vec::new();
+        (self.writer)(file, &mut bytes)?;
+
+        if tymed & TYMED_ISTREAM.0 as u32 != 0 {
+            let stream = unsafe { SHCreateMemStream(Some(&bytes)) }.ok_or_else(|| "Failed to create stream".to_string())?;
+            return Ok(STGMEDIUM {
+                tymed: TYMED_ISTREAM.0 as _,
+                u: STGMEDIUM_0 {
+                    pstm: ManuallyDrop::new(Some(stream)),
+                },
+                pUnkForRelease: ManuallyDrop::new(None),
+            });
+        }
+
+        let hglobal = GlobalMemory::new(bytes.len().max(1))?;
+        let ptr = hglobal.lock()?;
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len()) };
+        hglobal.unlock();
+
+        let handle = hglobal.handle();
+        // The receiver frees the memory
+        std::mem::forget(hglobal);
+
+        Ok(STGMEDIUM {
+            tymed: TYMED_HGLOBAL.0 as _,
+            u: STGMEDIUM_0 {
+                hGlobal: handle,
+            },
+            pUnkForRelease: ManuallyDrop::new(None),
+        })
+    }
+}
+
+#[allow(non_snake_case)]
+impl IDataObject_Impl for VirtualDataObject_Impl {
+    fn GetData(&self, pformatetcin: *const FORMATETC) -> windows::core::Result<STGMEDIUM> {
+        let format = unsafe { &*pformatetcin };
+
+        if format.cfFormat == self.descriptor_format {
+            return self.get_descriptor().map_err(|e| windows::core::Error::new(E_FAIL, e));
+        }
+
+        if format.cfFormat == self.contents_format {
+            return self.get_contents(format.lindex, format.tymed).map_err(|e| {
+                (self.on_error)(e.clone());
+                windows::core::Error::new(E_FAIL, e)
+            });
+        }
+
+        unsafe { self.inner.GetData(pformatetcin) }
+    }
+
+    fn GetDataHere(&self, pformatetc: *const FORMATETC, pmedium: *mut STGMEDIUM) -> windows::core::Result<()> {
+        unsafe { self.inner.GetDataHere(pformatetc, pmedium) }
+    }
+
+    fn QueryGetData(&self, pformatetc: *const FORMATETC) -> HRESULT {
+        let format = unsafe { &*pformatetc };
+        if format.cfFormat == self.descriptor_format || format.cfFormat == self.contents_format {
+            return S_OK;
+        }
+
+        unsafe { self.inner.QueryGetData(pformatetc) }
+    }
+
+    fn GetCanonicalFormatEtc(&self, pformatectin: *const FORMATETC, pformatetcout: *mut FORMATETC) -> HRESULT {
+        unsafe { self.inner.GetCanonicalFormatEtc(pformatectin, pformatetcout) }
+    }
+
+    fn SetData(&self, pformatetc: *const FORMATETC, pmedium: *const STGMEDIUM, frelease: BOOL) -> windows::core::Result<()> {
+        unsafe { self.inner.SetData(pformatetc, pmedium, frelease.as_bool()) }
+    }
+
+    fn EnumFormatEtc(&self, dwdirection: u32) -> windows::core::Result<IEnumFORMATETC> {
+        if dwdirection != DATADIR_GET {
+            return Err(E_NOTIMPL.into());
+        }
+
+        unsafe { SHCreateStdEnumFmtEtc(&self.formats()) }
+    }
+
+    fn DAdvise(&self, _pformatetc: *const FORMATETC, _advf: u32, _padvsink: Ref<IAdviseSink>) -> windows::core::Result<u32> {
+        Err(OLE_E_ADVISENOTSUPPORTED.into())
+    }
+
+    fn DUnadvise(&self, _dwconnection: u32) -> windows::core::Result<()> {
+        Err(OLE_E_ADVISENOTSUPPORTED.into())
+    }
+
+    fn EnumDAdvise(&self) -> windows::core::Result<IEnumSTATDATA> {
+        Err(OLE_E_ADVISENOTSUPPORTED.into())
+    }
+}
+
 #[implement(IDropSource)]
 struct DragDropTarget;
 
@@ -137,35 +369,157 @@ impl IDropSource_Impl for DragDropTarget_Impl {
 }
 
 /// Registers the window as a drop target
-pub fn register(window_handle: isize) -> Result<(), String> {
+pub fn register(window_handle: isize, handlers: DropTargetHandlers) -> Result<(), String> {
     let _ = unregister(window_handle);
-    let drag_drop_target: IDropTarget = DropTarget.into();
-    unsafe { RegisterDragDrop(HWND(window_handle as _), &drag_drop_target).map_err(|e| e.message()) }
+    let drag_drop_target: IDropTarget = DropTarget {
+        window_handle: HWND(window_handle as _),
+        handlers,
+        paths: RefCell::new(Vec::new()),
+        operation: RefCell::new(Operation::None),
+    }
+    .into();
+    unsafe { RegisterDragDrop(HWND(window_handle as _), &drag_drop_target).map_err(|e| e.message()) }?;
+    // Revoked automatically when the window is destroyed
+    super::window::with_state(window_handle, |window_state| window_state.drop_target = true);
+    Ok(())
 }
 
 /// Revokes a drop target
 pub fn unregister(window_handle: isize) -> Result<(), String> {
+    super::window::with_existing_state(window_handle, |window_state| window_state.drop_target = false);
+    revoke(window_handle)
+}
+
+pub(crate) fn revoke(window_handle: isize) -> Result<(), String> {
     unsafe { RevokeDragDrop(HWND(window_handle as _)).map_err(|e| e.message()) }
 }
 
 #[implement(IDropTarget)]
-struct DropTarget;
+struct DropTarget {
+    window_handle: HWND,
+    handlers: DropTargetHandlers,
+    // Files extracted on DragEnter, since DragOver does not receive the data object
+    paths: RefCell<Vec<String>>,
+    // Last operation returned by the callbacks, which is used on Drop
+    operation: RefCell<Operation>,
+}
+
+impl DropTarget {
+    fn to_event(&self, key_state: MODIFIERKEYS_FLAGS, pt: &POINTL) -> DropTargetEvent {
+        let mut point = POINT {
+            x: pt.x,
+            y: pt.y,
+        };
+        let _ = unsafe { ScreenToClient(self.window_handle, &mut point) };
+
+        let operation = if key_state.contains(MK_SHIFT) && !key_state.contains(MK_CONTROL) {
+            Operation::Move
+        } else {
+            Operation::Copy
+        };
+
+        DropTargetEvent {
+            paths: self.paths.borrow().clone(),
+            x: point.x,
+            y: point.y,
+            ctrl_key: key_state.contains(MK_CONTROL),
+            shift_key: key_state.contains(MK_SHIFT),
+            alt_key: key_state.contains(MK_ALT),
+            operation,
+        }
+    }
+}
+
+fn to_drop_effect(operation: Operation) -> DROPEFFECT {
+    match operation {
+        Operation::Copy => DROPEFFECT_COPY,
+        Operation::Move => DROPEFFECT_MOVE,
+        Operation::None => DROPEFFECT_NONE,
+    }
+}
+
+fn get_paths(data_object: &IDataObject) -> Vec<String> {
+    let format_etc = FORMATETC {
+        cfFormat: CF_HDROP.0,
+        ptd: std::ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL.0 as _,
+    };
+
+    let Ok(mut stg_medium) = (unsafe { data_object.GetData(&format_etc) }) else {
+        return Vec::new();
+    };
+
+    let hdrop = HDROP(unsafe { stg_medium.u.hGlobal.0 });
+    let count = unsafe { DragQueryFileW(hdrop, 0xFFFFFFFF, None) };
+    let mut paths = Vec::new();
+    for i in 0..count {
+        let len = unsafe { DragQueryFileW(hdrop, i, None) } as usize;
+        let mut buffer = vec![0u16; len + 1];
+        unsafe { DragQueryFileW(hdrop, i, Some(&mut buffer)) };
+        paths.push(decode_wide(&buffer));
+    }
+
+    unsafe { ReleaseStgMedium(&mut stg_medium) };
+
+    paths
+}
 
 #[allow(non_snake_case)]
 impl IDropTarget_Impl for DropTarget_Impl {
-    fn DragEnter(&self, _pDataObj: Ref<IDataObject>, _grfKeyState: MODIFIERKEYS_FLAGS, _pt: &POINTL, _pdwEffect: *mut DROPEFFECT) -> windows::core::Result<()> {
+    fn DragEnter(&self, pDataObj: Ref<IDataObject>, grfKeyState: MODIFIERKEYS_FLAGS, pt: &POINTL, pdwEffect: *mut DROPEFFECT) -> windows::core::Result<()> {
+        *self.paths.borrow_mut() = pDataObj.ok().map(get_paths).unwrap_or_default();
+
+        let event = self.to_event(grfKeyState, pt);
+        let operation = match &self.handlers.on_enter {
+            Some(on_enter) => on_enter(&event),
+            None => event.operation,
+        };
+        unsafe { *pdwEffect = to_drop_effect(operation.clone()) & *pdwEffect };
+        *self.operation.borrow_mut() = operation;
+
         Ok(())
     }
 
-    fn DragOver(&self, _grfKeyState: MODIFIERKEYS_FLAGS, _pt: &POINTL, _pdwEffect: *mut DROPEFFECT) -> windows::core::Result<()> {
+    fn DragOver(&self, grfKeyState: MODIFIERKEYS_FLAGS, pt: &POINTL, pdwEffect: *mut DROPEFFECT) -> windows::core::Result<()> {
+        let event = self.to_event(grfKeyState, pt);
+        let operation = match &self.handlers.on_over {
+            Some(on_over) => on_over(&event),
+            None => event.operation,
+        };
+        unsafe { *pdwEffect = to_drop_effect(operation.clone()) & *pdwEffect };
+        *self.operation.borrow_mut() = operation;
+
         Ok(())
     }
 
     fn DragLeave(&self) -> windows::core::Result<()> {
+        self.paths.borrow_mut().clear();
+        *self.operation.borrow_mut() = Operation::None;
+
+        if let Some(on_leave) = &self.handlers.on_leave {
+            on_leave();
+        }
+
         Ok(())
     }
 
-    fn Drop(&self, _pDataObj: Ref<IDataObject>, _grfKeyState: MODIFIERKEYS_FLAGS, _pt: &POINTL, _pdwEffect: *mut DROPEFFECT) -> windows::core::Result<()> {
+    fn Drop(&self, pDataObj: Ref<IDataObject>, grfKeyState: MODIFIERKEYS_FLAGS, pt: &POINTL, pdwEffect: *mut DROPEFFECT) -> windows::core::Result<()> {
+        if let Ok(data_object) = pDataObj.ok() {
+            *self.paths.borrow_mut() = get_paths(data_object);
+        }
+
+        let mut event = self.to_event(grfKeyState, pt);
+        event.operation = self.operation.replace(Operation::None);
+        unsafe { *pdwEffect = to_drop_effect(event.operation.clone()) & *pdwEffect };
+
+        if let Some(on_drop) = &self.handlers.on_drop {
+            on_drop(&event);
+        }
+
+        self.paths.borrow_mut().clear();
+
         Ok(())
     }
 }
@@ -1,33 +1,79 @@
 use super::{
+    fs_ext::{execute_file_operation, execute_with_resolver},
     shell,
     util::{decode_wide, encode_wide, prefixed, ComGuard},
 };
-use crate::{Dirent, FileAttribute, RecycleBinDirent, RecycleBinItem, Volume};
-use std::{collections::HashMap, path::Path};
+#[cfg(feature = "listing")]
+pub use crate::listing::{export_listing, import_listing};
+pub use crate::transaction::Transaction;
+use crate::{
+    compare::compare_readers, AccessMask, CancelToken, CompareMode, CompletionNotice, DirListing, DirPage, Dirent, FileAttribute, FileComparison, FileHash, HashAlgorithm, MkdirOptions,
+    OperationEvent, PreflightReport, ProgressThrottle, ReaddirOptions, RecycleBinDirent, RecycleBinInfo, RecycleBinItem, RetryOptions, SetAttributeOptions, UndeleteConflict, Volume,
+};
+use smol::channel::{Receiver, Sender};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+};
 use windows::{
-    core::{Interface, PCSTR, PCWSTR},
+    core::{Interface, BOOL, PCSTR, PCWSTR, PWSTR},
     Win32::{
-        Foundation::{CloseHandle, FILETIME, HANDLE, HWND, MAX_PATH, PROPERTYKEY, S_OK},
+        Foundation::{CloseHandle, LocalFree, ERROR_HANDLE_EOF, FILETIME, GENERIC_WRITE, HANDLE, HLOCAL, HWND, LPARAM, LRESULT, MAX_PATH, PROPERTYKEY, S_OK, WPARAM},
+        Security::{
+            AccessCheck, Authorization::ConvertSidToStringSidW, DuplicateToken, GetFileSecurityW, GetSecurityDescriptorOwner, LookupAccountSidW, SecurityImpersonation, DACL_SECURITY_INFORMATION,
+            GENERIC_MAPPING, GROUP_SECURITY_INFORMATION, OWNER_SECURITY_INFORMATION, PRIVILEGE_SET, PSECURITY_DESCRIPTOR, PSID, SID_NAME_USE, TOKEN_DUPLICATE, TOKEN_QUERY,
+        },
         Storage::FileSystem::{
-            CreateFileW, FindClose, FindExInfoBasic, FindExSearchNameMatch, FindFirstFileExW, FindFirstVolumeW, FindNextFileW, FindNextVolumeW, FindVolumeClose, GetDiskFreeSpaceExW, GetDriveTypeW,
-            GetVolumeInformationW, GetVolumePathNamesForVolumeNameW, SetFileTime, FILE_ATTRIBUTE_DEVICE, FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_READONLY,
-            FILE_ATTRIBUTE_REPARSE_POINT, FILE_ATTRIBUTE_SYSTEM, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_WRITE_ATTRIBUTES,
-            FIND_FIRST_EX_FLAGS, OPEN_EXISTING, WIN32_FIND_DATAW,
+            CreateFileW, CreateHardLinkW, FindClose, FindExInfoBasic, FindExSearchNameMatch, FindFirstFileExW, FindFirstStreamW, FindFirstVolumeW, FindNextFileW, FindNextStreamW, FindNextVolumeW,
+            FindStreamInfoStandard, FindVolumeClose, GetDiskFreeSpaceExW, GetDriveTypeW, GetFileAttributesW, GetVolumeInformationW, GetVolumePathNameW, GetVolumePathNamesForVolumeNameW,
+            SetFileAttributesW, SetFileTime, FILE_ATTRIBUTE_DEVICE, FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_NOT_CONTENT_INDEXED, FILE_ATTRIBUTE_READONLY,
+            FILE_ATTRIBUTE_REPARSE_POINT, FILE_ATTRIBUTE_SYSTEM, FILE_FLAGS_AND_ATTRIBUTES, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT, FILE_SHARE_DELETE, FILE_SHARE_READ,
+            FILE_SHARE_WRITE, FILE_WRITE_ATTRIBUTES, FIND_FIRST_EX_FLAGS, INVALID_FILE_ATTRIBUTES, OPEN_EXISTING, WIN32_FIND_DATAW, WIN32_FIND_STREAM_DATA,
         },
         System::{
             Com::{CoCreateInstance, CoTaskMemFree, CreateBindCtx, IPersistFile, CLSCTX_ALL, CLSCTX_INPROC_SERVER, STGM_READ},
+            Ioctl::{
+                BusType1394, BusTypeAta, BusTypeAtapi, BusTypeFileBackedVirtual, BusTypeMmc, BusTypeNvme, BusTypeRAID, BusTypeSas, BusTypeSata, BusTypeScsi, BusTypeSd, BusTypeUsb, BusTypeVirtual,
+                BusTypeiScsi, PropertyStandardQuery, StorageDeviceProperty, IOCTL_STORAGE_QUERY_PROPERTY, STORAGE_DEVICE_DESCRIPTOR, STORAGE_PROPERTY_QUERY,
+            },
+            SystemServices::{IO_REPARSE_TAG_MOUNT_POINT, IO_REPARSE_TAG_SYMLINK},
+            Threading::{GetCurrentProcess, OpenProcessToken},
             Variant::{VariantChangeType, VariantClear, VariantGetStringElem, VariantToFileTime, PSTIME_FLAGS, VARIANT, VAR_CHANGE_FLAGS, VT_BSTR, VT_DATE},
+            IO::DeviceIoControl,
         },
         UI::Shell::{
+            BHID_EnumItems,
             Common::{ITEMIDLIST, STRRET},
-            FMTID_Storage, FOLDERID_RecycleBinFolder, FileOperation, IContextMenu, IEnumIDList, IFileOperation, IShellFolder, IShellFolder2, IShellItem, IShellItemArray, IShellLinkW,
-            SHCreateItemFromParsingName, SHCreateShellItemArrayFromIDLists, SHEmptyRecycleBinW, SHGetDataFromIDListW, SHGetDesktopFolder, SHGetKnownFolderIDList, SHParseDisplayName, ShellLink,
-            CMINVOKECOMMANDINFO, FOF_ALLOWUNDO, FOF_NOCONFIRMATION, FOF_RENAMEONCOLLISION, KF_FLAG_DEFAULT, PID_DISPLACED_DATE, PSGUID_DISPLACED, SHCONTF_FOLDERS, SHCONTF_NONFOLDERS,
-            SHGDFIL_FINDDATA, SHGDN_NORMAL, SLGP_UNCPRIORITY,
+            FMTID_Storage, FOLDERID_RecycleBinFolder, FileOperation, IContextMenu, IEnumIDList, IEnumShellItems, IFileOperation, IShellFolder, IShellFolder2, IShellItem, IShellItemArray, IShellLinkW,
+            SHCNRF_InterruptLevel, SHCNRF_ShellLevel, SHChangeNotifyDeregister, SHChangeNotifyEntry, SHChangeNotifyRegister, SHCreateItemFromParsingName, SHCreateItemWithParent,
+            SHCreateShellItemArrayFromIDLists, SHEmptyRecycleBinW, SHGetDataFromIDListW, SHGetDesktopFolder, SHGetKnownFolderIDList, SHParseDisplayName, SHQueryRecycleBinW, ShellLink,
+            CMIC_MASK_FLAG_NO_UI, CMINVOKECOMMANDINFO, COPYENGINE_S_USER_IGNORED, FILEOPERATION_FLAGS, FOF_ALLOWUNDO, FOF_NOCONFIRMATION, FOF_RENAMEONCOLLISION, FOF_WANTNUKEWARNING, KF_FLAG_DEFAULT,
+            PID_DISPLACED_DATE, PSGUID_DISPLACED, SHCNE_ALLEVENTS, SHCONTF_FOLDERS, SHCONTF_NONFOLDERS, SHGDFIL_FINDDATA, SHGDN_NORMAL, SHQUERYRBINFO, SIGDN_FILESYSPATH, SLGP_UNCPRIORITY,
+        },
+        UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, GetWindowLongPtrW, PostMessageW, PostQuitMessage, RegisterClassW, SetWindowLongPtrW, TranslateMessage,
+            GWLP_USERDATA, HWND_MESSAGE, MSG, WINDOW_EX_STYLE, WM_APP, WM_CLOSE, WM_DESTROY, WNDCLASSW, WS_OVERLAPPED,
         },
     },
 };
 
+static OPEN_DIRECTORIES: LazyLock<Mutex<HashMap<String, OpenDirectory>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_CURSOR: AtomicU64 = AtomicU64::new(1);
+static RECYCLE_BIN_WINDOW: Mutex<isize> = Mutex::new(0);
+
+const DRIVE_REMOVABLE: u32 = 2;
+const DRIVE_FIXED: u32 = 3;
+const DRIVE_REMOTE: u32 = 4;
+const DRIVE_CDROM: u32 = 5;
+const FILE_READ_ONLY_VOLUME: u32 = 0x00080000;
+const WM_RECYCLE_BIN_CHANGED: u32 = WM_APP + 1;
+
 /// Lists volumes
 pub fn list_volumes() -> Result<Vec<Volume>, String> {
     let mut volumes: Vec<Volume> = Vec::new();
@@ -43,37 +89,45 @@ pub fn list_volumes() -> Result<Vec<Volume>, String> {
         let mount_point = decode_wide(&drive_paths);
 
         let mut volume_label_ptr = vec![0u16; (MAX_PATH + 1) as usize];
-        unsafe { GetVolumeInformationW(PCWSTR(volume_path_guid.as_ptr()), Some(&mut volume_label_ptr), None, None, None, None).map_err(|e| e.message()) }?;
+        let mut file_system_ptr = vec![0u16; (MAX_PATH + 1) as usize];
+        let mut flags = 0;
+        unsafe { GetVolumeInformationW(PCWSTR(volume_path_guid.as_ptr()), Some(&mut volume_label_ptr), None, None, Some(&mut flags), Some(&mut file_system_ptr)).map_err(|e| e.message()) }?;
 
         let mut volume_label = decode_wide(&volume_label_ptr);
+        let drive_type = unsafe { GetDriveTypeW(PCWSTR::from_raw(drive_paths.as_ptr())) };
 
         if volume_label.is_empty() {
-            volume_label = match unsafe { GetDriveTypeW(PCWSTR::from_raw(drive_paths.as_ptr())) } {
-                2 => "Removable Drive".to_string(),
-                3 => "Disk Drive".to_string(),
-                4 => "Network Drive".to_string(),
+            volume_label = match drive_type {
+                DRIVE_REMOVABLE => "Removable Drive".to_string(),
+                DRIVE_FIXED => "Disk Drive".to_string(),
+                DRIVE_REMOTE => "Network Drive".to_string(),
                 _ => "Unknown".to_string(),
             }
         }
 
-        if mount_point.is_empty() {
-            volumes.push(Volume {
-                mount_point,
-                volume_label,
-                available_units: 0,
-                total_units: 0,
-            });
+        let (available_units, total_units) = if mount_point.is_empty() {
+            (0, 0)
         } else {
             let mut available = 0;
             let mut total = 0;
             unsafe { GetDiskFreeSpaceExW(PCWSTR::from_raw(drive_paths.as_ptr()), None, Some(&mut total), Some(&mut available)).map_err(|e| e.message()) }?;
-            volumes.push(Volume {
-                mount_point,
-                volume_label,
-                available_units: available,
-                total_units: total,
-            });
-        }
+            (available, total)
+        };
+
+        let device_path = decode_wide(&volume_path_guid);
+
+        volumes.push(Volume {
+            mount_point,
+            volume_label,
+            available_units,
+            total_units,
+            file_system: decode_wide(&file_system_ptr),
+            is_removable: drive_type == DRIVE_REMOVABLE || drive_type == DRIVE_CDROM,
+            is_network: drive_type == DRIVE_REMOTE,
+            is_read_only: flags & FILE_READ_ONLY_VOLUME != 0,
+            bus_type: get_bus_type(&device_path),
+            device_path,
+        });
 
         volume_path_guid = vec![0u16; MAX_PATH as usize];
         let next = unsafe { FindNextVolumeW(handle, &mut volume_path_guid) };
@@ -87,8 +141,71 @@ pub fn list_volumes() -> Result<Vec<Volume>, String> {
     Ok(volumes)
 }
 
+fn get_bus_type(device_path: &str) -> String {
+    // CreateFileW opens the volume device only without the trailing backslash
+    let wide = encode_wide(device_path.trim_end_matches('\\'));
+    let handle = match unsafe { CreateFileW(PCWSTR::from_raw(wide.as_ptr()), 0, FILE_SHARE_READ | FILE_SHARE_WRITE, None, OPEN_EXISTING, FILE_FLAGS_AND_ATTRIBUTES(0), None) } {
+        Ok(handle) => handle,
+        Err(_) => return String::new(),
+    };
+
+    let query = STORAGE_PROPERTY_QUERY {
+        PropertyId: StorageDeviceProperty,
+        QueryType: PropertyStandardQuery,
+        ..Default::default()
+    };
+    let mut descriptor = STORAGE_DEVICE_DESCRIPTOR::default();
+    let mut bytes_returned = 0;
+    let result = unsafe {
+        DeviceIoControl(
+            handle,
+            IOCTL_STORAGE_QUERY_PROPERTY,
+            Some(&query as *const _ as _),
+            size_of::<STORAGE_PROPERTY_QUERY>() as _,
+            Some(&mut descriptor as *mut _ as _),
+            size_of::<STORAGE_DEVICE_DESCRIPTOR>() as _,
+            Some(&mut bytes_returned),
+            None,
+        )
+    };
+    let _ = unsafe { CloseHandle(handle) };
+
+    if result.is_err() {
+        return String::new();
+    }
+
+    match descriptor.BusType {
+        BusTypeScsi => "scsi",
+        BusTypeAtapi | BusTypeAta => "ata",
+        BusType1394 => "ieee1394",
+        BusTypeUsb => "usb",
+        BusTypeRAID => "raid",
+        BusTypeiScsi => "iscsi",
+        BusTypeSas => "sas",
+        BusTypeSata => "sata",
+        BusTypeSd => "sd",
+        BusTypeMmc => "mmc",
+        BusTypeVirtual | BusTypeFileBackedVirtual => "virtual",
+        BusTypeNvme => "nvme",
+        _ => "",
+    }
+    .to_string()
+}
+
 /// Lists all files/directories under the specified directory
 pub fn readdir<P: AsRef<Path>>(directory: P, recursive: bool, with_mime_type: bool) -> Result<Vec<Dirent>, String> {
+    readdir_with(
+        directory,
+        recursive,
+        &ReaddirOptions {
+            with_mime: with_mime_type,
+            ..Default::default()
+        },
+    )
+}
+
+/// Lists all files/directories under the specified directory, reading the values requested in the options in the same pass
+pub fn readdir_with<P: AsRef<Path>>(directory: P, recursive: bool, options: &ReaddirOptions) -> Result<Vec<Dirent>, String> {
     let mut entries = Vec::new();
 
     if !directory.as_ref().is_dir() {
@@ -107,12 +224,41 @@ pub fn readdir<P: AsRef<Path>>(directory: P, recursive: bool, with_mime_type: bo
         return Ok(entries);
     }
 
-    try_readdir(handle, directory, &mut entries, recursive, with_mime_type)?;
+    // The icon location is read through the shell
+    let _guard = options.with_icon.then(ComGuard::new);
+    try_readdir(handle, directory, &mut entries, recursive, options)?;
 
     Ok(entries)
 }
 
-fn try_readdir<P: AsRef<Path>>(handle: HANDLE, parent: P, entries: &mut Vec<Dirent>, recursive: bool, with_mime_type: bool) -> Result<&mut Vec<Dirent>, String> {
+/* Enumerates a search-ms: folder, which Explorer answers from the Windows Search index */
+pub(crate) fn list_search_results(search_url: &str, cancel: &CancelToken) -> Result<Vec<String>, String> {
+    let _guard = ComGuard::new();
+
+    let wide = encode_wide(search_url);
+    let folder: IShellItem = unsafe { SHCreateItemFromParsingName(PCWSTR::from_raw(wide.as_ptr()), None).map_err(|e| e.message()) }?;
+    let items: IEnumShellItems = unsafe { folder.BindToHandler(None, &BHID_EnumItems).map_err(|e| e.message()) }?;
+
+    let mut paths = Vec::new();
+    let mut fetched = [None];
+    while !cancel.is_cancelled() && unsafe { items.Next(&mut fetched, None) } == S_OK {
+        if let Some(item) = fetched[0].take() {
+            let path_ptr = unsafe { item.GetDisplayName(SIGDN_FILESYSPATH).map_err(|e| e.message()) }?;
+            paths.push(decode_wide(unsafe { path_ptr.as_wide() }));
+            unsafe { CoTaskMemFree(Some(path_ptr.0 as _)) };
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Lists entries in the directory as a compact `DirListing`
+pub fn readdir_listing<P: AsRef<Path>>(directory: P, recursive: bool, with_mime_type: bool) -> Result<DirListing, String> {
+    let entries = readdir(directory.as_ref(), recursive, with_mime_type)?;
+    Ok(DirListing::new(directory, entries))
+}
+
+fn try_readdir<'a, P: AsRef<Path>>(handle: HANDLE, parent: P, entries: &'a mut Vec<Dirent>, recursive: bool, options: &ReaddirOptions) -> Result<&'a mut Vec<Dirent>, String> {
     let mut data: WIN32_FIND_DATAW = unsafe { std::mem::zeroed() };
 
     while unsafe { FindNextFileW(handle, &mut data) }.is_ok() {
@@ -121,32 +267,7 @@ fn try_readdir<P: AsRef<Path>>(handle: HANDLE, parent: P, entries: &mut Vec<Dire
             continue;
         }
 
-        let mut full_path = parent.as_ref().to_path_buf();
-
-        if full_path.to_str().unwrap().ends_with(":") {
-            full_path.push(std::path::MAIN_SEPARATOR_STR);
-        }
-        full_path.push(name.clone());
-
-        let attributes = get_attribute(&full_path, &data)?;
-
-        let mime_type = if with_mime_type {
-            get_mime_type(if attributes.is_symbolic_link {
-                &attributes.link_path
-            } else {
-                &name
-            })
-        } else {
-            String::new()
-        };
-
-        entries.push(Dirent {
-            name: name.clone(),
-            parent_path: parent.as_ref().to_string_lossy().to_string(),
-            full_path: full_path.to_string_lossy().to_string(),
-            attributes,
-            mime_type,
-        });
+        entries.push(find_data_to_dirent(parent.as_ref(), &name, &data, options)?);
 
         if data.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0 && recursive {
             let mut search_path = parent.as_ref().to_path_buf();
@@ -157,7 +278,7 @@ fn try_readdir<P: AsRef<Path>>(handle: HANDLE, parent: P, entries: &mut Vec<Dire
             let path = PCWSTR::from_raw(wide.as_ptr());
             let next_handle = unsafe { FindFirstFileExW(path, FindExInfoBasic, &mut data as *mut _ as _, FindExSearchNameMatch, None, FIND_FIRST_EX_FLAGS(0)).map_err(|e| e.message()) }?;
             if !next_handle.is_invalid() {
-                try_readdir(next_handle, next_parent, entries, recursive, with_mime_type)?;
+                try_readdir(next_handle, next_parent, entries, recursive, options)?;
             }
         }
     }
@@ -167,6 +288,154 @@ fn try_readdir<P: AsRef<Path>>(handle: HANDLE, parent: P, entries: &mut Vec<Dire
     Ok(entries)
 }
 
+fn find_data_to_dirent(parent: &Path, name: &str, data: &WIN32_FIND_DATAW, options: &ReaddirOptions) -> Result<Dirent, String> {
+    let mut full_path = parent.to_path_buf();
+
+    if full_path.to_str().unwrap().ends_with(":") {
+        full_path.push(std::path::MAIN_SEPARATOR_STR);
+    }
+    full_path.push(name);
+
+    let attributes = get_attribute(&full_path, data)?;
+
+    let mime_type = if options.with_mime {
+        get_mime_type(if attributes.is_symbolic_link {
+            &attributes.link_path
+        } else {
+            name
+        })
+    } else {
+        String::new()
+    };
+
+    let link_target = if options.with_link_target && (attributes.is_symbolic_link || attributes.is_junction) && !attributes.link_path.is_empty() {
+        Some(resolve_link_target(parent, &attributes.link_path))
+    } else {
+        None
+    };
+
+    Ok(Dirent {
+        name: name.to_string(),
+        parent_path: parent.to_string_lossy().to_string(),
+        full_path: full_path.to_string_lossy().to_string(),
+        attributes,
+        mime_type,
+        icon: options.with_icon.then(|| shell::get_file_icon_location(&full_path)).flatten(),
+        link_target,
+        owner: options.with_owner.then(|| get_owner(&full_path)).flatten(),
+    })
+}
+
+/* Relative targets are relative to the directory of the link. A broken link resolves to its target as it is */
+fn resolve_link_target(parent: &Path, link_path: &str) -> String {
+    let target = parent.join(link_path);
+    match std::fs::canonicalize(&target) {
+        Ok(resolved) => {
+            let resolved = resolved.to_string_lossy().to_string();
+            resolved.strip_prefix("\\\\?\\").map(|stripped| stripped.to_string()).unwrap_or(resolved)
+        }
+        Err(_) => target.to_string_lossy().to_string(),
+    }
+}
+
+struct OpenDirectory {
+    directory: PathBuf,
+    handle: isize,
+    // FindFirstFileExW returns the first entry together with the handle
+    pending: Option<WIN32_FIND_DATAW>,
+}
+
+/// Lists entries in the directory a page at a time, keeping the directory open between calls
+///
+/// Pass `None` as the cursor to start listing and the returned cursor to continue. The cursor is `None` when the listing is complete
+pub fn readdir_paged<P: AsRef<Path>>(directory: P, cursor: Option<&str>, page_size: usize, with_mime_type: bool) -> Result<DirPage, String> {
+    let (cursor, mut open) = match cursor {
+        Some(cursor) => {
+            let open = OPEN_DIRECTORIES.lock().unwrap().remove(cursor).ok_or("Invalid cursor")?;
+            if open.directory != directory.as_ref() {
+                OPEN_DIRECTORIES.lock().unwrap().insert(cursor.to_string(), open);
+                return Err("Cursor does not belong to the directory".to_string());
+            }
+            (cursor.to_string(), open)
+        }
+        None => {
+            let mut search_path = directory.as_ref().to_path_buf();
+            search_path.push("*");
+
+            let wide = encode_wide(prefixed(search_path));
+            let mut data: WIN32_FIND_DATAW = unsafe { std::mem::zeroed() };
+            let handle = unsafe { FindFirstFileExW(PCWSTR::from_raw(wide.as_ptr()), FindExInfoBasic, &mut data as *mut _ as _, FindExSearchNameMatch, None, FIND_FIRST_EX_FLAGS(0)).map_err(|e| e.message()) }?;
+
+            (
+                NEXT_CURSOR.fetch_add(1, Ordering::SeqCst).to_string(),
+                OpenDirectory {
+                    directory: directory.as_ref().to_path_buf(),
+                    handle: handle.0 as _,
+                    pending: Some(data),
+                },
+            )
+        }
+    };
+
+    let handle = HANDLE(open.handle as _);
+    let options = ReaddirOptions {
+        with_mime: with_mime_type,
+        ..Default::default()
+    };
+    let mut entries = Vec::new();
+    let mut finished = false;
+
+    while entries.len() < page_size.max(1) {
+        let data = match open.pending.take() {
+            Some(data) => data,
+            None => {
+                let mut data: WIN32_FIND_DATAW = unsafe { std::mem::zeroed() };
+                if unsafe { FindNextFileW(handle, &mut data) }.is_err() {
+                    finished = true;
+                    break;
+                }
+                data
+            }
+        };
+
+        let name = decode_wide(&data.cFileName);
+        if name == "." || name == ".." {
+            continue;
+        }
+
+        match find_data_to_dirent(&open.directory, &name, &data, &options) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                let _ = unsafe { FindClose(handle) };
+                return Err(e);
+            }
+        }
+    }
+
+    if finished {
+        unsafe { FindClose(handle).map_err(|e| e.message()) }?;
+        return Ok(DirPage {
+            entries,
+            cursor: None,
+        });
+    }
+
+    OPEN_DIRECTORIES.lock().unwrap().insert(cursor.clone(), open);
+
+    Ok(DirPage {
+        entries,
+        cursor: Some(cursor),
+    })
+}
+
+/// Closes the directory kept open by `readdir_paged` before the listing is complete
+pub fn close_readdir_cursor(cursor: &str) -> Result<(), String> {
+    if let Some(open) = OPEN_DIRECTORIES.lock().unwrap().remove(cursor) {
+        unsafe { FindClose(HANDLE(open.handle as _)).map_err(|e| e.message()) }?;
+    }
+    Ok(())
+}
+
 /// Gets file/directory attributes
 pub fn stat<P: AsRef<Path>>(file_path: P) -> Result<FileAttribute, String> {
     let wide = encode_wide(prefixed(file_path.as_ref()));
@@ -182,11 +451,16 @@ pub fn stat<P: AsRef<Path>>(file_path: P) -> Result<FileAttribute, String> {
 
 fn get_attribute<P: AsRef<Path>>(file_path: &P, data: &WIN32_FIND_DATAW) -> Result<FileAttribute, String> {
     let attributes = data.dwFileAttributes;
-    let possible_file_type = get_file_type(&file_path, attributes);
-    let (file_type, is_symbolic_link, link_path) = if possible_file_type == FileType::Link {
-        get_link_path(file_path.as_ref())?
+    let (file_type, is_symbolic_link, is_junction, link_path) = if attributes & FILE_ATTRIBUTE_REPARSE_POINT.0 != 0 {
+        get_reparse_point(file_path.as_ref(), data)
     } else {
-        (possible_file_type, false, String::new())
+        let possible_file_type = get_file_type(&file_path, attributes);
+        if possible_file_type == FileType::Link {
+            let (file_type, is_symbolic_link, link_path) = get_link_path(file_path.as_ref())?;
+            (file_type, is_symbolic_link, false, link_path)
+        } else {
+            (possible_file_type, false, false, String::new())
+        }
     };
 
     Ok(FileAttribute {
@@ -197,6 +471,8 @@ fn get_attribute<P: AsRef<Path>>(file_path: &P, data: &WIN32_FIND_DATAW) -> Resu
         is_device: file_type == FileType::Device,
         is_file: file_type == FileType::File,
         is_symbolic_link,
+        is_junction,
+        is_not_indexed: attributes & FILE_ATTRIBUTE_NOT_CONTENT_INDEXED.0 != 0,
         ctime_ms: 0,
         mtime_ms: to_msecs_from_file_time(data.ftLastWriteTime.dwLowDateTime, data.ftLastWriteTime.dwHighDateTime),
         atime_ms: to_msecs_from_file_time(data.ftLastAccessTime.dwLowDateTime, data.ftLastAccessTime.dwHighDateTime),
@@ -206,6 +482,30 @@ fn get_attribute<P: AsRef<Path>>(file_path: &P, data: &WIN32_FIND_DATAW) -> Resu
     })
 }
 
+/* dwReserved0 holds the reparse tag when FILE_ATTRIBUTE_REPARSE_POINT is set */
+fn get_reparse_point(file_path: &Path, data: &WIN32_FIND_DATAW) -> (FileType, bool, bool, String) {
+    let file_type = if data.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0 {
+        FileType::Dir
+    } else {
+        FileType::File
+    };
+
+    let link_path = match std::fs::read_link(file_path) {
+        Ok(target) => {
+            let target = target.to_string_lossy().to_string();
+            target.strip_prefix("\\\\?\\").map(|stripped| stripped.to_string()).unwrap_or(target)
+        }
+        Err(_) => String::new(),
+    };
+
+    match data.dwReserved0 {
+        IO_REPARSE_TAG_MOUNT_POINT => (file_type, false, true, link_path),
+        IO_REPARSE_TAG_SYMLINK => (file_type, true, false, link_path),
+        // Cloud placeholders, dedup files, etc.
+        _ => (file_type, false, false, String::new()),
+    }
+}
+
 #[derive(PartialEq, Debug)]
 enum FileType {
     Device,
@@ -225,14 +525,14 @@ fn get_file_type<P: AsRef<Path>>(file_path: &P, attr: u32) -> FileType {
 
     // Shortcut/file/archive are all FILE_ATTRIBUTE_ARCHIVE
     // So determine type by extension
-    if attr & FILE_ATTRIBUTE_REPARSE_POINT.0 != 0 || file_path.as_ref().extension().unwrap_or_default() == "lnk" {
+    if file_path.as_ref().extension().unwrap_or_default() == "lnk" {
         return FileType::Link;
     }
 
     FileType::File
 }
 
-fn get_link_path<P: AsRef<Path>>(full_path: P) -> Result<(FileType, bool, String), String> {
+pub(crate) fn get_link_path<P: AsRef<Path>>(full_path: P) -> Result<(FileType, bool, String), String> {
     let _guard = ComGuard::new();
 
     let shell_link: IShellLinkW = unsafe { CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER).map_err(|e| e.message()) }?;
@@ -284,6 +584,394 @@ pub fn create_symlink<P1: AsRef<Path>, P2: AsRef<Path>>(full_path: P1, link_path
     Ok(())
 }
 
+/// Creates a directory
+///
+/// Fails if the parent directory does not exist
+pub fn mkdir<P: AsRef<Path>>(directory: P, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+    let _guard = ComGuard::new();
+
+    if directory.as_ref().exists() {
+        return Err(format!("File already exists:{}", directory.as_ref().to_string_lossy()));
+    }
+
+    create_directory(directory.as_ref(), &options.unwrap_or_default())?;
+    to_dirent(directory)
+}
+
+/// Creates a directory and all of its missing parent directories
+///
+/// Options are applied to the last directory only
+pub fn mkdir_all<P: AsRef<Path>>(directory: P, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+    let _guard = ComGuard::new();
+
+    if directory.as_ref().is_dir() {
+        return to_dirent(directory);
+    }
+
+    let mut ancestors: Vec<&Path> = directory.as_ref().ancestors().skip(1).take_while(|ancestor| ancestor.parent().is_some() && !ancestor.exists()).collect();
+    ancestors.reverse();
+    for ancestor in ancestors {
+        create_directory(ancestor, &MkdirOptions::default())?;
+    }
+
+    create_directory(directory.as_ref(), &options.unwrap_or_default())?;
+    to_dirent(directory)
+}
+
+fn create_directory(directory: &Path, options: &MkdirOptions) -> Result<(), String> {
+    let parent = directory.parent().ok_or("No parent directory found")?;
+    let name = directory.file_name().ok_or("Invalid directory name")?;
+
+    let parent_wide = encode_wide(parent);
+    let parent_item: IShellItem = unsafe { SHCreateItemFromParsingName(PCWSTR::from_raw(parent_wide.as_ptr()), None).map_err(|e| e.message()) }?;
+
+    // Attributes are applied by the shell when the item is created
+    let mut attributes = FILE_ATTRIBUTE_DIRECTORY.0;
+    if options.is_hidden {
+        attributes |= FILE_ATTRIBUTE_HIDDEN.0;
+    }
+    if options.is_read_only {
+        attributes |= FILE_ATTRIBUTE_READONLY.0;
+    }
+
+    let name_wide = encode_wide(name);
+    let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
+    unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
+    unsafe { op.NewItem(&parent_item, attributes, PCWSTR::from_raw(name_wide.as_ptr()), PCWSTR::null(), None).map_err(|e| e.message()) }?;
+    execute(op)
+}
+
+/// Changes file/directory attributes
+///
+/// `is_backup_excluded` has no effect on Windows
+pub fn set_attributes<P: AsRef<Path>>(file_path: P, options: SetAttributeOptions) -> Result<(), String> {
+    let wide = encode_wide(prefixed(file_path.as_ref()));
+    let path = PCWSTR::from_raw(wide.as_ptr());
+
+    let mut attributes = unsafe { GetFileAttributesW(path) };
+    if attributes == INVALID_FILE_ATTRIBUTES {
+        return Err(format!("Failed to read attributes:{}", file_path.as_ref().to_string_lossy()));
+    }
+
+    for (value, attribute) in [(options.is_hidden, FILE_ATTRIBUTE_HIDDEN), (options.is_read_only, FILE_ATTRIBUTE_READONLY), (options.is_system, FILE_ATTRIBUTE_SYSTEM)] {
+        match value {
+            Some(true) => attributes |= attribute.0,
+            Some(false) => attributes &= !attribute.0,
+            None => {}
+        }
+    }
+
+    unsafe { SetFileAttributesW(path, FILE_FLAGS_AND_ATTRIBUTES(attributes)).map_err(|e| e.message()) }
+}
+
+/// Excludes the item from or includes it back in search indexing, as "Allow files in this folder to have contents indexed" does
+///
+/// On Windows, sets FILE_ATTRIBUTE_NOT_CONTENT_INDEXED, which a directory passes on to items created in it later
+pub fn set_no_index<P: AsRef<Path>>(file_path: P, no_index: bool) -> Result<(), String> {
+    let wide = encode_wide(prefixed(file_path.as_ref()));
+    let path = PCWSTR::from_raw(wide.as_ptr());
+
+    let attributes = unsafe { GetFileAttributesW(path) };
+    if attributes == INVALID_FILE_ATTRIBUTES {
+        return Err(format!("Failed to read attributes:{}", file_path.as_ref().to_string_lossy()));
+    }
+
+    let attributes = if no_index {
+        attributes | FILE_ATTRIBUTE_NOT_CONTENT_INDEXED.0
+    } else {
+        attributes & !FILE_ATTRIBUTE_NOT_CONTENT_INDEXED.0
+    };
+
+    unsafe { SetFileAttributesW(path, FILE_FLAGS_AND_ATTRIBUTES(attributes)).map_err(|e| e.message()) }
+}
+
+const FILE_GENERIC_READ: u32 = 0x00120089;
+const FILE_GENERIC_WRITE: u32 = 0x00120116;
+const FILE_GENERIC_EXECUTE: u32 = 0x001200A0;
+const FILE_ALL_ACCESS: u32 = 0x001F01FF;
+const DELETE: u32 = 0x00010000;
+const FILE_DELETE_CHILD: u32 = 0x00000040;
+
+/// Checks if the current user has all the requested access to the file/directory without performing the operation
+pub fn access<P: AsRef<Path>>(file_path: P, mask: AccessMask) -> Result<bool, String> {
+    let wide = encode_wide(prefixed(file_path.as_ref()));
+    let attributes = unsafe { GetFileAttributesW(PCWSTR::from_raw(wide.as_ptr())) };
+    if attributes == INVALID_FILE_ATTRIBUTES {
+        return Err(format!("Failed to read attributes:{}", file_path.as_ref().to_string_lossy()));
+    }
+
+    // Read-only files cannot be written or deleted regardless of ACL
+    let is_read_only = attributes & FILE_ATTRIBUTE_READONLY.0 != 0 && attributes & FILE_ATTRIBUTE_DIRECTORY.0 == 0;
+    if is_read_only && (mask.write || mask.delete) {
+        return Ok(false);
+    }
+
+    let mut desired = 0;
+    if mask.read {
+        desired |= FILE_GENERIC_READ;
+    }
+    if mask.write {
+        desired |= FILE_GENERIC_WRITE;
+    }
+    if mask.execute {
+        desired |= FILE_GENERIC_EXECUTE;
+    }
+
+    let token = impersonation_token()?;
+
+    let result = (|| {
+        if desired != 0 && !has_access(token, file_path.as_ref(), desired)? {
+            return Ok(false);
+        }
+
+        if mask.delete && !has_access(token, file_path.as_ref(), DELETE)? {
+            // Deleting is also allowed by the parent's FILE_DELETE_CHILD
+            return match file_path.as_ref().parent() {
+                Some(parent) => has_access(token, parent, FILE_DELETE_CHILD),
+                None => Ok(false),
+            };
+        }
+
+        Ok(true)
+    })();
+
+    let _ = unsafe { CloseHandle(token) };
+
+    result
+}
+
+fn impersonation_token() -> Result<HANDLE, String> {
+    let mut process_token = HANDLE::default();
+    unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_DUPLICATE | TOKEN_QUERY, &mut process_token) }.map_err(|e| e.message())?;
+
+    let mut token = HANDLE::default();
+    let result = unsafe { DuplicateToken(process_token, SecurityImpersonation, &mut token) }.map_err(|e| e.message());
+
+    let _ = unsafe { CloseHandle(process_token) };
+
+    result.map(|_| token)
+}
+
+/* Returns "DOMAIN\user" of the owner SID, or the SID itself when the account no longer exists */
+fn get_owner(file_path: &Path) -> Option<String> {
+    let wide = encode_wide(prefixed(file_path));
+
+    let mut size = 0;
+    let _ = unsafe { GetFileSecurityW(PCWSTR::from_raw(wide.as_ptr()), OWNER_SECURITY_INFORMATION.0, None, 0, &mut size) };
+    if size == 0 {
+        return None;
+    }
+
+    let mut descriptor = vec![0u8; size as usize];
+    let security_descriptor = PSECURITY_DESCRIPTOR(descriptor.as_mut_ptr() as _);
+    if !unsafe { GetFileSecurityW(PCWSTR::from_raw(wide.as_ptr()), OWNER_SECURITY_INFORMATION.0, Some(security_descriptor), size, &mut size) }.as_bool() {
+        return None;
+    }
+
+    let mut sid = PSID::default();
+    let mut defaulted = BOOL::default();
+    unsafe { GetSecurityDescriptorOwner(security_descriptor, &mut sid, &mut defaulted) }.ok()?;
+
+    let mut name = [0u16; 256];
+    let mut domain = [0u16; 256];
+    let (mut name_len, mut domain_len) = (name.len() as u32, domain.len() as u32);
+    let mut sid_type = SID_NAME_USE::default();
+    if unsafe { LookupAccountSidW(PCWSTR::null(), sid, Some(PWSTR(name.as_mut_ptr())), &mut name_len, Some(PWSTR(domain.as_mut_ptr())), &mut domain_len, &mut sid_type) }.is_err() {
+        let mut sid_string = PWSTR::null();
+        unsafe { ConvertSidToStringSidW(sid, &mut sid_string) }.ok()?;
+        let owner = decode_wide(unsafe { sid_string.as_wide() });
+        let _ = unsafe { LocalFree(Some(HLOCAL(sid_string.0 as _))) };
+        return Some(owner);
+    }
+
+    let name = decode_wide(&name[..name_len as usize]);
+    if domain_len == 0 {
+        Some(name)
+    } else {
+        Some(format!("{}\\{}", decode_wide(&domain[..domain_len as usize]), name))
+    }
+}
+
+fn has_access(token: HANDLE, file_path: &Path, desired: u32) -> Result<bool, String> {
+    let wide = encode_wide(prefixed(file_path));
+    let information = OWNER_SECURITY_INFORMATION.0 | GROUP_SECURITY_INFORMATION.0 | DACL_SECURITY_INFORMATION.0;
+
+    let mut size = 0;
+    let _ = unsafe { GetFileSecurityW(PCWSTR::from_raw(wide.as_ptr()), information, None, 0, &mut size) };
+    if size == 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    let mut descriptor = vec![0u8; size as usize];
+    let security_descriptor = PSECURITY_DESCRIPTOR(descriptor.as_mut_ptr() as _);
+    if !unsafe { GetFileSecurityW(PCWSTR::from_raw(wide.as_ptr()), information, Some(security_descriptor), size, &mut size) }.as_bool() {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    let mapping = GENERIC_MAPPING {
+        GenericRead: FILE_GENERIC_READ,
+        GenericWrite: FILE_GENERIC_WRITE,
+        GenericExecute: FILE_GENERIC_EXECUTE,
+        GenericAll: FILE_ALL_ACCESS,
+    };
+    let mut privileges = PRIVILEGE_SET::default();
+    let mut privileges_size = std::mem::size_of::<PRIVILEGE_SET>() as u32;
+    let mut granted = 0;
+    let mut status = BOOL::default();
+
+    unsafe { AccessCheck(security_descriptor, token, desired, &mapping, Some(&mut privileges), &mut privileges_size, &mut granted, &mut status) }.map_err(|e| e.message())?;
+
+    Ok(status.as_bool())
+}
+
+fn to_dirent<P: AsRef<Path>>(file_path: P) -> Result<Dirent, String> {
+    let attributes = stat(file_path.as_ref())?;
+    let mime_type = if attributes.is_file {
+        get_mime_type(file_path.as_ref())
+    } else {
+        String::new()
+    };
+
+    Ok(Dirent {
+        name: file_path.as_ref().file_name().unwrap_or_default().to_string_lossy().to_string(),
+        parent_path: file_path.as_ref().parent().unwrap_or(Path::new("")).to_string_lossy().to_string(),
+        full_path: file_path.as_ref().to_string_lossy().to_string(),
+        attributes,
+        mime_type,
+        icon: None,
+        link_target: None,
+        owner: None,
+    })
+}
+
+/// Creates a hard link at `link_path` to the existing file
+pub fn create_hardlink<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, link_path: P2) -> Result<(), String> {
+    let file_wide = encode_wide(prefixed(file_path.as_ref()));
+    let link_wide = encode_wide(prefixed(link_path.as_ref()));
+    unsafe { CreateHardLinkW(PCWSTR::from_raw(link_wide.as_ptr()), PCWSTR::from_raw(file_wide.as_ptr()), None).map_err(|e| e.message()) }
+}
+
+const FSCTL_SET_REPARSE_POINT: u32 = 0x000900A4;
+// ReparseTag, ReparseDataLength, Reserved
+const REPARSE_DATA_BUFFER_HEADER_SIZE: usize = 8;
+// SubstituteNameOffset, SubstituteNameLength, PrintNameOffset, PrintNameLength
+const MOUNT_POINT_HEADER_SIZE: usize = 8;
+
+/// Creates a junction at `directory` pointing to the target directory
+pub fn create_junction<P1: AsRef<Path>, P2: AsRef<Path>>(directory: P1, target: P2) -> Result<(), String> {
+    let target = std::path::absolute(target.as_ref()).map_err(|e| e.to_string())?;
+    if !target.is_dir() {
+        return Err(format!("Target is not a directory:{}", target.to_string_lossy()));
+    }
+
+    std::fs::create_dir(directory.as_ref()).map_err(|e| e.to_string())?;
+
+    let print_name: Vec<u16> = target.as_os_str().to_string_lossy().encode_utf16().collect();
+    let substitute_name: Vec<u16> = format!("\\??\\{}", target.to_string_lossy()).encode_utf16().collect();
+
+    // Both names are null terminated
+    let path_buffer_size = (substitute_name.len() + 1 + print_name.len() + 1) * 2;
+    let mut buffer: Vec<u8> = Vec::with_capacity(REPARSE_DATA_BUFFER_HEADER_SIZE + MOUNT_POINT_HEADER_SIZE + path_buffer_size);
+    buffer.extend_from_slice(&IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+    buffer.extend_from_slice(&((MOUNT_POINT_HEADER_SIZE + path_buffer_size) as u16).to_le_bytes());
+    buffer.extend_from_slice(&0u16.to_le_bytes());
+    buffer.extend_from_slice(&0u16.to_le_bytes());
+    buffer.extend_from_slice(&((substitute_name.len() * 2) as u16).to_le_bytes());
+    buffer.extend_from_slice(&(((substitute_name.len() + 1) * 2) as u16).to_le_bytes());
+    buffer.extend_from_slice(&((print_name.len() * 2) as u16).to_le_bytes());
+    for c in substitute_name.iter().chain(std::iter::once(&0)).chain(print_name.iter()).chain(std::iter::once(&0)) {
+        buffer.extend_from_slice(&c.to_le_bytes());
+    }
+
+    let result = set_mount_point(directory.as_ref(), &buffer);
+    // Do not leave an empty directory behind
+    if result.is_err() {
+        let _ = std::fs::remove_dir(directory.as_ref());
+    }
+
+    result
+}
+
+/* Writes the reparse data of the mount point to the empty directory */
+fn set_mount_point(directory: &Path, buffer: &[u8]) -> Result<(), String> {
+    let wide = encode_wide(prefixed(directory));
+    let handle = unsafe {
+        CreateFileW(
+            PCWSTR::from_raw(wide.as_ptr()),
+            GENERIC_WRITE.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+            None,
+        )
+        .map_err(|e| e.message())?
+    };
+
+    let result = unsafe { DeviceIoControl(handle, FSCTL_SET_REPARSE_POINT, Some(buffer.as_ptr() as _), buffer.len() as _, None, 0, None, None) };
+    let _ = unsafe { CloseHandle(handle) };
+
+    result.map_err(|e| e.message())
+}
+
+/// Lists the names of the NTFS alternate data streams of the file such as "Zone.Identifier"
+///
+/// On Linux, the extended attributes in the "user" namespace without the "user." prefix
+pub fn list_streams<P: AsRef<Path>>(file_path: P) -> Result<Vec<String>, String> {
+    let wide = encode_wide(prefixed(file_path.as_ref()));
+    let mut data = WIN32_FIND_STREAM_DATA::default();
+    let handle = match unsafe { FindFirstStreamW(PCWSTR::from_raw(wide.as_ptr()), FindStreamInfoStandard, &mut data as *mut _ as _, None) } {
+        Ok(handle) => handle,
+        // Directories without named streams have no stream at all
+        Err(e) if e.code() == ERROR_HANDLE_EOF.to_hresult() => return Ok(Vec::new()),
+        Err(e) => return Err(e.message()),
+    };
+
+    let mut names = Vec::new();
+    loop {
+        // ":name:$DATA", or "::$DATA" for the main stream
+        let stream_name = decode_wide(&data.cStreamName);
+        if let Some(name) = stream_name.strip_prefix(':').and_then(|name| name.strip_suffix(":$DATA")) {
+            if !name.is_empty() {
+                names.push(name.to_string());
+            }
+        }
+
+        if unsafe { FindNextStreamW(handle, &mut data as *mut _ as _) }.is_err() {
+            break;
+        }
+    }
+
+    unsafe { FindClose(handle).map_err(|e| e.message()) }?;
+
+    Ok(names)
+}
+
+/// Reads the alternate data stream of the file
+pub fn read_stream<P: AsRef<Path>>(file_path: P, name: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(stream_path(file_path.as_ref(), name)?).map_err(|e| e.to_string())
+}
+
+/// Creates or replaces the alternate data stream of the file
+///
+/// The stream is lost when the file is copied to a file system other than NTFS
+pub fn write_stream<P: AsRef<Path>>(file_path: P, name: &str, data: &[u8]) -> Result<(), String> {
+    std::fs::write(stream_path(file_path.as_ref(), name)?, data).map_err(|e| e.to_string())
+}
+
+/// Removes the alternate data stream of the file
+pub fn remove_stream<P: AsRef<Path>>(file_path: P, name: &str) -> Result<(), String> {
+    std::fs::remove_file(stream_path(file_path.as_ref(), name)?).map_err(|e| e.to_string())
+}
+
+fn stream_path(file_path: &Path, name: &str) -> Result<String, String> {
+    if name.is_empty() || name.contains([':', '\\', '/']) {
+        return Err(format!("Invalid stream name:{}", name));
+    }
+
+    Ok(prefixed(format!("{}:{}", file_path.to_string_lossy(), name)))
+}
+
 /// Gets mime type of the file
 pub fn get_mime_type<P: AsRef<Path>>(file_path: P) -> String {
     match mime_guess::from_path(file_path).first() {
@@ -304,6 +992,9 @@ fn get_mime_type_fallback<P: AsRef<Path>>(file_path: P) -> String {
 
 /// Moves an item
 pub fn mv<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2) -> Result<(), String> {
+    crate::policy::check_transfer(&[from.as_ref()], to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Move, &[from.as_ref()], Some(to.as_ref()))?;
+
     let _guard = ComGuard::new();
 
     let from_wide = encode_wide(from.as_ref());
@@ -314,11 +1005,16 @@ pub fn mv<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2) -> Result<(), Stri
     let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
     unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
     unsafe { op.MoveItem(&from_item, &to_item, None, None).map_err(|e| e.message()) }?;
-    execute(op)
+    execute(op)?;
+    crate::history::record(crate::history::HistoryAction::Move, &[from.as_ref()], Some(to.as_ref()));
+    Ok(())
 }
 
 /// Moves multiple items
 pub fn mv_all<P1: AsRef<Path>, P2: AsRef<Path>>(from: &[P1], to: P2) -> Result<(), String> {
+    crate::policy::check_transfer(from, to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Move, from, Some(to.as_ref()))?;
+
     let _guard = ComGuard::new();
 
     let from_item_array = get_id_lists(from)?;
@@ -328,11 +1024,16 @@ pub fn mv_all<P1: AsRef<Path>, P2: AsRef<Path>>(from: &[P1], to: P2) -> Result<(
     let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
     unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
     unsafe { op.MoveItems(&from_item_array, &to_item).map_err(|e| e.message()) }?;
-    execute(op)
+    execute(op)?;
+    crate::history::record(crate::history::HistoryAction::Move, from, Some(to.as_ref()));
+    Ok(())
 }
 
 /// Copies an item
 pub fn copy<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2) -> Result<(), String> {
+    crate::policy::check_transfer(&[from.as_ref()], to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Copy, &[from.as_ref()], Some(to.as_ref()))?;
+
     let _guard = ComGuard::new();
 
     let from_wide = encode_wide(from.as_ref());
@@ -347,11 +1048,16 @@ pub fn copy<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2) -> Result<(), St
         unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
     }
     unsafe { op.CopyItem(&from_item, &to_item, None, None).map_err(|e| e.message()) }?;
-    execute(op)
+    execute(op)?;
+    crate::history::record(crate::history::HistoryAction::Copy, &[from.as_ref()], Some(to.as_ref()));
+    Ok(())
 }
 
 /// Copies multiple items
 pub fn copy_all<P1: AsRef<Path>, P2: AsRef<Path>>(from: &[P1], to: P2) -> Result<(), String> {
+    crate::policy::check_transfer(from, to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Copy, from, Some(to.as_ref()))?;
+
     let _guard = ComGuard::new();
 
     let from_item_array = get_id_lists(from)?;
@@ -366,11 +1072,85 @@ pub fn copy_all<P1: AsRef<Path>, P2: AsRef<Path>>(from: &[P1], to: P2) -> Result
         unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
     }
     unsafe { op.CopyItems(&from_item_array, &to_item).map_err(|e| e.message()) }?;
-    execute(op)
+    execute(op)?;
+    crate::history::record(crate::history::HistoryAction::Copy, from, Some(to.as_ref()));
+    Ok(())
+}
+
+/// Measures the operation before starting it so that a lack of space can be reported up front
+///
+/// Stages multiple operations to be completed all together or rolled back on failure
+pub fn transaction() -> Transaction {
+    Transaction::default()
+}
+
+/// Checks the free space of the destination volume, whether a move is a rename within one volume and the length of each destination path
+pub fn preflight<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>) -> Result<PreflightReport, String> {
+    if !matches!(operation, FileOperation::Copy | FileOperation::Move) {
+        return crate::preflight::measure(froms, None, |_| false);
+    }
+
+    let to = to.ok_or("No destination specified")?;
+    // The shell copy engine does not accept paths of MAX_PATH or longer
+    let report = crate::preflight::measure(froms, Some(to.as_ref()), |path| encode_wide(path).len() > MAX_PATH as usize)?;
+
+    let volume = get_volume_path(to.as_ref())?;
+    let is_same_volume = operation == FileOperation::Move && froms.iter().all(|from| get_volume_path(from.as_ref()).is_ok_and(|from_volume| from_volume.eq_ignore_ascii_case(&volume)));
+
+    let wide = encode_wide(prefixed(to.as_ref()));
+    let mut available = 0;
+    unsafe { GetDiskFreeSpaceExW(PCWSTR::from_raw(wide.as_ptr()), Some(&mut available), None, None).map_err(|e| e.message()) }?;
+
+    Ok(crate::preflight::complete(report, Some(available), is_same_volume))
+}
+
+fn get_volume_path(file_path: &Path) -> Result<String, String> {
+    let wide = encode_wide(prefixed(file_path));
+    let mut volume_path = vec![0u16; (MAX_PATH + 1) as usize];
+    unsafe { GetVolumePathNameW(PCWSTR::from_raw(wide.as_ptr()), &mut volume_path).map_err(|e| e.message()) }?;
+    Ok(decode_wide(&volume_path))
+}
+
+/// Coalesces `OperationStatus::Progress` of file operations, copy_resumable and hash_file. None reports every progress
+///
+/// Takes effect from the next operation
+pub fn set_progress_throttle(throttle: Option<ProgressThrottle>) {
+    crate::throttle::set(throttle);
+}
+
+/// Copies a file, resuming from the last copied offset when reading or writing fails
+///
+/// Each retry is reported as `OperationStatus::Retry` after waiting for the backoff. `max_retries` limits consecutive failures without progress
+pub fn copy_resumable<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2, options: RetryOptions, callback: impl FnMut(OperationStatus) -> Response) -> Result<(), String> {
+    crate::resumable::copy_resumable(from.as_ref(), to.as_ref(), options, callback, |path| PathBuf::from(prefixed(path)))
+}
+
+/// Computes the hex digest of a file, reporting progress as `OperationStatus::Progress`
+///
+/// Returns an error if the callback cancels the operation
+pub fn hash_file<P: AsRef<Path>>(file_path: P, algorithm: HashAlgorithm, callback: impl FnMut(OperationStatus) -> Response) -> Result<String, String> {
+    let file = std::fs::File::open(prefixed(file_path.as_ref())).map_err(|e| e.to_string())?;
+    crate::hash::hash_file(file, &algorithm, callback)
+}
+
+/// Computes the hex digests of files, reporting each file as `OperationStatus::Start` and `OperationStatus::End`
+///
+/// Files that fail to hash are reported as `OperationStatus::Error` and skipped
+pub fn hash_files<P: AsRef<Path>>(file_paths: &[P], algorithm: HashAlgorithm, callback: impl FnMut(OperationStatus) -> Response) -> Result<Vec<FileHash>, String> {
+    crate::hash::hash_files(file_paths, &algorithm, callback, |file_path| std::fs::File::open(prefixed(file_path)))
+}
+
+/// Compares two files byte by byte, returning the offset of the first difference, or line by line, returning a line diff summary
+pub fn compare_files<P1: AsRef<Path>, P2: AsRef<Path>>(a: P1, b: P2, mode: CompareMode) -> Result<FileComparison, String> {
+    let file_a = std::fs::File::open(prefixed(a.as_ref())).map_err(|e| e.to_string())?;
+    let file_b = std::fs::File::open(prefixed(b.as_ref())).map_err(|e| e.to_string())?;
+    compare_readers(file_a, file_b, &mode)
 }
 
 /// Deletes an item
 pub fn delete<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
+    crate::hooks::before(crate::history::HistoryAction::Delete, &[file_path.as_ref()], None::<&Path>)?;
+
     let _guard = ComGuard::new();
 
     let file_wide = encode_wide(file_path.as_ref());
@@ -379,11 +1159,49 @@ pub fn delete<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
     let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
     unsafe { op.SetOperationFlags(FOF_NOCONFIRMATION).map_err(|e| e.message()) }?;
     unsafe { op.DeleteItem(&shell_item, None).map_err(|e| e.message()) }?;
-    execute(op)
+    execute(op)?;
+    #[cfg(feature = "audit")]
+    crate::audit::record(crate::audit::AuditAction::Delete, file_path.as_ref());
+    crate::history::record(crate::history::HistoryAction::Delete, &[file_path.as_ref()], None::<&Path>);
+    Ok(())
+}
+
+/// Copies the items into the directory in an elevated helper process once the user approves the UAC prompt
+///
+/// For when `copy_all` fails with access denied. The helper is the app's own executable, which must call `run_elevated_helper` at the start of `main`.
+/// Returning Cancel from the callback stops the helper after the file being copied.
+/// Returns false if the user declined the prompt or the callback cancelled, in which case nothing is recorded in history
+pub fn copy_elevated<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2, callback: impl FnMut(OperationStatus) -> Response) -> Result<bool, String> {
+    crate::policy::check_transfer(froms, to.as_ref())?;
+    crate::hooks::before(crate::history::HistoryAction::Copy, froms, Some(to.as_ref()))?;
+    if crate::elevation::run(crate::elevation::ElevatedOperation::Copy, froms, Some(to.as_ref()), callback)? == crate::elevation::ElevatedOutcome::Cancelled {
+        return Ok(false);
+    }
+    crate::history::record(crate::history::HistoryAction::Copy, froms, Some(to.as_ref()));
+    Ok(true)
+}
+
+/// Deletes the items permanently in an elevated helper process once the user approves the UAC prompt
+///
+/// For when `delete_all` fails with access denied. See `copy_elevated` for the helper and the result
+pub fn delete_elevated<P: AsRef<Path>>(file_paths: &[P], callback: impl FnMut(OperationStatus) -> Response) -> Result<bool, String> {
+    crate::hooks::before(crate::history::HistoryAction::Delete, file_paths, None::<&Path>)?;
+    if crate::elevation::run(crate::elevation::ElevatedOperation::Delete, file_paths, None, callback)? == crate::elevation::ElevatedOutcome::Cancelled {
+        return Ok(false);
+    }
+    crate::history::record(crate::history::HistoryAction::Delete, file_paths, None::<&Path>);
+    Ok(true)
+}
+
+/// Runs the operation and exits if the process was started as the helper of `copy_elevated` or `delete_elevated`. Returns if it was not
+pub fn run_elevated_helper() {
+    crate::elevation::run_helper();
 }
 
 /// Deletes multiple items
 pub fn delete_all<P: AsRef<Path>>(file_paths: &[P]) -> Result<(), String> {
+    crate::hooks::before(crate::history::HistoryAction::Delete, file_paths, None::<&Path>)?;
+
     let _guard = ComGuard::new();
 
     let item_array = get_id_lists(file_paths)?;
@@ -391,11 +1209,17 @@ pub fn delete_all<P: AsRef<Path>>(file_paths: &[P]) -> Result<(), String> {
     let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
     unsafe { op.SetOperationFlags(FOF_NOCONFIRMATION).map_err(|e| e.message()) }?;
     unsafe { op.DeleteItems(&item_array).map_err(|e| e.message()) }?;
-    execute(op)
+    execute(op)?;
+    #[cfg(feature = "audit")]
+    file_paths.iter().for_each(|file_path| crate::audit::record(crate::audit::AuditAction::Delete, file_path.as_ref()));
+    crate::history::record(crate::history::HistoryAction::Delete, file_paths, None::<&Path>);
+    Ok(())
 }
 
 /// Moves an item to the OS-specific trash location
 pub fn trash<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
+    crate::hooks::before(crate::history::HistoryAction::Trash, &[file_path.as_ref()], None::<&Path>)?;
+
     let _guard = ComGuard::new();
 
     let file_wide = encode_wide(file_path.as_ref());
@@ -404,21 +1228,183 @@ pub fn trash<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
     let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
     unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
     unsafe { op.DeleteItem(&shell_item, None).map_err(|e| e.message()) }?;
-    execute(op)
+    execute(op)?;
+    #[cfg(feature = "audit")]
+    crate::audit::record(crate::audit::AuditAction::Trash, file_path.as_ref());
+    crate::history::record(crate::history::HistoryAction::Trash, &[file_path.as_ref()], None::<&Path>);
+    Ok(())
 }
 
 /// Moves multiple items to the OS-specific trash location
 pub fn trash_all<P: AsRef<Path>>(file_paths: &[P]) -> Result<(), String> {
+    crate::hooks::before(crate::history::HistoryAction::Trash, file_paths, None::<&Path>)?;
+
     let _guard = ComGuard::new();
 
     let item_array = get_id_lists(file_paths)?;
     let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
     unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
     unsafe { op.DeleteItems(&item_array).map_err(|e| e.message()) }?;
-    execute(op)
+    execute(op)?;
+    #[cfg(feature = "audit")]
+    file_paths.iter().for_each(|file_path| crate::audit::record(crate::audit::AuditAction::Trash, file_path.as_ref()));
+    crate::history::record(crate::history::HistoryAction::Trash, file_paths, None::<&Path>);
+    Ok(())
+}
+
+/// Execute file operation
+///
+/// Blocks until the operation is finished. The callback is asked to resolve each conflict as on Linux.
+pub fn operate<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, callback: impl AsyncFnMut(OperationStatus) -> Response + 'static) -> Result<(), String> {
+    execute_file_operation(operation, froms, to, None, callback)
+}
+
+/// Execute file operation that can be paused, resumed and cancelled through the handle from another thread
+///
+/// Blocks until the operation is finished. Unlike on Linux, pausing suspends the item being transferred
+pub fn operate_with_handle<P1: AsRef<Path>, P2: AsRef<Path>>(
+    operation: FileOperation,
+    froms: &[P1],
+    to: Option<P2>,
+    handle: &OperationHandle,
+    callback: impl AsyncFnMut(OperationStatus) -> Response + 'static,
+) -> Result<(), String> {
+    execute_file_operation(operation, froms, to, Some(handle.clone()), callback)
+}
+
+/// Execute file operation, recording the IFileOperation calls made for it
+///
+/// Blocks until the operation is finished. The calls are returned even if the operation fails
+pub fn operate_traced<P1: AsRef<Path>, P2: AsRef<Path>>(
+    operation: FileOperation,
+    froms: &[P1],
+    to: Option<P2>,
+    callback: impl AsyncFnMut(OperationStatus) -> Response + 'static,
+) -> (Result<(), String>, Vec<String>) {
+    let handle = OperationHandle {
+        trace: crate::trace::CallTrace::enabled(),
+        ..Default::default()
+    };
+
+    let result = execute_file_operation(operation, froms, to, Some(handle.clone()), callback);
+    (result, handle.trace())
+}
+
+/// Execute file operation on a worker thread, reporting it as events received from `OperationHandle::events`
+pub fn operate_with_events<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>) -> OperationHandle {
+    let (event_tx, event_rx) = smol::channel::unbounded();
+    let (response_tx, response_rx) = smol::channel::bounded(1);
+
+    let handle = OperationHandle {
+        events: Some(event_rx),
+        responses: Some(response_tx),
+        ..Default::default()
+    };
+
+    let froms: Vec<PathBuf> = froms.iter().map(|from| from.as_ref().to_path_buf()).collect();
+    let to = to.map(|to| to.as_ref().to_path_buf());
+    let thread_handle = handle.clone();
+
+    std::thread::spawn(move || {
+        let error_tx = event_tx.clone();
+        if let Err(e) = execute_file_operation(operation, &froms, to, Some(thread_handle), crate::events::forward(event_tx, response_rx)) {
+            let _ = error_tx.try_send(OperationEvent::Error(e));
+        }
+    });
+
+    handle
+}
+
+/// Execute file operation, then shows a balloon notification and/or plays a sound as the notice specifies if no window of the app is in the foreground
+///
+/// Blocks until the operation is finished
+pub fn operate_with_notice<P1: AsRef<Path>, P2: AsRef<Path>>(
+    operation: FileOperation,
+    froms: &[P1],
+    to: Option<P2>,
+    notice: CompletionNotice,
+    mut callback: impl AsyncFnMut(OperationStatus) -> Response + 'static,
+) -> Result<(), String> {
+    let outcome = Rc::new(RefCell::new(crate::notice::Outcome::default()));
+    let observer = outcome.clone();
+
+    let result = execute_file_operation(operation, froms, to, None, async move |status: OperationStatus| {
+        observer.borrow_mut().observe(&status);
+        callback(status).await
+    });
+
+    if let Err(e) = &result {
+        outcome.borrow_mut().fail(e);
+    }
+
+    super::window::notify_completion(&notice, operation, froms.len(), outcome.borrow().error());
+    result
+}
+
+/// Copies or moves items, asking the resolver about every item whose destination exists before starting
+///
+/// Each item is then replaced, skipped or renamed as chosen. Conflicts inside directories being merged are replaced
+pub fn operate_with_resolver<P1: AsRef<Path>, P2: AsRef<Path>>(
+    operation: FileOperation,
+    froms: &[P1],
+    to: P2,
+    mut resolver: impl ConflictResolver,
+    callback: impl AsyncFnMut(OperationStatus) -> Response + 'static,
+) -> Result<(), String> {
+    execute_with_resolver(operation, froms, to, &mut resolver, callback)
+}
+
+/// Execute file operation with the progress, conflict and confirmation dialogs of Explorer, owned by the window
+///
+/// Blocks until the operation is finished or cancelled from the dialog. Moving to Recycle Bin warns about items too large for it
+pub fn operate_with_system_dialog<P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: Option<P2>, window_handle: isize) -> Result<(), String> {
+    crate::hooks::before(operation.into(), froms, to.as_ref())?;
+
+    let _guard = ComGuard::new();
+
+    let item_array = get_id_lists(froms)?;
+    let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
+    unsafe { op.SetOwnerWindow(HWND(window_handle as _)).map_err(|e| e.message()) }?;
+
+    match operation {
+        FileOperation::Copy | FileOperation::Move => {
+            let to = to.as_ref().ok_or("No destination specified")?;
+            crate::policy::check_transfer(froms, to.as_ref())?;
+            let to_wide = encode_wide(to.as_ref());
+            let to_item: IShellItem = unsafe { SHCreateItemFromParsingName(PCWSTR::from_raw(to_wide.as_ptr()), None).map_err(|e| e.message()) }?;
+
+            // Explorer asks about each conflict
+            unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
+            if operation == FileOperation::Copy {
+                unsafe { op.CopyItems(&item_array, &to_item).map_err(|e| e.message()) }?;
+            } else {
+                unsafe { op.MoveItems(&item_array, &to_item).map_err(|e| e.message()) }?;
+            }
+        }
+        FileOperation::Delete => {
+            // Explorer asks for confirmation
+            unsafe { op.SetOperationFlags(FILEOPERATION_FLAGS(0)).map_err(|e| e.message()) }?;
+            unsafe { op.DeleteItems(&item_array).map_err(|e| e.message()) }?;
+        }
+        FileOperation::Trash => {
+            unsafe { op.SetOperationFlags(FOF_ALLOWUNDO | FOF_WANTNUKEWARNING).map_err(|e| e.message()) }?;
+            unsafe { op.DeleteItems(&item_array).map_err(|e| e.message()) }?;
+        }
+    }
+
+    execute(op)?;
+
+    #[cfg(feature = "audit")]
+    match operation {
+        FileOperation::Delete => froms.iter().for_each(|from| crate::audit::record(crate::audit::AuditAction::Delete, from.as_ref())),
+        FileOperation::Trash => froms.iter().for_each(|from| crate::audit::record(crate::audit::AuditAction::Trash, from.as_ref())),
+        _ => {}
+    }
+    crate::history::record(operation.into(), froms, to);
+    Ok(())
 }
 
-fn get_id_lists<P: AsRef<Path>>(from: &[P]) -> Result<IShellItemArray, String> {
+pub(crate) fn get_id_lists<P: AsRef<Path>>(from: &[P]) -> Result<IShellItemArray, String> {
     let items: Vec<*const ITEMIDLIST> = from
         .iter()
         .map(|path| {
@@ -438,9 +1424,15 @@ fn get_id_lists<P: AsRef<Path>>(from: &[P]) -> Result<IShellItemArray, String> {
     array
 }
 
-fn execute(op: IFileOperation) -> Result<(), String> {
+pub(crate) fn execute(op: IFileOperation) -> Result<(), String> {
+    let _guard = crate::lifecycle::begin_operation()?;
     let result = unsafe { op.PerformOperations() };
 
+    // Skipped items are reported to the sink, the rest of the operation succeeded
+    if result.as_ref().is_err_and(|e| e.code() == COPYENGINE_S_USER_IGNORED) {
+        return Ok(());
+    }
+
     if result.is_err() {
         if unsafe { op.GetAnyOperationsAborted().map_err(|e| e.message()) }?.as_bool() {
             return Ok(());
@@ -543,21 +1535,71 @@ struct ItemData {
 pub fn undelete<P: AsRef<Path>>(file_paths: &[P]) -> Result<(), String> {
     let _guard = ComGuard::new();
 
-    let file_paths: Vec<String> = file_paths.iter().map(|f| f.as_ref().to_string_lossy().to_string()).collect();
     let recycle_bin = get_recycle_bin()?;
+    let map = find_latest_items_in_recycle_bin(&recycle_bin, file_paths)?;
+    let items: Vec<*const ITEMIDLIST> = map.values().map(|a| a.item as _).collect();
+
+    let _ = invoke_undelete(&recycle_bin, &items);
+
+    for item in items {
+        unsafe { CoTaskMemFree(Some(item as _)) };
+    }
+
+    Ok(())
+}
+
+/// Undos a trash operation, asking the resolver what to do with each item whose original path is already taken
+///
+/// Pass a closure returning a fixed choice such as `|_| UndeleteConflict::Skip` to apply it to all items
+pub fn undelete_with_conflict<P: AsRef<Path>>(file_paths: &[P], mut resolve: impl FnMut(&str) -> UndeleteConflict) -> Result<(), String> {
+    let _guard = ComGuard::new();
+
+    let recycle_bin = get_recycle_bin()?;
+    let map = find_latest_items_in_recycle_bin(&recycle_bin, file_paths)?;
+
+    let mut restores: Vec<*const ITEMIDLIST> = Vec::new();
+    let mut overwrites: Vec<(*const ITEMIDLIST, PathBuf)> = Vec::new();
+    let mut keep_boths: Vec<(*const ITEMIDLIST, PathBuf)> = Vec::new();
+
+    for (orig_path, data) in map.iter() {
+        if Path::new(orig_path).symlink_metadata().is_err() {
+            restores.push(data.item as _);
+            continue;
+        }
+
+        match resolve(orig_path) {
+            UndeleteConflict::Overwrite => overwrites.push((data.item as _, PathBuf::from(orig_path))),
+            UndeleteConflict::KeepBoth => keep_boths.push((data.item as _, PathBuf::from(orig_path))),
+            UndeleteConflict::Skip => {}
+        }
+    }
+
+    let result = invoke_undelete(&recycle_bin, &restores)
+        .and_then(|_| move_from_recycle_bin(&recycle_bin, &overwrites, FOF_ALLOWUNDO | FOF_NOCONFIRMATION))
+        .and_then(|_| move_from_recycle_bin(&recycle_bin, &keep_boths, FOF_ALLOWUNDO | FOF_RENAMEONCOLLISION));
+
+    for item in map.values() {
+        unsafe { CoTaskMemFree(Some(item.item as _)) };
+    }
+
+    result
+}
+
+fn find_latest_items_in_recycle_bin<P: AsRef<Path>>(recycle_bin: &IShellFolder2, file_paths: &[P]) -> Result<HashMap<String, ItemData>, String> {
+    let file_paths: Vec<String> = file_paths.iter().map(|f| f.as_ref().to_string_lossy().to_string()).collect();
     let mut enum_list: Option<IEnumIDList> = None;
     let _ = unsafe { recycle_bin.EnumObjects(HWND::default(), (SHCONTF_FOLDERS.0 | SHCONTF_NONFOLDERS.0) as _, &mut enum_list) };
 
+    let mut map: HashMap<String, ItemData> = HashMap::new();
+
     if enum_list.is_none() {
-        return Ok(());
+        return Ok(map);
     }
 
     let list = enum_list.unwrap();
     let mut rgelt: Vec<*mut ITEMIDLIST> = vec![std::ptr::null_mut()];
     let cnt: Option<*mut u32> = None;
 
-    let mut map: HashMap<String, ItemData> = HashMap::new();
-
     while unsafe { list.Next(&mut rgelt, cnt) } == S_OK {
         if rgelt.is_empty() {
             continue;
@@ -565,8 +1607,8 @@ pub fn undelete<P: AsRef<Path>>(file_paths: &[P]) -> Result<(), String> {
 
         let item = *(rgelt.first().unwrap());
 
-        let old_path = to_original_path(&recycle_bin, item)?;
-        let deleted_date_ms = to_time_ms_from_variant(&recycle_bin, item, &PKEY_DELETED_DATE)?;
+        let old_path = to_original_path(recycle_bin, item)?;
+        let deleted_date_ms = to_time_ms_from_variant(recycle_bin, item, &PKEY_DELETED_DATE)?;
 
         if file_paths.contains(&old_path) {
             let data = ItemData {
@@ -590,31 +1632,21 @@ pub fn undelete<P: AsRef<Path>>(file_paths: &[P]) -> Result<(), String> {
         rgelt = vec![std::ptr::null_mut()];
     }
 
-    let items: Vec<*const ITEMIDLIST> = map.values().map(|a| a.item as _).collect();
-
-    if !items.is_empty() {
-        let menu: IContextMenu = unsafe { recycle_bin.GetUIObjectOf(HWND::default(), &items, None).map_err(|e| e.message()) }?;
-        let invoke = CMINVOKECOMMANDINFO {
-            cbSize: std::mem::size_of::<CMINVOKECOMMANDINFO>() as u32,
-            lpVerb: PCSTR(c"undelete".as_ptr() as _),
-            ..Default::default()
-        };
+    Ok(map)
+}
 
-        match unsafe { menu.InvokeCommand(&invoke) } {
-            Ok(_) => {
-                for item in items {
-                    unsafe { CoTaskMemFree(Some(item as _)) };
-                }
-            }
-            Err(_) => {
-                for item in items {
-                    unsafe { CoTaskMemFree(Some(item as _)) };
-                }
-            }
-        }
+fn invoke_undelete(recycle_bin: &IShellFolder2, items: &[*const ITEMIDLIST]) -> Result<(), String> {
+    if items.is_empty() {
+        return Ok(());
     }
 
-    Ok(())
+    let menu: IContextMenu = unsafe { recycle_bin.GetUIObjectOf(HWND::default(), items, None).map_err(|e| e.message()) }?;
+    let invoke = CMINVOKECOMMANDINFO {
+        cbSize: std::mem::size_of::<CMINVOKECOMMANDINFO>() as u32,
+        lpVerb: PCSTR(c"undelete".as_ptr() as _),
+        ..Default::default()
+    };
+    unsafe { menu.InvokeCommand(&invoke).map_err(|e| e.message()) }
 }
 
 /// Undos a trash operation by deleted time
@@ -622,8 +1654,7 @@ pub fn undelete_by_time(targets: &[RecycleBinItem]) -> Result<(), String> {
     let _guard = ComGuard::new();
 
     let recycle_bin = get_recycle_bin()?;
-    let args: HashMap<String, u64> = targets.iter().map(|target| (target.original_path.clone(), target.deleted_time_ms)).collect();
-    let items = find_items_in_recycle_bin(&recycle_bin, args)?;
+    let items = find_items_in_recycle_bin(&recycle_bin, &to_recycle_bin_keys(targets))?;
 
     if !items.is_empty() {
         let menu: IContextMenu = unsafe { recycle_bin.GetUIObjectOf(HWND::default(), &items, None).map_err(|e| e.message()) }?;
@@ -650,13 +1681,59 @@ pub fn undelete_by_time(targets: &[RecycleBinItem]) -> Result<(), String> {
     Ok(())
 }
 
+/// Restores items in Recycle Bin into the destination directory instead of their original paths
+///
+/// Items trashed from the same path are told apart by the deleted time
+pub fn undelete_to<P: AsRef<Path>>(targets: &[RecycleBinItem], destination: P) -> Result<(), String> {
+    let original_paths: Vec<&str> = targets.iter().map(|target| target.original_path.as_str()).collect();
+    crate::policy::check_transfer(&original_paths, destination.as_ref())?;
+
+    let _guard = ComGuard::new();
+
+    let recycle_bin = get_recycle_bin()?;
+    let items = find_items_in_recycle_bin(&recycle_bin, &to_recycle_bin_keys(targets))?;
+
+    let result = items
+        .iter()
+        .map(|item| {
+            let orig_path = to_original_path(&recycle_bin, *item)?;
+            let name = Path::new(&orig_path).file_name().ok_or(format!("Invalid original path:{}", orig_path))?.to_os_string();
+            Ok((*item, destination.as_ref().join(name)))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .and_then(|moves| move_from_recycle_bin(&recycle_bin, &moves, FOF_ALLOWUNDO));
+
+    for item in items {
+        unsafe { CoTaskMemFree(Some(item as _)) };
+    }
+
+    result
+}
+
+/* Items in Recycle Bin are named like $R0A1B2C.txt, so the original name is given explicitly */
+fn move_from_recycle_bin(recycle_bin: &IShellFolder2, moves: &[(*const ITEMIDLIST, PathBuf)], flags: FILEOPERATION_FLAGS) -> Result<(), String> {
+    if moves.is_empty() {
+        return Ok(());
+    }
+
+    let op: IFileOperation = unsafe { CoCreateInstance(&FileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
+    unsafe { op.SetOperationFlags(flags).map_err(|e| e.message()) }?;
+    for (item, dest) in moves {
+        let from_item: IShellItem = unsafe { SHCreateItemWithParent(None, recycle_bin, *item).map_err(|e| e.message()) }?;
+        let to_wide = encode_wide(dest.parent().unwrap_or(dest));
+        let to_item: IShellItem = unsafe { SHCreateItemFromParsingName(PCWSTR::from_raw(to_wide.as_ptr()), None).map_err(|e| e.message()) }?;
+        let name = encode_wide(dest.file_name().unwrap_or_default());
+        unsafe { op.MoveItem(&from_item, &to_item, PCWSTR::from_raw(name.as_ptr()), None).map_err(|e| e.message()) }?;
+    }
+    execute(op)
+}
+
 /// Delete files in Recycle Bin
 pub fn delete_from_recycle_bin(targets: &[RecycleBinItem]) -> Result<(), String> {
     let _guard = ComGuard::new();
 
     let recycle_bin = get_recycle_bin()?;
-    let args: HashMap<String, u64> = targets.iter().map(|target| (target.original_path.clone(), target.deleted_time_ms)).collect();
-    let items = find_items_in_recycle_bin(&recycle_bin, args)?;
+    let items = find_items_in_recycle_bin(&recycle_bin, &to_recycle_bin_keys(targets))?;
 
     if !items.is_empty() {
         let menu: IContextMenu = unsafe { recycle_bin.GetUIObjectOf(HWND::default(), &items, None).map_err(|e| e.message()) }?;
@@ -683,7 +1760,46 @@ pub fn delete_from_recycle_bin(targets: &[RecycleBinItem]) -> Result<(), String>
     Ok(())
 }
 
-fn find_items_in_recycle_bin(recycle_bin: &IShellFolder2, map: HashMap<String, u64>) -> Result<Vec<*const ITEMIDLIST>, String> {
+/// Permanently deletes the items from the Recycle Bin without confirmation
+///
+/// Fails without deleting anything if any item is not found. Items trashed from the same path are told apart by the deleted time
+pub fn purge_from_recycle_bin(targets: &[RecycleBinItem]) -> Result<(), String> {
+    let _guard = ComGuard::new();
+
+    let recycle_bin = get_recycle_bin()?;
+    let keys = to_recycle_bin_keys(targets);
+    let items = find_items_in_recycle_bin(&recycle_bin, &keys)?;
+
+    let result = if items.len() < keys.len() {
+        Err("Some items are not in Recycle Bin".to_string())
+    } else if items.is_empty() {
+        Ok(())
+    } else {
+        unsafe { recycle_bin.GetUIObjectOf::<IContextMenu>(HWND::default(), &items, None) }
+            .and_then(|menu| {
+                let invoke = CMINVOKECOMMANDINFO {
+                    cbSize: std::mem::size_of::<CMINVOKECOMMANDINFO>() as u32,
+                    fMask: CMIC_MASK_FLAG_NO_UI,
+                    lpVerb: PCSTR(c"delete".as_ptr() as _),
+                    ..Default::default()
+                };
+                unsafe { menu.InvokeCommand(&invoke) }
+            })
+            .map_err(|e| e.message())
+    };
+
+    for item in items {
+        unsafe { CoTaskMemFree(Some(item as _)) };
+    }
+
+    result
+}
+
+fn to_recycle_bin_keys(targets: &[RecycleBinItem]) -> HashSet<(String, u64)> {
+    targets.iter().map(|target| (target.original_path.clone(), target.deleted_time_ms)).collect()
+}
+
+fn find_items_in_recycle_bin(recycle_bin: &IShellFolder2, keys: &HashSet<(String, u64)>) -> Result<Vec<*const ITEMIDLIST>, String> {
     let mut enum_list: Option<IEnumIDList> = None;
     let _ = unsafe { recycle_bin.EnumObjects(HWND::default(), (SHCONTF_FOLDERS.0 | SHCONTF_NONFOLDERS.0) as _, &mut enum_list) };
 
@@ -707,7 +1823,7 @@ fn find_items_in_recycle_bin(recycle_bin: &IShellFolder2, map: HashMap<String, u
         let old_path = to_original_path(recycle_bin, item)?;
         let deleted_date_ms = to_time_ms_from_variant(recycle_bin, item, &PKEY_DELETED_DATE)?;
 
-        if map.contains_key(&old_path) && *map.get(&old_path).unwrap() == deleted_date_ms {
+        if keys.contains(&(old_path, deleted_date_ms)) {
             items.push(item);
         } else {
             unsafe { CoTaskMemFree(Some(item as _)) };
@@ -748,6 +1864,149 @@ pub fn empty_recycle_bin(root: Option<String>) -> Result<(), String> {
     Ok(())
 }
 
+/// Gets the number of items and total bytes in Recycle Bin
+///
+/// If root is None, all drives are queried
+pub fn recycle_bin_info(root: Option<String>) -> Result<RecycleBinInfo, String> {
+    let root = root.map(encode_wide);
+    let drive = if let Some(root) = &root {
+        PCWSTR::from_raw(root.as_ptr())
+    } else {
+        PCWSTR::null()
+    };
+    let mut info = SHQUERYRBINFO {
+        cbSize: std::mem::size_of::<SHQUERYRBINFO>() as _,
+        ..Default::default()
+    };
+    unsafe { SHQueryRecycleBinW(drive, &mut info).map_err(|e| e.message()) }?;
+
+    Ok(RecycleBinInfo {
+        item_count: info.i64NumItems as _,
+        total_size: info.i64Size as _,
+    })
+}
+
+struct RecycleBinWatcher {
+    callback: Box<dyn FnMut(RecycleBinInfo) + Send>,
+    info: RecycleBinInfo,
+    registration: u32,
+}
+
+/// Get notification when items are added to or removed from Recycle Bin
+///
+/// The callback receives the new item count and total bytes of all drives
+pub fn watch_recycle_bin<F: FnMut(RecycleBinInfo) + Send + 'static>(callback: F) -> bool {
+    unwatch_recycle_bin();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        let class_name = encode_wide("ZouniRecycleBinWatcher");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(recycle_bin_window_proc),
+            lpszClassName: PCWSTR::from_raw(class_name.as_ptr()),
+            ..Default::default()
+        };
+        unsafe { RegisterClassW(&class) };
+
+        let hwnd = match unsafe { CreateWindowExW(WINDOW_EX_STYLE::default(), PCWSTR::from_raw(class_name.as_ptr()), PCWSTR::null(), WS_OVERLAPPED, 0, 0, 0, 0, Some(HWND_MESSAGE), None, None, None) }
+        {
+            Ok(hwnd) => hwnd,
+            Err(_) => {
+                let _ = sender.send(false);
+                return;
+            }
+        };
+
+        let recycle_bin_item: *mut ITEMIDLIST = match unsafe { SHGetKnownFolderIDList(&FOLDERID_RecycleBinFolder, KF_FLAG_DEFAULT.0 as _, None) } {
+            Ok(item) => item,
+            Err(_) => {
+                let _ = unsafe { DestroyWindow(hwnd) };
+                let _ = sender.send(false);
+                return;
+            }
+        };
+        let entry = SHChangeNotifyEntry {
+            pidl: recycle_bin_item,
+            fRecursive: true.into(),
+        };
+        let registration = unsafe { SHChangeNotifyRegister(hwnd, SHCNRF_ShellLevel | SHCNRF_InterruptLevel, SHCNE_ALLEVENTS.0 as _, WM_RECYCLE_BIN_CHANGED, 1, &entry) };
+        unsafe { CoTaskMemFree(Some(recycle_bin_item as _)) };
+
+        if registration == 0 {
+            let _ = unsafe { DestroyWindow(hwnd) };
+            let _ = sender.send(false);
+            return;
+        }
+
+        let watcher = RecycleBinWatcher {
+            callback: Box::new(callback),
+            info: recycle_bin_info(None).unwrap_or_default(),
+            registration,
+        };
+        unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(Box::new(watcher)) as _) };
+
+        *RECYCLE_BIN_WINDOW.lock().unwrap() = hwnd.0 as _;
+        let _ = sender.send(true);
+
+        let mut msg = MSG::default();
+        while unsafe { GetMessageW(&mut msg, None, 0, 0) }.as_bool() {
+            unsafe {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+    });
+
+    receiver.recv().unwrap_or(false)
+}
+
+/// Stops notification started by watch_recycle_bin
+pub fn unwatch_recycle_bin() {
+    if let Ok(mut window) = RECYCLE_BIN_WINDOW.lock() {
+        if *window != 0 {
+            let _ = unsafe { PostMessageW(Some(HWND(*window as _)), WM_CLOSE, WPARAM(0), LPARAM(0)) };
+            *window = 0;
+        }
+    }
+}
+
+unsafe extern "system" fn recycle_bin_window_proc(window: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_RECYCLE_BIN_CHANGED => {
+            let watcher = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut RecycleBinWatcher;
+            if !watcher.is_null() {
+                let watcher = &mut *watcher;
+                // Several notifications arrive for a single deletion, so report only actual changes
+                if let Ok(info) = recycle_bin_info(None) {
+                    if info != watcher.info {
+                        watcher.info = info.clone();
+                        (watcher.callback)(info);
+                    }
+                }
+            }
+            LRESULT(0)
+        }
+
+        WM_CLOSE => {
+            let _ = DestroyWindow(window);
+            LRESULT(0)
+        }
+
+        WM_DESTROY => {
+            let watcher = SetWindowLongPtrW(window, GWLP_USERDATA, 0) as *mut RecycleBinWatcher;
+            if !watcher.is_null() {
+                let watcher = Box::from_raw(watcher);
+                let _ = SHChangeNotifyDeregister(watcher.registration);
+            }
+            PostQuitMessage(0);
+            LRESULT(0)
+        }
+
+        _ => DefWindowProcW(window, msg, wparam, lparam),
+    }
+}
+
 /// Changes the modification and access timestamps of a file
 pub fn utimes<P: AsRef<Path>>(file: P, atime_ms: u64, mtime_ms: u64) -> Result<(), String> {
     let wide = encode_wide(file.as_ref());
@@ -794,3 +2053,134 @@ fn to_msecs_from_file_time(low: u32, high: u32) -> u64 {
 
     milliseconds - windows_epoch
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileOperation {
+    Copy,
+    Move,
+    Delete,
+    Trash,
+}
+
+#[derive(Debug)]
+pub enum OperationStatus {
+    Ready(Total),
+    Start(String),
+    // proccessed size and total size
+    Progress(i64, i64),
+    End,
+    // Sent before Error when the item failed because other processes have it open
+    InUseBy(Vec<crate::process::ProcessInfo>),
+    Error(String),
+    Confirm(String),
+    // Source of an item skipped after Confirm
+    Skipped(String),
+    // number of retries so far
+    Retry(u32),
+    Finished,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Response {
+    Proceed,
+    Cancel,
+    Replace,
+    Skip,
+}
+
+/// Controls a file operation started by `operate_with_handle` or `operate_with_events`
+#[derive(Debug, Clone, Default)]
+pub struct OperationHandle {
+    paused: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+    events: Option<Receiver<OperationEvent>>,
+    responses: Option<Sender<Response>>,
+    pub(crate) trace: crate::trace::CallTrace,
+}
+
+impl OperationHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Suspends the operation at the next progress notification
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resumes the paused operation
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Cancels the operation. The shell removes a halfway copied item
+    pub fn cancel(&self) {
+        self.resume();
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Receives the events of an operation started by `operate_with_events`
+    pub fn events(&self) -> Option<Receiver<OperationEvent>> {
+        self.events.clone()
+    }
+
+    /// Answers `OperationEvent::Conflict` with Replace, Skip or Cancel
+    pub fn respond(&self, response: Response) {
+        if let Some(responses) = &self.responses {
+            let _ = responses.try_send(response);
+        }
+    }
+
+    /// Gets the OS calls recorded so far by an operation started by `operate_traced`
+    pub fn trace(&self) -> Vec<String> {
+        self.trace.calls()
+    }
+}
+
+/// Item whose destination already exists
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub source: String,
+    pub destination: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictAction {
+    Replace,
+    Skip,
+    // Copies or moves the item as "name (2).ext"
+    KeepBoth,
+    Cancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConflictChoice {
+    pub action: ConflictAction,
+    // Applies the action to the remaining conflicts without asking
+    pub apply_to_all: bool,
+}
+
+/// Decides what to do with each conflicting item before `operate_with_resolver` starts
+pub trait ConflictResolver {
+    fn resolve(&mut self, conflict: &Conflict) -> ConflictChoice;
+}
+
+impl<F: FnMut(&Conflict) -> ConflictChoice> ConflictResolver for F {
+    fn resolve(&mut self, conflict: &Conflict) -> ConflictChoice {
+        self(conflict)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Total {
+    pub total_size: u64,
+    pub total_count: u64,
+}
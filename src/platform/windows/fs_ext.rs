@@ -0,0 +1,425 @@
+use super::{
+    fs::{execute, get_id_lists, Conflict, ConflictAction, ConflictResolver, FileOperation, OperationHandle, OperationStatus, Response},
+    util::{decode_wide, encode_wide, ComGuard},
+};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use windows::{
+    core::{implement, Ref, HRESULT, PCWSTR},
+    Win32::{
+        Foundation::{ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION, E_ABORT},
+        System::Com::{CoCreateInstance, CoTaskMemFree, CLSCTX_ALL},
+        UI::Shell::{
+            FileOperation as ShellFileOperation, IFileOperation, IFileOperationProgressSink, IFileOperationProgressSink_Impl, IShellItem, SHCreateItemFromParsingName,
+            COPYENGINE_E_SHARING_VIOLATION_DEST, COPYENGINE_E_SHARING_VIOLATION_SRC, COPYENGINE_S_USER_IGNORED, FOF_ALLOWUNDO, FOF_NOCONFIRMATION, FOF_RENAMEONCOLLISION, SIGDN_FILESYSPATH,
+        },
+    },
+};
+
+pub(crate) fn execute_file_operation<F, P1: AsRef<Path>, P2: AsRef<Path>>(
+    operation: FileOperation,
+    froms: &[P1],
+    to: Option<P2>,
+    handle: Option<OperationHandle>,
+    mut callback: F,
+) -> Result<(), String>
+where
+    F: AsyncFnMut(OperationStatus) -> Response + 'static,
+{
+    if matches!(operation, FileOperation::Copy | FileOperation::Move) {
+        crate::policy::check_transfer(froms, to.as_ref().ok_or("No destination specified")?.as_ref())?;
+    }
+    crate::hooks::before(operation.into(), froms, to.as_ref())?;
+
+    let _guard = ComGuard::new();
+    let trace = handle.as_ref().map(|handle| handle.trace.clone()).unwrap_or_default();
+
+    let item_array = get_id_lists(froms)?;
+    let destination = to.as_ref().map(|to| to.as_ref().to_path_buf());
+
+    let op: IFileOperation = unsafe { CoCreateInstance(&ShellFileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
+
+    match operation {
+        FileOperation::Copy | FileOperation::Move => {
+            let to = to.ok_or("No destination specified")?;
+            let to_wide = encode_wide(to.as_ref());
+            let to_item: IShellItem = unsafe { SHCreateItemFromParsingName(PCWSTR::from_raw(to_wide.as_ptr()), None).map_err(|e| e.message()) }?;
+
+            // Conflicts are resolved by the callback, so let the shell replace silently
+            let from_sample = froms.first().ok_or("No items specified")?;
+            if operation == FileOperation::Copy && from_sample.as_ref().parent() == Some(to.as_ref()) {
+                trace.record(|| "IFileOperation::SetOperationFlags(FOF_ALLOWUNDO | FOF_RENAMEONCOLLISION)".to_string());
+                unsafe { op.SetOperationFlags(FOF_ALLOWUNDO | FOF_RENAMEONCOLLISION).map_err(|e| e.message()) }?;
+            } else {
+                trace.record(|| "IFileOperation::SetOperationFlags(FOF_ALLOWUNDO | FOF_NOCONFIRMATION)".to_string());
+                unsafe { op.SetOperationFlags(FOF_ALLOWUNDO | FOF_NOCONFIRMATION).map_err(|e| e.message()) }?;
+            }
+
+            if operation == FileOperation::Copy {
+                trace.record(|| format!("IFileOperation::CopyItems({:?}, {:?})", paths(froms), to.as_ref()));
+                unsafe { op.CopyItems(&item_array, &to_item).map_err(|e| e.message()) }?;
+            } else {
+                trace.record(|| format!("IFileOperation::MoveItems({:?}, {:?})", paths(froms), to.as_ref()));
+                unsafe { op.MoveItems(&item_array, &to_item).map_err(|e| e.message()) }?;
+            }
+        }
+        FileOperation::Delete => {
+            trace.record(|| "IFileOperation::SetOperationFlags(FOF_NOCONFIRMATION)".to_string());
+            unsafe { op.SetOperationFlags(FOF_NOCONFIRMATION).map_err(|e| e.message()) }?;
+            trace.record(|| format!("IFileOperation::DeleteItems({:?})", paths(froms)));
+            unsafe { op.DeleteItems(&item_array).map_err(|e| e.message()) }?;
+        }
+        FileOperation::Trash => {
+            trace.record(|| "IFileOperation::SetOperationFlags(FOF_ALLOWUNDO)".to_string());
+            unsafe { op.SetOperationFlags(FOF_ALLOWUNDO).map_err(|e| e.message()) }?;
+            trace.record(|| format!("IFileOperation::DeleteItems({:?})", paths(froms)));
+            unsafe { op.DeleteItems(&item_array).map_err(|e| e.message()) }?;
+        }
+    }
+
+    let sink: IFileOperationProgressSink = FileOperationSink {
+        callback: RefCell::new(Box::new(crate::throttle::throttled(move |status| smol::block_on(callback(status))))),
+        is_trash: operation == FileOperation::Trash,
+        resolved: false,
+        handle,
+        overwrites: RefCell::new(HashSet::new()),
+    }
+    .into();
+
+    let cookie = unsafe { op.Advise(&sink).map_err(|e| e.message()) }?;
+    let result = execute(op.clone());
+    trace.record(|| format!("IFileOperation::PerformOperations() -> {:?}", result));
+    unsafe { op.Unadvise(cookie).map_err(|e| e.message()) }?;
+
+    if result.is_ok() {
+        crate::history::record(operation.into(), froms, destination);
+    }
+
+    result
+}
+
+pub(crate) fn execute_with_resolver<F, P1: AsRef<Path>, P2: AsRef<Path>>(operation: FileOperation, froms: &[P1], to: P2, resolver: &mut dyn ConflictResolver, mut callback: F) -> Result<(), String>
+where
+    F: AsyncFnMut(OperationStatus) -> Response + 'static,
+{
+    if !matches!(operation, FileOperation::Copy | FileOperation::Move) {
+        return Err("Conflicts are resolved only on copy and move".to_string());
+    }
+
+    crate::policy::check_transfer(froms, to.as_ref())?;
+    crate::hooks::before(operation.into(), froms, Some(to.as_ref()))?;
+
+    let _guard = ComGuard::new();
+
+    let op: IFileOperation = unsafe { CoCreateInstance(&ShellFileOperation, None, CLSCTX_ALL).map_err(|e| e.message()) }?;
+    unsafe { op.SetOperationFlags(FOF_ALLOWUNDO | FOF_NOCONFIRMATION).map_err(|e| e.message()) }?;
+
+    let to_wide = encode_wide(to.as_ref());
+    let to_item: IShellItem = unsafe { SHCreateItemFromParsingName(PCWSTR::from_raw(to_wide.as_ptr()), None).map_err(|e| e.message()) }?;
+
+    let mut action_for_all = None;
+    let mut queued: Vec<PathBuf> = Vec::new();
+
+    for from in froms {
+        let dest = to.as_ref().join(from.as_ref().file_name().unwrap_or_default());
+
+        let action = if !dest.exists() {
+            ConflictAction::Replace
+        } else if let Some(action) = action_for_all {
+            action
+        } else {
+            let choice = resolver.resolve(&Conflict {
+                source: from.as_ref().to_string_lossy().to_string(),
+                destination: dest.to_string_lossy().to_string(),
+            });
+            if choice.apply_to_all {
+                action_for_all = Some(choice.action);
+            }
+            choice.action
+        };
+
+        let new_name = match action {
+            ConflictAction::Cancel => return Ok(()),
+            ConflictAction::Skip => continue,
+            ConflictAction::Replace => None,
+            ConflictAction::KeepBoth => Some(encode_wide(unique_name(&dest))),
+        };
+        let new_name = new_name.as_ref().map_or(PCWSTR::null(), |name| PCWSTR::from_raw(name.as_ptr()));
+
+        let from_wide = encode_wide(from.as_ref());
+        let item: IShellItem = unsafe { SHCreateItemFromParsingName(PCWSTR::from_raw(from_wide.as_ptr()), None).map_err(|e| e.message()) }?;
+        if operation == FileOperation::Copy {
+            unsafe { op.CopyItem(&item, &to_item, new_name, None::<&IFileOperationProgressSink>).map_err(|e| e.message()) }?;
+        } else {
+            unsafe { op.MoveItem(&item, &to_item, new_name, None::<&IFileOperationProgressSink>).map_err(|e| e.message()) }?;
+        }
+
+        queued.push(from.as_ref().to_path_buf());
+    }
+
+    if queued.is_empty() {
+        return Ok(());
+    }
+
+    let sink: IFileOperationProgressSink = FileOperationSink {
+        callback: RefCell::new(Box::new(crate::throttle::throttled(move |status| smol::block_on(callback(status))))),
+        is_trash: false,
+        resolved: true,
+        handle: None,
+        overwrites: RefCell::new(HashSet::new()),
+    }
+    .into();
+
+    let cookie = unsafe { op.Advise(&sink).map_err(|e| e.message()) }?;
+    let result = execute(op.clone());
+    unsafe { op.Unadvise(cookie).map_err(|e| e.message()) }?;
+
+    if result.is_ok() {
+        crate::history::record(operation.into(), &queued, Some(to.as_ref()));
+    }
+
+    result
+}
+
+fn paths<P: AsRef<Path>>(froms: &[P]) -> Vec<&Path> {
+    froms.iter().map(|from| from.as_ref()).collect()
+}
+
+/* "name (2).ext", "name (3).ext" and so on as Explorer names copies */
+fn unique_name(dest: &Path) -> String {
+    let (stem, extension) = if dest.is_dir() {
+        (dest.file_name().unwrap_or_default().to_string_lossy().to_string(), String::new())
+    } else {
+        (dest.file_stem().unwrap_or_default().to_string_lossy().to_string(), dest.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default())
+    };
+
+    (2..).map(|index| format!("{} ({}){}", stem, index, extension)).find(|name| !dest.with_file_name(name).exists()).unwrap()
+}
+
+#[implement(IFileOperationProgressSink)]
+struct FileOperationSink {
+    callback: RefCell<Box<dyn FnMut(OperationStatus) -> Response>>,
+    #[cfg_attr(not(feature = "audit"), allow(dead_code))]
+    is_trash: bool,
+    // Conflicts were resolved before starting, so the callback is not asked again
+    resolved: bool,
+    handle: Option<OperationHandle>,
+    // Destinations the callback agreed to replace
+    overwrites: RefCell<HashSet<String>>,
+}
+
+impl FileOperationSink {
+    fn notify(&self, status: OperationStatus) -> windows::core::Result<()> {
+        // The copy engine calls back on the thread performing the operation, so blocking here suspends it
+        if let Some(handle) = &self.handle {
+            while handle.is_paused() && !handle.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+
+            if handle.is_cancelled() {
+                return Err(E_ABORT.into());
+            }
+        }
+
+        if crate::lifecycle::is_shutting_down() || (self.callback.borrow_mut())(status) == Response::Cancel {
+            Err(E_ABORT.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /* Asks the callback what to do when the destination already exists */
+    fn confirm(&self, item: Ref<IShellItem>, destination_folder: Ref<IShellItem>, new_name: &PCWSTR) -> windows::core::Result<()> {
+        let source = to_path(item.ok()?)?;
+        let folder = to_path(destination_folder.ok()?)?;
+        let name = if new_name.is_null() {
+            Path::new(&source).file_name().unwrap_or_default().to_string_lossy().to_string()
+        } else {
+            unsafe { new_name.to_string() }?
+        };
+
+        self.notify(OperationStatus::Start(name.clone()))?;
+
+        let dest = Path::new(&folder).join(name);
+        if !dest.exists() {
+            return Ok(());
+        }
+
+        if self.resolved {
+            self.overwrites.borrow_mut().insert(dest.to_string_lossy().to_string());
+            return Ok(());
+        }
+
+        match (self.callback.borrow_mut())(OperationStatus::Confirm(source)) {
+            Response::Cancel => Err(E_ABORT.into()),
+            Response::Skip => Err(COPYENGINE_S_USER_IGNORED.into()),
+            _ => {
+                self.overwrites.borrow_mut().insert(dest.to_string_lossy().to_string());
+                Ok(())
+            }
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn complete_transfer(&self, result: HRESULT, item: Ref<IShellItem>, destination_folder: Ref<IShellItem>, new_name: &PCWSTR) -> windows::core::Result<()> {
+        #[cfg(feature = "audit")]
+        if result.is_ok() && !self.overwrites.borrow().is_empty() {
+            if let (Some(item), Some(folder)) = (item.as_ref(), destination_folder.as_ref()) {
+                let source = to_path(item)?;
+                let name = if new_name.is_null() {
+                    Path::new(&source).file_name().unwrap_or_default().to_string_lossy().to_string()
+                } else {
+                    unsafe { new_name.to_string() }?
+                };
+                let dest = Path::new(&to_path(folder)?).join(name).to_string_lossy().to_string();
+                if self.overwrites.borrow_mut().remove(&dest) {
+                    crate::audit::record_with_source(crate::audit::AuditAction::Overwrite, dest, source);
+                }
+            }
+        }
+
+        self.complete(result, item)
+    }
+
+    fn complete(&self, result: HRESULT, item: Ref<IShellItem>) -> windows::core::Result<()> {
+        // Returned by confirm for a skipped item, which does not fail the operation
+        if result == COPYENGINE_S_USER_IGNORED {
+            let source = item.as_ref().map(to_path).transpose()?.unwrap_or_default();
+            return self.notify(OperationStatus::Skipped(source));
+        }
+
+        if result.is_ok() {
+            return self.notify(OperationStatus::End);
+        }
+
+        if is_sharing_violation(result) {
+            if let Some(processes) = item.as_ref().and_then(|item| to_path(item).ok()).and_then(|path| crate::process::in_use_by(Path::new(&path))) {
+                self.notify(OperationStatus::InUseBy(processes))?;
+            }
+        }
+        self.notify(OperationStatus::Error(result.message()))
+    }
+}
+
+/* The copy engine reports a file open in another process as a sharing violation of the source or the destination */
+fn is_sharing_violation(result: HRESULT) -> bool {
+    [ERROR_SHARING_VIOLATION.to_hresult(), ERROR_LOCK_VIOLATION.to_hresult(), COPYENGINE_E_SHARING_VIOLATION_SRC, COPYENGINE_E_SHARING_VIOLATION_DEST].contains(&result)
+}
+
+fn to_path(item: &IShellItem) -> windows::core::Result<String> {
+    let path_ptr = unsafe { item.GetDisplayName(SIGDN_FILESYSPATH) }?;
+    let path = decode_wide(unsafe { path_ptr.as_wide() });
+    unsafe { CoTaskMemFree(Some(path_ptr.0 as _)) };
+    Ok(path)
+}
+
+#[allow(non_snake_case)]
+impl IFileOperationProgressSink_Impl for FileOperationSink_Impl {
+    fn StartOperations(&self) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn FinishOperations(&self, _hrresult: HRESULT) -> windows::core::Result<()> {
+        let _ = (self.callback.borrow_mut())(OperationStatus::Finished);
+        Ok(())
+    }
+
+    fn PreRenameItem(&self, _dwflags: u32, _psiitem: Ref<IShellItem>, _psznewname: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn PostRenameItem(&self, _dwflags: u32, _psiitem: Ref<IShellItem>, _psznewname: &PCWSTR, _hrrename: HRESULT, _psinewlycreated: Ref<IShellItem>) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn PreMoveItem(&self, _dwflags: u32, psiitem: Ref<IShellItem>, psidestinationfolder: Ref<IShellItem>, psznewname: &PCWSTR) -> windows::core::Result<()> {
+        self.confirm(psiitem, psidestinationfolder, psznewname)
+    }
+
+    fn PostMoveItem(
+        &self,
+        _dwflags: u32,
+        psiitem: Ref<IShellItem>,
+        psidestinationfolder: Ref<IShellItem>,
+        psznewname: &PCWSTR,
+        hrmove: HRESULT,
+        _psinewlycreated: Ref<IShellItem>,
+    ) -> windows::core::Result<()> {
+        self.complete_transfer(hrmove, psiitem, psidestinationfolder, psznewname)
+    }
+
+    fn PreCopyItem(&self, _dwflags: u32, psiitem: Ref<IShellItem>, psidestinationfolder: Ref<IShellItem>, psznewname: &PCWSTR) -> windows::core::Result<()> {
+        self.confirm(psiitem, psidestinationfolder, psznewname)
+    }
+
+    fn PostCopyItem(
+        &self,
+        _dwflags: u32,
+        psiitem: Ref<IShellItem>,
+        psidestinationfolder: Ref<IShellItem>,
+        psznewname: &PCWSTR,
+        hrcopy: HRESULT,
+        _psinewlycreated: Ref<IShellItem>,
+    ) -> windows::core::Result<()> {
+        self.complete_transfer(hrcopy, psiitem, psidestinationfolder, psznewname)
+    }
+
+    fn PreDeleteItem(&self, _dwflags: u32, psiitem: Ref<IShellItem>) -> windows::core::Result<()> {
+        let path = to_path(psiitem.ok()?)?;
+        self.notify(OperationStatus::Start(Path::new(&path).file_name().unwrap_or_default().to_string_lossy().to_string()))
+    }
+
+    #[allow(unused_variables)]
+    fn PostDeleteItem(&self, _dwflags: u32, psiitem: Ref<IShellItem>, hrdelete: HRESULT, _psinewlycreated: Ref<IShellItem>) -> windows::core::Result<()> {
+        #[cfg(feature = "audit")]
+        if hrdelete.is_ok() {
+            if let Some(item) = psiitem.as_ref() {
+                let action = if self.is_trash {
+                    crate::audit::AuditAction::Trash
+                } else {
+                    crate::audit::AuditAction::Delete
+                };
+                crate::audit::record(action, to_path(item)?);
+            }
+        }
+
+        self.complete(hrdelete, psiitem)
+    }
+
+    fn PreNewItem(&self, _dwflags: u32, _psidestinationfolder: Ref<IShellItem>, _psznewname: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn PostNewItem(
+        &self,
+        _dwflags: u32,
+        _psidestinationfolder: Ref<IShellItem>,
+        _psznewname: &PCWSTR,
+        _psztemplatename: &PCWSTR,
+        _dwfileattributes: u32,
+        _hrnew: HRESULT,
+        _psinewitem: Ref<IShellItem>,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn UpdateProgress(&self, iworktotal: u32, iworksofar: u32) -> windows::core::Result<()> {
+        self.notify(OperationStatus::Progress(iworksofar as i64, iworktotal as i64))
+    }
+
+    fn ResetTimer(&self) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn PauseTimer(&self) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn ResumeTimer(&self) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use super::util::{decode_wide, encode_wide};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+        System::DataExchange::COPYDATASTRUCT,
+        UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, FindWindowW, GetWindowLongPtrW, PeekMessageW, RegisterClassW, SendMessageW, SetWindowLongPtrW, TranslateMessage,
+            GWLP_USERDATA, HWND_MESSAGE, MSG, PM_REMOVE, WINDOW_EX_STYLE, WM_COPYDATA, WNDCLASSW, WS_OVERLAPPED,
+        },
+    },
+};
+
+/* Values from everything_ipc.h of the Everything SDK */
+const EVERYTHING_IPC_COPYDATAQUERYW: usize = 2;
+const EVERYTHING_IPC_REGEX: u32 = 0x00000008;
+const EVERYTHING_IPC_ALLRESULTS: u32 = 0xFFFFFFFF;
+const REPLY_ID: u32 = 0x5A4F;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/* EVERYTHING_IPC_LISTW starts with seven DWORDs, the sixth of which is the number of items that follow */
+const LIST_HEADER_COUNT: usize = 7;
+const LIST_NUMITEMS_INDEX: usize = 5;
+
+/* EVERYTHING_IPC_ITEMW. Offsets are from the start of the list */
+#[repr(C)]
+struct EverythingItem {
+    _flags: u32,
+    filename_offset: u32,
+    path_offset: u32,
+}
+
+/// Asks the running Everything for the full paths of the entries under the location whose names match the wildcard or regex pattern
+///
+/// Fails if Everything is not running
+pub(crate) fn locate(location: &Path, pattern: &str, is_regex: bool, recursive: bool) -> Result<Vec<String>, String> {
+    let everything = unsafe { FindWindowW(w!("EVERYTHING_TASKBAR_NOTIFICATION"), PCWSTR::null()) }.map_err(|_| "Everything is not running".to_string())?;
+
+    let class_name = encode_wide("ZouniEverythingReply");
+    let class = WNDCLASSW {
+        lpfnWndProc: Some(reply_window_proc),
+        lpszClassName: PCWSTR::from_raw(class_name.as_ptr()),
+        ..Default::default()
+    };
+    unsafe { RegisterClassW(&class) };
+    let hwnd = unsafe { CreateWindowExW(WINDOW_EX_STYLE::default(), PCWSTR::from_raw(class_name.as_ptr()), PCWSTR::null(), WS_OVERLAPPED, 0, 0, 0, 0, Some(HWND_MESSAGE), None, None, None) }
+        .map_err(|e| e.message())?;
+
+    let mut reply: Option<Vec<String>> = None;
+    unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, &mut reply as *mut _ as _) };
+
+    let result = send_query(everything, hwnd, &to_search_string(location, pattern, is_regex, recursive), is_regex).and_then(|_| {
+        let deadline = Instant::now() + TIMEOUT;
+        let mut msg = MSG::default();
+        // Everything sends the reply to the window, which is dispatched while peeking
+        while reply.is_none() && Instant::now() < deadline {
+            if unsafe { PeekMessageW(&mut msg, Some(hwnd), 0, 0, PM_REMOVE) }.as_bool() {
+                unsafe {
+                    let _ = TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            } else {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        reply.take().ok_or_else(|| "Everything did not reply".to_string())
+    });
+
+    unsafe {
+        SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+        let _ = DestroyWindow(hwnd);
+    }
+
+    result
+}
+
+/* Everything searches a path ending with a separator recursively, and only its children with parent:. The pattern is quoted so that spaces do not split it */
+fn to_search_string(location: &Path, pattern: &str, is_regex: bool, recursive: bool) -> String {
+    let location = location.to_string_lossy().trim_end_matches('\\').to_string();
+    let scope = if recursive {
+        format!("\"{}\\\"", location)
+    } else {
+        format!("parent:\"{}\"", location)
+    };
+
+    if is_regex {
+        format!("{} regex:\"{}\"", scope, pattern)
+    } else {
+        format!("{} \"{}\"", scope, pattern)
+    }
+}
+
+fn send_query(everything: HWND, reply_hwnd: HWND, search: &str, is_regex: bool) -> Result<(), String> {
+    let search = encode_wide(search);
+    let search_flags = if is_regex {
+        EVERYTHING_IPC_REGEX
+    } else {
+        0
+    };
+    let header = [reply_hwnd.0 as usize as u32, REPLY_ID, search_flags, 0, EVERYTHING_IPC_ALLRESULTS];
+
+    // EVERYTHING_IPC_QUERYW is five DWORDs followed by the null terminated search string
+    let mut query: Vec<u8> = header.iter().flat_map(|value| value.to_le_bytes()).collect();
+    query.extend(search.iter().flat_map(|c| c.to_le_bytes()));
+
+    let data = COPYDATASTRUCT {
+        dwData: EVERYTHING_IPC_COPYDATAQUERYW,
+        cbData: query.len() as _,
+        lpData: query.as_mut_ptr() as _,
+    };
+
+    let accepted = unsafe { SendMessageW(everything, WM_COPYDATA, Some(WPARAM(reply_hwnd.0 as _)), Some(LPARAM(&data as *const _ as _))) };
+    if accepted.0 == 0 {
+        return Err("Everything rejected the query".to_string());
+    }
+
+    Ok(())
+}
+
+unsafe extern "system" fn reply_window_proc(window: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if msg == WM_COPYDATA {
+        let data = &*(lparam.0 as *const COPYDATASTRUCT);
+        let reply = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut Option<Vec<String>>;
+        if data.dwData == REPLY_ID as usize && !reply.is_null() {
+            *reply = Some(read_list(data.lpData as *const u8));
+            return LRESULT(1);
+        }
+    }
+
+    DefWindowProcW(window, msg, wparam, lparam)
+}
+
+unsafe fn read_list(list: *const u8) -> Vec<String> {
+    let header = list as *const u32;
+    let numitems = *header.add(LIST_NUMITEMS_INDEX) as usize;
+    let items = header.add(LIST_HEADER_COUNT) as *const EverythingItem;
+
+    (0..numitems)
+        .map(|index| {
+            let item = &*items.add(index);
+            let path = read_string(list.add(item.path_offset as usize));
+            let name = read_string(list.add(item.filename_offset as usize));
+            format!("{}\\{}", path, name)
+        })
+        .collect()
+}
+
+unsafe fn read_string(start: *const u8) -> String {
+    let start = start as *const u16;
+    let mut len = 0;
+    while *start.add(len) != 0 {
+        len += 1;
+    }
+    decode_wide(std::slice::from_raw_parts(start, len))
+}
@@ -1,22 +1,52 @@
 use crate::{
     platform::windows::util::{encode_wide, ComGuard},
     shell::read_properties,
-    Size,
+    CancelToken, ImageInfo, MediaInfo, PrefetchPriority, Size,
+};
+use image::{metadata::Orientation, DynamicImage, ImageBuffer, ImageFormat, RgbImage};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
-use image::{ImageBuffer, ImageFormat, RgbImage};
-use std::{collections::HashMap, io::Cursor, path::Path};
 use windows::{
-    core::PCWSTR,
+    core::{w, GUID, PCWSTR, PROPVARIANT},
     Win32::{
-        Foundation::SIZE,
-        Graphics::Gdi::{DeleteObject, GetObjectW, BITMAP},
-        UI::Shell::{IShellItemImageFactory, SHCreateItemFromParsingName, SIIGBF_RESIZETOFIT, SIIGBF_THUMBNAILONLY},
+        Foundation::{GENERIC_READ, PROPERTYKEY, SIZE},
+        Graphics::{
+            Gdi::{DeleteObject, GetObjectW, BITMAP},
+            Imaging::{
+                CLSID_WICImagingFactory, GUID_WICPixelFormat24bppBGR, GUID_WICPixelFormat24bppRGB, GUID_WICPixelFormat32bppBGR, IWICColorContext, IWICImagingFactory, WICBitmapDitherTypeNone,
+                WICBitmapPaletteTypeCustom, WICColorContextExifColorSpace, WICColorContextProfile, WICDecodeMetadataCacheOnDemand, WINCODEC_ERR_COMPONENTNOTFOUND,
+            },
+        },
+        Media::MediaFoundation::{
+            IMFAttributes, IMFSample, IMFSourceReader, MFCreateAttributes, MFCreateMediaType, MFCreateSourceReaderFromURL, MFMediaType_Video, MFShutdown, MFStartup, MFVideoFormat_RGB32,
+            MF_MT_DEFAULT_STRIDE, MF_MT_FRAME_SIZE, MF_MT_MAJOR_TYPE, MF_MT_SUBTYPE, MF_SOURCE_READER_ENABLE_VIDEO_PROCESSING,
+        },
+        System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER},
+        UI::Shell::{
+            IShellItemImageFactory,
+            PropertiesSystem::{IPropertyStore, PSGetPropertyKeyFromName, SHGetPropertyStoreFromParsingName, GPS_DEFAULT},
+            SHCreateItemFromParsingName, SIIGBF_RESIZETOFIT, SIIGBF_THUMBNAILONLY,
+        },
     },
 };
 
+const ALBUM_ART_SIZE: u32 = 256;
+// Size prefetched when none is given
+const THUMBNAIL_SIZE: i32 = 256;
+const MF_VERSION: u32 = 0x0002_0070;
+const MFSTARTUP_LITE: u32 = 1;
+const MF_SOURCE_READER_FIRST_VIDEO_STREAM: u32 = 0xFFFF_FFFC;
+const MF_SOURCE_READERF_ENDOFSTREAM: u32 = 0x2;
+const EXIF_COLOR_SPACE_SRGB: u32 = 1;
+
 pub fn extract_video_thumbnail<P: AsRef<Path>>(file_path: P, size: Option<Size>) -> Result<Vec<u8>, String> {
     let _guard = ComGuard::new();
-    unsafe { get_video_thumbnail(file_path, size).map_err(|e| e.message()) }
+    extract_thumbnail(file_path.as_ref(), size)
 }
 
 pub fn extract_video_thumbnails<P: AsRef<Path>>(file_paths: &[P], size: Option<Size>) -> Result<HashMap<String, Vec<u8>>, String> {
@@ -24,14 +54,381 @@ pub fn extract_video_thumbnails<P: AsRef<Path>>(file_paths: &[P], size: Option<S
 
     let mut result = HashMap::new();
     for file_path in file_paths {
-        let thumbnail = unsafe { get_video_thumbnail(file_path, size.clone()).map_err(|e| e.message()) }?;
+        let thumbnail = extract_thumbnail(file_path.as_ref(), size.clone())?;
         let _ = result.insert(file_path.as_ref().to_string_lossy().to_string(), thumbnail);
     }
 
     Ok(result)
 }
 
+/// Extracts thumbnails on up to `max_concurrency` worker threads, calling `callback` with the path of each file as soon as it is done
+///
+/// Returns immediately. Files not started yet are skipped once `cancel_token` is cancelled
+pub fn extract_video_thumbnails_batch<P: AsRef<Path>, F: Fn(String, Result<Vec<u8>, String>) + Send + Sync + 'static>(
+    file_paths: &[P],
+    size: Option<Size>,
+    max_concurrency: usize,
+    cancel_token: CancelToken,
+    callback: F,
+) {
+    let queue: Arc<Mutex<VecDeque<PathBuf>>> = Arc::new(Mutex::new(file_paths.iter().map(|file_path| file_path.as_ref().to_path_buf()).collect()));
+    let callback = Arc::new(callback);
+
+    for _ in 0..max_concurrency.clamp(1, file_paths.len().max(1)) {
+        let queue = queue.clone();
+        let size = size.clone();
+        let cancel_token = cancel_token.clone();
+        let callback = callback.clone();
+
+        std::thread::spawn(move || {
+            let _guard = ComGuard::new();
+
+            while !cancel_token.is_cancelled() {
+                let Some(file_path) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+
+                let result = extract_thumbnail(&file_path, size.clone());
+                callback(file_path.to_string_lossy().to_string(), result);
+            }
+        });
+    }
+}
+
+/// Warms the thumbnail cache for files about to be shown, on worker threads shared by all prefetches
+///
+/// Returns immediately. Files already queued are not queued again. Cancel the returned token when the files are scrolled away
+pub fn prefetch<P: AsRef<Path>>(file_paths: &[P], size: Option<Size>, priority: PrefetchPriority) -> CancelToken {
+    let cancel_token = CancelToken::new();
+    crate::prefetch::enqueue(file_paths, size, priority, &cancel_token, warm_thumbnail);
+    cancel_token
+}
+
+/// Converts thumbnails of photos with an embedded color profile to sRGB so that wide-gamut photos do not look washed out
+///
+/// Enabled by default. Disabling skips reading the profile for speed
+pub fn set_color_management(enabled: bool) {
+    crate::color::set_enabled(enabled);
+}
+
+/// Extracts thumbnails in a helper process that is killed after `timeout`, so that a malformed file cannot crash or hang the app. None extracts them in process
+///
+/// The helper is the app's own executable, which must call `run_sandbox_helper` at the start of `main`
+pub fn set_sandbox(timeout: Option<Duration>) {
+    crate::sandbox::set(timeout);
+}
+
+/// Extracts the thumbnail and exits if the process was started as the helper of `set_sandbox`. Returns if it was not
+pub fn run_sandbox_helper() {
+    crate::sandbox::run_helper(|path, size| {
+        let _guard = ComGuard::new();
+        unsafe { get_video_thumbnail(path, size).map_err(|e| e.message()) }
+    });
+}
+
+fn extract_thumbnail(path: &Path, size: Option<Size>) -> Result<Vec<u8>, String> {
+    match crate::sandbox::timeout() {
+        Some(timeout) => crate::sandbox::extract(path, size, timeout),
+        None => unsafe { get_video_thumbnail(path, size).map_err(|e| e.message()) },
+    }
+}
+
+/* The shell caches the thumbnail as it extracts it */
+fn warm_thumbnail(file_path: &Path, size: Option<&Size>) {
+    let _guard = ComGuard::new();
+
+    let wide = encode_wide(file_path);
+    let Ok(factory) = (unsafe { SHCreateItemFromParsingName::<_, _, IShellItemImageFactory>(PCWSTR(wide.as_ptr()), None) }) else {
+        return;
+    };
+
+    let size = size.map_or(
+        SIZE {
+            cx: THUMBNAIL_SIZE,
+            cy: THUMBNAIL_SIZE,
+        },
+        |size| SIZE {
+            cx: size.width as i32,
+            cy: size.height as i32,
+        },
+    );
+
+    if let Ok(hbitmap) = unsafe { factory.GetImage(size, SIIGBF_THUMBNAILONLY | SIIGBF_RESIZETOFIT) } {
+        let _ = unsafe { DeleteObject(hbitmap.into()) };
+    }
+}
+
+/// Extracts the frame at the timestamp as JPEG, scaled down to fit in `size`
+///
+/// Decodes from the keyframe before the timestamp, so the frame is exact rather than the nearest keyframe
+pub fn extract_video_frame<P: AsRef<Path>>(file_path: P, timestamp: Duration, size: Option<Size>) -> Result<Vec<u8>, String> {
+    let _guard = ComGuard::new();
+
+    unsafe { MFStartup(MF_VERSION, MFSTARTUP_LITE) }.map_err(|e| e.message())?;
+    let result = read_video_frame(file_path.as_ref(), timestamp);
+    let _ = unsafe { MFShutdown() };
+
+    let image = result?;
+    let image = match size {
+        Some(size) if image.width() > size.width || image.height() > size.height => image.thumbnail(size.width, size.height),
+        _ => image,
+    };
+
+    let mut bytes: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+fn read_video_frame(file_path: &Path, timestamp: Duration) -> Result<DynamicImage, String> {
+    let mut attributes: Option<IMFAttributes> = None;
+    unsafe { MFCreateAttributes(&mut attributes, 1) }.map_err(|e| e.message())?;
+    let attributes = attributes.ok_or_else(|| "Failed to create attributes".to_string())?;
+    // Converts decoded frames to RGB32
+    unsafe { attributes.SetUINT32(&MF_SOURCE_READER_ENABLE_VIDEO_PROCESSING, 1) }.map_err(|e| e.message())?;
+
+    let wide = encode_wide(file_path);
+    let reader: IMFSourceReader = unsafe { MFCreateSourceReaderFromURL(PCWSTR::from_raw(wide.as_ptr()), &attributes) }.map_err(|e| e.message())?;
+
+    let media_type = unsafe { MFCreateMediaType() }.map_err(|e| e.message())?;
+    unsafe { media_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video) }.map_err(|e| e.message())?;
+    unsafe { media_type.SetGUID(&MF_MT_SUBTYPE, &MFVideoFormat_RGB32) }.map_err(|e| e.message())?;
+    unsafe { reader.SetCurrentMediaType(MF_SOURCE_READER_FIRST_VIDEO_STREAM, None, &media_type) }.map_err(|e| e.message())?;
+
+    // In 100ns. Seeks to the keyframe before the position
+    let position = (timestamp.as_nanos() / 100) as i64;
+    unsafe { reader.SetCurrentPosition(&GUID::zeroed(), &PROPVARIANT::from(position)) }.map_err(|e| e.message())?;
+
+    let mut frame: Option<IMFSample> = None;
+    loop {
+        let mut flags = 0;
+        let mut sample_time = 0;
+        let mut sample: Option<IMFSample> = None;
+        unsafe { reader.ReadSample(MF_SOURCE_READER_FIRST_VIDEO_STREAM, 0, None, Some(&mut flags), Some(&mut sample_time), Some(&mut sample)) }.map_err(|e| e.message())?;
+
+        if sample.is_some() {
+            frame = sample;
+        }
+
+        // Uses the last frame if the timestamp is beyond the end
+        if sample_time >= position || flags & MF_SOURCE_READERF_ENDOFSTREAM != 0 {
+            break;
+        }
+    }
+
+    let frame = frame.ok_or_else(|| "No video frames".to_string())?;
+
+    let current_type = unsafe { reader.GetCurrentMediaType(MF_SOURCE_READER_FIRST_VIDEO_STREAM) }.map_err(|e| e.message())?;
+    let frame_size = unsafe { current_type.GetUINT64(&MF_MT_FRAME_SIZE) }.map_err(|e| e.message())?;
+    let (width, height) = ((frame_size >> 32) as u32, (frame_size & 0xFFFF_FFFF) as u32);
+    let stride = unsafe { current_type.GetUINT32(&MF_MT_DEFAULT_STRIDE) }.map(|stride| stride as i32).unwrap_or(width as i32 * 4);
+
+    let buffer = unsafe { frame.ConvertToContiguousBuffer() }.map_err(|e| e.message())?;
+    let mut data = std::ptr::null_mut();
+    let mut len = 0;
+    unsafe { buffer.Lock(&mut data, None, Some(&mut len)) }.map_err(|e| e.message())?;
+    let pixels = unsafe { std::slice::from_raw_parts(data, len as usize) }.to_vec();
+    let _ = unsafe { buffer.Unlock() };
+
+    // RGB32 is BGRX. Negative stride means bottom-up rows
+    let mut image: RgbImage = ImageBuffer::new(width, height);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let row = if stride < 0 {
+            height - 1 - y
+        } else {
+            y
+        };
+        let offset = row as usize * stride.unsigned_abs() as usize + x as usize * 4;
+        if let Some(bgr) = pixels.get(offset..offset + 3) {
+            *pixel = image::Rgb([bgr[2], bgr[1], bgr[0]]);
+        }
+    }
+
+    Ok(DynamicImage::ImageRgb8(image))
+}
+
+/// Decodes the image with WIC and encodes it as JPEG, scaled down to fit in `size` and rotated to the EXIF orientation
+///
+/// HEIC and AVIF need the HEIF and AV1 Video Extensions from the Microsoft Store. `diagnostics()` reports whether they are installed
+pub fn extract_image_preview<P: AsRef<Path>>(file_path: P, size: Option<Size>) -> Result<Vec<u8>, String> {
+    let _guard = ComGuard::new();
+
+    let factory: IWICImagingFactory = unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER) }.map_err(|e| e.message())?;
+    let wide = encode_wide(file_path.as_ref());
+    let decoder = unsafe { factory.CreateDecoderFromFilename(PCWSTR::from_raw(wide.as_ptr()), None, GENERIC_READ, WICDecodeMetadataCacheOnDemand) }.map_err(|e| {
+        if e.code() == WINCODEC_ERR_COMPONENTNOTFOUND {
+            format!("No codec is installed for the image:{}", file_path.as_ref().to_string_lossy())
+        } else {
+            e.message()
+        }
+    })?;
+    let frame = unsafe { decoder.GetFrame(0) }.map_err(|e| e.message())?;
+
+    let converter = unsafe { factory.CreateFormatConverter() }.map_err(|e| e.message())?;
+    unsafe { converter.Initialize(&frame, &GUID_WICPixelFormat24bppRGB, WICBitmapDitherTypeNone, None, 0.0, WICBitmapPaletteTypeCustom) }.map_err(|e| e.message())?;
+
+    let (mut width, mut height) = (0, 0);
+    unsafe { converter.GetSize(&mut width, &mut height) }.map_err(|e| e.message())?;
+    let mut pixels = vec![0u8; width as usize * height as usize * 3];
+    unsafe { converter.CopyPixels(std::ptr::null(), width * 3, &mut pixels) }.map_err(|e| e.message())?;
+
+    let image = RgbImage::from_raw(width, height, pixels).ok_or_else(|| "Failed to decode image".to_string())?;
+    let image = match size {
+        Some(size) if width > size.width || height > size.height => DynamicImage::ImageRgb8(image).thumbnail(size.width, size.height),
+        _ => DynamicImage::ImageRgb8(image),
+    };
+    let image = apply_orientation(file_path.as_ref(), image);
+
+    let mut bytes: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Reads duration, bitrate, codecs, dimensions, frame rate and tags from the property store
+pub fn read_media_info<P: AsRef<Path>>(file_path: P) -> Result<MediaInfo, String> {
+    let _guard = ComGuard::new();
+
+    let store = open_property_store(file_path.as_ref())?;
+    let read = |name: PCWSTR| read_property(&store, name);
+    let read_number = |name: PCWSTR| read_property(&store, name).and_then(|value| value.parse::<u64>().ok()).filter(|value| *value > 0);
+
+    let width = read_number(w!("System.Video.FrameWidth")).map(|width| width as u32);
+
+    // Audio files have the embedded album art as the thumbnail
+    let album_art = if width.is_none() {
+        unsafe {
+            get_video_thumbnail(
+                file_path.as_ref(),
+                Some(Size {
+                    width: ALBUM_ART_SIZE,
+                    height: ALBUM_ART_SIZE,
+                }),
+            )
+        }
+        .ok()
+    } else {
+        None
+    };
+
+    Ok(MediaInfo {
+        // In 100ns
+        duration_ms: read_number(w!("System.Media.Duration")).map(|duration| duration / 10_000),
+        bitrate: read_number(w!("System.Video.TotalBitrate")).or_else(|| read_number(w!("System.Audio.EncodingBitrate"))),
+        video_codec: read(w!("System.Video.Compression")).map(|guid| to_video_codec(&guid)),
+        audio_codec: read(w!("System.Audio.Format")).map(|guid| to_audio_codec(&guid)),
+        width,
+        height: read_number(w!("System.Video.FrameHeight")).map(|height| height as u32),
+        // In frames per 1000 seconds
+        frame_rate: read_number(w!("System.Video.FrameRate")).map(|frame_rate| frame_rate as f64 / 1000.0),
+        sample_rate: read_number(w!("System.Audio.SampleRate")).map(|sample_rate| sample_rate as u32),
+        channels: read_number(w!("System.Audio.ChannelCount")).map(|channels| channels as u32),
+        title: read(w!("System.Title")),
+        artist: read(w!("System.Music.Artist")),
+        album: read(w!("System.Music.AlbumTitle")),
+        album_art,
+    })
+}
+
+/// Reads dimensions, EXIF orientation, camera and the date taken from the property store
+pub fn read_image_info<P: AsRef<Path>>(file_path: P) -> Result<ImageInfo, String> {
+    let _guard = ComGuard::new();
+
+    let store = open_property_store(file_path.as_ref())?;
+    let read = |name: PCWSTR| read_property(&store, name);
+    let read_number = |name: PCWSTR| read_property(&store, name).and_then(|value| value.parse::<u32>().ok()).filter(|value| *value > 0);
+
+    Ok(ImageInfo {
+        width: read_number(w!("System.Image.HorizontalSize")),
+        height: read_number(w!("System.Image.VerticalSize")),
+        orientation: read_orientation(&store),
+        camera_make: read(w!("System.Photo.CameraManufacturer")),
+        camera_model: read(w!("System.Photo.CameraModel")),
+        date_taken: read(w!("System.Photo.DateTaken")),
+    })
+}
+
+fn open_property_store(file_path: &Path) -> Result<IPropertyStore, String> {
+    let wide = encode_wide(file_path);
+    unsafe { SHGetPropertyStoreFromParsingName(PCWSTR::from_raw(wide.as_ptr()), None, GPS_DEFAULT) }.map_err(|e| e.message())
+}
+
+/* System.Photo.Orientation uses the EXIF values */
+fn read_orientation(store: &IPropertyStore) -> u16 {
+    read_property(store, w!("System.Photo.Orientation")).and_then(|value| value.parse::<u16>().ok()).filter(|value| (1..=8).contains(value)).unwrap_or(1)
+}
+
+/* Rotates the shell thumbnail of a photo to the EXIF orientation unless the aspect ratio shows it is already rotated */
+fn apply_orientation(file_path: &Path, mut image: DynamicImage) -> DynamicImage {
+    let Ok(store) = open_property_store(file_path) else {
+        return image;
+    };
+
+    let orientation = read_orientation(&store);
+    if orientation >= 5 {
+        let read_number = |name: PCWSTR| read_property(&store, name).and_then(|value| value.parse::<u32>().ok());
+        if let (Some(width), Some(height)) = (read_number(w!("System.Image.HorizontalSize")), read_number(w!("System.Image.VerticalSize"))) {
+            if width != height && (width > height) != (image.width() > image.height()) {
+                return image;
+            }
+        }
+    }
+
+    if let Some(orientation) = Orientation::from_exif(orientation as u8) {
+        image.apply_orientation(orientation);
+    }
+
+    image
+}
+
+fn read_property(store: &IPropertyStore, name: PCWSTR) -> Option<String> {
+    let mut key = PROPERTYKEY::default();
+    unsafe { PSGetPropertyKeyFromName(name, &mut key) }.ok()?;
+    let value = unsafe { store.GetValue(&key) }.ok()?.to_string();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/* Media subtype GUIDs start with the FOURCC or the WAVE_FORMAT tag */
+fn to_format_tag(guid: &str) -> Option<u32> {
+    u32::from_str_radix(guid.trim_start_matches('{').get(..8)?, 16).ok()
+}
+
+fn to_fourcc(tag: u32) -> String {
+    String::from_utf8_lossy(&tag.to_le_bytes()).trim().to_string()
+}
+
+fn to_video_codec(guid: &str) -> String {
+    match to_format_tag(guid) {
+        Some(tag) if tag > 0xFFFF => to_fourcc(tag),
+        _ => guid.to_string(),
+    }
+}
+
+fn to_audio_codec(guid: &str) -> String {
+    match to_format_tag(guid) {
+        Some(0x0001) => "PCM".to_string(),
+        Some(0x0003) => "PCM Float".to_string(),
+        Some(0x0055) => "MP3".to_string(),
+        Some(0x00FF) | Some(0x1610) => "AAC".to_string(),
+        Some(0x0160..=0x0163) => "WMA".to_string(),
+        Some(0x2000) => "AC3".to_string(),
+        Some(0x704F) => "Opus".to_string(),
+        Some(0xF1AC) => "FLAC".to_string(),
+        Some(tag) if tag > 0xFFFF => to_fourcc(tag),
+        _ => guid.to_string(),
+    }
+}
+
 unsafe fn get_video_thumbnail<P: AsRef<Path>>(path: P, size: Option<Size>) -> windows::core::Result<Vec<u8>> {
+    #[cfg(feature = "raw")]
+    if crate::raw::is_raw(path.as_ref()) {
+        if let Some(thumbnail) = get_raw_thumbnail(path.as_ref(), size.as_ref()) {
+            return Ok(thumbnail);
+        }
+    }
+
     let wide = encode_wide(path.as_ref());
     let factory: IShellItemImageFactory = SHCreateItemFromParsingName(PCWSTR(wide.as_ptr()), None)?;
 
@@ -67,14 +464,76 @@ unsafe fn get_video_thumbnail<P: AsRef<Path>>(path: P, size: Option<Size>) -> wi
 
     let _ = DeleteObject(hbitmap.into());
 
-    let bytes = into_buffer(&buffer, width as _, height as _, stride as _, bites_per_pixel);
+    // Shell thumbnails keep the pixel values of the source, so they are converted with its profile. Images without one are left as they are
+    if crate::color::is_enabled() {
+        let _ = convert_to_srgb(path.as_ref(), &mut buffer, width as _, height as _, stride as _, bites_per_pixel);
+    }
+
+    let image = apply_orientation(path.as_ref(), DynamicImage::ImageRgb8(into_image(&buffer, width as _, height as _, stride as _, bites_per_pixel)));
+
+    let mut bytes: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg).unwrap();
 
     Ok(bytes)
 }
 
-fn into_buffer(data: &[u8], width: u32, height: u32, stride: usize, bits_per_pixel: u16) -> Vec<u8> {
+/* Scales down the JPEG preview embedded in a RAW photo, which is faster than a RAW codec and works without one */
+#[cfg(feature = "raw")]
+fn get_raw_thumbnail(path: &Path, size: Option<&Size>) -> Option<Vec<u8>> {
+    let preview = crate::raw::read_preview(path)?;
+    let mut image = image::load_from_memory_with_format(&preview.jpeg, ImageFormat::Jpeg).ok()?;
+
+    if let Some(size) = size {
+        if image.width() > size.width || image.height() > size.height {
+            image = image.thumbnail(size.width, size.height);
+        }
+    }
+    if let Some(orientation) = Orientation::from_exif(preview.orientation as u8) {
+        image.apply_orientation(orientation);
+    }
+
     let mut bytes: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg).ok()?;
+    Some(bytes)
+}
+
+/* Converts BGR pixels with the color context of the source image to sRGB in place */
+fn convert_to_srgb(file_path: &Path, pixels: &mut [u8], width: u32, height: u32, stride: u32, bits_per_pixel: u16) -> windows::core::Result<()> {
+    let factory: IWICImagingFactory = unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER) }?;
+    let wide = encode_wide(file_path);
+    let decoder = unsafe { factory.CreateDecoderFromFilename(PCWSTR::from_raw(wide.as_ptr()), None, GENERIC_READ, WICDecodeMetadataCacheOnDemand) }?;
+    let frame = unsafe { decoder.GetFrame(0) }?;
 
+    // Called without contexts to get the count
+    let mut count = 0;
+    unsafe { frame.GetColorContexts(&mut [], &mut count) }?;
+    let mut contexts: Vec<Option<IWICColorContext>> = (0..count).map(|_| unsafe { factory.CreateColorContext() }.ok()).collect();
+    unsafe { frame.GetColorContexts(&mut contexts, &mut count) }?;
+
+    // An EXIF color space other than sRGB is Adobe RGB, which WIC knows without a profile
+    let Some(source) = contexts.into_iter().flatten().find(|context| match unsafe { context.GetType() } {
+        Ok(context_type) if context_type == WICColorContextProfile => true,
+        Ok(context_type) if context_type == WICColorContextExifColorSpace => unsafe { context.GetExifColorSpace() }.is_ok_and(|color_space| color_space != EXIF_COLOR_SPACE_SRGB),
+        _ => false,
+    }) else {
+        return Ok(());
+    };
+
+    let srgb = unsafe { factory.CreateColorContext() }?;
+    unsafe { srgb.InitializeFromExifColorSpace(EXIF_COLOR_SPACE_SRGB) }?;
+
+    let format = if bits_per_pixel == 32 {
+        GUID_WICPixelFormat32bppBGR
+    } else {
+        GUID_WICPixelFormat24bppBGR
+    };
+    let bitmap = unsafe { factory.CreateBitmapFromMemory(width, height, &format, stride, pixels) }?;
+    let transform = unsafe { factory.CreateColorTransformer() }?;
+    unsafe { transform.Initialize(&bitmap, &source, &srgb, &format) }?;
+    unsafe { transform.CopyPixels(std::ptr::null(), stride, pixels) }
+}
+
+fn into_image(data: &[u8], width: u32, height: u32, stride: usize, bits_per_pixel: u16) -> RgbImage {
     let bytes_per_pixel = match bits_per_pixel {
         32 => 4,
         24 => 3,
@@ -88,7 +547,5 @@ fn into_buffer(data: &[u8], width: u32, height: u32, stride: usize, bits_per_pix
         *pixel = image::Rgb([data[offset + 2], data[offset + 1], data[offset]]);
     }
 
-    buffer.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg).unwrap();
-
-    bytes
+    buffer
 }
@@ -0,0 +1,91 @@
+use super::{util::encode_wide, window::place};
+use crate::dialog::{DialogPlacement, MessageDialogKind, MessageDialogOptions};
+use windows::{
+    core::{BOOL, HRESULT, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, S_OK, WPARAM},
+        UI::Controls::{
+            TaskDialogIndirect, TASKDIALOGCONFIG, TASKDIALOGCONFIG_0, TASKDIALOG_BUTTON, TASKDIALOG_NOTIFICATIONS, TDF_ALLOW_DIALOG_CANCELLATION, TDF_POSITION_RELATIVE_TO_WINDOW,
+            TDF_VERIFICATION_FLAG_CHECKED, TDN_CREATED, TD_ERROR_ICON, TD_INFORMATION_ICON, TD_WARNING_ICON,
+        },
+    },
+};
+
+// IDOK, IDCANCEL and so on are below 100
+const BUTTON_ID_OFFSET: i32 = 100;
+
+/// Shows a task dialog and returns the index of the pressed button, or None if closed with Esc, with the state of the verification checkbox
+///
+/// Labels may contain "&" before the access key. The dialog runs on a blocking thread
+pub(crate) async fn show(options: &MessageDialogOptions, buttons: &[String], default_id: Option<usize>) -> (Option<usize>, bool) {
+    let options = options.clone();
+    let buttons = buttons.to_vec();
+    smol::unblock(move || show_task_dialog(&options, &buttons, default_id)).await
+}
+
+fn show_task_dialog(options: &MessageDialogOptions, buttons: &[String], default_id: Option<usize>) -> (Option<usize>, bool) {
+    let title = encode_wide(options.title.as_deref().unwrap_or_default());
+    let message = encode_wide(&options.message);
+    let checkbox_label = options.checkbox_label.as_deref().map(encode_wide);
+    let detail = options.detail.as_deref().map(encode_wide);
+    let labels: Vec<Vec<u16>> = buttons.iter().map(encode_wide).collect();
+    let task_buttons: Vec<TASKDIALOG_BUTTON> = labels
+        .iter()
+        .enumerate()
+        .map(|(index, label)| TASKDIALOG_BUTTON {
+            nButtonID: BUTTON_ID_OFFSET + index as i32,
+            pszButtonText: PCWSTR::from_raw(label.as_ptr()),
+        })
+        .collect();
+
+    let icon = match options.kind {
+        Some(MessageDialogKind::Warning) => TD_WARNING_ICON,
+        Some(MessageDialogKind::Error) => TD_ERROR_ICON,
+        _ => TD_INFORMATION_ICON,
+    };
+
+    let placement = options.placement.clone().unwrap_or_default();
+
+    // Esc and the close button cancel the dialog
+    let mut flags = TDF_ALLOW_DIALOG_CANCELLATION | TDF_POSITION_RELATIVE_TO_WINDOW;
+    if options.checkbox_checked {
+        flags |= TDF_VERIFICATION_FLAG_CHECKED;
+    }
+
+    let config = TASKDIALOGCONFIG {
+        cbSize: size_of::<TASKDIALOGCONFIG>() as _,
+        hwndParent: HWND(placement.parent.unwrap_or_default() as _),
+        dwFlags: flags,
+        pszWindowTitle: PCWSTR::from_raw(title.as_ptr()),
+        Anonymous1: TASKDIALOGCONFIG_0 {
+            pszMainIcon: icon,
+        },
+        pszContent: PCWSTR::from_raw(message.as_ptr()),
+        cButtons: task_buttons.len() as _,
+        pButtons: task_buttons.as_ptr(),
+        nDefaultButton: default_id.map(|default_id| BUTTON_ID_OFFSET + default_id as i32).unwrap_or_default(),
+        // Null pointers leave out the checkbox and the expander
+        pszVerificationText: checkbox_label.as_ref().map(|label| PCWSTR::from_raw(label.as_ptr())).unwrap_or_default(),
+        pszExpandedInformation: detail.as_ref().map(|detail| PCWSTR::from_raw(detail.as_ptr())).unwrap_or_default(),
+        pfCallback: Some(callback),
+        lpCallbackData: &placement as *const _ as _,
+        ..Default::default()
+    };
+
+    let mut pressed = 0;
+    let mut checked = BOOL::default();
+    if unsafe { TaskDialogIndirect(&config, Some(&mut pressed), None, Some(&mut checked)) }.is_err() {
+        return (None, false);
+    }
+
+    let index = pressed - BUTTON_ID_OFFSET;
+    ((0..buttons.len() as i32).contains(&index).then_some(index as usize), checked.as_bool())
+}
+
+unsafe extern "system" fn callback(hwnd: HWND, msg: TASKDIALOG_NOTIFICATIONS, _wparam: WPARAM, _lparam: LPARAM, data: isize) -> HRESULT {
+    if msg == TDN_CREATED {
+        let placement = &*(data as *const DialogPlacement);
+        place(hwnd.0 as isize, placement);
+    }
+    S_OK
+}
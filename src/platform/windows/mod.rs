@@ -1,9 +1,21 @@
 pub mod clipboard;
+#[cfg(feature = "device")]
 pub mod device;
+pub(crate) mod diagnostics;
+#[cfg(feature = "drag_drop")]
 pub mod drag_drop;
 pub mod fs;
+mod fs_ext;
+#[cfg(feature = "instant_search")]
+pub(crate) mod locate;
+#[cfg(feature = "media")]
 pub mod media;
+pub(crate) mod message_box;
+pub(crate) mod progress_dialog;
 pub mod shell;
+#[cfg(feature = "thumbbar")]
+mod thumbbar;
 mod util;
 #[cfg(feature = "webview2")]
 pub mod webview2;
+pub(crate) mod window;
@@ -0,0 +1,153 @@
+use super::{util::encode_wide, window::place};
+use crate::dialog::{DialogPlacement, ProgressDialogOptions, ProgressHandle};
+use windows::{
+    core::{HRESULT, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, S_FALSE, S_OK, WPARAM},
+        UI::{
+            Controls::{
+                TaskDialogIndirect, TASKDIALOGCONFIG, TASKDIALOG_BUTTON, TASKDIALOG_NOTIFICATIONS, TDCBF_CANCEL_BUTTON, TDE_CONTENT, TDE_MAIN_INSTRUCTION, TDF_ALLOW_DIALOG_CANCELLATION,
+                TDF_CALLBACK_TIMER, TDF_POSITION_RELATIVE_TO_WINDOW, TDF_SHOW_PROGRESS_BAR, TDM_ENABLE_BUTTON, TDM_SET_ELEMENT_TEXT, TDM_SET_PROGRESS_BAR_POS, TDM_SET_PROGRESS_BAR_RANGE,
+                TDM_SET_PROGRESS_BAR_STATE, TDN_BUTTON_CLICKED, TDN_CREATED, TDN_TIMER,
+            },
+            WindowsAndMessaging::{EndDialog, SendMessageW, IDCANCEL},
+        },
+    },
+};
+
+// IDOK, IDCANCEL and so on are below 100
+const PAUSE_BUTTON_ID: i32 = 100;
+const RESUME_BUTTON_ID: i32 = 101;
+// Steps of the progress bar, finer than the default 0 to 100
+const PROGRESS_RANGE: usize = 1000;
+const PBST_NORMAL: usize = 1;
+const PBST_PAUSED: usize = 3;
+
+/* Owned by the dialog thread and passed to the callback */
+struct Context {
+    handle: ProgressHandle,
+    placement: DialogPlacement,
+    revision: u64,
+}
+
+/// Shows a task dialog with a progress bar that follows the handle until it is closed or cancelled
+///
+/// The dialog runs its own message loop on a separate thread, so the handle can be updated from any thread
+pub(crate) fn show(options: &ProgressDialogOptions, handle: ProgressHandle) {
+    let options = options.clone();
+    std::thread::spawn(move || {
+        let _ = show_task_dialog(&options, handle.clone());
+        handle.close();
+    });
+}
+
+fn show_task_dialog(options: &ProgressDialogOptions, handle: ProgressHandle) -> Result<(), String> {
+    let title = encode_wide(options.title.as_deref().unwrap_or_default());
+    // The elements must exist when the dialog is created to be updated later
+    let message = encode_wide(" ");
+    let detail = encode_wide(" ");
+    let pause = encode_wide("&Pause");
+    let resume = encode_wide("&Resume");
+    let buttons = [
+        TASKDIALOG_BUTTON {
+            nButtonID: PAUSE_BUTTON_ID,
+            pszButtonText: PCWSTR::from_raw(pause.as_ptr()),
+        },
+        TASKDIALOG_BUTTON {
+            nButtonID: RESUME_BUTTON_ID,
+            pszButtonText: PCWSTR::from_raw(resume.as_ptr()),
+        },
+    ];
+
+    let mut context = Context {
+        handle,
+        placement: options.placement.clone().unwrap_or_default(),
+        revision: u64::MAX,
+    };
+
+    let config = TASKDIALOGCONFIG {
+        cbSize: size_of::<TASKDIALOGCONFIG>() as _,
+        hwndParent: HWND(context.placement.parent.unwrap_or_default() as _),
+        // Esc and the close button cancel the task
+        dwFlags: TDF_SHOW_PROGRESS_BAR | TDF_CALLBACK_TIMER | TDF_ALLOW_DIALOG_CANCELLATION | TDF_POSITION_RELATIVE_TO_WINDOW,
+        dwCommonButtons: TDCBF_CANCEL_BUTTON,
+        pszWindowTitle: PCWSTR::from_raw(title.as_ptr()),
+        pszMainInstruction: PCWSTR::from_raw(message.as_ptr()),
+        pszContent: PCWSTR::from_raw(detail.as_ptr()),
+        cButtons: if options.pausable {
+            buttons.len() as _
+        } else {
+            0
+        },
+        pButtons: buttons.as_ptr(),
+        pfCallback: Some(callback),
+        lpCallbackData: &mut context as *mut _ as _,
+        ..Default::default()
+    };
+
+    unsafe { TaskDialogIndirect(&config, None, None, None) }.map_err(|e| e.message())
+}
+
+unsafe extern "system" fn callback(hwnd: HWND, msg: TASKDIALOG_NOTIFICATIONS, wparam: WPARAM, _lparam: LPARAM, data: isize) -> HRESULT {
+    let context = &mut *(data as *mut Context);
+
+    match msg {
+        TDN_CREATED => {
+            place(hwnd.0 as isize, &context.placement);
+            send(hwnd, TDM_SET_PROGRESS_BAR_RANGE.0, 0, (PROGRESS_RANGE << 16) as isize);
+            send(hwnd, TDM_ENABLE_BUTTON.0, RESUME_BUTTON_ID as usize, 0);
+            update(hwnd, context);
+            S_OK
+        }
+        TDN_TIMER => {
+            update(hwnd, context);
+            S_OK
+        }
+        TDN_BUTTON_CLICKED if wparam.0 as i32 == PAUSE_BUTTON_ID || wparam.0 as i32 == RESUME_BUTTON_ID => {
+            let paused = context.handle.toggle_pause();
+            send(hwnd, TDM_ENABLE_BUTTON.0, PAUSE_BUTTON_ID as usize, (!paused) as isize);
+            send(hwnd, TDM_ENABLE_BUTTON.0, RESUME_BUTTON_ID as usize, paused as isize);
+            send(
+                hwnd,
+                TDM_SET_PROGRESS_BAR_STATE.0,
+                if paused {
+                    PBST_PAUSED
+                } else {
+                    PBST_NORMAL
+                },
+                0,
+            );
+            // Keeps the dialog open
+            S_FALSE
+        }
+        TDN_BUTTON_CLICKED if wparam.0 as i32 == IDCANCEL.0 => {
+            context.handle.cancel();
+            S_OK
+        }
+        _ => S_OK,
+    }
+}
+
+/* Picks up changes of the handle, closing the dialog once the handle is closed */
+fn update(hwnd: HWND, context: &mut Context) {
+    let state = context.handle.state();
+    if state.closed {
+        let _ = unsafe { EndDialog(hwnd, IDCANCEL.0 as isize) };
+        return;
+    }
+
+    if context.revision == state.revision {
+        return;
+    }
+    context.revision = state.revision;
+
+    let message = encode_wide(&state.message);
+    let detail = encode_wide(&state.detail);
+    send(hwnd, TDM_SET_PROGRESS_BAR_POS.0, (state.fraction * PROGRESS_RANGE as f64) as usize, 0);
+    send(hwnd, TDM_SET_ELEMENT_TEXT.0, TDE_MAIN_INSTRUCTION.0 as usize, message.as_ptr() as isize);
+    send(hwnd, TDM_SET_ELEMENT_TEXT.0, TDE_CONTENT.0 as usize, detail.as_ptr() as isize);
+}
+
+fn send(hwnd: HWND, msg: i32, wparam: usize, lparam: isize) {
+    unsafe { SendMessageW(hwnd, msg as u32, Some(WPARAM(wparam)), Some(LPARAM(lparam))) };
+}
@@ -1,42 +1,113 @@
 use super::util::{decode_wide, encode_wide, ComGuard};
-use crate::{AppInfo, Icon, Size, ThumbButton};
+#[cfg(feature = "thumbbar")]
+pub use super::thumbbar::set_thumbar_buttons;
+use crate::{
+    AppInfo, Breadcrumb, BreadcrumbKind, DefaultAction, ExecuteOptions, ExecuteResult, FolderViewHints, Icon, JumpListCategory, JumpListItem, PropertyValue, ShellCapabilities, ShellColumn, ShellVerb,
+    ShowState, Size,
+};
 use std::{
     collections::HashMap,
-    path::{Path, PathBuf},
-    sync::OnceLock,
+    ffi::OsString,
+    path::Path,
+    sync::{LazyLock, Mutex},
 };
 use windows::{
-    core::{Interface, HSTRING, PCWSTR, PWSTR},
+    core::{Interface, GUID, HSTRING, PCSTR, PCWSTR, PROPVARIANT, PSTR, PWSTR},
+    ApplicationModel::DataTransfer::{DataRequestedEventArgs, DataTransferManager},
+    Foundation::{Collections::IIterable, TypedEventHandler},
     Management::Deployment::PackageManager,
+    Storage::{IStorageItem, StorageFile, StorageFolder},
     Win32::{
-        Foundation::{GENERIC_READ, HWND, LPARAM, LRESULT, MAX_PATH, PROPERTYKEY, SIZE, WPARAM},
+        Foundation::{CloseHandle, ERROR_CANCELLED, ERROR_SUCCESS, HANDLE, HWND, LPARAM, LRESULT, MAX_PATH, POINT, PROPERTYKEY, SIZE, WPARAM},
         Globalization::{GetLocaleInfoEx, LOCALE_SNAME},
         Graphics::{
-            Gdi::{CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, BITMAPINFO, BITMAPINFOHEADER, DIB_RGB_COLORS, HPALETTE},
+            Gdi::{ClientToScreen, DeleteObject, HPALETTE},
             Imaging::{
-                CLSID_WICImagingFactory, GUID_ContainerFormatPng, GUID_WICPixelFormat32bppPBGRA, GUID_WICPixelFormat32bppRGBA, IWICBitmapFrameEncode, IWICImagingFactory, WICBitmapDitherTypeNone,
-                WICBitmapEncoderNoCache, WICBitmapPaletteTypeCustom, WICBitmapUseAlpha, WICDecodeMetadataCacheOnDemand,
+                CLSID_WICImagingFactory, GUID_ContainerFormatPng, GUID_WICPixelFormat32bppRGBA, IWICBitmapFrameEncode, IWICImagingFactory, WICBitmapDitherTypeNone, WICBitmapEncoderNoCache,
+                WICBitmapPaletteTypeCustom, WICBitmapUseAlpha,
+            },
+        },
+        Storage::FileSystem::{SetFileAttributesW, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_SYSTEM, FILE_FLAGS_AND_ATTRIBUTES},
+        System::{
+            Com::{
+                CoCreateInstance, CoTaskMemFree,
+                StructuredStorage::{IPropertyBag2, InitPropVariantFromStringAsVector},
+                CLSCTX_INPROC_SERVER, STATFLAG_NONAME, STATSTG, STREAM_SEEK_SET,
             },
+            Registry::{RegGetValueW, HKEY_CLASSES_ROOT, RRF_RT_REG_SZ},
+            SystemServices::{SFGAO_CANCOPY, SFGAO_CANDELETE, SFGAO_CANLINK, SFGAO_CANMOVE, SFGAO_CANRENAME, SFGAO_FOLDER, SFGAO_HASPROPSHEET, SFGAO_REMOVABLE},
+            Threading::{CreateProcessW, GetExitCodeProcess, GetProcessId, WaitForSingleObject, CREATE_UNICODE_ENVIRONMENT, INFINITE, PROCESS_INFORMATION, STARTF_USESHOWWINDOW, STARTUPINFOW},
+            Variant::{VT_BOOL, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_LPWSTR, VT_R4, VT_R8, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UINT, VT_VECTOR},
+            WindowsProgramming::{GetPrivateProfileStringW, WritePrivateProfileStringW},
         },
-        System::Com::{CoCreateInstance, CoTaskMemFree, StructuredStorage::IPropertyBag2, CLSCTX_INPROC_SERVER, STATFLAG_NONAME, STATSTG, STREAM_SEEK_SET},
-        UI::{
-            Shell::{
-                DefSubclassProc, IShellItem, IShellItemImageFactory, ITaskbarList3,
-                PropertiesSystem::{IPropertyStore, PSGetNameFromPropertyKey, SHGetPropertyStoreFromParsingName, GPS_DEFAULT},
-                RemoveWindowSubclass, SHAssocEnumHandlers, SHCreateItemFromParsingName, SHLoadIndirectString, SHOpenFolderAndSelectItems, SHParseDisplayName, SetWindowSubclass, ShellExecuteExW,
-                TaskbarList, ASSOC_FILTER_RECOMMENDED, SEE_MASK_INVOKEIDLIST, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW, SIIGBF_ICONONLY, THBF_ENABLED, THBF_HIDDEN, THBN_CLICKED, THB_FLAGS,
-                THB_ICON, THB_TOOLTIP, THUMBBUTTON,
+        UI::Shell::{
+            AssocIsDangerous, AssocQueryStringW,
+            Common::{IObjectArray, IObjectCollection, ITEMIDLIST},
+            DefSubclassProc, DestinationList, EnumerableObjectCollection, FOLDERID_Recent, IContextMenu, IContextMenu2, IContextMenu3, ICustomDestinationList, IDataTransferManagerInterop,
+            ILFindLastID, IShellFolder, IShellItem, IShellItemImageFactory, IShellLinkW, PathMakeSystemFolderW,
+            PropertiesSystem::{
+                IPropertyDescription, IPropertyStore, PSCoerceToCanonicalValue, PSFormatForDisplayAlloc, PSGetNameFromPropertyKey, PSGetPropertyDescription, PSGetPropertyKeyFromName,
+                SHGetPropertyStoreFromParsingName, GPS_DEFAULT, GPS_READWRITE, PDFF_DEFAULT,
             },
-            WindowsAndMessaging::{CreateIconIndirect, HICON, ICONINFO, WM_COMMAND, WM_DESTROY},
+            RemoveWindowSubclass, SHAddToRecentDocs, SHAssocEnumHandlers, SHBindToParent, SHChangeNotify, SHCreateItemFromParsingName, SHGetFileInfoW, SHGetIDListFromObject, SHGetKnownFolderPath,
+            SHLoadIndirectString, SHOpenFolderAndSelectItems, SHOpenWithDialog, SHParseDisplayName, SetWindowSubclass, ShellExecuteExW, ShellLink, ASSOCF_NONE, ASSOCSTR, ASSOCSTR_EXECUTABLE,
+            ASSOCSTR_FRIENDLYAPPNAME, ASSOC_FILTER_NONE, ASSOC_FILTER_RECOMMENDED, CMINVOKECOMMANDINFO, CMINVOKECOMMANDINFOEX, KF_FLAG_DEFAULT, OAIF_ALLOW_REGISTRATION, OAIF_FORCE_REGISTRATION,
+            OAIF_REGISTER_EXT, OPENASINFO, SEE_MASK_INVOKEIDLIST, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHCNE_UPDATEDIR, SHCNF_PATHW, SHELLEXECUTEINFOW, SHFILEINFOW, SHGFI_ICONLOCATION,
+            SHGFI_PIDL, SIGDN_NORMALDISPLAY, SIIGBF_ICONONLY,
+        },
+        UI::WindowsAndMessaging::{
+            CreatePopupMenu, DestroyMenu, GetMenuItemCount, GetMenuItemID, GetMenuStringW, TrackPopupMenuEx, HMENU, MF_BYCOMMAND, WM_DRAWITEM, WM_INITMENUPOPUP, WM_MEASUREITEM, WM_MENUCHAR,
         },
     },
 };
 
-static BUTTONS_ADDED: OnceLock<bool> = OnceLock::new();
+/* DataRequested handlers by window, replaced each time the window shares */
+static SHARE_HANDLERS: LazyLock<Mutex<HashMap<isize, i64>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+const SW_HIDE: i32 = 0;
 const SW_SHOWNORMAL: i32 = 1;
+const SW_SHOWMINIMIZED: i32 = 2;
+const SW_SHOWMAXIMIZED: i32 = 3;
+const SHARD_PATHW: u32 = 3;
+const PKEY_TITLE: PROPERTYKEY = PROPERTYKEY {
+    fmtid: GUID::from_u128(0xf29f85e0_4ff9_1068_ab91_08002b27b3d9),
+    pid: 2,
+};
+const CMF_NORMAL: u32 = 0;
+const GCS_VERBW: u32 = 4;
+const CMIC_MASK_UNICODE: u32 = 0x4000;
+const CMIC_MASK_PTINVOKE: u32 = 0x20000000;
+const TPM_RIGHTBUTTON: u32 = 0x0002;
+const TPM_RETURNCMD: u32 = 0x0100;
+const FIRST_COMMAND_ID: u32 = 1;
+const LAST_COMMAND_ID: u32 = 0x7FFF;
+const CONTEXT_MENU_SUBCLASS_ID: usize = 201;
+const THIS_PC_FOLDER: &str = "::{20D04FE0-3AEA-1069-A2D8-08002B30309D}";
+const NETWORK_FOLDER: &str = "::{F02C1A0D-BE21-4350-88B0-7367FC96EF3C}";
+const DESKTOP_INI: &str = "desktop.ini";
+const SHELL_CLASS_INFO: &str = ".ShellClassInfo";
+const VIEW_STATE: &str = "ViewState";
+/* Column key, display name and canonical property name */
+const COLUMNS: [(&str, &str, &str); 13] = [
+    ("size", "Size", "System.Size"),
+    ("modified", "Date modified", "System.DateModified"),
+    ("type", "Type", "System.ItemTypeText"),
+    ("dimensions", "Dimensions", "System.Image.Dimensions"),
+    ("duration", "Length", "System.Media.Duration"),
+    ("rating", "Rating", "System.Rating"),
+    ("author", "Authors", "System.Author"),
+    ("title", "Title", "System.Title"),
+    ("artist", "Contributing artists", "System.Music.Artist"),
+    ("album", "Album", "System.Music.AlbumTitle"),
+    ("bitrate", "Bit rate", "System.Audio.EncodingBitrate"),
+    ("date_taken", "Date taken", "System.Photo.DateTaken"),
+    ("camera_model", "Camera model", "System.Photo.CameraModel"),
+];
 
 /// Opens the file with the default/associated application
 pub fn open_path<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
+    crate::policy::check_execute(file_path.as_ref())?;
+
     let _guard = ComGuard::new();
 
     let wide_verb = encode_wide("open");
@@ -55,10 +126,12 @@ pub fn open_path<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
 
 /// Opens the file with the specified application
 pub fn open_path_with<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2) -> Result<(), String> {
+    crate::policy::check_execute(app_path.as_ref())?;
+
     let _guard = ComGuard::new();
 
     let app_path = encode_wide(app_path.as_ref());
-    let file_path = encode_wide(file_path.as_ref());
+    let file_path = encode_wide(quote_arg(&file_path.as_ref().to_string_lossy()));
     let mut info = SHELLEXECUTEINFOW {
         cbSize: size_of::<SHELLEXECUTEINFOW>() as u32,
         hwnd: HWND::default(),
@@ -72,10 +145,12 @@ pub fn open_path_with<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path:
 }
 
 pub fn execute<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2) -> Result<(), String> {
+    crate::policy::check_execute(app_path.as_ref())?;
+
     let _guard = ComGuard::new();
 
     let app_path = encode_wide(app_path.as_ref());
-    let file_path = encode_wide(file_path.as_ref());
+    let file_path = encode_wide(quote_arg(&file_path.as_ref().to_string_lossy()));
     let mut info = SHELLEXECUTEINFOW {
         cbSize: size_of::<SHELLEXECUTEINFOW>() as u32,
         hwnd: HWND::default(),
@@ -88,11 +163,13 @@ pub fn execute<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2) ->
 }
 
 pub fn execute_as<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2) -> Result<(), String> {
+    crate::policy::check_execute(app_path.as_ref())?;
+
     let _guard = ComGuard::new();
 
     let wide_verb = encode_wide("runas");
     let app_path = encode_wide(app_path.as_ref());
-    let file_path = encode_wide(file_path.as_ref());
+    let file_path = encode_wide(quote_arg(&file_path.as_ref().to_string_lossy()));
     let mut info = SHELLEXECUTEINFOW {
         cbSize: size_of::<SHELLEXECUTEINFOW>() as u32,
         hwnd: HWND::default(),
@@ -105,6 +182,154 @@ pub fn execute_as<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2)
     unsafe { ShellExecuteExW(&mut info).map_err(|e| e.message()) }
 }
 
+/// Starts the application with the file, passing the arguments, working directory, environment and window state in the options
+///
+/// Uses CreateProcess instead of ShellExecuteEx when environment variables are set, so the application must then be an executable
+pub fn execute_with<P1: AsRef<Path>, P2: AsRef<Path>>(file_path: P1, app_path: P2, options: &ExecuteOptions) -> Result<ExecuteResult, String> {
+    crate::policy::check_execute(app_path.as_ref())?;
+
+    let mut args = options.args.clone();
+    args.push(file_path.as_ref().to_string_lossy().to_string());
+    let parameters = args.iter().map(|arg| quote_arg(arg)).collect::<Vec<_>>().join(" ");
+
+    let process = if options.env.is_empty() {
+        shell_execute(app_path.as_ref(), &parameters, options)?
+    } else {
+        create_process(app_path.as_ref(), &parameters, options)?
+    };
+
+    finish_process(process, options.wait)
+}
+
+fn shell_execute(app_path: &Path, parameters: &str, options: &ExecuteOptions) -> Result<HANDLE, String> {
+    let _guard = ComGuard::new();
+
+    let app_path = encode_wide(app_path);
+    let parameters = encode_wide(parameters);
+    let directory = options.cwd.as_ref().map(encode_wide);
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: size_of::<SHELLEXECUTEINFOW>() as u32,
+        hwnd: HWND::default(),
+        lpFile: PCWSTR::from_raw(app_path.as_ptr()),
+        lpParameters: PCWSTR::from_raw(parameters.as_ptr()),
+        lpDirectory: directory.as_ref().map_or(PCWSTR::null(), |directory| PCWSTR::from_raw(directory.as_ptr())),
+        fMask: SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC,
+        nShow: show_command(options.show),
+        ..Default::default()
+    };
+    unsafe { ShellExecuteExW(&mut info) }.map_err(|e| e.message())?;
+
+    Ok(info.hProcess)
+}
+
+fn create_process(app_path: &Path, parameters: &str, options: &ExecuteOptions) -> Result<HANDLE, String> {
+    // Inherited variables are replaced case-insensitively, and the block has to be sorted
+    let mut variables: Vec<(OsString, OsString)> = std::env::vars_os().filter(|(key, _)| !options.env.iter().any(|(name, _)| key.eq_ignore_ascii_case(name))).collect();
+    variables.extend(options.env.iter().map(|(name, value)| (OsString::from(name), OsString::from(value))));
+    variables.sort_by_key(|(key, _)| key.to_string_lossy().to_uppercase());
+
+    let mut environment: Vec<u16> = Vec::new();
+    for (key, value) in variables {
+        let mut variable = key;
+        variable.push("=");
+        variable.push(value);
+        environment.extend(encode_wide(variable));
+    }
+    environment.push(0);
+
+    let application = encode_wide(app_path);
+    // The command line starts with the application itself
+    let mut command_line = encode_wide(format!("{} {}", quote_arg(&app_path.to_string_lossy()), parameters));
+    let directory = options.cwd.as_ref().map(encode_wide);
+    let startup_info = STARTUPINFOW {
+        cb: size_of::<STARTUPINFOW>() as u32,
+        dwFlags: STARTF_USESHOWWINDOW,
+        wShowWindow: show_command(options.show) as u16,
+        ..Default::default()
+    };
+    let mut process_info = PROCESS_INFORMATION::default();
+
+    unsafe {
+        CreateProcessW(
+            PCWSTR::from_raw(application.as_ptr()),
+            Some(PWSTR::from_raw(command_line.as_mut_ptr())),
+            None,
+            None,
+            false,
+            CREATE_UNICODE_ENVIRONMENT,
+            Some(environment.as_ptr() as _),
+            directory.as_ref().map_or(PCWSTR::null(), |directory| PCWSTR::from_raw(directory.as_ptr())),
+            &startup_info,
+            &mut process_info,
+        )
+    }
+    .map_err(|e| e.message())?;
+    let _ = unsafe { CloseHandle(process_info.hThread) };
+
+    Ok(process_info.hProcess)
+}
+
+/* Waits for the process if asked and closes its handle, which is null when the file went to a running instance */
+fn finish_process(process: HANDLE, wait: bool) -> Result<ExecuteResult, String> {
+    if process.is_invalid() {
+        return Ok(ExecuteResult::default());
+    }
+
+    let pid = unsafe { GetProcessId(process) };
+    let mut exit_code = None;
+    if wait {
+        unsafe { WaitForSingleObject(process, INFINITE) };
+        let mut code = 0;
+        if unsafe { GetExitCodeProcess(process, &mut code) }.is_ok() {
+            exit_code = Some(code as i32);
+        }
+    }
+    let _ = unsafe { CloseHandle(process) };
+
+    Ok(ExecuteResult {
+        pid: (pid != 0).then_some(pid),
+        exit_code,
+    })
+}
+
+fn show_command(show: ShowState) -> i32 {
+    match show {
+        ShowState::Normal => SW_SHOWNORMAL,
+        ShowState::Hidden => SW_HIDE,
+        ShowState::Minimized => SW_SHOWMINIMIZED,
+        ShowState::Maximized => SW_SHOWMAXIMIZED,
+    }
+}
+
+/* Quotes the argument for CommandLineToArgvW when it has spaces or quotes, escaping quotes and the backslashes before them */
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\u{0B}', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let escapes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.push_str(&"\\".repeat(escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    // Backslashes before the closing quote would escape it
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+
+    quoted
+}
+
 /// Shows the application chooser dialog
 pub fn show_open_with_dialog<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
     let _guard = ComGuard::new();
@@ -122,6 +347,36 @@ pub fn show_open_with_dialog<P: AsRef<Path>>(file_path: P) -> Result<(), String>
     unsafe { ShellExecuteExW(&mut info).map_err(|e| e.message()) }
 }
 
+/// Shows the application chooser dialog without launching the chosen application
+///
+/// Windows does not tell which application was chosen, so `callback` receives the new default only when "Always use this app" is checked, otherwise None
+pub fn choose_open_with_app<P: AsRef<Path>, F: FnOnce(Option<AppInfo>) + 'static>(file_path: P, callback: F) -> Result<(), String> {
+    let _guard = ComGuard::new();
+
+    let extension = file_path.as_ref().extension().map(|extension| to_dot_extension(&extension.to_string_lossy())).ok_or_else(|| "File has no extension".to_string())?;
+    let current = get_default_app(&extension).map(|app| app.path);
+
+    let wide_path = encode_wide(file_path.as_ref());
+    let info = OPENASINFO {
+        pcszFile: PCWSTR::from_raw(wide_path.as_ptr()),
+        pcszClass: PCWSTR::null(),
+        oaifInFlags: OAIF_ALLOW_REGISTRATION | OAIF_REGISTER_EXT,
+    };
+
+    if let Err(e) = unsafe { SHOpenWithDialog(None, &info) } {
+        if e.code() == ERROR_CANCELLED.to_hresult() {
+            callback(None);
+            return Ok(());
+        }
+        return Err(e.message());
+    }
+
+    let chosen = get_default_app(&extension).filter(|app| current.as_ref().is_none_or(|path| !path.eq_ignore_ascii_case(&app.path)));
+    callback(chosen);
+
+    Ok(())
+}
+
 /// Lists the applications that can open the file
 pub fn get_open_with<P: AsRef<Path>>(file_path: P) -> Vec<AppInfo> {
     let mut apps = Vec::new();
@@ -199,6 +454,129 @@ pub fn get_open_with<P: AsRef<Path>>(file_path: P) -> Vec<AppInfo> {
     apps
 }
 
+/// Returns the application that opens files with the extension such as "txt" or ".txt"
+pub fn get_default_app(extension: &str) -> Option<AppInfo> {
+    let _guard = ComGuard::new();
+
+    let extension = to_dot_extension(extension);
+    let path = query_association(&extension, ASSOCSTR_EXECUTABLE)?;
+    let name = query_association(&extension, ASSOCSTR_FRIENDLYAPPNAME).unwrap_or_else(|| Path::new(&path).file_stem().unwrap_or_default().to_string_lossy().to_string());
+
+    Some(AppInfo {
+        icon_path: path.clone(),
+        path,
+        name,
+    })
+}
+
+/// Sets the application listed by `get_open_with` as the default for the extension
+///
+/// Windows 10 or later does not allow apps to change the default silently, so the "Open with" prompt is shown when it fails
+pub fn set_default_app<P: AsRef<Path>>(extension: &str, app_path: P) -> Result<(), String> {
+    let _guard = ComGuard::new();
+
+    let extension = to_dot_extension(extension);
+    let app_path = app_path.as_ref().to_string_lossy().to_string();
+    let wide_extension = encode_wide(&extension);
+
+    let enum_handlers = unsafe { SHAssocEnumHandlers(PCWSTR::from_raw(wide_extension.as_ptr()), ASSOC_FILTER_NONE) }.map_err(|e| e.message())?;
+    loop {
+        let mut handlers = [None; 1];
+        let result = unsafe { enum_handlers.Next(&mut handlers, None) };
+        if result.is_err() {
+            break;
+        }
+
+        let Some(handler) = handlers[0].take() else {
+            break;
+        };
+
+        let name = match unsafe { handler.GetName() } {
+            Ok(name_ptr) => decode_wide(unsafe { name_ptr.as_wide() }),
+            Err(_) => continue,
+        };
+        if !name.eq_ignore_ascii_case(&app_path) {
+            continue;
+        }
+
+        let description = unsafe { handler.GetUIName() }.map(|name_ptr| decode_wide(unsafe { name_ptr.as_wide() })).unwrap_or_default();
+        let wide_description = encode_wide(description);
+        if unsafe { handler.MakeDefault(PCWSTR::from_raw(wide_description.as_ptr())) }.is_ok() && get_default_app(&extension).is_some_and(|app| app.path.eq_ignore_ascii_case(&app_path)) {
+            return Ok(());
+        }
+
+        break;
+    }
+
+    // The file does not need to exist without OAIF_EXEC
+    let wide_file = encode_wide(format!("file{}", extension));
+    let info = OPENASINFO {
+        pcszFile: PCWSTR::from_raw(wide_file.as_ptr()),
+        pcszClass: PCWSTR::null(),
+        oaifInFlags: OAIF_ALLOW_REGISTRATION | OAIF_REGISTER_EXT | OAIF_FORCE_REGISTRATION,
+    };
+    unsafe { SHOpenWithDialog(None, &info) }.map_err(|e| e.message())
+}
+
+/// Makes the application chosen by `choose_open_with_app` the default for the extension such as "txt" or MIME type such as "text/plain"
+///
+/// Windows protects the default with a UserChoice hash that apps cannot compute, so the "Open with" prompt is shown when the default cannot be changed silently
+pub fn set_always_open_with(extension_or_mime: &str, app: &AppInfo) -> Result<(), String> {
+    let extension = if extension_or_mime.contains('/') {
+        get_extension_for_mime(extension_or_mime).ok_or_else(|| format!("No extension registered for {}", extension_or_mime))?
+    } else {
+        to_dot_extension(extension_or_mime)
+    };
+
+    set_default_app(&extension, &app.path)
+}
+
+/* HKCR\MIME\Database\Content Type\<mime> has the extension of the MIME type */
+fn get_extension_for_mime(mime: &str) -> Option<String> {
+    let wide_key = encode_wide(format!("MIME\\Database\\Content Type\\{}", mime));
+    let wide_name = encode_wide("Extension");
+    let (key, name) = (PCWSTR::from_raw(wide_key.as_ptr()), PCWSTR::from_raw(wide_name.as_ptr()));
+
+    let mut size = 0;
+    if unsafe { RegGetValueW(HKEY_CLASSES_ROOT, key, name, RRF_RT_REG_SZ, None, None, Some(&mut size)) } != ERROR_SUCCESS {
+        return None;
+    }
+
+    let mut buffer = vec![0u16; size as usize / 2];
+    if unsafe { RegGetValueW(HKEY_CLASSES_ROOT, key, name, RRF_RT_REG_SZ, None, Some(buffer.as_mut_ptr() as _), Some(&mut size)) } != ERROR_SUCCESS {
+        return None;
+    }
+
+    let extension = decode_wide(&buffer);
+    if extension.is_empty() {
+        None
+    } else {
+        Some(extension)
+    }
+}
+
+fn to_dot_extension(extension: &str) -> String {
+    format!(".{}", extension.trim_start_matches('.'))
+}
+
+fn query_association(extension: &str, assoc_str: ASSOCSTR) -> Option<String> {
+    let wide_extension = encode_wide(extension);
+    let wide_verb = encode_wide("open");
+
+    let mut len = 0;
+    unsafe { AssocQueryStringW(ASSOCF_NONE, assoc_str, PCWSTR::from_raw(wide_extension.as_ptr()), PCWSTR::from_raw(wide_verb.as_ptr()), None, &mut len) }.ok().ok()?;
+
+    let mut buffer = vec![0u16; len as _];
+    unsafe { AssocQueryStringW(ASSOCF_NONE, assoc_str, PCWSTR::from_raw(wide_extension.as_ptr()), PCWSTR::from_raw(wide_verb.as_ptr()), Some(PWSTR(buffer.as_mut_ptr())), &mut len) }.ok().ok()?;
+
+    let value = decode_wide(&buffer);
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
 fn extract_app_user_model_id(input: PWSTR) -> Option<String> {
     let input_string = decode_wide(unsafe { input.as_wide() });
     if let Some(start) = input_string.find('{') {
@@ -327,182 +705,632 @@ pub fn show_item_in_folder<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
     Ok(())
 }
 
-struct InnerThumbButtons {
-    callback: Box<dyn Fn(String)>,
-    id_map: HashMap<u32, String>,
+/// Shows the Share UI for the files, letting the user send them to another app or a nearby device
+pub fn share<P: AsRef<Path>>(file_paths: &[P], window_handle: isize) -> Result<(), String> {
+    if file_paths.is_empty() {
+        return Ok(());
+    }
+
+    let _guard = ComGuard::new();
+
+    let hwnd = HWND(window_handle as _);
+    let paths: Vec<String> = file_paths.iter().map(|path| path.as_ref().to_string_lossy().to_string()).collect();
+    let interop = windows::core::factory::<DataTransferManager, IDataTransferManagerInterop>().map_err(|e| e.message())?;
+    let manager: DataTransferManager = unsafe { interop.GetForWindow(hwnd) }.map_err(|e| e.message())?;
+
+    {
+        let mut handlers = SHARE_HANDLERS.lock().unwrap();
+        if let Some(token) = handlers.remove(&window_handle) {
+            let _ = manager.RemoveDataRequested(token);
+        }
+
+        let handler = TypedEventHandler::<DataTransferManager, DataRequestedEventArgs>::new(move |_, args| {
+            let data = args.ok()?.Request()?.Data()?;
+            data.Properties()?.SetTitle(&HSTRING::from(to_share_title(&paths)))?;
+            data.SetStorageItems(&IIterable::from(to_storage_items(&paths)?))
+        });
+        let token = manager.DataRequested(&handler).map_err(|e| e.message())?;
+        handlers.insert(window_handle, token);
+    }
+
+    unsafe { interop.ShowShareUIForWindow(hwnd) }.map_err(|e| e.message())
+}
+
+fn to_share_title(paths: &[String]) -> String {
+    match paths {
+        [path] => Path::new(path).file_name().unwrap_or_default().to_string_lossy().to_string(),
+        _ => format!("{} items", paths.len()),
+    }
 }
 
-/// Adds a thumbnail toolbar with specified buttons to a taskbar layout of an application window
-pub fn set_thumbar_buttons<F: Fn(String) + 'static>(window_handle: isize, buttons: &[ThumbButton], callback: F) -> Result<(), String> {
+fn to_storage_items(paths: &[String]) -> windows::core::Result<Vec<Option<IStorageItem>>> {
+    paths
+        .iter()
+        .map(|path| {
+            let item: IStorageItem = if Path::new(path).is_dir() {
+                StorageFolder::GetFolderFromPathAsync(&HSTRING::from(path))?.get()?.cast()?
+            } else {
+                StorageFile::GetFileFromPathAsync(&HSTRING::from(path))?.get()?.cast()?
+            };
+            Ok(Some(item))
+        })
+        .collect()
+}
+
+/// Shows the Explorer context menu of the files at the position in client coordinates
+///
+/// `callback` receives the verb of the invoked command such as "open", "copy" or "properties", or the menu text if the command has no verb
+pub fn show_context_menu<P: AsRef<Path>, F: FnOnce(String) + 'static>(window_handle: isize, file_paths: &[P], x: i32, y: i32, callback: F) -> Result<(), String> {
+    if file_paths.is_empty() {
+        return Err("No files specified".to_string());
+    }
+
+    let _guard = ComGuard::new();
+
     let hwnd = HWND(window_handle as _);
+    let paths: Vec<&Path> = file_paths.iter().map(|file_path| file_path.as_ref()).collect();
+    let result = with_context_menu(hwnd, file_paths, |context_menu| {
+        let menu = unsafe { CreatePopupMenu() }.map_err(|e| e.message())?;
+        let result = track_menu(hwnd, context_menu, menu, x, y, &paths);
+        let _ = unsafe { DestroyMenu(menu) };
+        result
+    });
+
+    if let Some(verb) = result? {
+        callback(verb);
+    }
+
+    Ok(())
+}
 
+/// Lists the verbs of the file's context menu such as "open", "edit", "print", "runas" and verbs added by shell extensions
+pub fn list_verbs<P: AsRef<Path>>(file_path: P) -> Result<Vec<ShellVerb>, String> {
     let _guard = ComGuard::new();
 
-    let mut thumb_buttons: Vec<THUMBBUTTON> = Vec::new();
-    let mut id_map = HashMap::new();
+    with_context_menu(HWND::default(), &[file_path], |context_menu| {
+        let menu = unsafe { CreatePopupMenu() }.map_err(|e| e.message())?;
+        let result = unsafe { context_menu.QueryContextMenu(menu, 0, FIRST_COMMAND_ID, LAST_COMMAND_ID, CMF_NORMAL) }.map_err(|e| e.message()).map(|_| {
+            let mut verbs = Vec::new();
+            for position in 0..unsafe { GetMenuItemCount(Some(menu)) }.max(0) as u32 {
+                // Separators and submenus have no command
+                let command = unsafe { GetMenuItemID(menu, position as _) };
+                if command < FIRST_COMMAND_ID || command > LAST_COMMAND_ID {
+                    continue;
+                }
 
-    for i in 0..7 {
-        // Set hidden buttons to the limit(7 buttons) so that new buttons can replace the existing buttons
-        if i >= buttons.len() {
-            thumb_buttons.push(THUMBBUTTON {
-                iId: i as _,
-                dwFlags: THBF_HIDDEN,
-                dwMask: THB_FLAGS,
-                ..Default::default()
-            });
-            continue;
-        }
+                if let Some(id) = get_verb(context_menu, command - FIRST_COMMAND_ID) {
+                    verbs.push(ShellVerb {
+                        id,
+                        name: get_menu_text(menu, command),
+                    });
+                }
+            }
+            verbs
+        });
+        let _ = unsafe { DestroyMenu(menu) };
+        result
+    })
+}
 
-        let button = buttons.get(i).unwrap();
-        id_map.insert(i as _, button.id.clone());
+/// Invokes the verb returned by `list_verbs`
+///
+/// Any verb may start a handler for the file, so the policy is checked for each of them
+pub fn invoke_verb<P: AsRef<Path>>(file_path: P, verb: &str) -> Result<(), String> {
+    crate::policy::check_execute(file_path.as_ref())?;
 
-        let hicon = create_hicon(&button.icon)?;
+    let _guard = ComGuard::new();
 
-        let mut thumb_button = THUMBBUTTON {
-            iId: i as _,
-            iBitmap: 0,
-            hIcon: hicon,
-            szTip: [0; 260],
-            dwMask: THB_FLAGS | THB_ICON | THB_TOOLTIP,
-            dwFlags: THBF_ENABLED,
-        };
+    with_context_menu(HWND::default(), &[file_path], |context_menu| {
+        let menu = unsafe { CreatePopupMenu() }.map_err(|e| e.message())?;
+        let result = unsafe { context_menu.QueryContextMenu(menu, 0, FIRST_COMMAND_ID, LAST_COMMAND_ID, CMF_NORMAL) }.map_err(|e| e.message()).and_then(|_| {
+            let ansi_verb = std::ffi::CString::new(verb).map_err(|e| e.to_string())?;
+            let wide_verb = encode_wide(verb);
+            let info = CMINVOKECOMMANDINFOEX {
+                cbSize: size_of::<CMINVOKECOMMANDINFOEX>() as _,
+                fMask: CMIC_MASK_UNICODE,
+                lpVerb: PCSTR(ansi_verb.as_ptr() as _),
+                lpVerbW: PCWSTR::from_raw(wide_verb.as_ptr()),
+                nShow: SW_SHOWNORMAL,
+                ..Default::default()
+            };
+            unsafe { context_menu.InvokeCommand(&info as *const _ as *const CMINVOKECOMMANDINFO) }.map_err(|e| e.message())
+        });
+        let _ = unsafe { DestroyMenu(menu) };
+        result
+    })
+}
 
-        // Set tooltip
-        if let Some(tooltip) = &button.tool_tip {
-            let tooltip_wide = encode_wide(tooltip);
-            thumb_button.szTip[..tooltip_wide.len()].copy_from_slice(&tooltip_wide);
-        }
+/// Queries what the shell allows for the file or virtual shell item such as "::{645FF040-5081-101B-9F08-00AA002F954E}"
+pub fn capabilities<P: AsRef<Path>>(path: P) -> Result<ShellCapabilities, String> {
+    let _guard = ComGuard::new();
+
+    let wide = encode_wide(path.as_ref());
+    let item: IShellItem = unsafe { SHCreateItemFromParsingName(PCWSTR(wide.as_ptr()), None) }.map_err(|e| e.message())?;
+
+    let mask = SFGAO_CANRENAME | SFGAO_CANDELETE | SFGAO_CANCOPY | SFGAO_CANMOVE | SFGAO_CANLINK | SFGAO_FOLDER | SFGAO_REMOVABLE | SFGAO_HASPROPSHEET;
+    // Returns S_FALSE when only some of the attributes are set
+    let attributes = unsafe { item.GetAttributes(mask) }.map_err(|e| e.message())?;
+
+    Ok(ShellCapabilities {
+        can_rename: attributes.contains(SFGAO_CANRENAME),
+        can_delete: attributes.contains(SFGAO_CANDELETE),
+        can_copy: attributes.contains(SFGAO_CANCOPY),
+        can_move: attributes.contains(SFGAO_CANMOVE),
+        can_link: attributes.contains(SFGAO_CANLINK),
+        is_folder: attributes.contains(SFGAO_FOLDER),
+        is_removable_media: attributes.contains(SFGAO_REMOVABLE),
+        has_properties: attributes.contains(SFGAO_HASPROPSHEET),
+    })
+}
 
-        thumb_buttons.push(thumb_button);
+/// Tells whether double-clicking the file would open it, run it or ask the user
+///
+/// Programs marked as downloaded from the Internet by the "Zone.Identifier" stream and files without an associated application are Prompt
+pub fn get_default_action<P: AsRef<Path>>(file_path: P) -> Result<DefaultAction, String> {
+    let metadata = std::fs::metadata(file_path.as_ref()).map_err(|e| e.to_string())?;
+    if metadata.is_dir() {
+        return Ok(DefaultAction::Open);
     }
 
-    let taskbar: ITaskbarList3 = unsafe { CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER).map_err(|e| e.message()) }?;
+    let extension = to_dot_extension(&file_path.as_ref().extension().unwrap_or_default().to_string_lossy());
+    let wide_extension = encode_wide(&extension);
 
-    unsafe { taskbar.HrInit().map_err(|e| e.message()) }?;
+    // Extensions such as .exe, .bat and .msi are run instead of opened
+    if unsafe { AssocIsDangerous(PCWSTR::from_raw(wide_extension.as_ptr())) }.as_bool() {
+        return Ok(if is_from_internet(file_path.as_ref()) {
+            DefaultAction::Prompt
+        } else {
+            DefaultAction::Run
+        });
+    }
 
-    if BUTTONS_ADDED.get().is_none() {
-        unsafe { taskbar.ThumbBarAddButtons(hwnd, &thumb_buttons).map_err(|e| e.message()) }?;
-        BUTTONS_ADDED.set(true).unwrap();
+    if query_association(&extension, ASSOCSTR_EXECUTABLE).is_some() {
+        Ok(DefaultAction::Open)
     } else {
-        unsafe { taskbar.ThumbBarUpdateButtons(hwnd, &thumb_buttons).map_err(|e| e.message()) }?;
+        Ok(DefaultAction::Prompt)
     }
+}
 
-    let inner = InnerThumbButtons {
-        callback: Box::new(callback),
-        id_map,
+/* Zone 3 is Internet and 4 is Restricted sites */
+fn is_from_internet(file_path: &Path) -> bool {
+    let Ok(data) = crate::fs::read_stream(file_path, "Zone.Identifier") else {
+        return false;
     };
 
-    unsafe {
-        let _ = SetWindowSubclass(hwnd, Some(subclass_proc), 200, Box::into_raw(Box::new(inner)) as _);
+    String::from_utf8_lossy(&data).lines().find_map(|line| line.trim().strip_prefix("ZoneId=").and_then(|zone| zone.trim().parse::<u32>().ok())).is_some_and(|zone| zone >= 3)
+}
+
+/// Splits the location into segments from This PC or Network down to the location itself
+///
+/// Archive files such as zip in the middle of the path are returned as Archive
+pub fn breadcrumbs(path_or_uri: &str) -> Result<Vec<Breadcrumb>, String> {
+    let _guard = ComGuard::new();
+
+    let location = path_or_uri.strip_prefix(r"\\?\").unwrap_or(path_or_uri).replace('/', "\\");
+    let mut segments: Vec<(String, BreadcrumbKind)> = Vec::new();
+
+    let names: Vec<&str> = if let Some(unc) = location.strip_prefix(r"\\") {
+        let mut names = unc.split('\\').filter(|name| !name.is_empty());
+        let host = names.next().ok_or_else(|| "Invalid location".to_string())?;
+        segments.push((NETWORK_FOLDER.to_string(), BreadcrumbKind::Root));
+        segments.push((format!(r"\\{}", host), BreadcrumbKind::Host));
+        if let Some(share) = names.next() {
+            segments.push((format!(r"\\{}\{}", host, share), BreadcrumbKind::Share));
+        }
+        names.collect()
+    } else if location.len() >= 2 && location.as_bytes()[1] == b':' {
+        segments.push((THIS_PC_FOLDER.to_string(), BreadcrumbKind::Root));
+        segments.push((format!("{}\\", location[..2].to_uppercase()), BreadcrumbKind::Drive));
+        location[2..].split('\\').filter(|name| !name.is_empty()).collect()
+    } else {
+        return Err("Unsupported location".to_string());
+    };
+
+    let mut current = segments.last().map(|(path, _)| path.trim_end_matches('\\').to_string()).unwrap_or_default();
+    for (index, name) in names.iter().enumerate() {
+        current = format!("{}\\{}", current, name);
+        let kind = if !Path::new(&current).is_file() {
+            BreadcrumbKind::Folder
+        } else if index < names.len() - 1 {
+            BreadcrumbKind::Archive
+        } else {
+            BreadcrumbKind::File
+        };
+        segments.push((current.clone(), kind));
     }
 
-    Ok(())
+    Ok(segments.into_iter().map(|(path, kind)| to_breadcrumb(path, kind)).collect())
 }
 
-fn create_hicon(file_path: &PathBuf) -> Result<HICON, String> {
-    let imaging_factory: IWICImagingFactory = unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER).map_err(|e| e.message()) }?;
+fn to_breadcrumb(path: String, kind: BreadcrumbKind) -> Breadcrumb {
+    let wide = encode_wide(&path);
+    let item: Option<IShellItem> = unsafe { SHCreateItemFromParsingName(PCWSTR(wide.as_ptr()), None) }.ok();
+
+    let name = item.as_ref().and_then(get_display_name).unwrap_or_else(|| path.rsplit('\\').find(|name| !name.is_empty()).unwrap_or(&path).to_string());
+    let icon = item.as_ref().and_then(get_icon_location);
+
+    Breadcrumb {
+        name,
+        path,
+        kind,
+        icon,
+    }
+}
+
+fn get_display_name(item: &IShellItem) -> Option<String> {
+    let name_ptr = unsafe { item.GetDisplayName(SIGDN_NORMALDISPLAY) }.ok()?;
+    let name = decode_wide(unsafe { name_ptr.as_wide() });
+    unsafe { CoTaskMemFree(Some(name_ptr.0 as _)) };
+    Some(name)
+}
+
+/* Returns "file,index" such as "C:\Windows\system32\imageres.dll,-109" */
+fn get_icon_location(item: &IShellItem) -> Option<String> {
+    let pidl = unsafe { SHGetIDListFromObject(item) }.ok()?;
+    let mut info = SHFILEINFOW::default();
+    let result = unsafe { SHGetFileInfoW(PCWSTR(pidl as _), FILE_FLAGS_AND_ATTRIBUTES(0), Some(&mut info), std::mem::size_of::<SHFILEINFOW>() as u32, SHGFI_PIDL | SHGFI_ICONLOCATION) };
+    unsafe { CoTaskMemFree(Some(pidl as _)) };
+
+    let file = decode_wide(&info.szDisplayName);
+    if result == 0 || file.is_empty() {
+        None
+    } else {
+        Some(format!("{},{}", file, info.iIcon))
+    }
+}
 
+/* Same as get_icon_location for a file system path. Indirect strings such as "@{Package?ms-resource://...}" of UWP apps are resolved to the logo path */
+pub(crate) fn get_file_icon_location(file_path: &Path) -> Option<String> {
     let wide = encode_wide(file_path);
-    let decoder = unsafe { imaging_factory.CreateDecoderFromFilename(PCWSTR::from_raw(wide.as_ptr()), None, GENERIC_READ, WICDecodeMetadataCacheOnDemand).map_err(|e| e.message()) }?;
-
-    let frame = unsafe { decoder.GetFrame(0).unwrap() };
-
-    let converter = unsafe { imaging_factory.CreateFormatConverter().unwrap() };
-    unsafe { converter.Initialize(&frame, &GUID_WICPixelFormat32bppPBGRA, WICBitmapDitherTypeNone, None, 0.0, WICBitmapPaletteTypeCustom).map_err(|e| e.message()) }?;
-
-    let mut width = 0;
-    let mut height = 0;
-    unsafe { converter.GetSize(&mut width, &mut height).map_err(|e| e.message()) }?;
-
-    let stride = (width * 4) as usize;
-
-    let buffer_size = stride * height as usize;
-    let mut pixel_data = vec![0u8; buffer_size];
-
-    // Copy WIC bitmap to HBITMAP
-    unsafe { converter.CopyPixels(std::ptr::null(), width * 4, &mut pixel_data).map_err(|e| e.message()) }?;
-
-    let bmi = BITMAPINFO {
-        bmiHeader: BITMAPINFOHEADER {
-            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
-            biWidth: width as i32,
-            biHeight: -(height as i32),
-            biPlanes: 1,
-            biBitCount: 32,
-            biCompression: 0,
-            biSizeImage: 0,
-            biXPelsPerMeter: 0,
-            biYPelsPerMeter: 0,
-            biClrUsed: 0,
-            biClrImportant: 0,
-        },
-        ..Default::default()
+    let mut info = SHFILEINFOW::default();
+    let result = unsafe { SHGetFileInfoW(PCWSTR::from_raw(wide.as_ptr()), FILE_FLAGS_AND_ATTRIBUTES(0), Some(&mut info), size_of::<SHFILEINFOW>() as u32, SHGFI_ICONLOCATION) };
+
+    let file = decode_wide(&info.szDisplayName);
+    if result == 0 || file.is_empty() {
+        None
+    } else if is_uwp(PWSTR(info.szDisplayName.as_mut_ptr())) {
+        Some(get_icon_path(PWSTR(info.szDisplayName.as_mut_ptr())))
+    } else {
+        Some(format!("{},{}", file, info.iIcon))
+    }
+}
+
+/* Creates IContextMenu of the files and frees the item ID lists after the callback */
+fn with_context_menu<P: AsRef<Path>, T>(hwnd: HWND, file_paths: &[P], f: impl FnOnce(&IContextMenu) -> Result<T, String>) -> Result<T, String> {
+    let mut pidls: Vec<*mut ITEMIDLIST> = Vec::new();
+    let mut result = Ok(());
+    for file_path in file_paths {
+        let wide_path = encode_wide(file_path.as_ref());
+        let mut pidl = std::ptr::null_mut();
+        if let Err(e) = unsafe { SHParseDisplayName(PCWSTR::from_raw(wide_path.as_ptr()), None, &mut pidl, 0, None) } {
+            result = Err(e.message());
+            break;
+        }
+        pidls.push(pidl);
+    }
+
+    let result = result.and_then(|_| get_context_menu(hwnd, &pidls)).and_then(|context_menu| f(&context_menu));
+
+    for pidl in pidls {
+        unsafe { CoTaskMemFree(Some(pidl as _)) };
+    }
+
+    result
+}
+
+fn get_context_menu(hwnd: HWND, pidls: &[*mut ITEMIDLIST]) -> Result<IContextMenu, String> {
+    // Items are expected to be in the same folder as Explorer does
+    let folder: IShellFolder = unsafe { SHBindToParent(pidls[0], None) }.map_err(|e| e.message())?;
+    let children: Vec<*const ITEMIDLIST> = pidls.iter().map(|pidl| unsafe { ILFindLastID(*pidl) } as *const _).collect();
+    unsafe { folder.GetUIObjectOf(hwnd, &children, None) }.map_err(|e| e.message())
+}
+
+fn track_menu(hwnd: HWND, context_menu: &IContextMenu, menu: HMENU, x: i32, y: i32, file_paths: &[&Path]) -> Result<Option<String>, String> {
+    unsafe { context_menu.QueryContextMenu(menu, 0, FIRST_COMMAND_ID, LAST_COMMAND_ID, CMF_NORMAL) }.map_err(|e| e.message())?;
+
+    let mut point = POINT {
+        x,
+        y,
     };
+    let _ = unsafe { ClientToScreen(hwnd, &mut point) };
+
+    // Submenus such as "Open with" and "Send to" are populated by the handler on window messages
+    let handler: Option<IContextMenu2> = context_menu.cast().ok();
+    if let Some(handler) = &handler {
+        let _ = unsafe { SetWindowSubclass(hwnd, Some(context_menu_proc), CONTEXT_MENU_SUBCLASS_ID, handler.as_raw() as _) };
+    }
 
-    let hdc = unsafe { CreateCompatibleDC(None) };
-    let mut bits_ptr: *mut u8 = std::ptr::null_mut();
-    let hbitmap = unsafe { CreateDIBSection(Some(hdc), &bmi, DIB_RGB_COLORS, &mut bits_ptr as *mut *mut u8 as *mut *mut _, None, 0).map_err(|e| e.message()) }?;
+    let command = unsafe { TrackPopupMenuEx(menu, TPM_RETURNCMD | TPM_RIGHTBUTTON, point.x, point.y, hwnd, None) }.0 as u32;
 
-    if hbitmap.is_invalid() || pixel_data.is_empty() {
-        let _ = unsafe { DeleteDC(hdc) };
-        return Ok(HICON(0 as _));
+    if handler.is_some() {
+        let _ = unsafe { RemoveWindowSubclass(hwnd, Some(context_menu_proc), CONTEXT_MENU_SUBCLASS_ID) };
     }
 
-    // Copy pixel data into the HBITMAP memory
-    unsafe { std::ptr::copy_nonoverlapping(pixel_data.as_ptr(), bits_ptr, buffer_size) };
+    if command < FIRST_COMMAND_ID {
+        return Ok(None);
+    }
+
+    let offset = command - FIRST_COMMAND_ID;
+    let verb = get_verb(context_menu, offset).unwrap_or_else(|| get_menu_text(menu, command));
 
-    let _ = unsafe { DeleteDC(hdc) };
+    // Any command may start a handler for the files, as invoke_verb does
+    for file_path in file_paths {
+        crate::policy::check_execute(file_path)?;
+    }
 
-    let icon_info = ICONINFO {
-        fIcon: true.into(),
-        xHotspot: 0,
-        yHotspot: 0,
-        hbmMask: hbitmap,
-        hbmColor: hbitmap,
+    let info = CMINVOKECOMMANDINFOEX {
+        cbSize: size_of::<CMINVOKECOMMANDINFOEX>() as _,
+        fMask: CMIC_MASK_UNICODE | CMIC_MASK_PTINVOKE,
+        hwnd,
+        // Command offset instead of verb string
+        lpVerb: PCSTR(offset as usize as _),
+        lpVerbW: PCWSTR(offset as usize as _),
+        nShow: SW_SHOWNORMAL,
+        ptInvoke: point,
+        ..Default::default()
     };
+    unsafe { context_menu.InvokeCommand(&info as *const _ as *const CMINVOKECOMMANDINFO) }.map_err(|e| e.message())?;
 
-    let hicon = unsafe { CreateIconIndirect(&icon_info).map_err(|e| e.message()) }?;
+    Ok(Some(verb))
+}
 
-    let _ = unsafe { DeleteObject(hbitmap.into()) };
+fn get_verb(context_menu: &IContextMenu, offset: u32) -> Option<String> {
+    let mut buffer = vec![0u16; MAX_PATH as _];
+    unsafe { context_menu.GetCommandString(offset as _, GCS_VERBW, None, PSTR(buffer.as_mut_ptr() as _), buffer.len() as _) }.ok()?;
+    let verb = decode_wide(&buffer);
+    if verb.is_empty() {
+        None
+    } else {
+        Some(verb)
+    }
+}
 
-    Ok(hicon)
+fn get_menu_text(menu: HMENU, command: u32) -> String {
+    let mut buffer = vec![0u16; MAX_PATH as _];
+    let _ = unsafe { GetMenuStringW(menu, command, Some(&mut buffer), MF_BYCOMMAND) };
+    decode_wide(&buffer).replace('&', "")
 }
 
-unsafe extern "system" fn subclass_proc(window: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM, _uidsubclass: usize, dwrefdata: usize) -> LRESULT {
+unsafe extern "system" fn context_menu_proc(window: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM, _uidsubclass: usize, dwrefdata: usize) -> LRESULT {
     match msg {
-        WM_COMMAND => {
-            let hiword = HIWORD(wparam.0 as _);
-
-            if hiword == THBN_CLICKED as u16 {
-                let button_in = LOWORD(wparam.0 as _) as u32;
-                let inner = unsafe { &mut *(dwrefdata as *mut InnerThumbButtons) };
-                if let Some(id) = inner.id_map.get(&button_in) {
-                    (inner.callback)(id.to_string());
+        WM_INITMENUPOPUP | WM_DRAWITEM | WM_MEASUREITEM | WM_MENUCHAR => {
+            if let Some(handler) = IContextMenu2::from_raw_borrowed(&(dwrefdata as *mut std::ffi::c_void)) {
+                if let Ok(handler) = handler.cast::<IContextMenu3>() {
+                    let mut result = LRESULT(0);
+                    if handler.HandleMenuMsg2(msg, wparam, lparam, Some(&mut result)).is_ok() {
+                        return result;
+                    }
+                } else if handler.HandleMenuMsg(msg, wparam, lparam).is_ok() {
+                    return LRESULT(0);
                 }
-
-                return LRESULT(0);
             }
-
             DefSubclassProc(window, msg, wparam, lparam)
         }
+        _ => DefSubclassProc(window, msg, wparam, lparam),
+    }
+}
 
-        WM_DESTROY => {
-            let _ = RemoveWindowSubclass(window, Some(subclass_proc), 200);
-            DefSubclassProc(window, msg, wparam, lparam)
+/// Adds the file to the recent documents of the shell
+pub fn add_to_recent<P: AsRef<Path>>(file_path: P) {
+    let wide_path = encode_wide(file_path.as_ref());
+    unsafe { SHAddToRecentDocs(SHARD_PATHW, Some(wide_path.as_ptr() as _)) };
+}
+
+/// Lists the files in the Recent folder, newest first
+pub fn get_recent_files() -> Result<Vec<String>, String> {
+    let _guard = ComGuard::new();
+
+    let recent_ptr = unsafe { SHGetKnownFolderPath(&FOLDERID_Recent, KF_FLAG_DEFAULT, None) }.map_err(|e| e.message())?;
+    let recent_dir = decode_wide(unsafe { recent_ptr.as_wide() });
+    unsafe { CoTaskMemFree(Some(recent_ptr.0 as _)) };
+
+    let mut links: Vec<(std::time::SystemTime, std::path::PathBuf)> = std::fs::read_dir(recent_dir)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .filter(|entry| entry.path().extension().unwrap_or_default() == "lnk")
+        .map(|entry| (entry.metadata().and_then(|metadata| metadata.modified()).unwrap_or(std::time::UNIX_EPOCH), entry.path()))
+        .collect();
+    links.sort_by(|a, b| b.0.cmp(&a.0));
+
+    Ok(links.into_iter().filter_map(|(_, link)| super::fs::get_link_path(link).ok()).filter(|(_, _, path)| !path.is_empty()).map(|(_, _, path)| path).collect())
+}
+
+/// Replaces the jump list of the taskbar button. A category without name is shown as Tasks
+pub fn set_jump_list(categories: &[JumpListCategory]) -> Result<(), String> {
+    let _guard = ComGuard::new();
+
+    let destination_list: ICustomDestinationList = unsafe { CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER) }.map_err(|e| e.message())?;
+    let mut min_slots = 0;
+    let _removed: IObjectArray = unsafe { destination_list.BeginList(&mut min_slots) }.map_err(|e| e.message())?;
+
+    for category in categories {
+        let collection: IObjectCollection = unsafe { CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER) }.map_err(|e| e.message())?;
+        for item in &category.items {
+            let link = create_jump_list_link(item)?;
+            unsafe { collection.AddObject(&link) }.map_err(|e| e.message())?;
         }
 
-        _ => DefSubclassProc(window, msg, wparam, lparam),
+        let array: IObjectArray = collection.cast().map_err(|e| e.message())?;
+        if category.name.is_empty() {
+            unsafe { destination_list.AddUserTasks(&array) }.map_err(|e| e.message())?;
+        } else {
+            unsafe { destination_list.AppendCategory(&HSTRING::from(&category.name), &array) }.map_err(|e| e.message())?;
+        }
     }
+
+    unsafe { destination_list.CommitList() }.map_err(|e| e.message())
 }
 
-#[allow(non_snake_case)]
-fn LOWORD(dword: u32) -> u16 {
-    (dword & 0xFFFF) as u16
+fn create_jump_list_link(item: &JumpListItem) -> Result<IShellLinkW, String> {
+    let link: IShellLinkW = unsafe { CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER) }.map_err(|e| e.message())?;
+
+    unsafe { link.SetPath(&HSTRING::from(item.path.as_path())) }.map_err(|e| e.message())?;
+    if let Some(arguments) = &item.arguments {
+        unsafe { link.SetArguments(&HSTRING::from(arguments)) }.map_err(|e| e.message())?;
+    }
+    if let Some(icon) = &item.icon {
+        unsafe { link.SetIconLocation(&HSTRING::from(icon.as_path()), 0) }.map_err(|e| e.message())?;
+    }
+    if let Some(description) = &item.description {
+        unsafe { link.SetDescription(&HSTRING::from(description)) }.map_err(|e| e.message())?;
+    }
+
+    // Jump lists show the title property instead of the file name
+    let store: IPropertyStore = link.cast().map_err(|e| e.message())?;
+    let title = PROPVARIANT::from(item.title.as_str());
+    unsafe { store.SetValue(&PKEY_TITLE, &title) }.map_err(|e| e.message())?;
+    unsafe { store.Commit() }.map_err(|e| e.message())?;
+
+    Ok(link)
 }
 
-#[allow(non_snake_case)]
-fn HIWORD(dword: u32) -> u16 {
-    ((dword & 0xFFFF_0000) >> 16) as u16
+/// Lists the columns supported by `column_value`
+pub fn list_columns() -> Vec<ShellColumn> {
+    COLUMNS
+        .iter()
+        .map(|(key, name, _)| ShellColumn {
+            key: key.to_string(),
+            name: name.to_string(),
+        })
+        .collect()
+}
+
+/// Returns the value of the column listed by `list_columns` formatted as Explorer shows it, or None if the file has no value
+pub fn column_value<P: AsRef<Path>>(file_path: P, column_key: &str) -> Result<Option<String>, String> {
+    let _guard = ComGuard::new();
+
+    let key = to_column_property_key(column_key)?;
+    let store = get_property_store(file_path.as_ref())?;
+    Ok(format_property(&store, &key))
+}
+
+/// Returns the values of the columns for each file, opening the property store of each file once
+///
+/// Files that cannot be read and columns without values are omitted
+pub fn column_values<P: AsRef<Path>>(file_paths: &[P], column_keys: &[&str]) -> Result<HashMap<String, HashMap<String, String>>, String> {
+    let _guard = ComGuard::new();
+
+    let keys = column_keys.iter().map(|column_key| to_column_property_key(column_key).map(|key| (column_key.to_string(), key))).collect::<Result<Vec<_>, String>>()?;
+
+    let mut result = HashMap::new();
+    for file_path in file_paths {
+        let Ok(store) = get_property_store(file_path.as_ref()) else {
+            continue;
+        };
+
+        let values = keys.iter().filter_map(|(column_key, key)| format_property(&store, key).map(|value| (column_key.clone(), value))).collect();
+        result.insert(file_path.as_ref().to_string_lossy().to_string(), values);
+    }
+
+    Ok(result)
+}
+
+/// Reads the properties by canonical name such as "System.Rating" or "System.Title"
+///
+/// Properties without values are omitted
+pub fn get_properties<P: AsRef<Path>>(file_path: P, keys: &[&str]) -> Result<HashMap<String, PropertyValue>, String> {
+    let _guard = ComGuard::new();
+
+    let store = get_property_store(file_path.as_ref())?;
+
+    let mut result = HashMap::new();
+    for name in keys {
+        let key = to_property_key(name)?;
+        let value = unsafe { store.GetValue(&key) }.map_err(|e| e.message())?;
+        if let Some(value) = to_property_value(&key, &value) {
+            result.insert(name.to_string(), value);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Writes the properties by canonical name and commits them to the file
+///
+/// Values are converted to the type of each property, so Rating accepts Integer from 0 to 99
+pub fn set_properties<P: AsRef<Path>>(file_path: P, values: &HashMap<String, PropertyValue>) -> Result<(), String> {
+    let _guard = ComGuard::new();
+
+    let wide = encode_wide(file_path.as_ref());
+    let store: IPropertyStore = unsafe { SHGetPropertyStoreFromParsingName(PCWSTR::from_raw(wide.as_ptr()), None, GPS_READWRITE) }.map_err(|e| e.message())?;
+
+    for (name, value) in values {
+        let key = to_property_key(name)?;
+        let mut value = to_propvariant(value)?;
+        unsafe { PSCoerceToCanonicalValue(&key, &mut value) }.map_err(|e| e.message())?;
+        unsafe { store.SetValue(&key, &value) }.map_err(|e| e.message())?;
+    }
+
+    unsafe { store.Commit() }.map_err(|e| e.message())
+}
+
+fn to_property_value(key: &PROPERTYKEY, value: &PROPVARIANT) -> Option<PropertyValue> {
+    if value.is_empty() {
+        return None;
+    }
+
+    let var_type = unsafe { PSGetPropertyDescription::<IPropertyDescription>(key) }.and_then(|description| unsafe { description.GetPropertyType() }).unwrap_or(VT_LPWSTR);
+
+    // Vectors are joined with "; " by PropVariantToString
+    if var_type.0 & VT_VECTOR.0 != 0 {
+        return Some(PropertyValue::StringList(value.to_string().split("; ").map(|value| value.to_string()).collect()));
+    }
+
+    let value = match var_type {
+        VT_BOOL => PropertyValue::Bool(bool::try_from(value).ok()?),
+        VT_I1 | VT_I2 | VT_I4 | VT_I8 | VT_INT | VT_UI1 | VT_UI2 | VT_UI4 | VT_UI8 | VT_UINT => PropertyValue::Integer(i64::try_from(value).ok()?),
+        VT_R4 | VT_R8 => PropertyValue::Float(f64::try_from(value).ok()?),
+        _ => PropertyValue::String(value.to_string()),
+    };
+
+    Some(value)
+}
+
+fn to_propvariant(value: &PropertyValue) -> Result<PROPVARIANT, String> {
+    match value {
+        PropertyValue::String(value) => Ok(PROPVARIANT::from(value.as_str())),
+        PropertyValue::Integer(value) => Ok(PROPVARIANT::from(*value)),
+        PropertyValue::Float(value) => Ok(PROPVARIANT::from(*value)),
+        PropertyValue::Bool(value) => Ok(PROPVARIANT::from(*value)),
+        PropertyValue::StringList(values) => {
+            let wide = encode_wide(values.join(";"));
+            unsafe { InitPropVariantFromStringAsVector(PCWSTR::from_raw(wide.as_ptr())) }.map_err(|e| e.message())
+        }
+    }
+}
+
+fn to_column_property_key(column_key: &str) -> Result<PROPERTYKEY, String> {
+    let (_, _, canonical_name) = COLUMNS.iter().find(|(key, _, _)| *key == column_key).ok_or_else(|| format!("Unknown column: {}", column_key))?;
+    to_property_key(canonical_name)
+}
+
+fn to_property_key(canonical_name: &str) -> Result<PROPERTYKEY, String> {
+    let wide_name = encode_wide(canonical_name);
+    let mut key = PROPERTYKEY::default();
+    unsafe { PSGetPropertyKeyFromName(PCWSTR::from_raw(wide_name.as_ptr()), &mut key) }.map_err(|e| e.message())?;
+    Ok(key)
+}
+
+fn get_property_store(file_path: &Path) -> Result<IPropertyStore, String> {
+    let wide = encode_wide(file_path);
+    unsafe { SHGetPropertyStoreFromParsingName(PCWSTR::from_raw(wide.as_ptr()), None, GPS_DEFAULT) }.map_err(|e| e.message())
+}
+
+fn format_property(store: &IPropertyStore, key: &PROPERTYKEY) -> Option<String> {
+    let value = unsafe { store.GetValue(key) }.ok()?;
+    if value.is_empty() {
+        return None;
+    }
+
+    let display = unsafe { PSFormatForDisplayAlloc(key, &value, PDFF_DEFAULT) }.ok()?;
+    let text = decode_wide(unsafe { display.as_wide() });
+    unsafe { CoTaskMemFree(Some(display.0 as _)) };
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
 }
 
 pub(crate) fn read_properties<P: AsRef<Path>>(file_path: P) -> HashMap<String, String> {
@@ -530,6 +1358,80 @@ pub(crate) fn read_properties<P: AsRef<Path>>(file_path: P) -> HashMap<String, S
     result
 }
 
+/// Reads the folder type and icon that Explorer keeps in desktop.ini of the directory
+pub fn get_folder_view_hints<P: AsRef<Path>>(directory: P) -> Result<FolderViewHints, String> {
+    let ini = directory.as_ref().join(DESKTOP_INI);
+    if !ini.is_file() {
+        return Ok(FolderViewHints::default());
+    }
+
+    // IconResource replaced IconFile and IconIndex, which older folders still have
+    let icon = read_ini(&ini, SHELL_CLASS_INFO, "IconResource").or_else(|| {
+        read_ini(&ini, SHELL_CLASS_INFO, "IconFile").map(|file| match read_ini(&ini, SHELL_CLASS_INFO, "IconIndex") {
+            Some(index) => format!("{},{}", file, index),
+            None => file,
+        })
+    });
+
+    Ok(FolderViewHints {
+        folder_type: read_ini(&ini, VIEW_STATE, "FolderType"),
+        icon,
+    })
+}
+
+/// Writes the folder type and icon to desktop.ini of the directory so that Explorer applies the same template. None removes the value
+///
+/// desktop.ini is hidden and the directory is marked as a system folder, which Explorer requires to read it
+pub fn set_folder_view_hints<P: AsRef<Path>>(directory: P, hints: &FolderViewHints) -> Result<(), String> {
+    let ini = directory.as_ref().join(DESKTOP_INI);
+    // WritePrivateProfileString keeps the encoding of an existing file but creates an ANSI one, so start with a UTF-16 BOM
+    if !ini.exists() {
+        std::fs::write(&ini, [0xFF, 0xFE]).map_err(|e| format!("{}:{}", e, ini.to_string_lossy()))?;
+    }
+
+    write_ini(&ini, VIEW_STATE, "FolderType", hints.folder_type.as_deref())?;
+    write_ini(&ini, SHELL_CLASS_INFO, "IconResource", hints.icon.as_deref())?;
+    write_ini(&ini, SHELL_CLASS_INFO, "IconFile", None)?;
+    write_ini(&ini, SHELL_CLASS_INFO, "IconIndex", None)?;
+
+    let ini_wide = encode_wide(&ini);
+    unsafe { SetFileAttributesW(PCWSTR::from_raw(ini_wide.as_ptr()), FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) }.map_err(|e| e.message())?;
+    let directory_wide = encode_wide(directory.as_ref());
+    unsafe { PathMakeSystemFolderW(PCWSTR::from_raw(directory_wide.as_ptr())) }.ok().map_err(|e| e.message())?;
+
+    unsafe { SHChangeNotify(SHCNE_UPDATEDIR, SHCNF_PATHW, Some(directory_wide.as_ptr() as _), None) };
+
+    Ok(())
+}
+
+fn read_ini(ini: &Path, section: &str, key: &str) -> Option<String> {
+    let ini = encode_wide(ini);
+    let section = encode_wide(section);
+    let key = encode_wide(key);
+    let mut buffer = vec![0u16; MAX_PATH as usize * 2];
+
+    let len = unsafe { GetPrivateProfileStringW(PCWSTR::from_raw(section.as_ptr()), PCWSTR::from_raw(key.as_ptr()), PCWSTR::null(), Some(&mut buffer), PCWSTR::from_raw(ini.as_ptr())) };
+    Some(String::from_utf16_lossy(&buffer[..len as usize])).filter(|value| !value.is_empty())
+}
+
+/* A null value deletes the key */
+fn write_ini(ini: &Path, section: &str, key: &str, value: Option<&str>) -> Result<(), String> {
+    let ini = encode_wide(ini);
+    let section = encode_wide(section);
+    let key = encode_wide(key);
+    let value = value.map(encode_wide);
+
+    unsafe {
+        WritePrivateProfileStringW(
+            PCWSTR::from_raw(section.as_ptr()),
+            PCWSTR::from_raw(key.as_ptr()),
+            value.as_ref().map_or(PCWSTR::null(), |value| PCWSTR::from_raw(value.as_ptr())),
+            PCWSTR::from_raw(ini.as_ptr()),
+        )
+    }
+    .map_err(|e| e.message())
+}
+
 pub fn get_locale() -> String {
     let size = unsafe { GetLocaleInfoEx(PCWSTR::null(), LOCALE_SNAME, None) };
     let mut locale = vec![0u16; size as _];
@@ -0,0 +1,204 @@
+use super::util::{encode_wide, ComGuard};
+use crate::ThumbButton;
+use std::{collections::HashMap, path::PathBuf, rc::Rc};
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{GENERIC_READ, HWND, WPARAM},
+        Graphics::{
+            Gdi::{CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, BITMAPINFO, BITMAPINFOHEADER, DIB_RGB_COLORS},
+            Imaging::{CLSID_WICImagingFactory, GUID_WICPixelFormat32bppPBGRA, IWICImagingFactory, WICBitmapDitherTypeNone, WICBitmapPaletteTypeCustom, WICDecodeMetadataCacheOnDemand},
+        },
+        System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER},
+        UI::{
+            Shell::{ITaskbarList3, TaskbarList, THBF_ENABLED, THBF_HIDDEN, THBN_CLICKED, THB_FLAGS, THB_ICON, THB_TOOLTIP, THUMBBUTTON},
+            WindowsAndMessaging::{CreateIconIndirect, DestroyIcon, HICON, ICONINFO},
+        },
+    },
+};
+
+/// Thumb buttons kept in the window registry
+pub(crate) struct ThumbButtons {
+    callback: Rc<dyn Fn(String)>,
+    id_map: HashMap<u32, String>,
+    icons: Vec<HICON>,
+}
+
+impl Drop for ThumbButtons {
+    fn drop(&mut self) {
+        for icon in &self.icons {
+            let _ = unsafe { DestroyIcon(*icon) };
+        }
+    }
+}
+
+/// Adds a thumbnail toolbar with specified buttons to a taskbar layout of an application window
+pub fn set_thumbar_buttons<F: Fn(String) + 'static>(window_handle: isize, buttons: &[ThumbButton], callback: F) -> Result<(), String> {
+    let hwnd = HWND(window_handle as _);
+
+    let _guard = ComGuard::new();
+
+    let mut thumb_buttons: Vec<THUMBBUTTON> = Vec::new();
+    let mut id_map = HashMap::new();
+    let mut icons = Vec::new();
+
+    for i in 0..7 {
+        // Set hidden buttons to the limit(7 buttons) so that new buttons can replace the existing buttons
+        if i >= buttons.len() {
+            thumb_buttons.push(THUMBBUTTON {
+                iId: i as _,
+                dwFlags: THBF_HIDDEN,
+                dwMask: THB_FLAGS,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let button = buttons.get(i).unwrap();
+        id_map.insert(i as _, button.id.clone());
+
+        let hicon = create_hicon(&button.icon)?;
+        icons.push(hicon);
+
+        let mut thumb_button = THUMBBUTTON {
+            iId: i as _,
+            iBitmap: 0,
+            hIcon: hicon,
+            szTip: [0; 260],
+            dwMask: THB_FLAGS | THB_ICON | THB_TOOLTIP,
+            dwFlags: THBF_ENABLED,
+        };
+
+        // Set tooltip
+        if let Some(tooltip) = &button.tool_tip {
+            let tooltip_wide = encode_wide(tooltip);
+            thumb_button.szTip[..tooltip_wide.len()].copy_from_slice(&tooltip_wide);
+        }
+
+        thumb_buttons.push(thumb_button);
+    }
+
+    let taskbar: ITaskbarList3 = unsafe { CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER).map_err(|e| e.message()) }?;
+
+    unsafe { taskbar.HrInit().map_err(|e| e.message()) }?;
+
+    let state = ThumbButtons {
+        callback: Rc::new(callback),
+        id_map,
+        icons,
+    };
+
+    // Buttons can be added only once per window, so later calls update the existing buttons
+    let buttons_added = super::window::with_existing_state(window_handle, |window_state| window_state.thumb_buttons.is_some()).unwrap_or(false);
+
+    if buttons_added {
+        unsafe { taskbar.ThumbBarUpdateButtons(hwnd, &thumb_buttons).map_err(|e| e.message()) }?;
+    } else if unsafe { taskbar.ThumbBarAddButtons(hwnd, &thumb_buttons) }.is_err() {
+        // Buttons remain on the taskbar if the window was detached explicitly
+        unsafe { taskbar.ThumbBarUpdateButtons(hwnd, &thumb_buttons).map_err(|e| e.message()) }?;
+    }
+
+    let previous = super::window::with_state(window_handle, |window_state| window_state.thumb_buttons.replace(state));
+    drop(previous);
+
+    Ok(())
+}
+
+/* Called from the window subclass. Returns true if the command was a thumb button click */
+pub(crate) fn handle_command(window_handle: isize, wparam: WPARAM) -> bool {
+    if HIWORD(wparam.0 as _) != THBN_CLICKED as u16 {
+        return false;
+    }
+
+    let button_in = LOWORD(wparam.0 as _) as u32;
+    let clicked = super::window::with_existing_state(window_handle, |window_state| {
+        window_state.thumb_buttons.as_ref().and_then(|buttons| buttons.id_map.get(&button_in).map(|id| (buttons.callback.clone(), id.clone())))
+    })
+    .flatten();
+
+    // Called after the registry is released so that the callback can update the buttons
+    if let Some((callback, id)) = clicked {
+        callback(id);
+    }
+
+    true
+}
+
+fn create_hicon(file_path: &PathBuf) -> Result<HICON, String> {
+    let imaging_factory: IWICImagingFactory = unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER).map_err(|e| e.message()) }?;
+
+    let wide = encode_wide(file_path);
+    let decoder = unsafe { imaging_factory.CreateDecoderFromFilename(PCWSTR::from_raw(wide.as_ptr()), None, GENERIC_READ, WICDecodeMetadataCacheOnDemand).map_err(|e| e.message()) }?;
+
+    let frame = unsafe { decoder.GetFrame(0).unwrap() };
+
+    let converter = unsafe { imaging_factory.CreateFormatConverter().unwrap() };
+    unsafe { converter.Initialize(&frame, &GUID_WICPixelFormat32bppPBGRA, WICBitmapDitherTypeNone, None, 0.0, WICBitmapPaletteTypeCustom).map_err(|e| e.message()) }?;
+
+    let mut width = 0;
+    let mut height = 0;
+    unsafe { converter.GetSize(&mut width, &mut height).map_err(|e| e.message()) }?;
+
+    let stride = (width * 4) as usize;
+
+    let buffer_size = stride * height as usize;
+    let mut pixel_data = vec![0u8; buffer_size];
+
+    // Copy WIC bitmap to HBITMAP
+    unsafe { converter.CopyPixels(std::ptr::null(), width * 4, &mut pixel_data).map_err(|e| e.message()) }?;
+
+    let bmi = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width as i32,
+            biHeight: -(height as i32),
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: 0,
+            biSizeImage: 0,
+            biXPelsPerMeter: 0,
+            biYPelsPerMeter: 0,
+            biClrUsed: 0,
+            biClrImportant: 0,
+        },
+        ..Default::default()
+    };
+
+    let hdc = unsafe { CreateCompatibleDC(None) };
+    let mut bits_ptr: *mut u8 = std::ptr::null_mut();
+    let hbitmap = unsafe { CreateDIBSection(Some(hdc), &bmi, DIB_RGB_COLORS, &mut bits_ptr as *mut *mut u8 as *mut *mut _, None, 0).map_err(|e| e.message()) }?;
+
+    if hbitmap.is_invalid() || pixel_data.is_empty() {
+        let _ = unsafe { DeleteDC(hdc) };
+        return Ok(HICON(0 as _));
+    }
+
+    // Copy pixel data into the HBITMAP memory
+    unsafe { std::ptr::copy_nonoverlapping(pixel_data.as_ptr(), bits_ptr, buffer_size) };
+
+    let _ = unsafe { DeleteDC(hdc) };
+
+    let icon_info = ICONINFO {
+        fIcon: true.into(),
+        xHotspot: 0,
+        yHotspot: 0,
+        hbmMask: hbitmap,
+        hbmColor: hbitmap,
+    };
+
+    let hicon = unsafe { CreateIconIndirect(&icon_info).map_err(|e| e.message()) }?;
+
+    let _ = unsafe { DeleteObject(hbitmap.into()) };
+
+    Ok(hicon)
+}
+
+#[allow(non_snake_case)]
+fn LOWORD(dword: u32) -> u16 {
+    (dword & 0xFFFF) as u16
+}
+
+#[allow(non_snake_case)]
+fn HIWORD(dword: u32) -> u16 {
+    ((dword & 0xFFFF_0000) >> 16) as u16
+}
@@ -1,15 +1,16 @@
 use super::util::encode_wide;
 use serde::{Deserialize, Serialize};
-use std::{
-    collections::HashMap,
-    sync::{LazyLock, Mutex},
-};
+use serde_json::{json, Value};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use webview2_com::{
-    ExecuteScriptCompletedHandler,
+    AddScriptToExecuteOnDocumentCreatedCompletedHandler, ExecuteScriptCompletedHandler,
     Microsoft::Web::WebView2::Win32::{ICoreWebView2, ICoreWebView2File, ICoreWebView2WebMessageReceivedEventArgs, ICoreWebView2WebMessageReceivedEventArgs2},
-    WebMessageReceivedEventHandler,
+    WebMessageReceivedEventHandler, WindowCloseRequestedEventHandler,
+};
+use windows::{
+    core::{Interface, PCWSTR, PWSTR},
+    Win32::System::Com::CoTaskMemFree,
 };
-use windows::core::{Interface, PCWSTR, PWSTR};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct FileDropEvent {
@@ -17,78 +18,186 @@ pub struct FileDropEvent {
 }
 
 struct DropHandler {
+    // Keeps the webview alive, so that its address is not reused by another webview while registered
+    webview: ICoreWebView2,
+    token: i64,
+    close_token: i64,
+    script_id: String,
+    callback: Rc<dyn Fn(FileDropEvent) + 'static>,
+}
+
+struct Bridge {
+    webview: ICoreWebView2,
     token: i64,
-    callback: Box<dyn Fn(FileDropEvent) + 'static + Send>,
+    close_token: i64,
+    script_id: String,
 }
 
-static HANDLERS: LazyLock<Mutex<HashMap<isize, DropHandler>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+thread_local! {
+    // WebView2 is used on the thread that created it, which is the only one that can register
+    static HANDLERS: RefCell<HashMap<isize, DropHandler>> = RefCell::new(HashMap::new());
+    static BRIDGES: RefCell<HashMap<isize, Bridge>> = RefCell::new(HashMap::new());
+}
 
-pub fn register_file_drop<F: Fn(FileDropEvent) + 'static + Send>(webview: &ICoreWebView2, target_id: Option<String>, callback: F) -> Result<(), String> {
-    let js = if let Some(target) = &target_id {
-        format!(
-            r#"
-                const __zouni__drop__handler__ = (e) => {{
-                    const mached = e.composed ? e.composedPath().some((p) => p.id == "{}") : e.target.id == "{}";
-                    if ( mached ) {{
+type Dispatcher = dyn Fn(&str, &Value) -> Option<Result<Value, String>>;
+
+const BRIDGE_SCRIPT: &str = r#"
+    (() => {
+        if (window.__zouni__) return;
+        const pending = new Map();
+        let nextId = 1;
+        window.chrome.webview.addEventListener("message", (e) => {
+            const data = e.data;
+            if (data && data.__zouni__ === "result" && pending.has(data.id)) {
+                const { resolve, reject } = pending.get(data.id);
+                pending.delete(data.id);
+                data.error == null ? resolve(data.result) : reject(new Error(data.error));
+            }
+        });
+        window.__zouni__ = {
+            invoke: (command, args) => new Promise((resolve, reject) => {
+                const id = nextId++;
+                pending.set(id, { resolve, reject });
+                window.chrome.webview.postMessage({ __zouni__: "command", id, command, args: args ?? {} });
+            }),
+        };
+    })();
+"#;
+
+/// Passes the paths of files dropped on the element with `target_id`, or anywhere on the page, to the callback
+///
+/// The handler is removed when the page closes the webview by `window.close()`. Otherwise call `unregister_file_drop` before closing the controller, since the webview is kept alive until then
+pub fn register_file_drop<F: Fn(FileDropEvent) + 'static>(webview: &ICoreWebView2, target_id: Option<String>, callback: F) -> Result<(), String> {
+    // The handler is kept on window so that registering again replaces it
+    let matcher = if let Some(target) = &target_id {
+        format!(r#"(e.composed ? e.composedPath().some((p) => p.id == "{0}") : e.target.id == "{0}")"#, target)
+    } else {
+        "true".to_string()
+    };
+    let js = format!(
+        r#"
+            (() => {{
+                if (window.__zouni__drop__handler__) {{
+                    document.removeEventListener("drop", window.__zouni__drop__handler__);
+                }}
+                window.__zouni__drop__handler__ = (e) => {{
+                    if ( {} ) {{
                         e.preventDefault();
                         if (e.dataTransfer && e.dataTransfer.files) {{
                             window.chrome.webview.postMessageWithAdditionalObjects("getPathForFiles", e.dataTransfer.files);
                         }}
                     }}
-                }}
-
-                document.removeEventListener("drop", __zouni__drop__handler__);
-                document.addEventListener("drop", __zouni__drop__handler__);
-            "#,
-            target.clone(),
-            target.clone()
-        )
-    } else {
-        r#"
-            const __zouni__drop__handler__ = (e) => {{
-                e.preventDefault();
-                if (e.dataTransfer && e.dataTransfer.files) {{
-                    window.chrome.webview.postMessageWithAdditionalObjects("getPathForFiles", e.dataTransfer.files);
-                }}
-            }}
-
-            document.removeEventListener("drop", __zouni__drop__handler__);
-            document.addEventListener("drop", __zouni__drop__handler__);
-        "#
-        .to_string()
-    };
+                }};
+                document.addEventListener("drop", window.__zouni__drop__handler__);
+            }})();
+        "#,
+        matcher
+    );
 
-    unsafe { webview.ExecuteScript(PCWSTR::from_raw(encode_wide(js.clone()).as_ptr()), &ExecuteScriptCompletedHandler::create(Box::new(|_, _| Ok(())))) }.map_err(|e| e.message())?;
+    unregister_file_drop(webview)?;
 
     let mut token = 0;
     unsafe { webview.add_WebMessageReceived(&WebMessageReceivedEventHandler::create(Box::new(drop_handler)), &mut token) }.map_err(|e| e.message())?;
+    let close_token = on_close(webview, |webview| {
+        let _ = unregister_file_drop(webview);
+    })?;
 
-    let old_handler = HANDLERS.lock().unwrap().insert(
-        webview.as_raw() as _,
-        DropHandler {
-            token,
-            callback: Box::new(callback),
-        },
-    );
+    let id: isize = webview.as_raw() as _;
+    HANDLERS.with_borrow_mut(|handlers| {
+        handlers.insert(
+            id,
+            DropHandler {
+                webview: webview.clone(),
+                token,
+                close_token,
+                script_id: String::new(),
+                callback: Rc::new(callback),
+            },
+        )
+    });
+
+    inject_script(webview, &js, move |script_id| {
+        HANDLERS.with_borrow_mut(|handlers| {
+            if let Some(handler) = handlers.get_mut(&id) {
+                handler.script_id = script_id;
+            }
+        });
+    })
+}
 
-    if let Some(handler) = old_handler {
-        unsafe { webview.remove_WebMessageReceived(handler.token) }.map_err(|e| e.message())?;
+/// Removes the drop handler and the injected script
+///
+/// The handler is forgotten even if the webview has already been closed, in which case the error of removing it is returned
+pub fn unregister_file_drop(webview: &ICoreWebView2) -> Result<(), String> {
+    let handler = HANDLERS.with_borrow_mut(|handlers| handlers.remove(&(webview.as_raw() as isize)));
+    if let Some(handler) = handler {
+        unsafe { handler.webview.remove_WebMessageReceived(handler.token) }.map_err(|e| e.message())?;
+        unsafe { handler.webview.remove_WindowCloseRequested(handler.close_token) }.map_err(|e| e.message())?;
+        remove_script(&handler.webview, &handler.script_id)?;
     }
 
     Ok(())
 }
 
+/// Forgets the drop handlers and command bridges registered on this thread and releases their webviews without unregistering from them
 pub fn clear() {
-    let _ = {
-        let mut lock = HANDLERS.lock().unwrap();
-        std::mem::take(&mut *lock)
-    };
+    // Taken out before dropping, since releasing a webview may run its handlers
+    let _ = HANDLERS.with_borrow_mut(std::mem::take);
+    let _ = BRIDGES.with_borrow_mut(std::mem::take);
+}
+
+/* Runs the script on the current document and on every document created by navigation */
+fn inject_script<F: FnOnce(String) + 'static>(webview: &ICoreWebView2, js: &str, on_added: F) -> Result<(), String> {
+    let wide = encode_wide(js);
+    unsafe { webview.ExecuteScript(PCWSTR::from_raw(wide.as_ptr()), &ExecuteScriptCompletedHandler::create(Box::new(|_, _| Ok(())))) }.map_err(|e| e.message())?;
+    unsafe {
+        webview.AddScriptToExecuteOnDocumentCreated(
+            PCWSTR::from_raw(wide.as_ptr()),
+            &AddScriptToExecuteOnDocumentCreatedCompletedHandler::create(Box::new(move |result, script_id| {
+                if result.is_ok() {
+                    on_added(script_id);
+                }
+                Ok(())
+            })),
+        )
+    }
+    .map_err(|e| e.message())
+}
+
+fn remove_script(webview: &ICoreWebView2, script_id: &str) -> Result<(), String> {
+    if script_id.is_empty() {
+        return Ok(());
+    }
+
+    unsafe { webview.RemoveScriptToExecuteOnDocumentCreated(PCWSTR::from_raw(encode_wide(script_id).as_ptr())) }.map_err(|e| e.message())
+}
+
+/* Handlers are removed when the page closes the webview. Otherwise they must be unregistered before closing the controller */
+fn on_close<F: Fn(&ICoreWebView2) + 'static>(webview: &ICoreWebView2, callback: F) -> Result<i64, String> {
+    let mut token = 0;
+    unsafe {
+        webview.add_WindowCloseRequested(
+            &WindowCloseRequestedEventHandler::create(Box::new(move |webview, _| {
+                if let Some(webview) = webview {
+                    callback(&webview);
+                }
+                Ok(())
+            })),
+            &mut token,
+        )
+    }
+    .map_err(|e| e.message())?;
+
+    Ok(token)
 }
 
 fn drop_handler(webview: Option<ICoreWebView2>, args: Option<ICoreWebView2WebMessageReceivedEventArgs>) -> windows::core::Result<()> {
     if let Some(args) = args {
         let mut webmessageasstring = PWSTR::null();
-        unsafe { args.TryGetWebMessageAsString(&mut webmessageasstring) }?;
+        // Messages posted as JSON objects, such as bridge commands, are not for this handler
+        if unsafe { args.TryGetWebMessageAsString(&mut webmessageasstring) }.is_err() {
+            return Ok(());
+        }
 
         if unsafe { webmessageasstring.to_string().unwrap() } == "getPathForFiles" {
             let args2: ICoreWebView2WebMessageReceivedEventArgs2 = args.cast()?;
@@ -112,8 +221,9 @@ fn drop_handler(webview: Option<ICoreWebView2>, args: Option<ICoreWebView2WebMes
 
                 if let Some(webview) = webview {
                     let id: isize = webview.as_raw() as _;
-                    if let Some(handler) = HANDLERS.lock().unwrap().get(&id) {
-                        (handler.callback)(FileDropEvent {
+                    // The callback may unregister, so it is called after the map is released
+                    if let Some(callback) = HANDLERS.with_borrow(|handlers| handlers.get(&id).map(|handler| handler.callback.clone())) {
+                        callback(FileDropEvent {
                             paths,
                         });
                     }
@@ -124,3 +234,111 @@ fn drop_handler(webview: Option<ICoreWebView2>, args: Option<ICoreWebView2WebMes
 
     Ok(())
 }
+
+/// Lets the page invoke the commands in `allowlist` by `window.__zouni__.invoke(command, args)`, which resolves with the JSON result
+///
+/// `dispatcher` handles the command first. When it returns `None`, the built-in "open_path", "show_item_in_folder" and "trash" commands are executed with `{ path }` or `{ paths }`.
+/// As with `register_file_drop`, call `unregister_command_bridge` before closing the controller unless the page closes the webview
+pub fn register_command_bridge<F>(webview: &ICoreWebView2, allowlist: &[&str], dispatcher: F) -> Result<(), String>
+where
+    F: Fn(&str, &Value) -> Option<Result<Value, String>> + 'static,
+{
+    unregister_command_bridge(webview)?;
+
+    let allowlist: Vec<String> = allowlist.iter().map(|command| command.to_string()).collect();
+    let mut token = 0;
+    unsafe { webview.add_WebMessageReceived(&WebMessageReceivedEventHandler::create(Box::new(move |webview, args| bridge_handler(webview, args, &allowlist, &dispatcher))), &mut token) }
+        .map_err(|e| e.message())?;
+    let close_token = on_close(webview, |webview| {
+        let _ = unregister_command_bridge(webview);
+    })?;
+
+    let id: isize = webview.as_raw() as _;
+    BRIDGES.with_borrow_mut(|bridges| {
+        bridges.insert(
+            id,
+            Bridge {
+                webview: webview.clone(),
+                token,
+                close_token,
+                script_id: String::new(),
+            },
+        )
+    });
+
+    inject_script(webview, BRIDGE_SCRIPT, move |script_id| {
+        BRIDGES.with_borrow_mut(|bridges| {
+            if let Some(bridge) = bridges.get_mut(&id) {
+                bridge.script_id = script_id;
+            }
+        });
+    })
+}
+
+/// Stops handling commands from the page
+///
+/// The bridge is forgotten even if the webview has already been closed, in which case the error of removing it is returned
+pub fn unregister_command_bridge(webview: &ICoreWebView2) -> Result<(), String> {
+    let bridge = BRIDGES.with_borrow_mut(|bridges| bridges.remove(&(webview.as_raw() as isize)));
+    if let Some(bridge) = bridge {
+        unsafe { bridge.webview.remove_WebMessageReceived(bridge.token) }.map_err(|e| e.message())?;
+        unsafe { bridge.webview.remove_WindowCloseRequested(bridge.close_token) }.map_err(|e| e.message())?;
+        remove_script(&bridge.webview, &bridge.script_id)?;
+    }
+
+    Ok(())
+}
+
+fn bridge_handler(webview: Option<ICoreWebView2>, args: Option<ICoreWebView2WebMessageReceivedEventArgs>, allowlist: &[String], dispatcher: &Dispatcher) -> windows::core::Result<()> {
+    let (Some(webview), Some(args)) = (webview, args) else {
+        return Ok(());
+    };
+
+    let mut json_ptr = PWSTR::null();
+    unsafe { args.WebMessageAsJson(&mut json_ptr) }?;
+    let message = unsafe { json_ptr.to_string() }.unwrap_or_default();
+    unsafe { CoTaskMemFree(Some(json_ptr.0 as _)) };
+
+    // Messages of the drop handler and the page itself are not commands
+    let Ok(request) = serde_json::from_str::<Value>(&message) else {
+        return Ok(());
+    };
+    if request["__zouni__"] != "command" {
+        return Ok(());
+    }
+
+    let command = request["command"].as_str().unwrap_or_default();
+    let result = if allowlist.iter().any(|allowed| allowed == command) {
+        dispatch(command, &request["args"], dispatcher)
+    } else {
+        Err(format!("{} is not allowed", command))
+    };
+
+    let response = match result {
+        Ok(result) => json!({ "__zouni__": "result", "id": request["id"], "result": result }),
+        Err(error) => json!({ "__zouni__": "result", "id": request["id"], "error": error }),
+    };
+
+    unsafe { webview.PostWebMessageAsJson(PCWSTR::from_raw(encode_wide(response.to_string()).as_ptr())) }
+}
+
+fn dispatch(command: &str, args: &Value, dispatcher: &Dispatcher) -> Result<Value, String> {
+    if let Some(result) = dispatcher(command, args) {
+        return result;
+    }
+
+    let path = || args["path"].as_str().ok_or(format!("path is required for {}", command));
+
+    match command {
+        "open_path" => crate::shell::open_path(path()?).map(|_| Value::Null),
+        "show_item_in_folder" => crate::shell::show_item_in_folder(path()?).map(|_| Value::Null),
+        "trash" => match args["paths"].as_array() {
+            Some(paths) => {
+                let paths: Vec<&str> = paths.iter().filter_map(|path| path.as_str()).collect();
+                crate::fs::trash_all(&paths).map(|_| Value::Null)
+            }
+            None => crate::fs::trash(path()?).map(|_| Value::Null),
+        },
+        _ => Err(format!("Unknown command: {}", command)),
+    }
+}
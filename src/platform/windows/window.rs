@@ -0,0 +1,257 @@
+use super::util::encode_wide;
+use crate::{dialog::DialogPlacement, fs::FileOperation, CompletionNotice};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+#[cfg(feature = "thumbbar")]
+use windows::Win32::UI::WindowsAndMessaging::WM_COMMAND;
+use windows::{
+    core::BOOL,
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+        Graphics::Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO},
+        System::Threading::GetCurrentProcessId,
+        UI::{
+            Shell::{DefSubclassProc, RemoveWindowSubclass, SetWindowSubclass, Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_TIP, NIIF_ERROR, NIIF_INFO, NIM_ADD, NIM_DELETE, NOTIFYICONDATAW},
+            WindowsAndMessaging::{
+                EnumWindows, FlashWindowEx, GetForegroundWindow, GetWindowRect, GetWindowThreadProcessId, IsWindowVisible, LoadIconW, MessageBeep, SetWindowPos, FLASHWINFO, FLASHW_ALL,
+                FLASHW_TIMERNOFG, HWND_TOPMOST, IDI_APPLICATION, MB_ICONASTERISK, MB_ICONHAND, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE, SWP_NOZORDER, WM_DESTROY,
+            },
+        },
+    },
+};
+
+const WINDOW_SUBCLASS_ID: usize = 200;
+// How long the notification icon stays so that its balloon can be shown
+const BALLOON_DURATION: Duration = Duration::from_secs(10);
+
+static BALLOON_ID: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    // Windows belong to the thread that created them, so the state is kept per thread
+    static WINDOWS: RefCell<HashMap<isize, WindowState>> = RefCell::new(HashMap::new());
+}
+
+/// State kept for a window until it is destroyed or detached
+#[derive(Default)]
+pub(crate) struct WindowState {
+    #[cfg(feature = "thumbbar")]
+    pub thumb_buttons: Option<super::thumbbar::ThumbButtons>,
+    #[cfg(feature = "drag_drop")]
+    pub drop_target: bool,
+}
+
+/// Runs the function with the state of the window, subclassing the window on first use
+pub(crate) fn with_state<T>(window_handle: isize, f: impl FnOnce(&mut WindowState) -> T) -> T {
+    WINDOWS.with(|windows| {
+        let mut windows = windows.borrow_mut();
+        let state = windows.entry(window_handle).or_insert_with(|| {
+            let _ = unsafe { SetWindowSubclass(HWND(window_handle as _), Some(subclass_proc), WINDOW_SUBCLASS_ID, 0) };
+            WindowState::default()
+        });
+        f(state)
+    })
+}
+
+/// Runs the function with the state of the window if it is attached
+pub(crate) fn with_existing_state<T>(window_handle: isize, f: impl FnOnce(&mut WindowState) -> T) -> Option<T> {
+    WINDOWS.with(|windows| windows.borrow_mut().get_mut(&window_handle).map(f))
+}
+
+/// Detaches all windows attached on the current thread
+pub(crate) fn detach_all() {
+    let window_handles: Vec<isize> = WINDOWS.with(|windows| windows.borrow().keys().copied().collect());
+    for window_handle in window_handles {
+        detach(window_handle);
+    }
+}
+
+/// Releases the state kept for the window and removes the subclass
+pub(crate) fn detach(window_handle: isize) {
+    let Some(state) = WINDOWS.with(|windows| windows.borrow_mut().remove(&window_handle)) else {
+        return;
+    };
+
+    #[cfg(feature = "drag_drop")]
+    if state.drop_target {
+        let _ = super::drag_drop::revoke(window_handle);
+    }
+
+    // Dropped outside of the borrow since releasing the state may run other callbacks
+    drop(state);
+
+    let _ = unsafe { RemoveWindowSubclass(HWND(window_handle as _), Some(subclass_proc), WINDOW_SUBCLASS_ID) };
+}
+
+/// Flashes the caption and taskbar button of the window until it comes to the foreground
+pub(crate) fn flash(window_handle: isize) {
+    let info = FLASHWINFO {
+        cbSize: size_of::<FLASHWINFO>() as _,
+        hwnd: HWND(window_handle as _),
+        dwFlags: FLASHW_ALL | FLASHW_TIMERNOFG,
+        uCount: 0,
+        dwTimeout: 0,
+    };
+    let _ = unsafe { FlashWindowEx(&info) };
+}
+
+/// Applies the placement to a window created by this crate
+///
+/// The monitor is used only when there is no parent
+pub(crate) fn place(window_handle: isize, placement: &DialogPlacement) {
+    let hwnd = HWND(window_handle as _);
+
+    if placement.keep_above {
+        let _ = unsafe { SetWindowPos(hwnd, Some(HWND_TOPMOST), 0, 0, 0, 0, SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE) };
+    }
+
+    if placement.urgent {
+        if let Some(parent) = placement.parent {
+            flash(parent);
+        }
+    }
+
+    if placement.parent.is_some() {
+        return;
+    }
+
+    let Some(area) = placement.monitor.and_then(monitor_work_area) else {
+        return;
+    };
+
+    let mut rect = RECT::default();
+    if unsafe { GetWindowRect(hwnd, &mut rect) }.is_ok() {
+        let width = rect.right - rect.left;
+        let height = rect.bottom - rect.top;
+        let x = area.left + (area.right - area.left - width) / 2;
+        let y = area.top + (area.bottom - area.top - height) / 2;
+        let _ = unsafe { SetWindowPos(hwnd, None, x, y, 0, 0, SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE) };
+    }
+}
+
+/* Work area of the monitor in the enumeration order */
+fn monitor_work_area(index: u32) -> Option<RECT> {
+    let mut monitors: Vec<HMONITOR> = Vec::new();
+    let _ = unsafe { EnumDisplayMonitors(None, None, Some(enum_monitor), LPARAM(&mut monitors as *mut _ as _)) };
+
+    let monitor = monitors.get(index as usize)?;
+    let mut info = MONITORINFO {
+        cbSize: size_of::<MONITORINFO>() as _,
+        ..Default::default()
+    };
+    unsafe { GetMonitorInfoW(*monitor, &mut info) }.as_bool().then_some(info.rcWork)
+}
+
+unsafe extern "system" fn enum_monitor(monitor: HMONITOR, _hdc: HDC, _rect: *mut RECT, data: LPARAM) -> BOOL {
+    let monitors = &mut *(data.0 as *mut Vec<HMONITOR>);
+    monitors.push(monitor);
+    true.into()
+}
+
+/// Shows a balloon notification and plays the system sound as the notice specifies unless a window of the app is in the foreground
+pub(crate) fn notify_completion(notice: &CompletionNotice, operation: FileOperation, count: usize, error: Option<&str>) {
+    let mut process_id = 0;
+    unsafe { GetWindowThreadProcessId(GetForegroundWindow(), Some(&mut process_id)) };
+    let is_app_focused = process_id == unsafe { GetCurrentProcessId() };
+
+    let Some((title, body)) = crate::notice::compose(notice, operation, count, error, is_app_focused) else {
+        return;
+    };
+
+    if notice.sound {
+        let _ = unsafe {
+            MessageBeep(if error.is_some() {
+                MB_ICONHAND
+            } else {
+                MB_ICONASTERISK
+            })
+        };
+    }
+
+    if notice.notification {
+        let _ = show_balloon(&title, &body, error.is_some());
+    }
+}
+
+/* Adds a notification area icon owned by a window of the app, removed after the balloon has been shown */
+fn show_balloon(title: &str, body: &str, is_error: bool) -> Result<(), String> {
+    let mut window_handle = HWND::default();
+    let _ = unsafe { EnumWindows(Some(enum_app_window), LPARAM(&mut window_handle as *mut _ as _)) };
+    if window_handle.is_invalid() {
+        return Err("No window to show notification".to_string());
+    }
+
+    let mut data = NOTIFYICONDATAW {
+        cbSize: size_of::<NOTIFYICONDATAW>() as _,
+        hWnd: window_handle,
+        uID: BALLOON_ID.fetch_add(1, Ordering::Relaxed),
+        uFlags: NIF_ICON | NIF_INFO | NIF_TIP,
+        hIcon: unsafe { LoadIconW(None, IDI_APPLICATION) }.map_err(|e| e.message())?,
+        dwInfoFlags: if is_error {
+            NIIF_ERROR
+        } else {
+            NIIF_INFO
+        },
+        ..Default::default()
+    };
+    copy_wide(&mut data.szTip, title);
+    copy_wide(&mut data.szInfoTitle, title);
+    copy_wide(&mut data.szInfo, body);
+
+    unsafe { Shell_NotifyIconW(NIM_ADD, &data) }.ok().map_err(|e| e.message())?;
+
+    let window_handle = window_handle.0 as isize;
+    let id = data.uID;
+    std::thread::spawn(move || {
+        std::thread::sleep(BALLOON_DURATION);
+        let data = NOTIFYICONDATAW {
+            cbSize: size_of::<NOTIFYICONDATAW>() as _,
+            hWnd: HWND(window_handle as _),
+            uID: id,
+            ..Default::default()
+        };
+        let _ = unsafe { Shell_NotifyIconW(NIM_DELETE, &data) };
+    });
+
+    Ok(())
+}
+
+/* Copies as much of the value as fits, leaving the terminating null */
+fn copy_wide(dest: &mut [u16], value: &str) {
+    let wide = encode_wide(value);
+    let len = (wide.len() - 1).min(dest.len() - 1);
+    dest[..len].copy_from_slice(&wide[..len]);
+}
+
+unsafe extern "system" fn enum_app_window(window: HWND, data: LPARAM) -> BOOL {
+    let mut process_id = 0;
+    GetWindowThreadProcessId(window, Some(&mut process_id));
+    if process_id == GetCurrentProcessId() && IsWindowVisible(window).as_bool() {
+        *(data.0 as *mut HWND) = window;
+        return false.into();
+    }
+    true.into()
+}
+
+unsafe extern "system" fn subclass_proc(window: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM, _uidsubclass: usize, _dwrefdata: usize) -> LRESULT {
+    match msg {
+        #[cfg(feature = "thumbbar")]
+        WM_COMMAND => {
+            if super::thumbbar::handle_command(window.0 as isize, wparam) {
+                return LRESULT(0);
+            }
+
+            DefSubclassProc(window, msg, wparam, lparam)
+        }
+
+        WM_DESTROY => {
+            detach(window.0 as isize);
+            DefSubclassProc(window, msg, wparam, lparam)
+        }
+
+        _ => DefSubclassProc(window, msg, wparam, lparam),
+    }
+}
@@ -0,0 +1,140 @@
+use crate::fs::list_volumes;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+static PROVIDER: RwLock<Option<Arc<dyn PolicyProvider>>> = RwLock::new(None);
+
+/// Decides whether shell and fs functions may proceed
+///
+/// Each method returns the reason as an error to deny the action
+pub trait PolicyProvider: Send + Sync {
+    /// Called before a file is opened or an application is launched
+    fn check_execute(&self, _file_path: &Path) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called for each item before it is copied or moved into `to`
+    fn check_transfer(&self, _from: &Path, _to: &Path) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called once with every item of a copy or move before anything is transferred
+    ///
+    /// Calls `check_transfer` for each item unless overridden to look up the destination once per operation
+    fn check_operation(&self, froms: &[&Path], to: &Path) -> Result<(), String> {
+        froms.iter().try_for_each(|from| self.check_transfer(from, to))
+    }
+}
+
+/// Built-in policy covering common enterprise restrictions
+#[derive(Debug, Clone, Default)]
+pub struct StandardPolicy {
+    /// Extensions without the leading dot, compared case-insensitively
+    pub blocked_extensions: Vec<String>,
+    pub block_removable_media: bool,
+    /// Limit for each file transferred, including every file inside transferred directories
+    pub max_file_size: Option<u64>,
+}
+
+impl PolicyProvider for StandardPolicy {
+    fn check_execute(&self, file_path: &Path) -> Result<(), String> {
+        let extension = file_path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+        if self.blocked_extensions.iter().any(|blocked| blocked.trim_start_matches('.').to_lowercase() == extension) {
+            return Err(format!("Execution of .{} files is blocked by policy", extension));
+        }
+        Ok(())
+    }
+
+    fn check_transfer(&self, from: &Path, to: &Path) -> Result<(), String> {
+        self.check_operation(&[from], to)
+    }
+
+    fn check_operation(&self, froms: &[&Path], to: &Path) -> Result<(), String> {
+        // Listing volumes runs lsblk on Linux, so the destination is looked up once for all items
+        if self.block_removable_media {
+            let volumes = list_volumes()?;
+            if volumes.iter().any(|volume| volume.is_removable && !volume.mount_point.is_empty() && to.starts_with(&volume.mount_point)) {
+                return Err("Copying to removable media is blocked by policy".to_string());
+            }
+        }
+
+        if let Some(max_file_size) = self.max_file_size {
+            froms.iter().try_for_each(|from| check_file_size(from, max_file_size))?;
+        }
+
+        Ok(())
+    }
+}
+
+/* Fails with the first file under the path that is larger than the limit */
+fn check_file_size(file_path: &Path, max_file_size: u64) -> Result<(), String> {
+    match std::fs::symlink_metadata(file_path) {
+        Ok(metadata) if metadata.is_dir() => match std::fs::read_dir(file_path) {
+            Ok(entries) => entries.flatten().try_for_each(|entry| check_file_size(&entry.path(), max_file_size)),
+            Err(_) => Ok(()),
+        },
+        Ok(metadata) if metadata.len() > max_file_size => Err(format!("{} exceeds the maximum file size allowed by policy", file_path.to_string_lossy())),
+        _ => Ok(()),
+    }
+}
+
+/// Sets the policy consulted by shell and fs functions
+pub fn set_provider<P: PolicyProvider + 'static>(provider: P) {
+    *PROVIDER.write().unwrap() = Some(Arc::new(provider));
+}
+
+/// Removes the policy so that every action is allowed
+pub fn clear_provider() {
+    *PROVIDER.write().unwrap() = None;
+}
+
+fn provider() -> Option<Arc<dyn PolicyProvider>> {
+    PROVIDER.read().unwrap().clone()
+}
+
+pub(crate) fn check_execute<P: AsRef<Path>>(file_path: P) -> Result<(), String> {
+    match provider() {
+        Some(provider) => provider.check_execute(file_path.as_ref()),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn check_transfer<P1: AsRef<Path>, P2: AsRef<Path>>(froms: &[P1], to: P2) -> Result<(), String> {
+    froms.iter().try_for_each(|from| check_inside_source(from.as_ref(), to.as_ref()))?;
+
+    match provider() {
+        Some(provider) => provider.check_operation(&froms.iter().map(|from| from.as_ref()).collect::<Vec<_>>(), to.as_ref()),
+        None => Ok(()),
+    }
+}
+
+/* Copying or moving a directory into its own subtree never ends, so it is denied whatever the provider says. Symlinks are resolved on both sides */
+fn check_inside_source(from: &Path, to: &Path) -> Result<(), String> {
+    let (Ok(source), Some(dest)) = (std::fs::canonicalize(from), canonicalize_nonexistent(to)) else {
+        return Ok(());
+    };
+
+    if source.is_dir() && dest.starts_with(&source) {
+        return Err(format!("Destination is inside the source:{}", from.to_string_lossy()));
+    }
+
+    Ok(())
+}
+
+/* A destination that does not exist yet is resolved through its nearest existing ancestor */
+fn canonicalize_nonexistent(path: &Path) -> Option<PathBuf> {
+    let mut missing = Vec::new();
+    let mut ancestor = path;
+    loop {
+        if let Ok(resolved) = std::fs::canonicalize(ancestor) {
+            return Some(missing.iter().rev().fold(resolved, |resolved, component| resolved.join(component)));
+        }
+        missing.push(ancestor.file_name()?);
+        ancestor = match ancestor.parent()? {
+            parent if parent.as_os_str().is_empty() => Path::new("."),
+            parent => parent,
+        };
+    }
+}
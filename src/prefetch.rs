@@ -0,0 +1,85 @@
+use crate::{CancelToken, PrefetchPriority, Size};
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Condvar, LazyLock, Mutex, Once},
+};
+
+const MAX_WORKERS: usize = 4;
+
+/// Warms the thumbnail cache for a file, ignoring failures
+pub(crate) type Warm = fn(&Path, Option<&Size>);
+
+struct Job {
+    file_path: PathBuf,
+    size: Option<Size>,
+    cancel_token: CancelToken,
+    warm: Warm,
+}
+
+static QUEUE: Mutex<VecDeque<Job>> = Mutex::new(VecDeque::new());
+static QUEUED: Condvar = Condvar::new();
+// Files queued or being warmed, so overlapping hints do not warm a file twice
+static PENDING: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+static WORKERS: Once = Once::new();
+
+/// Queues the files not pending yet, ahead of the queued files if the priority is High
+///
+/// Jobs cancelled since the last call are dropped first so that their files can be queued again
+pub(crate) fn enqueue<P: AsRef<Path>>(file_paths: &[P], size: Option<Size>, priority: PrefetchPriority, cancel_token: &CancelToken, warm: Warm) {
+    WORKERS.call_once(|| {
+        let count = std::thread::available_parallelism().map_or(1, |count| count.get()).min(MAX_WORKERS);
+        for _ in 0..count {
+            std::thread::spawn(work);
+        }
+    });
+
+    let mut pending = PENDING.lock().unwrap();
+    let mut queue = QUEUE.lock().unwrap();
+
+    queue.retain(|job| {
+        let is_cancelled = job.cancel_token.is_cancelled();
+        if is_cancelled {
+            pending.remove(&job.file_path);
+        }
+        !is_cancelled
+    });
+
+    let jobs: Vec<Job> = file_paths
+        .iter()
+        .filter(|file_path| pending.insert(file_path.as_ref().to_path_buf()))
+        .map(|file_path| Job {
+            file_path: file_path.as_ref().to_path_buf(),
+            size: size.clone(),
+            cancel_token: cancel_token.clone(),
+            warm,
+        })
+        .collect();
+
+    match priority {
+        PrefetchPriority::High => jobs.into_iter().rev().for_each(|job| queue.push_front(job)),
+        PrefetchPriority::Low => queue.extend(jobs),
+    }
+
+    QUEUED.notify_all();
+}
+
+fn work() {
+    loop {
+        let job = {
+            let mut queue = QUEUE.lock().unwrap();
+            loop {
+                if let Some(job) = queue.pop_front() {
+                    break job;
+                }
+                queue = QUEUED.wait(queue).unwrap();
+            }
+        };
+
+        if !job.cancel_token.is_cancelled() {
+            (job.warm)(&job.file_path, job.size.as_ref());
+        }
+
+        PENDING.lock().unwrap().remove(&job.file_path);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::PreflightReport;
+use std::path::Path;
+
+/// Sums the sizes of the files under the sources and collects the destination paths for which `is_too_long` returns true
+pub(crate) fn measure<P: AsRef<Path>>(froms: &[P], to: Option<&Path>, is_too_long: impl Fn(&Path) -> bool) -> Result<PreflightReport, String> {
+    let mut report = PreflightReport {
+        has_enough_space: true,
+        ..Default::default()
+    };
+
+    for from in froms {
+        let dest = to.map(|to| to.join(from.as_ref().file_name().unwrap_or_default()));
+        measure_entry(from.as_ref(), dest.as_deref(), &is_too_long, &mut report)?;
+    }
+
+    Ok(report)
+}
+
+fn measure_entry(from: &Path, dest: Option<&Path>, is_too_long: &impl Fn(&Path) -> bool, report: &mut PreflightReport) -> Result<(), String> {
+    let metadata = from.symlink_metadata().map_err(|e| format!("{}:{}", e, from.to_string_lossy()))?;
+
+    if let Some(dest) = dest {
+        if is_too_long(dest) {
+            report.too_long_paths.push(dest.to_string_lossy().to_string());
+        }
+    }
+
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(from).map_err(|e| e.to_string())?.flatten() {
+            let dest = dest.map(|dest| dest.join(entry.file_name()));
+            measure_entry(&entry.path(), dest.as_deref(), is_too_long, report)?;
+        }
+    } else {
+        report.total_size += metadata.len();
+        report.total_count += 1;
+    }
+
+    Ok(())
+}
+
+/// Fills in the destination volume of the measured operation
+pub(crate) fn complete(mut report: PreflightReport, available_space: Option<u64>, is_same_volume: bool) -> PreflightReport {
+    report.available_space = available_space;
+    report.is_same_volume = is_same_volume;
+    // Renames need no space
+    report.has_enough_space = is_same_volume || available_space.is_none_or(|available| available >= report.total_size);
+    report
+}
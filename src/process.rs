@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 use shared_child::SharedChild;
+#[cfg(target_os = "linux")]
+use std::os::unix::process::CommandExt;
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 use std::{
     collections::HashMap,
-    io::Read,
-    process::{Command, Stdio},
+    io::{Read, Write},
+    process::{ChildStdin, Command, Stdio},
     sync::{
         atomic::{AtomicU16, Ordering},
         Arc, LazyLock, Mutex,
@@ -35,6 +37,24 @@ pub struct Output {
     pub stderr: String,
 }
 
+/// Running process, as listed by `list` and `find_locking_processes`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    // Empty if the process belongs to another user or has exited
+    pub exe_path: String,
+    // Resident set size, or the working set on Windows, in bytes
+    pub memory: u64,
+}
+
+/// A line written by the program. `\r` also ends a line, so progress that tools redraw in place arrives as it is written
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
 impl Output {
     fn error(code: Option<i32>, error: Option<String>) -> Self {
         Self {
@@ -49,6 +69,7 @@ impl Output {
 }
 
 static CHILDREN: LazyLock<Mutex<HashMap<String, Arc<SharedChild>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static STDINS: LazyLock<Mutex<HashMap<String, ChildStdin>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
 static UUID: AtomicU16 = AtomicU16::new(0);
 
 pub async fn spawn(option: SpawnOption) -> Result<Output, Output> {
@@ -58,9 +79,7 @@ pub async fn spawn(option: SpawnOption) -> Result<Output, Output> {
     }
     command.stdout(Stdio::piped());
     command.stderr(Stdio::piped());
-
-    #[cfg(windows)]
-    command.creation_flags(CREATE_NO_WINDOW.0);
+    configure(&mut command);
 
     let token = if let Some(token) = option.cancellation_token {
         token
@@ -74,8 +93,10 @@ pub async fn spawn(option: SpawnOption) -> Result<Output, Output> {
     }
 
     smol::spawn(async move {
-        let mut children = CHILDREN.lock().unwrap();
-        let child = children.get(&token).unwrap();
+        // The lock is released while waiting so that kill and kill_tree can reach the child
+        let Some(child) = CHILDREN.lock().unwrap().get(&token).cloned() else {
+            return Err(Output::error(None, Some("Process was killed".to_string())));
+        };
         match child.wait() {
             Ok(exit_status) => {
                 let stdout = if let Some(mut out) = child.take_stdout() {
@@ -94,7 +115,7 @@ pub async fn spawn(option: SpawnOption) -> Result<Output, Output> {
                     String::new()
                 };
 
-                children.remove(&token);
+                CHILDREN.lock().unwrap().remove(&token);
 
                 let result = Output {
                     status: CommandStatus {
@@ -126,6 +147,112 @@ pub async fn spawn(option: SpawnOption) -> Result<Output, Output> {
     .await
 }
 
+/// Spawns the program and passes each line of stdout and stderr to the callback as it arrives, instead of buffering them until exit
+///
+/// Stdin stays open for write_stdin until close_stdin is called or the program exits. The cancellation token is required to write to stdin
+pub async fn spawn_streaming<F: FnMut(OutputLine) + Send + 'static>(option: SpawnOption, callback: F) -> Result<CommandStatus, CommandStatus> {
+    let mut command = Command::new(option.program);
+    if let Some(args) = option.args {
+        command.args(args);
+    }
+    command.stdin(Stdio::piped());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    configure(&mut command);
+
+    let token = if let Some(token) = option.cancellation_token {
+        token
+    } else {
+        UUID.fetch_add(1, Ordering::Relaxed).to_string()
+    };
+
+    let error = |e: std::io::Error| CommandStatus {
+        success: false,
+        code: e.raw_os_error(),
+        error: Some(e.to_string()),
+    };
+    let child = Arc::new(SharedChild::spawn(&mut command).map_err(error)?);
+    let stdout = child.take_stdout();
+    let stderr = child.take_stderr();
+    {
+        CHILDREN.lock().unwrap().insert(token.clone(), child.clone());
+        if let Some(stdin) = child.take_stdin() {
+            STDINS.lock().unwrap().insert(token.clone(), stdin);
+        }
+    }
+
+    smol::unblock(move || {
+        let callback = Arc::new(Mutex::new(callback));
+        let stdout_callback = callback.clone();
+        let stdout_reader = std::thread::spawn(move || {
+            if let Some(stdout) = stdout {
+                read_lines(stdout, |line| (stdout_callback.lock().unwrap())(OutputLine::Stdout(line)));
+            }
+        });
+        if let Some(stderr) = stderr {
+            read_lines(stderr, |line| (callback.lock().unwrap())(OutputLine::Stderr(line)));
+        }
+        let _ = stdout_reader.join();
+
+        let result = child.wait();
+        CHILDREN.lock().unwrap().remove(&token);
+        STDINS.lock().unwrap().remove(&token);
+
+        match result {
+            Ok(exit_status) if exit_status.success() => Ok(CommandStatus {
+                success: true,
+                code: exit_status.code(),
+                error: None,
+            }),
+            Ok(exit_status) => Err(CommandStatus {
+                success: false,
+                code: exit_status.code(),
+                error: None,
+            }),
+            Err(e) => Err(error(e)),
+        }
+    })
+    .await
+}
+
+/* Splits on \r as well as \n, and skips the empty lines that \r\n would produce */
+fn read_lines<R: Read>(mut reader: R, mut callback: impl FnMut(String)) {
+    let mut buffer = [0u8; 4096];
+    let mut line = Vec::new();
+
+    while let Ok(read) = reader.read(&mut buffer) {
+        if read == 0 {
+            break;
+        }
+        for &byte in &buffer[..read] {
+            if byte == b'\n' || byte == b'\r' {
+                if !line.is_empty() {
+                    callback(String::from_utf8_lossy(&line).to_string());
+                    line.clear();
+                }
+            } else {
+                line.push(byte);
+            }
+        }
+    }
+
+    if !line.is_empty() {
+        callback(String::from_utf8_lossy(&line).to_string());
+    }
+}
+
+/// Writes to stdin of a program started by spawn_streaming
+pub fn write_stdin(cancellation_token: &str, input: &[u8]) -> Result<(), String> {
+    let mut stdins = STDINS.lock().unwrap();
+    let stdin = stdins.get_mut(cancellation_token).ok_or_else(|| format!("No process to write to:{}", cancellation_token))?;
+    stdin.write_all(input).and_then(|_| stdin.flush()).map_err(|e| e.to_string())
+}
+
+/// Closes stdin of a program started by spawn_streaming, so that a program reading until EOF can finish
+pub fn close_stdin(cancellation_token: &str) {
+    STDINS.lock().unwrap().remove(cancellation_token);
+}
+
 pub fn kill(cancellation_token: String) -> Result<(), String> {
     if let Ok(mut children) = CHILDREN.try_lock() {
         if children.contains_key(&cancellation_token) {
@@ -137,6 +264,83 @@ pub fn kill(cancellation_token: String) -> Result<(), String> {
     Ok(())
 }
 
+/// Kills the program started with the token together with the processes it started
+///
+/// Kills the process group on Linux, where each program is started in its own group. On Windows, processes whose parent has already exited are not found
+pub fn kill_tree(cancellation_token: String) -> Result<(), String> {
+    let child = CHILDREN.lock().unwrap().remove(&cancellation_token);
+    STDINS.lock().unwrap().remove(&cancellation_token);
+
+    if let Some(child) = child {
+        kill_descendants(child.id());
+        child.kill().map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/* Hides the console window on Windows, and starts a new process group on Linux so that kill_tree can signal the descendants */
+fn configure(command: &mut Command) {
+    #[cfg(target_os = "windows")]
+    command.creation_flags(CREATE_NO_WINDOW.0);
+
+    #[cfg(target_os = "linux")]
+    command.process_group(0);
+}
+
+#[cfg(target_os = "linux")]
+fn kill_descendants(pid: u32) {
+    // The group ID is the PID of the program, which leads the group. Fails harmlessly if the group is already gone
+    unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) };
+}
+
+/* Walks a snapshot of parent IDs, since Windows has no process groups that can be killed at once without a job object */
+#[cfg(target_os = "windows")]
+fn kill_descendants(pid: u32) {
+    use windows::Win32::{
+        Foundation::CloseHandle,
+        System::{
+            Diagnostics::ToolHelp::{CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS},
+            Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE},
+        },
+    };
+
+    let Ok(snapshot) = (unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }) else {
+        return;
+    };
+    let mut processes = Vec::new();
+    let mut entry = PROCESSENTRY32W {
+        dwSize: size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    if unsafe { Process32FirstW(snapshot, &mut entry) }.is_ok() {
+        loop {
+            processes.push((entry.th32ProcessID, entry.th32ParentProcessID));
+            if unsafe { Process32NextW(snapshot, &mut entry) }.is_err() {
+                break;
+            }
+        }
+    }
+    let _ = unsafe { CloseHandle(snapshot) };
+
+    let mut descendants = vec![pid];
+    let mut index = 0;
+    while index < descendants.len() {
+        let parent = descendants[index];
+        let children: Vec<u32> = processes.iter().filter(|(child, ppid)| *ppid == parent && *child != parent && !descendants.contains(child)).map(|(child, _)| *child).collect();
+        descendants.extend(children);
+        index += 1;
+    }
+
+    // The program itself is killed by the caller
+    for descendant in descendants.into_iter().skip(1) {
+        if let Ok(process) = unsafe { OpenProcess(PROCESS_TERMINATE, false, descendant) } {
+            let _ = unsafe { TerminateProcess(process, 1) };
+            let _ = unsafe { CloseHandle(process) };
+        }
+    }
+}
+
 pub fn clear() {
     let children = {
         let mut lock = CHILDREN.lock().unwrap();
@@ -145,4 +349,293 @@ pub fn clear() {
     for child in children.into_values() {
         let _ = child.kill();
     }
+    STDINS.lock().unwrap().clear();
+}
+
+/// Runs the program and returns its stdout as bytes, killing it if it does not exit within `timeout`
+///
+/// Fails with stderr if the program exits with an error, or with the exit code if it crashes without writing to stderr
+pub(crate) fn run_with_timeout<S: AsRef<std::ffi::OsStr>>(program: &std::path::Path, args: &[S], timeout: std::time::Duration) -> Result<Vec<u8>, String> {
+    use std::time::{Duration, Instant};
+
+    let mut command = Command::new(program);
+    command.args(args);
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    configure(&mut command);
+
+    let child = SharedChild::spawn(&mut command).map_err(|e| e.to_string())?;
+
+    // Pipes are drained while waiting so that the child does not block on a full pipe
+    let mut stdout = child.take_stdout().ok_or("Failed to read stdout")?;
+    let mut stderr = child.take_stderr().ok_or("Failed to read stderr")?;
+    let stdout_reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = String::new();
+        let _ = stderr.read_to_string(&mut buf);
+        buf
+    });
+
+    let deadline = Instant::now() + timeout;
+    let exit_status = loop {
+        if let Some(exit_status) = child.try_wait().map_err(|e| e.to_string())? {
+            break exit_status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("Timed out after {}ms", timeout.as_millis()));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    let stdout = stdout_reader.join().map_err(|_| "Failed to read stdout".to_string())?.map_err(|e| e.to_string())?;
+    let stderr = stderr_reader.join().unwrap_or_default();
+
+    if exit_status.success() {
+        Ok(stdout)
+    } else if !stderr.trim().is_empty() {
+        Err(stderr.trim().to_string())
+    } else {
+        Err(format!("Exited with code {:?}", exit_status.code()))
+    }
+}
+
+/// Lists the running processes
+pub fn list() -> Result<Vec<ProcessInfo>, String> {
+    list_processes()
+}
+
+/// Lists the processes that have the file, or a file under the directory, open
+///
+/// Uses the Restart Manager on Windows, and the open files and mapped files in /proc on Linux as fuser does. Processes of other users are not found on Linux without root
+pub fn find_locking_processes<P: AsRef<std::path::Path>>(file_path: P) -> Result<Vec<ProcessInfo>, String> {
+    find_lockers(file_path.as_ref())
+}
+
+/// Terminates the process, for example one found by `find_locking_processes`
+///
+/// Sends SIGTERM on Linux so that the process can exit cleanly. Windows ends it immediately
+pub fn terminate(pid: u32) -> Result<(), String> {
+    terminate_process(pid)
+}
+
+/* Processes to name when an item fails with a sharing violation, or None if none are found */
+pub(crate) fn in_use_by(file_path: &std::path::Path) -> Option<Vec<ProcessInfo>> {
+    find_lockers(file_path).ok().filter(|processes| !processes.is_empty())
+}
+
+#[cfg(target_os = "linux")]
+fn list_processes() -> Result<Vec<ProcessInfo>, String> {
+    let entries = std::fs::read_dir("/proc").map_err(|e| e.to_string())?;
+    Ok(entries.flatten().filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok()).filter_map(process_info).collect())
+}
+
+#[cfg(target_os = "linux")]
+fn process_info(pid: u32) -> Option<ProcessInfo> {
+    let proc_dir = std::path::PathBuf::from(format!("/proc/{}", pid));
+    // The process may exit while the list is built
+    let name = std::fs::read_to_string(proc_dir.join("comm")).ok()?.trim_end().to_string();
+    let exe_path = std::fs::read_link(proc_dir.join("exe")).map(|exe| exe.to_string_lossy().to_string()).unwrap_or_default();
+    // The second field of statm is the resident set in pages
+    let resident_pages = std::fs::read_to_string(proc_dir.join("statm")).ok().and_then(|statm| statm.split_whitespace().nth(1)?.parse::<u64>().ok()).unwrap_or_default();
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as u64;
+
+    Some(ProcessInfo {
+        pid,
+        name,
+        exe_path,
+        memory: resident_pages * page_size,
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn find_lockers(file_path: &std::path::Path) -> Result<Vec<ProcessInfo>, String> {
+    let target = std::fs::canonicalize(file_path).map_err(|e| format!("{}:{}", e, file_path.to_string_lossy()))?;
+    let entries = std::fs::read_dir("/proc").map_err(|e| e.to_string())?;
+
+    let lockers = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            let proc_dir = std::path::PathBuf::from(format!("/proc/{}", pid));
+            let has_open_file = std::fs::read_dir(proc_dir.join("fd")).is_ok_and(|fds| fds.flatten().any(|fd| std::fs::read_link(fd.path()).is_ok_and(|open| open.starts_with(&target))));
+            // Libraries and executables are mapped rather than open. The path is the last column
+            let has_mapped_file = || {
+                std::fs::read_to_string(proc_dir.join("maps"))
+                    .is_ok_and(|maps| maps.lines().filter_map(|line| line.find('/').map(|start| &line[start..])).any(|mapped| std::path::Path::new(mapped).starts_with(&target)))
+            };
+            has_open_file || has_mapped_file()
+        })
+        .filter_map(process_info)
+        .collect();
+
+    Ok(lockers)
+}
+
+#[cfg(target_os = "linux")]
+fn terminate_process(pid: u32) -> Result<(), String> {
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn list_processes() -> Result<Vec<ProcessInfo>, String> {
+    use windows::Win32::{
+        Foundation::CloseHandle,
+        System::Diagnostics::ToolHelp::{CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS},
+    };
+
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }.map_err(|e| e.message())?;
+    let mut processes = Vec::new();
+    let mut entry = PROCESSENTRY32W {
+        dwSize: size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    if unsafe { Process32FirstW(snapshot, &mut entry) }.is_ok() {
+        loop {
+            let len = entry.szExeFile.iter().position(|c| *c == 0).unwrap_or(entry.szExeFile.len());
+            processes.push(process_info(entry.th32ProcessID, String::from_utf16_lossy(&entry.szExeFile[..len])));
+            if unsafe { Process32NextW(snapshot, &mut entry) }.is_err() {
+                break;
+            }
+        }
+    }
+    let _ = unsafe { CloseHandle(snapshot) };
+
+    Ok(processes)
+}
+
+/* Adds the path and memory, which are left empty for processes that cannot be opened such as system processes */
+#[cfg(target_os = "windows")]
+fn process_info(pid: u32, name: String) -> ProcessInfo {
+    use windows::{
+        core::PWSTR,
+        Win32::{
+            Foundation::{CloseHandle, MAX_PATH},
+            System::{
+                ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+                Threading::{OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION},
+            },
+        },
+    };
+
+    let mut info = ProcessInfo {
+        pid,
+        name,
+        ..Default::default()
+    };
+
+    // Limited information is enough for the memory counters since Windows 8.1
+    let Ok(process) = (unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }) else {
+        return info;
+    };
+
+    let mut buffer = vec![0u16; MAX_PATH as usize * 4];
+    let mut len = buffer.len() as u32;
+    if unsafe { QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR::from_raw(buffer.as_mut_ptr()), &mut len) }.is_ok() {
+        info.exe_path = String::from_utf16_lossy(&buffer[..len as usize]);
+    }
+
+    let mut counters = PROCESS_MEMORY_COUNTERS::default();
+    if unsafe { K32GetProcessMemoryInfo(process, &mut counters, size_of::<PROCESS_MEMORY_COUNTERS>() as u32) }.as_bool() {
+        info.memory = counters.WorkingSetSize as u64;
+    }
+    let _ = unsafe { CloseHandle(process) };
+
+    info
+}
+
+#[cfg(target_os = "windows")]
+fn find_lockers(file_path: &std::path::Path) -> Result<Vec<ProcessInfo>, String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::{
+        core::{PCWSTR, PWSTR},
+        Win32::{
+            Foundation::ERROR_MORE_DATA,
+            System::RestartManager::{RmEndSession, RmGetList, RmRegisterResources, RmStartSession, CCH_RM_SESSION_KEY, RM_PROCESS_INFO},
+        },
+    };
+    // Files of a directory are registered one by one, since the Restart Manager only takes files
+    const MAX_RESOURCES: usize = 4096;
+
+    let mut files = Vec::new();
+    collect_files(file_path, &mut files, MAX_RESOURCES);
+    let wide_files: Vec<Vec<u16>> = files.iter().map(|file| file.as_os_str().encode_wide().chain(std::iter::once(0)).collect()).collect();
+    let resources: Vec<PCWSTR> = wide_files.iter().map(|file| PCWSTR::from_raw(file.as_ptr())).collect();
+    if resources.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut session = 0;
+    let mut session_key = [0u16; CCH_RM_SESSION_KEY as usize + 1];
+    unsafe { RmStartSession(&mut session, 0, PWSTR::from_raw(session_key.as_mut_ptr())) }.ok().map_err(|e| e.message())?;
+
+    let result = (|| {
+        unsafe { RmRegisterResources(session, Some(&resources), None, None) }.ok().map_err(|e| e.message())?;
+
+        let mut processes: Vec<RM_PROCESS_INFO> = Vec::new();
+        loop {
+            let mut needed = 0;
+            let mut count = processes.len() as u32;
+            let mut reasons = 0;
+            let error = unsafe { RmGetList(session, &mut needed, &mut count, Some(processes.as_mut_ptr()), &mut reasons) };
+            if error == ERROR_MORE_DATA {
+                // Processes may have started in between, so ask again with the new size
+                processes.resize(needed as usize, RM_PROCESS_INFO::default());
+                continue;
+            }
+            error.ok().map_err(|e| e.message())?;
+            processes.truncate(count as usize);
+            break;
+        }
+
+        Ok(processes
+            .iter()
+            .map(|process| {
+                let len = process.strAppName.iter().position(|c| *c == 0).unwrap_or(process.strAppName.len());
+                process_info(process.Process.dwProcessId, String::from_utf16_lossy(&process.strAppName[..len]))
+            })
+            .collect())
+    })();
+
+    let _ = unsafe { RmEndSession(session) };
+
+    result
+}
+
+#[cfg(target_os = "windows")]
+fn collect_files(path: &std::path::Path, files: &mut Vec<std::path::PathBuf>, limit: usize) {
+    if files.len() >= limit {
+        return;
+    }
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+                collect_files(&entry.path(), files, limit);
+            }
+        }
+        Ok(_) => files.push(path.to_path_buf()),
+        Err(_) => {}
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn terminate_process(pid: u32) -> Result<(), String> {
+    use windows::Win32::{
+        Foundation::CloseHandle,
+        System::Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE},
+    };
+
+    let process = unsafe { OpenProcess(PROCESS_TERMINATE, false, pid) }.map_err(|e| e.message())?;
+    let result = unsafe { TerminateProcess(process, 1) }.map_err(|e| e.message());
+    let _ = unsafe { CloseHandle(process) };
+    result
 }
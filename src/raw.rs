@@ -0,0 +1,188 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const EXTENSIONS: [&str; 8] = ["cr2", "nef", "nrw", "arw", "srf", "sr2", "dng", "pef"];
+const COMPRESSION: u16 = 0x0103;
+const STRIP_OFFSETS: u16 = 0x0111;
+const ORIENTATION: u16 = 0x0112;
+const STRIP_BYTE_COUNTS: u16 = 0x0117;
+const SUB_IFDS: u16 = 0x014A;
+const JPEG_OFFSET: u16 = 0x0201;
+const JPEG_LENGTH: u16 = 0x0202;
+const TYPE_SHORT: u16 = 3;
+const COMPRESSION_OLD_JPEG: u32 = 6;
+const COMPRESSION_JPEG: u32 = 7;
+/* Limits for broken files, so that IFDs cannot loop and a bad length cannot allocate too much */
+const MAX_IFDS: usize = 32;
+const MAX_PREVIEW_SIZE: u32 = 64 * 1024 * 1024;
+
+/// JPEG preview embedded in a RAW photo. It is not rotated to the orientation of the photo
+pub(crate) struct Preview {
+    pub jpeg: Vec<u8>,
+    pub orientation: u16,
+}
+
+/// Whether the file is a TIFF based RAW photo such as CR2, NEF or ARW
+pub(crate) fn is_raw(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str()))
+}
+
+/// Reads the largest embedded JPEG preview without reading the RAW data
+pub(crate) fn read_preview(path: &Path) -> Option<Preview> {
+    let mut tiff = Tiff::open(path)?;
+    let ifd0 = tiff.u32(4)? as u64;
+
+    let mut ifds = vec![ifd0];
+    let mut visited = 0;
+    let mut orientation = 1;
+    let mut largest: Option<(u32, u32)> = None;
+
+    while let Some(ifd) = ifds.pop() {
+        visited += 1;
+        if ifd == 0 || visited > MAX_IFDS {
+            continue;
+        }
+        let Some(entries) = tiff.entries(ifd) else {
+            continue;
+        };
+
+        let (mut jpeg_offset, mut jpeg_length, mut strip_offset, mut strip_length, mut compression) = (None, None, None, None, None);
+        for &(tag, field_type, count, entry) in &entries {
+            match tag {
+                ORIENTATION if ifd == ifd0 => orientation = tiff.value(entry, field_type).filter(|value| (1..=8).contains(value)).unwrap_or(1) as u16,
+                COMPRESSION => compression = tiff.value(entry, field_type),
+                JPEG_OFFSET => jpeg_offset = tiff.value(entry, field_type),
+                JPEG_LENGTH => jpeg_length = tiff.value(entry, field_type),
+                // Previews stored as strips have a single strip
+                STRIP_OFFSETS if count == 1 => strip_offset = tiff.value(entry, field_type),
+                STRIP_BYTE_COUNTS if count == 1 => strip_length = tiff.value(entry, field_type),
+                // Offsets of more than one SubIFD do not fit in the entry and are stored elsewhere
+                SUB_IFDS if count == 1 => ifds.extend(tiff.u32(entry + 8).map(u64::from)),
+                SUB_IFDS => {
+                    let start = tiff.u32(entry + 8).unwrap_or_default() as u64;
+                    ifds.extend((0..count.min(MAX_IFDS as u32) as u64).filter_map(|index| tiff.u32(start + index * 4)).map(u64::from));
+                }
+                _ => {}
+            }
+        }
+
+        let mut candidates = vec![jpeg_offset.zip(jpeg_length)];
+        if matches!(compression, Some(COMPRESSION_OLD_JPEG) | Some(COMPRESSION_JPEG)) {
+            candidates.push(strip_offset.zip(strip_length));
+        }
+        for (offset, length) in candidates.into_iter().flatten() {
+            if length <= MAX_PREVIEW_SIZE && largest.is_none_or(|(_, largest_length)| length > largest_length) && tiff.is_baseline_jpeg(offset as u64) {
+                largest = Some((offset, length));
+            }
+        }
+
+        if let Some(next) = tiff.u32(ifd + 2 + entries.len() as u64 * 12) {
+            ifds.push(next as u64);
+        }
+    }
+
+    let (offset, length) = largest?;
+    Some(Preview {
+        jpeg: tiff.read(offset as u64, length as usize)?,
+        orientation,
+    })
+}
+
+/* Reads values at offsets from the start of the file, which is where TIFF offsets are counted from */
+struct Tiff {
+    reader: BufReader<File>,
+    little_endian: bool,
+}
+
+impl Tiff {
+    fn open(path: &Path) -> Option<Self> {
+        let mut reader = BufReader::new(File::open(path).ok()?);
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).ok()?;
+
+        let little_endian = match header {
+            [b'I', b'I', 42, 0] => true,
+            [b'M', b'M', 0, 42] => false,
+            _ => return None,
+        };
+
+        Some(Self {
+            reader,
+            little_endian,
+        })
+    }
+
+    fn read(&mut self, offset: u64, len: usize) -> Option<Vec<u8>> {
+        let mut bytes = vec![0u8; len];
+        self.reader.seek(SeekFrom::Start(offset)).ok()?;
+        self.reader.read_exact(&mut bytes).ok()?;
+        Some(bytes)
+    }
+
+    fn u16(&mut self, offset: u64) -> Option<u16> {
+        let bytes: [u8; 2] = self.read(offset, 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&mut self, offset: u64) -> Option<u32> {
+        let bytes: [u8; 4] = self.read(offset, 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /* Tag, type, count and the offset of each 12 byte entry */
+    fn entries(&mut self, ifd: u64) -> Option<Vec<(u16, u16, u32, u64)>> {
+        let count = self.u16(ifd)? as u64;
+        (0..count)
+            .map(|index| {
+                let offset = ifd + 2 + index * 12;
+                Some((self.u16(offset)?, self.u16(offset + 2)?, self.u32(offset + 4)?, offset))
+            })
+            .collect()
+    }
+
+    /* A single SHORT or LONG value, which is stored in the entry itself */
+    fn value(&mut self, entry: u64, field_type: u16) -> Option<u32> {
+        if field_type == TYPE_SHORT {
+            self.u16(entry + 8).map(u32::from)
+        } else {
+            self.u32(entry + 8)
+        }
+    }
+
+    /* The RAW data of some formats is lossless JPEG, which image decoders cannot read */
+    fn is_baseline_jpeg(&mut self, offset: u64) -> bool {
+        if self.read(offset, 2).is_none_or(|soi| soi != [0xFF, 0xD8]) {
+            return false;
+        }
+
+        let mut position = offset + 2;
+        for _ in 0..MAX_IFDS {
+            let Some(header) = self.read(position, 4) else {
+                return false;
+            };
+            if header[0] != 0xFF {
+                return false;
+            }
+            match header[1] {
+                // Baseline, extended sequential and progressive
+                0xC0..=0xC2 => return true,
+                // Other frame types and the start of scan before any frame
+                0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA => return false,
+                _ => position += 2 + u16::from_be_bytes([header[2], header[3]]) as u64,
+            }
+        }
+
+        false
+    }
+}
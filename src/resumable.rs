@@ -0,0 +1,99 @@
+use crate::{
+    fs::{OperationStatus, Response},
+    RetryOptions,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Copies the file into the directory, resuming from the last copied offset when reading or writing fails
+///
+/// The retries and the backoff start over once an attempt copies any data
+///
+/// `native_path` converts the source and destination paths before they are opened, such as adding the extended-length prefix on Windows
+pub(crate) fn copy_resumable(from: &Path, to: &Path, options: RetryOptions, callback: impl FnMut(OperationStatus) -> Response, native_path: impl Fn(&Path) -> PathBuf) -> Result<(), String> {
+    let mut callback = crate::throttle::throttled(callback);
+    crate::policy::check_transfer(&[from], to)?;
+    let _guard = crate::lifecycle::begin_operation()?;
+
+    let name = from.file_name().ok_or(format!("Invalid path:{}", from.to_string_lossy()))?;
+    let source = native_path(from);
+    let dest = native_path(&to.join(name));
+    let total = std::fs::metadata(&source).map_err(|e| e.to_string())?.len() as i64;
+
+    if callback(OperationStatus::Start(name.to_string_lossy().to_string())) == Response::Cancel {
+        return Ok(());
+    }
+
+    // Removed unless the copy is completed
+    let partial = crate::cleanup::PartialFile::new(&dest);
+
+    let mut offset = 0;
+    let mut retries = 0;
+    let mut backoff = options.initial_backoff_ms;
+
+    loop {
+        let resumed_at = offset;
+        match copy_chunks(&source, &dest, &mut offset, total, options.chunk_size, &mut callback) {
+            Ok(true) => break,
+            Ok(false) => return Ok(()),
+            Err(e) => {
+                // Only consecutive failures without progress count toward max_retries
+                if offset > resumed_at {
+                    retries = 0;
+                    backoff = options.initial_backoff_ms;
+                }
+
+                if retries >= options.max_retries {
+                    callback(OperationStatus::Error(e.clone()));
+                    return Err(e);
+                }
+
+                std::thread::sleep(Duration::from_millis(backoff));
+                backoff = (backoff * 2).min(options.max_backoff_ms);
+                retries += 1;
+
+                if callback(OperationStatus::Retry(retries)) == Response::Cancel {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    partial.complete();
+    callback(OperationStatus::End);
+    Ok(())
+}
+
+/* Returns false if cancelled */
+fn copy_chunks(source: &Path, dest: &Path, offset: &mut i64, total: i64, chunk_size: usize, callback: &mut impl FnMut(OperationStatus) -> Response) -> Result<bool, String> {
+    let mut input = File::open(source).map_err(|e| e.to_string())?;
+    input.seek(SeekFrom::Start(*offset as u64)).map_err(|e| e.to_string())?;
+
+    let mut output = OpenOptions::new().create(true).write(true).truncate(*offset == 0).open(dest).map_err(|e| e.to_string())?;
+    // Discard bytes written after the last successful chunk
+    output.set_len(*offset as u64).map_err(|e| e.to_string())?;
+    output.seek(SeekFrom::Start(*offset as u64)).map_err(|e| e.to_string())?;
+
+    let mut buffer = vec![0u8; chunk_size];
+    loop {
+        let len = input.read(&mut buffer).map_err(|e| e.to_string())?;
+        if len == 0 {
+            break;
+        }
+
+        output.write_all(&buffer[..len]).map_err(|e| e.to_string())?;
+
+        *offset += len as i64;
+
+        if crate::lifecycle::is_shutting_down() || callback(OperationStatus::Progress(*offset, total)) == Response::Cancel {
+            return Ok(false);
+        }
+    }
+
+    output.flush().map_err(|e| e.to_string())?;
+    Ok(true)
+}
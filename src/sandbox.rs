@@ -0,0 +1,58 @@
+use crate::Size;
+use std::{ffi::OsString, io::Write, path::Path, sync::RwLock, time::Duration};
+
+// First argument of the helper process, followed by the file path, width and height
+const HELPER_ARG: &str = "--zouni-thumbnail-helper";
+
+static TIMEOUT: RwLock<Option<Duration>> = RwLock::new(None);
+
+pub(crate) fn set(timeout: Option<Duration>) {
+    *TIMEOUT.write().unwrap() = timeout;
+}
+
+/// Time the helper may take before it is killed, None if thumbnails are extracted in process
+pub(crate) fn timeout() -> Option<Duration> {
+    *TIMEOUT.read().unwrap()
+}
+
+/// Extracts the thumbnail in a new process of the current executable, which is killed after the timeout
+pub(crate) fn extract(path: &Path, size: Option<Size>, timeout: Duration) -> Result<Vec<u8>, String> {
+    let program = std::env::current_exe().map_err(|e| e.to_string())?;
+    let (width, height) = size.map_or((0, 0), |size| (size.width, size.height));
+    let args: [OsString; 4] = [HELPER_ARG.into(), path.into(), width.to_string().into(), height.to_string().into()];
+
+    crate::process::run_with_timeout(&program, &args, timeout).map_err(|e| format!("{}:{}", e, path.to_string_lossy()))
+}
+
+/// Extracts the thumbnail named by the arguments, writes it to stdout and exits if the process was started by `extract`
+pub(crate) fn run_helper<F: FnOnce(&Path, Option<Size>) -> Result<Vec<u8>, String>>(extract: F) {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    if args.get(1).is_none_or(|arg| arg != HELPER_ARG) {
+        return;
+    }
+
+    let parse = |index: usize| args.get(index).and_then(|arg| arg.to_str()).and_then(|arg| arg.parse::<u32>().ok());
+    let (Some(path), Some(width), Some(height)) = (args.get(2), parse(3), parse(4)) else {
+        eprint!("Invalid helper arguments");
+        std::process::exit(2);
+    };
+    let size = Some(Size {
+        width,
+        height,
+    })
+    .filter(|size| size.width > 0 && size.height > 0);
+
+    match extract(Path::new(path), size) {
+        Ok(thumbnail) => {
+            // exit does not flush stdout
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(&thumbnail);
+            let _ = stdout.flush();
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprint!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
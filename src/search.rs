@@ -0,0 +1,497 @@
+#[cfg(all(feature = "instant_search", target_os = "linux"))]
+use crate::platform::linux::locate;
+#[cfg(all(feature = "instant_search", target_os = "windows"))]
+use crate::platform::windows::locate;
+use crate::{CancelToken, Dirent};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Condvar, Mutex},
+};
+
+#[cfg(target_os = "windows")]
+const EXTENSION: &str = "searchms";
+#[cfg(target_os = "linux")]
+const EXTENSION: &str = "json";
+const MAX_WORKERS: usize = 8;
+/* Larger files are not searched for content */
+const MAX_CONTENT_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SearchQuery {
+    // Directory to search in
+    pub location: PathBuf,
+    // File name with wildcards such as "*.txt", or a part of the name
+    pub pattern: String,
+    pub recursive: bool,
+    // Treats the pattern as a regular expression found anywhere in the name
+    #[serde(default)]
+    pub is_regex: bool,
+    #[serde(default)]
+    pub min_size: Option<u64>,
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    #[serde(default)]
+    pub modified_after_ms: Option<u64>,
+    #[serde(default)]
+    pub modified_before_ms: Option<u64>,
+    // Leaves out hidden and system entries
+    #[serde(default)]
+    pub skip_hidden: bool,
+    // Leaves out directories
+    #[serde(default)]
+    pub files_only: bool,
+    // Text the file must contain, ignoring case. UTF-8 and UTF-16 are detected and binary files are skipped
+    #[serde(default)]
+    pub content: Option<String>,
+    // Windows only. Narrows down the candidates with the Windows Search index, falling back to traversal when the location is not indexed
+    #[serde(default)]
+    pub use_index: bool,
+    // Requires the instant_search feature. Narrows down the candidates with Everything on Windows or plocate on Linux,
+    // falling back to traversal when they are not available. Entries added since their database was updated are missed
+    #[serde(default)]
+    pub use_instant_search: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    // Virtual folder passed to `readdir`
+    pub path: PathBuf,
+    pub query: SearchQuery,
+}
+
+/// Lists entries under the location that match the query, ignoring case
+pub fn search(query: &SearchQuery) -> Result<Vec<Dirent>, String> {
+    let mut entries = Vec::new();
+    search_with(query, &CancelToken::new(), |entry| entries.push(entry))?;
+    Ok(entries)
+}
+
+/// Passes each entry matching the query to the callback as soon as it is found
+///
+/// Directories are read on several threads, so entries do not come in order. The callback is called on the calling thread.
+/// Returns when the whole tree is searched or the token is cancelled
+pub fn search_with<F: FnMut(Dirent)>(query: &SearchQuery, cancel: &CancelToken, mut callback: F) -> Result<(), String> {
+    let filter = Filter::new(query)?;
+
+    #[cfg(target_os = "windows")]
+    if query.use_index && query.recursive {
+        if let Ok(paths) = crate::fs::list_search_results(&to_search_url(query), cancel) {
+            send_candidates(paths, &filter, cancel, &mut callback);
+            return Ok(());
+        }
+    }
+
+    #[cfg(feature = "instant_search")]
+    if query.use_instant_search {
+        let pattern = if query.is_regex {
+            query.pattern.clone()
+        } else {
+            to_wildcard(&query.pattern)
+        };
+        if let Ok(paths) = locate::locate(&query.location, &pattern, query.is_regex, query.recursive) {
+            send_candidates(paths, &filter, cancel, &mut callback);
+            return Ok(());
+        }
+    }
+
+    let traversal = Traversal {
+        queue: Mutex::new((VecDeque::from([query.location.clone()]), 0)),
+        changed: Condvar::new(),
+    };
+    let workers = std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1).min(MAX_WORKERS);
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (traversal, filter) = (&traversal, &filter);
+            scope.spawn(move || traversal.run(query.recursive, filter, cancel, sender));
+        }
+        drop(sender);
+
+        for entry in receiver {
+            if cancel.is_cancelled() {
+                break;
+            }
+            callback(entry);
+        }
+    });
+
+    Ok(())
+}
+
+/* Candidates from an index are checked against every condition, since the index matches the name only */
+#[cfg(any(target_os = "windows", feature = "instant_search"))]
+fn send_candidates<F: FnMut(Dirent)>(paths: Vec<String>, filter: &Filter, cancel: &CancelToken, callback: &mut F) {
+    for path in paths {
+        if cancel.is_cancelled() {
+            break;
+        }
+        if let Ok(entry) = crate::fs::stat(&path).map(|attributes| to_dirent(Path::new(&path), attributes)) {
+            if filter.matches(&entry) {
+                callback(entry);
+            }
+        }
+    }
+}
+
+/* Directories waiting to be read and the number of directories being read */
+struct Traversal {
+    queue: Mutex<(VecDeque<PathBuf>, usize)>,
+    changed: Condvar,
+}
+
+impl Traversal {
+    fn run(&self, recursive: bool, filter: &Filter, cancel: &CancelToken, sender: Sender<Dirent>) {
+        while let Some(directory) = self.next() {
+            let mut subdirectories = Vec::new();
+
+            if !cancel.is_cancelled() {
+                for entry in crate::fs::readdir(&directory, false, false).unwrap_or_default() {
+                    // Symlinks are not followed so that loops cannot occur
+                    if recursive && entry.attributes.is_directory && !entry.attributes.is_symbolic_link && !entry.attributes.is_junction {
+                        subdirectories.push(PathBuf::from(&entry.full_path));
+                    }
+                    // Sending fails only after the receiver stopped because of cancellation
+                    if filter.matches(&entry) && sender.send(entry).is_err() {
+                        break;
+                    }
+                }
+            }
+
+            self.complete(subdirectories, cancel.is_cancelled());
+        }
+    }
+
+    /* Waits until a directory is queued or every directory has been read */
+    fn next(&self) -> Option<PathBuf> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some(directory) = queue.0.pop_front() {
+                queue.1 += 1;
+                return Some(directory);
+            }
+            if queue.1 == 0 {
+                return None;
+            }
+            queue = self.changed.wait(queue).unwrap();
+        }
+    }
+
+    fn complete(&self, subdirectories: Vec<PathBuf>, is_cancelled: bool) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.1 -= 1;
+        if is_cancelled {
+            queue.0.clear();
+        } else {
+            queue.0.extend(subdirectories);
+        }
+        self.changed.notify_all();
+    }
+}
+
+enum NameMatcher {
+    Wildcard(Vec<char>),
+    Regex(Regex),
+}
+
+struct Filter {
+    name: NameMatcher,
+    query: SearchQuery,
+    content: Option<String>,
+}
+
+impl Filter {
+    fn new(query: &SearchQuery) -> Result<Self, String> {
+        let name = if query.is_regex {
+            NameMatcher::Regex(RegexBuilder::new(&query.pattern).case_insensitive(true).build().map_err(|e| e.to_string())?)
+        } else {
+            NameMatcher::Wildcard(to_wildcard(&query.pattern).to_lowercase().chars().collect())
+        };
+
+        Ok(Self {
+            name,
+            query: query.clone(),
+            content: query.content.as_ref().filter(|content| !content.is_empty()).map(|content| content.to_lowercase()),
+        })
+    }
+
+    fn matches(&self, entry: &Dirent) -> bool {
+        let attributes = &entry.attributes;
+        let query = &self.query;
+
+        let matches_name = match &self.name {
+            NameMatcher::Wildcard(pattern) => matches_wildcard(pattern, &entry.name.to_lowercase().chars().collect::<Vec<char>>()),
+            NameMatcher::Regex(regex) => regex.is_match(&entry.name),
+        };
+
+        if !matches_name
+            || (query.skip_hidden && (attributes.is_hidden || attributes.is_system))
+            || ((query.files_only || self.content.is_some()) && attributes.is_directory)
+            || query.min_size.is_some_and(|min_size| attributes.size < min_size)
+            || query.max_size.is_some_and(|max_size| attributes.size > max_size)
+            || query.modified_after_ms.is_some_and(|after| attributes.mtime_ms < after)
+            || query.modified_before_ms.is_some_and(|before| attributes.mtime_ms > before)
+        {
+            return false;
+        }
+
+        match &self.content {
+            Some(content) => attributes.size <= MAX_CONTENT_SIZE && contains_text(Path::new(&entry.full_path), content),
+            None => true,
+        }
+    }
+}
+
+fn contains_text(file_path: &Path, text: &str) -> bool {
+    let mut data = Vec::new();
+    if std::fs::File::open(file_path).and_then(|mut file| file.read_to_end(&mut data)).is_err() {
+        return false;
+    }
+
+    decode(&data).is_some_and(|decoded| decoded.to_lowercase().contains(text))
+}
+
+/* Detects UTF-8 and UTF-16 by BOM, then UTF-16 without BOM by where NUL bytes are. Other files containing NUL are binary */
+fn decode(data: &[u8]) -> Option<String> {
+    match data {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => return Some(String::from_utf8_lossy(rest).to_string()),
+        [0xFF, 0xFE, rest @ ..] => return Some(decode_utf16(rest, u16::from_le_bytes)),
+        [0xFE, 0xFF, rest @ ..] => return Some(decode_utf16(rest, u16::from_be_bytes)),
+        _ => {}
+    }
+
+    let sample = &data[..data.len().min(4096)];
+    if !sample.contains(&0) {
+        return Some(String::from_utf8_lossy(data).to_string());
+    }
+
+    let odd_nuls = sample.iter().skip(1).step_by(2).filter(|byte| **byte == 0).count();
+    let even_nuls = sample.iter().step_by(2).filter(|byte| **byte == 0).count();
+    let half = sample.len() / 2;
+
+    // ASCII text in UTF-16 has NUL in every other byte
+    if odd_nuls > half * 3 / 4 && even_nuls < half / 4 {
+        Some(decode_utf16(data, u16::from_le_bytes))
+    } else if even_nuls > half * 3 / 4 && odd_nuls < half / 4 {
+        Some(decode_utf16(data, u16::from_be_bytes))
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(data: &[u8], to_u16: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = data.chunks_exact(2).map(|pair| to_u16([pair[0], pair[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(any(target_os = "windows", feature = "instant_search"))]
+fn to_dirent(file_path: &Path, attributes: crate::FileAttribute) -> Dirent {
+    Dirent {
+        name: file_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        parent_path: file_path.parent().unwrap_or(Path::new("")).to_string_lossy().to_string(),
+        full_path: file_path.to_string_lossy().to_string(),
+        attributes,
+        mime_type: String::new(),
+        icon: None,
+        link_target: None,
+        owner: None,
+    }
+}
+
+/* Only the file name is given to the index. The other conditions are checked on each result */
+#[cfg(target_os = "windows")]
+fn to_search_url(query: &SearchQuery) -> String {
+    let name = if query.is_regex {
+        "*".to_string()
+    } else {
+        to_wildcard(&query.pattern)
+    };
+    format!("search-ms:query={}&crumb=location:{}", encode_uri_component(&format!("System.FileName:~\"{}\"", name)), encode_uri_component(&query.location.to_string_lossy()))
+}
+
+#[cfg(target_os = "windows")]
+fn encode_uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+/// Saves the query as a virtual folder in the storage data directory
+///
+/// The file is a .searchms file that Explorer can open on Windows and JSON on Linux. On Windows, only the location, pattern and recursion are saved
+pub fn save(query: &SearchQuery, name: &str) -> Result<SavedSearch, String> {
+    let path = searches_dir()?.join(format!("{}.{}", sanitize(name), EXTENSION));
+    std::fs::write(&path, serialize(query)?).map_err(|e| e.to_string())?;
+
+    Ok(SavedSearch {
+        name: name.to_string(),
+        path,
+        query: query.clone(),
+    })
+}
+
+/// Lists saved searches. Files that cannot be read are skipped
+pub fn list() -> Result<Vec<SavedSearch>, String> {
+    let mut searches: Vec<SavedSearch> = std::fs::read_dir(searches_dir()?)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == EXTENSION))
+        .filter_map(|path| load(path).ok())
+        .collect();
+    searches.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(searches)
+}
+
+/// Reads a saved search
+pub fn load<P: AsRef<Path>>(path: P) -> Result<SavedSearch, String> {
+    let text = std::fs::read_to_string(path.as_ref()).map_err(|e| e.to_string())?;
+
+    Ok(SavedSearch {
+        name: path.as_ref().file_stem().unwrap_or_default().to_string_lossy().to_string(),
+        path: path.as_ref().to_path_buf(),
+        query: deserialize(&text)?,
+    })
+}
+
+/// Re-evaluates the saved search and lists the matching entries
+pub fn readdir<P: AsRef<Path>>(path: P) -> Result<Vec<Dirent>, String> {
+    search(&load(path)?.query)
+}
+
+/// Deletes the saved search
+pub fn remove<P: AsRef<Path>>(path: P) -> Result<(), String> {
+    std::fs::remove_file(path.as_ref()).map_err(|e| e.to_string())
+}
+
+fn searches_dir() -> Result<PathBuf, String> {
+    let dir = crate::storage::data_dir()?.join("searches");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+/* Replaces characters that cannot be used in file names on either platform */
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if "<>:\"/\\|?*".contains(c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/* A pattern without wildcards matches names containing it */
+fn to_wildcard(pattern: &str) -> String {
+    if pattern.contains(['*', '?']) {
+        pattern.to_string()
+    } else {
+        format!("*{}*", pattern)
+    }
+}
+
+fn matches_wildcard(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position after the last '*' and the name position it matched up to
+    let mut backtrack = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            backtrack = Some((p, n));
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(target_os = "windows")]
+fn serialize(query: &SearchQuery) -> Result<String, String> {
+    Ok(format!(
+        r#"<?xml version="1.0"?>
+<persistedQuery version="1.0">
+  <query>
+    <conditions>
+      <condition type="leafCondition" property="System.FileName" operator="wildcard" propertyType="string" value="{}" valuetype="System.StructuredQueryType.String"/>
+    </conditions>
+    <kindList>
+      <kind name="item"/>
+    </kindList>
+    <scope>
+      <include path="{}" nonRecursive="{}"/>
+    </scope>
+  </query>
+</persistedQuery>
+"#,
+        escape(&to_wildcard(&query.pattern)),
+        escape(&query.location.to_string_lossy()),
+        !query.recursive
+    ))
+}
+
+#[cfg(target_os = "windows")]
+fn deserialize(text: &str) -> Result<SearchQuery, String> {
+    Ok(SearchQuery {
+        location: PathBuf::from(read_attribute(text, "include", "path").ok_or_else(|| "Search location not found".to_string())?),
+        pattern: read_attribute(text, "condition", "value").unwrap_or_else(|| "*".to_string()),
+        recursive: read_attribute(text, "include", "nonRecursive").is_none_or(|non_recursive| non_recursive != "true"),
+        ..Default::default()
+    })
+}
+
+/* Reads the attribute of the first element with the name, which is enough for files written by `save` and Explorer */
+#[cfg(target_os = "windows")]
+fn read_attribute(text: &str, element: &str, name: &str) -> Option<String> {
+    let start = text.find(&format!("<{} ", element))?;
+    let end = start + text[start..].find('>')?;
+    let tag = &text[start..end];
+
+    let key = format!(" {}=\"", name);
+    let value_start = tag.find(&key)? + key.len();
+    let value_end = value_start + tag[value_start..].find('"')?;
+    Some(unescape(&tag[value_start..value_end]))
+}
+
+#[cfg(target_os = "windows")]
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(target_os = "windows")]
+fn unescape(value: &str) -> String {
+    value.replace("&quot;", "\"").replace("&gt;", ">").replace("&lt;", "<").replace("&amp;", "&")
+}
+
+#[cfg(target_os = "linux")]
+fn serialize(query: &SearchQuery) -> Result<String, String> {
+    serde_json::to_string_pretty(query).map_err(|e| e.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn deserialize(text: &str) -> Result<SearchQuery, String> {
+    serde_json::from_str(text).map_err(|e| e.to_string())
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+const DEFAULT_DELAY: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SpringLoadEvent {
+    /// Hovering over the folder started. Highlight it until Navigate or Cancelled
+    Armed(String),
+    /// The folder was hovered for the delay. Navigate to it
+    Navigate(String),
+    /// Hovering left the folder before the delay
+    Cancelled(String),
+}
+
+/// Opens folders hovered during a drag after a delay, as Explorer and Finder do
+///
+/// Feed it the folder under the cursor from the drop target callbacks. Windows repeats DragOver while the cursor is still, so `hover` is enough there.
+/// Elsewhere call `poll` from a timer as well
+#[derive(Debug)]
+pub struct SpringLoader {
+    delay: Duration,
+    // Folder under the cursor and when hovering over it started
+    target: Option<(String, Instant)>,
+    // Navigate was emitted for the target, so it is not emitted again until another folder is hovered
+    opened: bool,
+}
+
+impl Default for SpringLoader {
+    fn default() -> Self {
+        Self::new(DEFAULT_DELAY)
+    }
+}
+
+impl SpringLoader {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            target: None,
+            opened: false,
+        }
+    }
+
+    /// Call from `on_enter` and `on_over` with the folder under the cursor, or None over files and empty space
+    pub fn hover(&mut self, folder: Option<&str>) -> Vec<SpringLoadEvent> {
+        let mut events = Vec::new();
+
+        if self.target.as_ref().map(|(target, _)| target.as_str()) != folder {
+            events.extend(self.reset());
+            if let Some(folder) = folder {
+                self.target = Some((folder.to_string(), Instant::now()));
+                events.push(SpringLoadEvent::Armed(folder.to_string()));
+            }
+        }
+
+        events.extend(self.poll());
+        events
+    }
+
+    /// Returns Navigate once the current folder has been hovered for the delay
+    pub fn poll(&mut self) -> Option<SpringLoadEvent> {
+        match &self.target {
+            Some((target, started)) if !self.opened && started.elapsed() >= self.delay => {
+                self.opened = true;
+                Some(SpringLoadEvent::Navigate(target.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Call from `on_leave` and `on_drop`. Returns Cancelled if a folder was armed but not opened yet
+    pub fn reset(&mut self) -> Option<SpringLoadEvent> {
+        let target = self.target.take();
+        let opened = std::mem::replace(&mut self.opened, false);
+        match target {
+            Some((target, _)) if !opened => Some(SpringLoadEvent::Cancelled(target)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+static LOCATION: RwLock<StorageLocation> = RwLock::new(StorageLocation::Default);
+
+const DIR_NAME: &str = "zouni";
+
+/// Where caches and state such as thumbnails, the operation journal and bookmarks are stored
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum StorageLocation {
+    /// %LOCALAPPDATA%\zouni on Windows, $XDG_DATA_HOME/zouni and $XDG_CACHE_HOME/zouni on Linux
+    #[default]
+    Default,
+    /// Directory relative to the executable, so that portable apps write nothing outside of their folder
+    Portable(PathBuf),
+    /// Absolute directory
+    Custom(PathBuf),
+}
+
+/// Sets where caches and state are stored. Call before using other functions
+pub fn set_location(location: StorageLocation) {
+    *LOCATION.write().unwrap() = location;
+}
+
+pub fn location() -> StorageLocation {
+    LOCATION.read().unwrap().clone()
+}
+
+/// Returns the directory for persistent state, creating it if missing
+pub fn data_dir() -> Result<PathBuf, String> {
+    let dir = match base_dir()? {
+        Some(base) => base.join("data"),
+        None => default_data_dir()?,
+    };
+    ensure_dir(dir)
+}
+
+/// Returns the directory for caches that can be deleted at any time, creating it if missing
+pub fn cache_dir() -> Result<PathBuf, String> {
+    let dir = match base_dir()? {
+        Some(base) => base.join("cache"),
+        None => default_cache_dir()?,
+    };
+    ensure_dir(dir)
+}
+
+/* None for the default location */
+fn base_dir() -> Result<Option<PathBuf>, String> {
+    match location() {
+        StorageLocation::Default => Ok(None),
+        StorageLocation::Portable(relative) => {
+            let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+            let exe_dir = exe.parent().ok_or_else(|| "Executable directory not found".to_string())?;
+            Ok(Some(exe_dir.join(relative)))
+        }
+        StorageLocation::Custom(dir) => Ok(Some(dir)),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn default_data_dir() -> Result<PathBuf, String> {
+    let local_app_data = std::env::var_os("LOCALAPPDATA").ok_or_else(|| "LOCALAPPDATA is not set".to_string())?;
+    Ok(Path::new(&local_app_data).join(DIR_NAME))
+}
+
+#[cfg(target_os = "windows")]
+fn default_cache_dir() -> Result<PathBuf, String> {
+    Ok(default_data_dir()?.join("cache"))
+}
+
+#[cfg(target_os = "linux")]
+fn default_data_dir() -> Result<PathBuf, String> {
+    xdg_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join(DIR_NAME))
+}
+
+#[cfg(target_os = "linux")]
+fn default_cache_dir() -> Result<PathBuf, String> {
+    xdg_dir("XDG_CACHE_HOME", ".cache").map(|dir| dir.join(DIR_NAME))
+}
+
+#[cfg(target_os = "linux")]
+fn xdg_dir(name: &str, fallback: &str) -> Result<PathBuf, String> {
+    match std::env::var_os(name) {
+        Some(dir) if Path::new(&dir).is_absolute() => Ok(PathBuf::from(dir)),
+        _ => {
+            let home = std::env::var_os("HOME").ok_or_else(|| "HOME is not set".to_string())?;
+            Ok(Path::new(&home).join(fallback))
+        }
+    }
+}
+
+fn ensure_dir(dir: PathBuf) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
@@ -0,0 +1,572 @@
+//! In-memory file system mirroring the `fs` module for unit tests
+//!
+//! Code written against `FsProvider` runs on the real file system with `NativeFs` and in memory with `MemoryFs`
+use crate::{
+    fs::{get_mime_type, FileOperation, OperationStatus, Response, Total},
+    Dirent, FileAttribute, MkdirOptions, RecycleBinDirent,
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone)]
+struct Node {
+    is_directory: bool,
+    is_hidden: bool,
+    is_read_only: bool,
+    content: Vec<u8>,
+    mtime_ms: u64,
+}
+
+impl Node {
+    fn file(content: &[u8]) -> Self {
+        Self {
+            is_directory: false,
+            is_hidden: false,
+            is_read_only: false,
+            content: content.to_vec(),
+            mtime_ms: now_ms(),
+        }
+    }
+
+    fn directory() -> Self {
+        Self {
+            is_directory: true,
+            is_hidden: false,
+            is_read_only: false,
+            content: Vec::new(),
+            mtime_ms: now_ms(),
+        }
+    }
+
+    fn to_file_attribute(&self) -> FileAttribute {
+        FileAttribute {
+            is_directory: self.is_directory,
+            is_read_only: self.is_read_only,
+            is_hidden: self.is_hidden,
+            is_system: false,
+            is_device: false,
+            is_symbolic_link: false,
+            is_junction: false,
+            is_file: !self.is_directory,
+            is_not_indexed: false,
+            ctime_ms: self.mtime_ms,
+            mtime_ms: self.mtime_ms,
+            atime_ms: self.mtime_ms,
+            birthtime_ms: self.mtime_ms,
+            size: self.content.len() as u64,
+            link_path: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TrashedNode {
+    original_path: PathBuf,
+    deleted_date_ms: u64,
+    nodes: Vec<(PathBuf, Node)>,
+}
+
+/// File system functions shared by the real file system and `MemoryFs`
+pub trait FsProvider: Send + Sync {
+    /// Gets file/directory attributes
+    fn stat(&self, file_path: &Path) -> Result<FileAttribute, String>;
+    /// Lists all files/directories under the specified directory
+    fn readdir(&self, directory: &Path, recursive: bool, with_mime_type: bool) -> Result<Vec<Dirent>, String>;
+    /// Reads the content of a file
+    fn read(&self, file_path: &Path) -> Result<Vec<u8>, String>;
+    /// Creates or overwrites a file
+    fn write(&self, file_path: &Path, content: &[u8]) -> Result<(), String>;
+    /// Creates a directory
+    fn mkdir(&self, directory: &Path, options: Option<MkdirOptions>) -> Result<Dirent, String>;
+    /// Creates a directory and all of its missing parent directories
+    fn mkdir_all(&self, directory: &Path, options: Option<MkdirOptions>) -> Result<Dirent, String>;
+    /// Copies an item into the directory
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), String>;
+    /// Moves an item into the directory
+    fn mv(&self, from: &Path, to: &Path) -> Result<(), String>;
+    /// Deletes an item
+    fn delete(&self, file_path: &Path) -> Result<(), String>;
+    /// Moves an item to trash
+    fn trash(&self, file_path: &Path) -> Result<(), String>;
+    /// Gets items in trash
+    fn read_recycle_bin(&self) -> Result<Vec<RecycleBinDirent>, String>;
+    /// Undos a trash operation
+    fn undelete(&self, file_paths: &[&Path]) -> Result<(), String>;
+}
+
+/// `FsProvider` calling the `fs` module
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeFs;
+
+impl FsProvider for NativeFs {
+    fn stat(&self, file_path: &Path) -> Result<FileAttribute, String> {
+        crate::fs::stat(file_path)
+    }
+
+    fn readdir(&self, directory: &Path, recursive: bool, with_mime_type: bool) -> Result<Vec<Dirent>, String> {
+        crate::fs::readdir(directory, recursive, with_mime_type)
+    }
+
+    fn read(&self, file_path: &Path) -> Result<Vec<u8>, String> {
+        std::fs::read(file_path).map_err(|e| e.to_string())
+    }
+
+    fn write(&self, file_path: &Path, content: &[u8]) -> Result<(), String> {
+        std::fs::write(file_path, content).map_err(|e| e.to_string())
+    }
+
+    fn mkdir(&self, directory: &Path, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+        crate::fs::mkdir(directory, options)
+    }
+
+    fn mkdir_all(&self, directory: &Path, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+        crate::fs::mkdir_all(directory, options)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), String> {
+        crate::fs::copy(from, to)
+    }
+
+    fn mv(&self, from: &Path, to: &Path) -> Result<(), String> {
+        crate::fs::mv(from, to)
+    }
+
+    fn delete(&self, file_path: &Path) -> Result<(), String> {
+        crate::fs::delete(file_path)
+    }
+
+    fn trash(&self, file_path: &Path) -> Result<(), String> {
+        crate::fs::trash(file_path)
+    }
+
+    fn read_recycle_bin(&self) -> Result<Vec<RecycleBinDirent>, String> {
+        crate::fs::read_recycle_bin()
+    }
+
+    fn undelete(&self, file_paths: &[&Path]) -> Result<(), String> {
+        crate::fs::undelete(file_paths)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// In-memory file system
+///
+/// Paths without a parent (e.g. "/" or "C:\\") always exist as root directories.
+/// Use `fail_on` to make any operation touching a path fail.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+    trash: Mutex<Vec<TrashedNode>>,
+    failures: Mutex<HashSet<PathBuf>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file creating missing parent directories
+    pub fn add_file<P: AsRef<Path>>(&self, file_path: P, content: &[u8]) -> Result<(), String> {
+        if let Some(parent) = file_path.as_ref().parent() {
+            self.add_dir(parent)?;
+        }
+        self.nodes.lock().unwrap().insert(file_path.as_ref().to_path_buf(), Node::file(content));
+        Ok(())
+    }
+
+    /// Adds a directory creating missing parent directories
+    pub fn add_dir<P: AsRef<Path>>(&self, directory: P) -> Result<(), String> {
+        let mut nodes = self.nodes.lock().unwrap();
+        for ancestor in directory.as_ref().ancestors().filter(|ancestor| ancestor.parent().is_some()) {
+            match nodes.get(ancestor) {
+                Some(node) if !node.is_directory => return Err(format!("Not a directory:{}", ancestor.to_string_lossy())),
+                Some(_) => {}
+                None => {
+                    nodes.insert(ancestor.to_path_buf(), Node::directory());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the content of a file
+    pub fn read<P: AsRef<Path>>(&self, file_path: P) -> Result<Vec<u8>, String> {
+        self.check(file_path.as_ref())?;
+        match self.nodes.lock().unwrap().get(file_path.as_ref()) {
+            Some(node) if !node.is_directory => Ok(node.content.clone()),
+            Some(_) => Err(format!("Is a directory:{}", file_path.as_ref().to_string_lossy())),
+            None => Err(not_found(file_path.as_ref())),
+        }
+    }
+
+    pub fn exists<P: AsRef<Path>>(&self, file_path: P) -> bool {
+        file_path.as_ref().parent().is_none() || self.nodes.lock().unwrap().contains_key(file_path.as_ref())
+    }
+
+    /// Makes every following operation touching the path fail
+    pub fn fail_on<P: AsRef<Path>>(&self, file_path: P) {
+        self.failures.lock().unwrap().insert(file_path.as_ref().to_path_buf());
+    }
+
+    /// Clears failures registered by `fail_on`
+    pub fn clear_failures(&self) {
+        self.failures.lock().unwrap().clear();
+    }
+
+    fn check(&self, file_path: &Path) -> Result<(), String> {
+        if self.failures.lock().unwrap().contains(file_path) {
+            Err(format!("Simulated failure:{}", file_path.to_string_lossy()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_dir(&self, file_path: &Path) -> bool {
+        file_path.parent().is_none() || self.nodes.lock().unwrap().get(file_path).map(|node| node.is_directory).unwrap_or(false)
+    }
+
+    /// Gets file/directory attributes
+    pub fn stat<P: AsRef<Path>>(&self, file_path: P) -> Result<FileAttribute, String> {
+        self.check(file_path.as_ref())?;
+        if file_path.as_ref().parent().is_none() {
+            return Ok(Node::directory().to_file_attribute());
+        }
+        self.nodes.lock().unwrap().get(file_path.as_ref()).map(|node| node.to_file_attribute()).ok_or_else(|| not_found(file_path.as_ref()))
+    }
+
+    /// Lists all files/directories under the specified directory
+    pub fn readdir<P: AsRef<Path>>(&self, directory: P, recursive: bool, with_mime_type: bool) -> Result<Vec<Dirent>, String> {
+        self.check(directory.as_ref())?;
+        if !self.is_dir(directory.as_ref()) {
+            return Ok(Vec::new());
+        }
+
+        let nodes = self.nodes.lock().unwrap();
+        let entries = nodes
+            .iter()
+            .filter(|(path, _)| {
+                if recursive {
+                    path.starts_with(directory.as_ref()) && path.as_path() != directory.as_ref()
+                } else {
+                    path.parent() == Some(directory.as_ref())
+                }
+            })
+            .map(|(path, node)| to_dirent(path, node, with_mime_type))
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Creates a directory
+    pub fn mkdir<P: AsRef<Path>>(&self, directory: P, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+        self.check(directory.as_ref())?;
+        if self.exists(directory.as_ref()) {
+            return Err(format!("File already exists:{}", directory.as_ref().to_string_lossy()));
+        }
+        if !directory.as_ref().parent().map(|parent| self.is_dir(parent)).unwrap_or(false) {
+            return Err(not_found(directory.as_ref().parent().unwrap_or(Path::new(""))));
+        }
+
+        let options = options.unwrap_or_default();
+        let mut node = Node::directory();
+        node.is_hidden = options.is_hidden;
+        node.is_read_only = options.is_read_only;
+        self.nodes.lock().unwrap().insert(directory.as_ref().to_path_buf(), node.clone());
+
+        Ok(to_dirent(directory.as_ref(), &node, false))
+    }
+
+    /// Creates a directory and all of its missing parent directories
+    pub fn mkdir_all<P: AsRef<Path>>(&self, directory: P, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+        if let Some(parent) = directory.as_ref().parent() {
+            self.add_dir(parent)?;
+        }
+
+        if self.is_dir(directory.as_ref()) {
+            let node = self.nodes.lock().unwrap().get(directory.as_ref()).cloned().unwrap_or(Node::directory());
+            return Ok(to_dirent(directory.as_ref(), &node, false));
+        }
+
+        self.mkdir(directory, options)
+    }
+
+    /// Copies an item
+    pub fn copy<P1: AsRef<Path>, P2: AsRef<Path>>(&self, from: P1, to: P2) -> Result<(), String> {
+        self.transfer(from.as_ref(), to.as_ref(), true)
+    }
+
+    /// Copies multiple items
+    pub fn copy_all<P1: AsRef<Path>, P2: AsRef<Path>>(&self, froms: &[P1], to: P2) -> Result<(), String> {
+        froms.iter().try_for_each(|from| self.copy(from, to.as_ref()))
+    }
+
+    /// Moves an item
+    pub fn mv<P1: AsRef<Path>, P2: AsRef<Path>>(&self, from: P1, to: P2) -> Result<(), String> {
+        self.transfer(from.as_ref(), to.as_ref(), false)
+    }
+
+    /// Moves multiple items
+    pub fn mv_all<P1: AsRef<Path>, P2: AsRef<Path>>(&self, froms: &[P1], to: P2) -> Result<(), String> {
+        froms.iter().try_for_each(|from| self.mv(from, to.as_ref()))
+    }
+
+    fn transfer(&self, from: &Path, to: &Path, is_copy: bool) -> Result<(), String> {
+        self.check(from)?;
+        self.check(to)?;
+
+        if !self.exists(from) {
+            return Err(not_found(from));
+        }
+        if !self.is_dir(to) {
+            return Err(not_found(to));
+        }
+        if to.starts_with(from) {
+            return Err(format!("Cannot move or copy into itself:{}", from.to_string_lossy()));
+        }
+
+        let dest = to.join(from.file_name().unwrap_or_default());
+        let mut nodes = self.nodes.lock().unwrap();
+        let sources: Vec<(PathBuf, Node)> = nodes.iter().filter(|(path, _)| path.starts_with(from)).map(|(path, node)| (path.clone(), node.clone())).collect();
+
+        for (path, node) in sources {
+            let relative = path.strip_prefix(from).unwrap_or(Path::new(""));
+            let target = if relative.as_os_str().is_empty() {
+                dest.clone()
+            } else {
+                dest.join(relative)
+            };
+
+            if !is_copy {
+                nodes.remove(&path);
+            }
+            nodes.insert(target, node);
+        }
+
+        Ok(())
+    }
+
+    /// Deletes an item
+    pub fn delete<P: AsRef<Path>>(&self, file_path: P) -> Result<(), String> {
+        self.remove(file_path.as_ref()).map(|_| ())
+    }
+
+    /// Deletes multiple items
+    pub fn delete_all<P: AsRef<Path>>(&self, file_paths: &[P]) -> Result<(), String> {
+        file_paths.iter().try_for_each(|file_path| self.delete(file_path))
+    }
+
+    fn remove(&self, file_path: &Path) -> Result<Vec<(PathBuf, Node)>, String> {
+        self.check(file_path)?;
+        if !self.nodes.lock().unwrap().contains_key(file_path) {
+            return Err(not_found(file_path));
+        }
+
+        let mut nodes = self.nodes.lock().unwrap();
+        let removed: Vec<PathBuf> = nodes.keys().filter(|path| path.starts_with(file_path)).cloned().collect();
+        Ok(removed.into_iter().filter_map(|path| nodes.remove(&path).map(|node| (path, node))).collect())
+    }
+
+    /// Moves an item to the in-memory trash
+    pub fn trash<P: AsRef<Path>>(&self, file_path: P) -> Result<(), String> {
+        let nodes = self.remove(file_path.as_ref())?;
+        self.trash.lock().unwrap().push(TrashedNode {
+            original_path: file_path.as_ref().to_path_buf(),
+            deleted_date_ms: now_ms(),
+            nodes,
+        });
+        Ok(())
+    }
+
+    /// Moves multiple items to the in-memory trash
+    pub fn trash_all<P: AsRef<Path>>(&self, file_paths: &[P]) -> Result<(), String> {
+        file_paths.iter().try_for_each(|file_path| self.trash(file_path))
+    }
+
+    /// Gets items in the in-memory trash
+    pub fn read_recycle_bin(&self) -> Result<Vec<RecycleBinDirent>, String> {
+        let trash = self.trash.lock().unwrap();
+        Ok(trash
+            .iter()
+            .filter_map(|trashed| {
+                trashed.nodes.iter().find(|(path, _)| *path == trashed.original_path).map(|(path, node)| RecycleBinDirent {
+                    name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                    original_path: path.to_string_lossy().to_string(),
+                    deleted_date_ms: trashed.deleted_date_ms,
+                    attributes: node.to_file_attribute(),
+                    mime_type: get_mime_type(path),
+                })
+            })
+            .collect())
+    }
+
+    /// Undos a trash operation
+    pub fn undelete<P: AsRef<Path>>(&self, file_paths: &[P]) -> Result<(), String> {
+        let mut trash = self.trash.lock().unwrap();
+        for file_path in file_paths {
+            // Restore the latest one
+            if let Some(index) = trash.iter().rposition(|trashed| trashed.original_path == file_path.as_ref()) {
+                let trashed = trash.remove(index);
+                self.nodes.lock().unwrap().extend(trashed.nodes);
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute file operation
+    ///
+    /// Reports the same statuses as the native implementations and asks the callback to resolve conflicts.
+    /// Unlike the native ones, the callback is synchronous.
+    pub fn operate<P1: AsRef<Path>, P2: AsRef<Path>>(&self, operation: FileOperation, froms: &[P1], to: Option<P2>, mut callback: impl FnMut(OperationStatus) -> Response) -> Result<(), String> {
+        let to = to.map(|to| to.as_ref().to_path_buf()).unwrap_or_default();
+
+        let mut total = Total::default();
+        {
+            let nodes = self.nodes.lock().unwrap();
+            for from in froms {
+                for (_, node) in nodes.iter().filter(|(path, node)| path.starts_with(from.as_ref()) && !node.is_directory) {
+                    total.total_size += node.content.len() as u64;
+                    total.total_count += 1;
+                }
+            }
+        }
+
+        if callback(OperationStatus::Ready(total)) == Response::Cancel {
+            return Ok(());
+        }
+
+        for from in froms {
+            let from = from.as_ref();
+            if callback(OperationStatus::Start(from.file_name().unwrap_or_default().to_string_lossy().to_string())) == Response::Cancel {
+                break;
+            }
+
+            if matches!(operation, FileOperation::Copy | FileOperation::Move) && self.exists(to.join(from.file_name().unwrap_or_default())) {
+                match callback(OperationStatus::Confirm(from.to_string_lossy().to_string())) {
+                    Response::Cancel => break,
+                    Response::Skip => {
+                        if callback(OperationStatus::Skipped(from.to_string_lossy().to_string())) == Response::Cancel {
+                            break;
+                        }
+                        continue;
+                    }
+                    _ => {
+                        let _ = self.remove(&to.join(from.file_name().unwrap_or_default()));
+                    }
+                }
+            }
+
+            let size: i64 = self.nodes.lock().unwrap().iter().filter(|(path, _)| path.starts_with(from)).map(|(_, node)| node.content.len() as i64).sum();
+
+            let result = match operation {
+                FileOperation::Copy => self.copy(from, &to),
+                FileOperation::Move => self.mv(from, &to),
+                FileOperation::Delete => self.delete(from),
+                FileOperation::Trash => self.trash(from),
+            };
+
+            let response = match result {
+                Ok(_) => {
+                    if callback(OperationStatus::Progress(size, size)) == Response::Cancel {
+                        break;
+                    }
+                    callback(OperationStatus::End)
+                }
+                Err(e) => callback(OperationStatus::Error(e)),
+            };
+
+            if response == Response::Cancel {
+                break;
+            }
+        }
+
+        callback(OperationStatus::Finished);
+
+        Ok(())
+    }
+}
+
+impl FsProvider for MemoryFs {
+    fn stat(&self, file_path: &Path) -> Result<FileAttribute, String> {
+        MemoryFs::stat(self, file_path)
+    }
+
+    fn readdir(&self, directory: &Path, recursive: bool, with_mime_type: bool) -> Result<Vec<Dirent>, String> {
+        MemoryFs::readdir(self, directory, recursive, with_mime_type)
+    }
+
+    fn read(&self, file_path: &Path) -> Result<Vec<u8>, String> {
+        MemoryFs::read(self, file_path)
+    }
+
+    fn write(&self, file_path: &Path, content: &[u8]) -> Result<(), String> {
+        self.check(file_path)?;
+        if !file_path.parent().map(|parent| self.is_dir(parent)).unwrap_or(false) {
+            return Err(not_found(file_path.parent().unwrap_or(Path::new(""))));
+        }
+        if self.is_dir(file_path) {
+            return Err(format!("Is a directory:{}", file_path.to_string_lossy()));
+        }
+        self.nodes.lock().unwrap().insert(file_path.to_path_buf(), Node::file(content));
+        Ok(())
+    }
+
+    fn mkdir(&self, directory: &Path, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+        MemoryFs::mkdir(self, directory, options)
+    }
+
+    fn mkdir_all(&self, directory: &Path, options: Option<MkdirOptions>) -> Result<Dirent, String> {
+        MemoryFs::mkdir_all(self, directory, options)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), String> {
+        MemoryFs::copy(self, from, to)
+    }
+
+    fn mv(&self, from: &Path, to: &Path) -> Result<(), String> {
+        MemoryFs::mv(self, from, to)
+    }
+
+    fn delete(&self, file_path: &Path) -> Result<(), String> {
+        MemoryFs::delete(self, file_path)
+    }
+
+    fn trash(&self, file_path: &Path) -> Result<(), String> {
+        MemoryFs::trash(self, file_path)
+    }
+
+    fn read_recycle_bin(&self) -> Result<Vec<RecycleBinDirent>, String> {
+        MemoryFs::read_recycle_bin(self)
+    }
+
+    fn undelete(&self, file_paths: &[&Path]) -> Result<(), String> {
+        MemoryFs::undelete(self, file_paths)
+    }
+}
+
+fn to_dirent(file_path: &Path, node: &Node, with_mime_type: bool) -> Dirent {
+    Dirent {
+        name: file_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        parent_path: file_path.parent().unwrap_or(Path::new("")).to_string_lossy().to_string(),
+        full_path: file_path.to_string_lossy().to_string(),
+        attributes: node.to_file_attribute(),
+        mime_type: if with_mime_type && !node.is_directory {
+            get_mime_type(file_path)
+        } else {
+            String::new()
+        },
+        icon: None,
+        link_target: None,
+        owner: None,
+    }
+}
+
+fn not_found(file_path: &Path) -> String {
+    format!("No such file or directory:{}", file_path.to_string_lossy())
+}
@@ -0,0 +1,57 @@
+use crate::{
+    fs::{OperationStatus, Response},
+    ProgressThrottle,
+};
+use std::{sync::RwLock, time::Instant};
+
+static THROTTLE: RwLock<Option<ProgressThrottle>> = RwLock::new(None);
+
+pub(crate) fn set(throttle: Option<ProgressThrottle>) {
+    *THROTTLE.write().unwrap() = throttle;
+}
+
+/// Drops progress reported sooner or by a smaller step than the throttle allows
+///
+/// Takes the throttle in effect when created, so changing it does not affect running operations
+pub(crate) struct ProgressFilter {
+    throttle: Option<ProgressThrottle>,
+    // When and at which amount progress was last passed
+    last: Option<(Instant, i64)>,
+}
+
+impl ProgressFilter {
+    pub(crate) fn new() -> Self {
+        Self {
+            throttle: THROTTLE.read().unwrap().clone(),
+            last: None,
+        }
+    }
+
+    /// The first and the final progress always pass
+    pub(crate) fn pass(&mut self, current: i64, total: i64) -> bool {
+        let Some(throttle) = &self.throttle else {
+            return true;
+        };
+
+        let pass = match self.last {
+            None => true,
+            _ if current >= total => true,
+            Some((time, amount)) => time.elapsed().as_millis() >= throttle.min_interval_ms as u128 && current.abs_diff(amount) >= throttle.min_delta,
+        };
+
+        if pass {
+            self.last = Some((Instant::now(), current));
+        }
+
+        pass
+    }
+}
+
+/// Wraps the callback so that throttled progress does not reach it
+pub(crate) fn throttled(mut callback: impl FnMut(OperationStatus) -> Response) -> impl FnMut(OperationStatus) -> Response {
+    let mut filter = ProgressFilter::new();
+    move |status| match status {
+        OperationStatus::Progress(current, total) if !filter.pass(current, total) => Response::Proceed,
+        _ => callback(status),
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::{Arc, Mutex};
+
+/// Records the OS calls an operation makes. The default trace records nothing
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CallTrace {
+    calls: Option<Arc<Mutex<Vec<String>>>>,
+}
+
+impl CallTrace {
+    pub(crate) fn enabled() -> Self {
+        Self {
+            calls: Some(Arc::new(Mutex::new(Vec::new()))),
+        }
+    }
+
+    /// Formats the call only when recording
+    pub(crate) fn record(&self, call: impl FnOnce() -> String) {
+        if let Some(calls) = &self.calls {
+            calls.lock().unwrap().push(call());
+        }
+    }
+
+    pub(crate) fn calls(&self) -> Vec<String> {
+        self.calls.as_ref().map(|calls| calls.lock().unwrap().clone()).unwrap_or_default()
+    }
+}
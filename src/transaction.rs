@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+enum Step {
+    Mkdir(PathBuf),
+    Copy(PathBuf, PathBuf),
+    Move(PathBuf, PathBuf),
+    Rename(PathBuf, String),
+}
+
+enum Undo {
+    Remove(PathBuf),
+    MoveBack(PathBuf, PathBuf),
+    RenameBack(PathBuf, PathBuf),
+}
+
+/// Operations staged by `fs::transaction` to be completed all together or not at all
+#[derive(Default)]
+pub struct Transaction {
+    steps: Vec<Step>,
+}
+
+impl Transaction {
+    /// Creates a directory. The parent directory must exist
+    pub fn mkdir<P: AsRef<Path>>(mut self, directory: P) -> Self {
+        self.steps.push(Step::Mkdir(directory.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Copies an item into the directory
+    pub fn copy<P1: AsRef<Path>, P2: AsRef<Path>>(mut self, from: P1, to: P2) -> Self {
+        self.steps.push(Step::Copy(from.as_ref().to_path_buf(), to.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Moves an item into the directory
+    pub fn mv<P1: AsRef<Path>, P2: AsRef<Path>>(mut self, from: P1, to: P2) -> Self {
+        self.steps.push(Step::Move(from.as_ref().to_path_buf(), to.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Renames an item within its directory
+    pub fn rename<P: AsRef<Path>>(mut self, from: P, new_name: &str) -> Self {
+        self.steps.push(Step::Rename(from.as_ref().to_path_buf(), new_name.to_string()));
+        self
+    }
+
+    /// Runs the staged operations in order
+    ///
+    /// If any of them fails, created items are deleted and moved or renamed items are put back in reverse order.
+    /// An operation fails if its destination already exists, so that nothing has to be restored from an overwrite
+    pub fn commit(self) -> Result<(), String> {
+        let mut undos: Vec<Undo> = Vec::new();
+
+        for step in &self.steps {
+            if let Err(e) = run(step, &mut undos) {
+                return match roll_back(undos) {
+                    Ok(_) => Err(e),
+                    Err(rollback_error) => Err(format!("{} (Failed to roll back:{})", e, rollback_error)),
+                };
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn run(step: &Step, undos: &mut Vec<Undo>) -> Result<(), String> {
+    let dest = match step {
+        Step::Mkdir(directory) => directory.clone(),
+        Step::Copy(from, to) | Step::Move(from, to) => to.join(from.file_name().ok_or(format!("Invalid path:{}", from.to_string_lossy()))?),
+        Step::Rename(from, new_name) => from.with_file_name(new_name),
+    };
+
+    if dest.symlink_metadata().is_ok() {
+        return Err(format!("Already exists:{}", dest.to_string_lossy()));
+    }
+
+    let result = match step {
+        Step::Mkdir(directory) => crate::fs::mkdir(directory, None).map(|_| Undo::Remove(dest.clone())),
+        Step::Copy(from, to) => crate::fs::copy(from, to).map(|_| Undo::Remove(dest.clone())),
+        Step::Move(from, to) => crate::fs::mv(from, to).map(|_| Undo::MoveBack(dest.clone(), from.clone())),
+        Step::Rename(from, _) => std::fs::rename(from, &dest).map(|_| Undo::RenameBack(dest.clone(), from.clone())).map_err(|e| e.to_string()),
+    };
+
+    match result {
+        Ok(undo) => {
+            undos.push(undo);
+            Ok(())
+        }
+        Err(e) => {
+            // A copy may fail halfway, so remove what was written
+            if matches!(step, Step::Mkdir(_) | Step::Copy(_, _)) && dest.symlink_metadata().is_ok() {
+                undos.push(Undo::Remove(dest));
+            }
+            Err(e)
+        }
+    }
+}
+
+fn roll_back(undos: Vec<Undo>) -> Result<(), String> {
+    let mut errors: Vec<String> = Vec::new();
+
+    for undo in undos.into_iter().rev() {
+        let result = match undo {
+            Undo::Remove(path) => remove_item(&path),
+            Undo::MoveBack(current, original) => crate::fs::mv(&current, original.parent().unwrap_or(Path::new(""))),
+            Undo::RenameBack(current, original) => std::fs::rename(&current, &original).map_err(|e| format!("{}:{}", e, current.to_string_lossy())),
+        };
+
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+fn remove_item(path: &Path) -> Result<(), String> {
+    let result = if path.symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    result.map_err(|e| format!("{}:{}", e, path.to_string_lossy()))
+}
@@ -0,0 +1,128 @@
+use std::{
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        mpsc::{channel, Sender},
+        LazyLock,
+    },
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/* Started on first use and kept for the life of the process */
+static WORKER: LazyLock<Result<Worker, String>> = LazyLock::new(start);
+
+#[cfg(target_os = "windows")]
+const WM_RUN_JOBS: u32 = windows::Win32::UI::WindowsAndMessaging::WM_APP + 1;
+
+struct Worker {
+    jobs: Sender<Job>,
+    #[cfg(target_os = "windows")]
+    thread_id: u32,
+}
+
+/// Runs the closure on the shared worker thread and resolves to its result, so that blocking fs and shell calls stay off the UI thread
+///
+/// The thread is a COM STA with a message loop on Windows, as IFileOperation and ShellExecuteEx require. Closures run one at a time in the order they were queued.
+/// Fails if the closure panics. GTK widgets still have to be used on the main thread on Linux
+pub fn run<T: Send + 'static, F: FnOnce() -> T + Send + 'static>(f: F) -> impl Future<Output = Result<T, String>> {
+    let (tx, rx) = smol::channel::bounded(1);
+    let queued = queue(Box::new(move || {
+        let _ = tx.try_send(f());
+    }));
+
+    async move {
+        queued?;
+        rx.recv().await.map_err(|_| "The job panicked on the worker thread".to_string())
+    }
+}
+
+/// Runs the closure on the shared worker thread and passes its result to the callback on that thread
+pub fn dispatch<T: 'static, F: FnOnce() -> T + Send + 'static, C: FnOnce(T) + Send + 'static>(f: F, callback: C) -> Result<(), String> {
+    queue(Box::new(move || callback(f())))
+}
+
+fn queue(job: Job) -> Result<(), String> {
+    let worker = WORKER.as_ref().map_err(|e| e.clone())?;
+    worker.jobs.send(job).map_err(|_| "The worker thread has stopped".to_string())?;
+
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::{
+            Foundation::{LPARAM, WPARAM},
+            UI::WindowsAndMessaging::PostThreadMessageW,
+        };
+        unsafe { PostThreadMessageW(worker.thread_id, WM_RUN_JOBS, WPARAM(0), LPARAM(0)) }.map_err(|e| e.message())?;
+    }
+
+    Ok(())
+}
+
+/* A panicking job must not take the thread down with it, since later jobs would never run */
+fn run_job(job: Job) {
+    let _ = std::panic::catch_unwind(AssertUnwindSafe(job));
+}
+
+#[cfg(target_os = "linux")]
+fn start() -> Result<Worker, String> {
+    let (tx, rx) = channel::<Job>();
+
+    std::thread::Builder::new()
+        .name("zouni-worker".to_string())
+        .spawn(move || {
+            for job in rx {
+                run_job(job);
+            }
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(Worker {
+        jobs: tx,
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn start() -> Result<Worker, String> {
+    use windows::Win32::{
+        System::{
+            Com::{CoInitializeEx, COINIT_APARTMENTTHREADED},
+            Threading::GetCurrentThreadId,
+        },
+        UI::WindowsAndMessaging::{DispatchMessageW, GetMessageW, PeekMessageW, TranslateMessage, MSG, PM_NOREMOVE, WM_USER},
+    };
+
+    let (tx, rx) = channel::<Job>();
+    let (ready_tx, ready_rx) = channel();
+
+    std::thread::Builder::new()
+        .name("zouni-worker".to_string())
+        .spawn(move || {
+            let _ = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+
+            // The message queue is created before the thread ID is handed out, so that no wake-up is lost
+            let mut msg = MSG::default();
+            let _ = unsafe { PeekMessageW(&mut msg, None, WM_USER, WM_USER, PM_NOREMOVE) };
+            let _ = ready_tx.send(unsafe { GetCurrentThreadId() });
+
+            while unsafe { GetMessageW(&mut msg, None, 0, 0) }.as_bool() {
+                if msg.hwnd.is_invalid() && msg.message == WM_RUN_JOBS {
+                    while let Ok(job) = rx.try_recv() {
+                        run_job(job);
+                    }
+                    continue;
+                }
+
+                // Windows created by jobs, such as the progress dialog of IFileOperation, are served here
+                let _ = unsafe { TranslateMessage(&msg) };
+                unsafe { DispatchMessageW(&msg) };
+            }
+        })
+        .map_err(|e| e.to_string())?;
+
+    let thread_id = ready_rx.recv().map_err(|_| "Failed to start the worker thread".to_string())?;
+
+    Ok(Worker {
+        jobs: tx,
+        thread_id,
+    })
+}
@@ -0,0 +1,115 @@
+#![allow(dead_code)]
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary directory tree removed on drop
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        let root = std::env::temp_dir().join(format!("zouni-test-{}-{}", std::process::id(), COUNTER.fetch_add(1, Ordering::SeqCst)));
+        std::fs::create_dir_all(&root).unwrap();
+        Self {
+            root,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    pub fn file(&self, relative: &str, content: &str) -> PathBuf {
+        let path = self.path(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    pub fn dir(&self, relative: &str) -> PathBuf {
+        let path = self.path(relative);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    pub fn read_only_file(&self, relative: &str, content: &str) -> PathBuf {
+        let path = self.file(relative, content);
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+        path
+    }
+
+    /// Returns None if the platform refuses to create the link (e.g. no privilege on Windows)
+    pub fn symlink(&self, relative: &str, target: &Path) -> Option<PathBuf> {
+        let path = self.path(relative);
+        #[cfg(target_os = "linux")]
+        let result = std::os::unix::fs::symlink(target, &path);
+        #[cfg(target_os = "windows")]
+        let result = if target.is_dir() {
+            std::os::windows::fs::symlink_dir(target, &path)
+        } else {
+            std::os::windows::fs::symlink_file(target, &path)
+        };
+        result.ok().map(|_| path)
+    }
+
+    /// Creates nested directories so that the full path exceeds MAX_PATH
+    pub fn long_dir(&self) -> PathBuf {
+        let mut path = self.root.clone();
+        while path.to_string_lossy().len() < 300 {
+            path.push("a_fairly_long_directory_name_to_exceed_max_path");
+        }
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+}
+
+/// Root can write to read-only files on Linux, so access based checks do not see them as read-only
+#[cfg(target_os = "linux")]
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_root() -> bool {
+    false
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        // Read-only files prevent removal on Windows
+        for entry in walk(&self.root) {
+            if let Ok(metadata) = std::fs::symlink_metadata(&entry) {
+                let mut permissions = metadata.permissions();
+                #[allow(clippy::permissions_set_readonly_false)]
+                permissions.set_readonly(false);
+                let _ = std::fs::set_permissions(&entry, permissions);
+            }
+        }
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+fn walk(directory: &Path) -> Vec<PathBuf> {
+    let mut entries = Vec::new();
+    if let Ok(children) = std::fs::read_dir(directory) {
+        for child in children.flatten() {
+            let path = child.path();
+            if child.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false) {
+                entries.extend(walk(&path));
+            }
+            entries.push(path);
+        }
+    }
+    entries
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::Sandbox;
+use std::{ffi::OsStr, path::Path, process::Command};
+use zouni::fs;
+
+// Arguments copy_elevated and delete_elevated start the helper with, followed by the operation, the destination and the items
+const HELPER_ARGS: [&str; 2] = ["--zouni-elevated-helper", "-"];
+
+/* The libtest harness would reject the helper arguments, so this test has its own main that runs as the helper as apps do */
+fn main() {
+    fs::run_elevated_helper();
+
+    copy_reports_progress_and_finishes();
+    delete_reports_each_item();
+    failure_is_reported_as_error();
+}
+
+/* Runs this executable as the helper without elevation and returns the reported lines and whether it succeeded */
+fn run_helper(operation: &str, to: &OsStr, items: &[&Path]) -> (Vec<String>, bool) {
+    let output = Command::new(std::env::current_exe().unwrap()).args(HELPER_ARGS).arg(operation).arg(to).args(items).output().unwrap();
+    (String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect(), output.status.success())
+}
+
+fn copy_reports_progress_and_finishes() {
+    let sandbox = Sandbox::new();
+    let file = sandbox.file("name with spaces 日本語.txt", "abc");
+    sandbox.file("dir/nested.txt", "de");
+    let dest = sandbox.dir("dest");
+
+    let (lines, success) = run_helper("copy", dest.as_os_str(), &[&file, &sandbox.path("dir")]);
+
+    assert!(success);
+    assert_eq!(lines, vec!["start name with spaces 日本語.txt", "progress 3 5", "end", "start dir", "progress 5 5", "end", "finished"]);
+    assert_eq!(std::fs::read_to_string(dest.join("name with spaces 日本語.txt")).unwrap(), "abc");
+    assert_eq!(std::fs::read_to_string(dest.join("dir").join("nested.txt")).unwrap(), "de");
+}
+
+fn delete_reports_each_item() {
+    let sandbox = Sandbox::new();
+    let file = sandbox.file("file.txt", "a");
+    sandbox.file("dir/nested.txt", "b");
+
+    let (lines, success) = run_helper("delete", OsStr::new(""), &[&file, &sandbox.path("dir")]);
+
+    assert!(success);
+    assert_eq!(lines, vec!["start file.txt", "end", "start dir", "end", "finished"]);
+    assert!(!file.exists());
+    assert!(!sandbox.path("dir").exists());
+}
+
+fn failure_is_reported_as_error() {
+    let sandbox = Sandbox::new();
+    let dest = sandbox.dir("dest");
+
+    let (lines, success) = run_helper("copy", dest.as_os_str(), &[&sandbox.path("missing.txt")]);
+
+    assert!(!success);
+    assert!(lines.last().is_some_and(|line| line.starts_with("error ")));
+    assert!(!lines.contains(&"finished".to_string()));
+}
@@ -0,0 +1,284 @@
+mod common;
+
+use common::{is_root, Sandbox};
+use zouni::{fs, MkdirOptions, ReaddirOptions, SetAttributeOptions};
+
+#[test]
+fn readdir_lists_unicode_names() {
+    let sandbox = Sandbox::new();
+    sandbox.file("日本語.txt", "a");
+    sandbox.file("émoji-😀.txt", "b");
+
+    let mut names: Vec<String> = fs::readdir(sandbox.root(), false, false).unwrap().into_iter().map(|entry| entry.name).collect();
+    names.sort();
+
+    assert_eq!(names, vec!["émoji-😀.txt".to_string(), "日本語.txt".to_string()]);
+}
+
+#[test]
+fn readdir_recursive_includes_nested_entries() {
+    let sandbox = Sandbox::new();
+    sandbox.file("dir/nested/file.txt", "a");
+
+    let entries = fs::readdir(sandbox.root(), true, false).unwrap();
+
+    assert_eq!(entries.len(), 3);
+    assert!(entries.iter().any(|entry| entry.full_path == sandbox.path("dir/nested/file.txt").to_string_lossy()));
+}
+
+#[test]
+fn readdir_of_missing_directory_is_empty() {
+    let sandbox = Sandbox::new();
+
+    assert!(fs::readdir(sandbox.path("missing"), false, false).unwrap().is_empty());
+}
+
+#[test]
+fn readdir_paged_continues_on_another_thread() {
+    let sandbox = Sandbox::new();
+    sandbox.file("a.txt", "a");
+    sandbox.file("b.txt", "b");
+    sandbox.file("c.txt", "c");
+
+    let first = fs::readdir_paged(sandbox.root(), None, 2, false).unwrap();
+    let cursor = first.cursor.clone().unwrap();
+    let root = sandbox.root().to_path_buf();
+    let rest = std::thread::spawn(move || fs::readdir_paged(root, Some(&cursor), 2, false).unwrap()).join().unwrap();
+
+    assert_eq!(first.entries.len(), 2);
+    assert_eq!(rest.entries.len(), 1);
+    assert!(rest.cursor.is_none());
+}
+
+#[test]
+fn readdir_with_resolves_link_chain() {
+    let sandbox = Sandbox::new();
+    let target = sandbox.file("target.txt", "a");
+    let Some(first) = sandbox.symlink("first.txt", &target) else {
+        return;
+    };
+    let Some(second) = sandbox.symlink("second.txt", &first) else {
+        return;
+    };
+
+    let options = ReaddirOptions {
+        with_link_target: true,
+        ..Default::default()
+    };
+    let entries = fs::readdir_with(sandbox.root(), false, &options).unwrap();
+    let link_target = |path: &std::path::Path| entries.iter().find(|entry| entry.name == path.file_name().unwrap().to_string_lossy()).unwrap().link_target.clone();
+
+    assert_eq!(std::fs::canonicalize(link_target(&second).unwrap()).unwrap(), std::fs::canonicalize(&target).unwrap());
+    assert_eq!(link_target(&target), None);
+}
+
+#[test]
+fn stat_reports_read_only_file() {
+    if is_root() {
+        return;
+    }
+    let sandbox = Sandbox::new();
+    let file = sandbox.read_only_file("read-only.txt", "a");
+
+    let attributes = fs::stat(&file).unwrap();
+
+    assert!(attributes.is_file);
+    assert!(attributes.is_read_only);
+    assert_eq!(attributes.size, 1);
+}
+
+#[test]
+fn stat_reports_symlink_target() {
+    let sandbox = Sandbox::new();
+    let target = sandbox.file("target.txt", "a");
+    let Some(link) = sandbox.symlink("link.txt", &target) else {
+        return;
+    };
+
+    let attributes = fs::stat(&link).unwrap();
+
+    assert!(attributes.is_symbolic_link);
+    assert_eq!(attributes.link_path, target.to_string_lossy());
+}
+
+#[test]
+fn copy_keeps_source() {
+    let sandbox = Sandbox::new();
+    let file = sandbox.file("file.txt", "content");
+    let dest = sandbox.dir("dest");
+
+    fs::copy(&file, &dest).unwrap();
+
+    assert!(file.exists());
+    assert_eq!(std::fs::read_to_string(dest.join("file.txt")).unwrap(), "content");
+}
+
+#[test]
+fn copy_directory_into_long_path() {
+    let sandbox = Sandbox::new();
+    sandbox.file("dir/file.txt", "content");
+    let dest = sandbox.long_dir();
+
+    fs::copy(sandbox.path("dir"), &dest).unwrap();
+
+    assert!(fs::stat(dest.join("dir").join("file.txt")).unwrap().is_file);
+}
+
+#[test]
+fn mv_removes_source() {
+    let sandbox = Sandbox::new();
+    let file = sandbox.file("file.txt", "content");
+    let dest = sandbox.dir("dest");
+
+    fs::mv(&file, &dest).unwrap();
+
+    assert!(!file.exists());
+    assert!(dest.join("file.txt").exists());
+}
+
+#[test]
+fn mv_all_moves_every_item() {
+    let sandbox = Sandbox::new();
+    let files = [sandbox.file("a.txt", "a"), sandbox.file("b.txt", "b")];
+    let dest = sandbox.dir("dest");
+
+    fs::mv_all(&files, &dest).unwrap();
+
+    assert!(files.iter().all(|file| !file.exists()));
+    assert!(dest.join("a.txt").exists() && dest.join("b.txt").exists());
+}
+
+#[test]
+fn copy_into_own_subfolder_fails() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("dir");
+    let sub = sandbox.dir("dir/sub");
+
+    assert!(fs::copy(&dir, &sub).is_err());
+    assert!(!sub.join("dir").exists());
+}
+
+#[test]
+fn copy_into_missing_subfolder_fails() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("dir");
+    let missing = sandbox.path("dir/missing/sub");
+
+    assert!(fs::copy(&dir, &missing).is_err_and(|e| e.contains("inside the source")));
+    assert!(!missing.exists());
+}
+
+#[test]
+fn mv_into_itself_fails() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("dir");
+
+    assert!(fs::mv(&dir, &dir).is_err());
+    assert!(dir.is_dir());
+}
+
+#[test]
+fn mv_into_subfolder_through_symlink_fails() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("dir");
+    let sub = sandbox.dir("dir/sub");
+    let Some(link) = sandbox.symlink("link", &sub) else {
+        return;
+    };
+
+    assert!(fs::mv(&dir, &link).is_err());
+    assert!(sub.is_dir());
+}
+
+#[test]
+fn copy_symlinked_source_into_its_target_fails() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("dir");
+    let sub = sandbox.dir("dir/sub");
+    let Some(link) = sandbox.symlink("link", &dir) else {
+        return;
+    };
+
+    assert!(fs::copy_all(&[&link], &sub).is_err());
+}
+
+#[test]
+fn delete_removes_directory_tree() {
+    let sandbox = Sandbox::new();
+    sandbox.file("dir/nested/file.txt", "a");
+
+    fs::delete(sandbox.path("dir")).unwrap();
+
+    assert!(!sandbox.path("dir").exists());
+}
+
+#[test]
+fn mkdir_all_creates_parents() {
+    let sandbox = Sandbox::new();
+
+    let dirent = fs::mkdir_all(sandbox.path("a/b/c"), None).unwrap();
+
+    assert!(dirent.attributes.is_directory);
+    assert_eq!(dirent.name, "c");
+    assert!(sandbox.path("a/b/c").is_dir());
+}
+
+#[test]
+fn mkdir_fails_without_parent() {
+    let sandbox = Sandbox::new();
+
+    assert!(fs::mkdir(sandbox.path("a/b"), Some(MkdirOptions::default())).is_err());
+}
+
+#[test]
+fn set_attributes_toggles_read_only() {
+    if is_root() {
+        return;
+    }
+    let sandbox = Sandbox::new();
+    let file = sandbox.file("file.txt", "a");
+
+    fs::set_attributes(
+        &file,
+        SetAttributeOptions {
+            is_read_only: Some(true),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(fs::stat(&file).unwrap().is_read_only);
+
+    fs::set_attributes(
+        &file,
+        SetAttributeOptions {
+            is_read_only: Some(false),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(!fs::stat(&file).unwrap().is_read_only);
+}
+
+#[test]
+#[ignore = "moves files to the user's trash"]
+fn trash_and_undelete_restore_item() {
+    let sandbox = Sandbox::new();
+    let file = sandbox.file("trashed.txt", "a");
+
+    fs::trash(&file).unwrap();
+    assert!(!file.exists());
+
+    fs::undelete(&[&file]).unwrap();
+    assert!(file.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn is_network_file_system_classifies_mount_types() {
+    for file_system in ["nfs", "nfs4", "cifs", "smb3", "fuse.sshfs", "9p"] {
+        assert!(fs::is_network_file_system(file_system), "{file_system}");
+    }
+    for file_system in ["ext4", "btrfs", "tmpfs", "fuseblk", "fuse.ntfs-3g"] {
+        assert!(!fs::is_network_file_system(file_system), "{file_system}");
+    }
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::Sandbox;
+use std::path::Path;
+use zouni::path;
+
+#[test]
+fn normalize_resolves_dots() {
+    assert_eq!(path::normalize(Path::new("a").join(".").join("b").join("..").join("c")), Path::new("a").join("c"));
+    assert_eq!(path::normalize(Path::new("..").join("a").join("..").join("..").join("b")), Path::new("..").join("..").join("b"));
+}
+
+#[test]
+fn relative_to_walks_up_from_base() {
+    let sandbox = Sandbox::new();
+    let path = sandbox.path("a/b/c.txt");
+    let base = sandbox.path("a/d");
+
+    assert_eq!(path::relative_to(&path, &base).unwrap(), Path::new("..").join("b").join("c.txt"));
+    assert_eq!(path::relative_to(&base, &base).unwrap(), Path::new("."));
+    assert_eq!(path::relative_to("a", &base), None);
+}
+
+#[test]
+fn is_same_path_ignores_case_only_on_windows() {
+    assert!(path::is_same_path(Path::new("a").join("b").join(".."), "a"));
+    assert_eq!(path::is_same_path("Readme.txt", "README.TXT"), cfg!(target_os = "windows"));
+}
+
+#[test]
+fn is_same_file_detects_hard_link() {
+    let sandbox = Sandbox::new();
+    let file = sandbox.file("file.txt", "a");
+    let other = sandbox.file("other.txt", "a");
+    let link = sandbox.path("link.txt");
+    std::fs::hard_link(&file, &link).unwrap();
+
+    assert!(path::is_same_file(&file, &link).unwrap());
+    assert!(!path::is_same_file(&file, &other).unwrap());
+    assert!(path::is_same_file(&file, sandbox.path("missing.txt")).is_err());
+}
@@ -0,0 +1,105 @@
+#![cfg(feature = "testing")]
+mod common;
+
+use common::Sandbox;
+use std::path::Path;
+use zouni::{
+    fs::{FileOperation, OperationStatus, Response},
+    testing::{FsProvider, MemoryFs, NativeFs},
+};
+
+/* Runs the check against the real file system in a sandbox and against MemoryFs with the same paths */
+fn on_both(check: impl Fn(&dyn FsProvider, &Path)) {
+    let sandbox = Sandbox::new();
+    check(&NativeFs, sandbox.root());
+
+    let memory = MemoryFs::new();
+    memory.add_dir(sandbox.root()).unwrap();
+    check(&memory, sandbox.root());
+}
+
+#[test]
+fn copy_keeps_source() {
+    on_both(|fs, root| {
+        fs.mkdir(&root.join("dest"), None).unwrap();
+        fs.write(&root.join("file.txt"), b"abc").unwrap();
+
+        fs.copy(&root.join("file.txt"), &root.join("dest")).unwrap();
+
+        assert_eq!(fs.read(&root.join("dest").join("file.txt")).unwrap(), b"abc");
+        assert!(fs.stat(&root.join("file.txt")).is_ok());
+    });
+}
+
+#[test]
+fn mv_moves_directory_tree() {
+    on_both(|fs, root| {
+        fs.mkdir_all(&root.join("src").join("nested"), None).unwrap();
+        fs.write(&root.join("src").join("nested").join("file.txt"), b"abc").unwrap();
+        fs.mkdir(&root.join("dest"), None).unwrap();
+
+        fs.mv(&root.join("src"), &root.join("dest")).unwrap();
+
+        assert!(fs.stat(&root.join("src")).is_err());
+        assert_eq!(fs.read(&root.join("dest").join("src").join("nested").join("file.txt")).unwrap(), b"abc");
+    });
+}
+
+#[test]
+fn delete_removes_directory_tree() {
+    on_both(|fs, root| {
+        fs.mkdir(&root.join("dir"), None).unwrap();
+        fs.write(&root.join("dir").join("file.txt"), b"abc").unwrap();
+
+        fs.delete(&root.join("dir")).unwrap();
+
+        assert!(fs.readdir(root, true, false).unwrap().is_empty());
+    });
+}
+
+#[test]
+fn mkdir_fails_on_existing_directory() {
+    on_both(|fs, root| {
+        fs.mkdir(&root.join("dir"), None).unwrap();
+
+        assert!(fs.mkdir(&root.join("dir"), None).is_err());
+        assert!(fs.mkdir_all(&root.join("dir"), None).is_ok());
+    });
+}
+
+#[test]
+fn readdir_reports_sizes() {
+    on_both(|fs, root| {
+        fs.mkdir(&root.join("dir"), None).unwrap();
+        fs.write(&root.join("dir").join("file.txt"), b"abcd").unwrap();
+
+        let entries = fs.readdir(root, true, false).unwrap();
+        let file = entries.iter().find(|entry| entry.name == "file.txt").unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(file.attributes.size, 4);
+        assert!(entries.iter().any(|entry| entry.name == "dir" && entry.attributes.is_directory));
+    });
+}
+
+#[test]
+fn operate_reports_skipped_conflicts() {
+    let memory = MemoryFs::new();
+    memory.add_file("/src/file.txt", b"new").unwrap();
+    memory.add_file("/dest/file.txt", b"old").unwrap();
+
+    let mut skipped = Vec::new();
+    memory
+        .operate(FileOperation::Copy, &["/src/file.txt"], Some("/dest"), |status| match status {
+            OperationStatus::Confirm(_) => Response::Skip,
+            OperationStatus::Skipped(source) => {
+                skipped.push(source);
+                Response::Proceed
+            }
+            _ => Response::Proceed,
+        })
+        .unwrap();
+
+    assert_eq!(skipped, vec!["/src/file.txt".to_string()]);
+    assert_eq!(memory.read("/dest/file.txt").unwrap(), b"old");
+}